@@ -0,0 +1,45 @@
+//! Generates the C header for the `capi` feature into `OUT_DIR` and checks that the checked in
+//! `include/fluxcore_ng.h` matches it. Build with `FLUXCORE_NG_UPDATE_HEADER=1` to copy the generated
+//! header over the checked in one after changing the C API.
+
+#[cfg(feature = "capi")]
+extern crate cbindgen;
+
+#[cfg(feature = "capi")]
+fn main() {
+    use std::env;
+    use std::fs;
+
+    let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let checked_in = format!("{}/include/fluxcore_ng.h", dir);
+    println!("cargo:rerun-if-changed=src/capi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed={}", checked_in);
+    println!("cargo:rerun-if-env-changed=FLUXCORE_NG_UPDATE_HEADER");
+
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", dir)).unwrap();
+    let generated = format!("{}/fluxcore_ng.h", env::var("OUT_DIR").unwrap());
+    cbindgen::Builder::new()
+        .with_crate(&dir)
+        .with_config(config)
+        .generate()
+        .expect("cannot generate the C header")
+        .write_to_file(&generated);
+
+    let same = match (fs::read(&generated), fs::read(&checked_in)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    };
+    if same {
+        return;
+    }
+    if env::var_os("FLUXCORE_NG_UPDATE_HEADER").is_some() {
+        fs::copy(&generated, &checked_in).expect("cannot update include/fluxcore_ng.h");
+    } else {
+        panic!("include/fluxcore_ng.h differs from the header generated from src/capi.rs ({}), build with FLUXCORE_NG_UPDATE_HEADER=1 to update it", generated);
+    }
+}
+
+#[cfg(not(feature = "capi"))]
+fn main() {
+}
@@ -0,0 +1,181 @@
+#ifndef FLUXCORE_NG_H
+#define FLUXCORE_NG_H
+
+/* Generated from src/capi.rs by cbindgen when building with --features capi, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Named values of the `axis` arguments, which are plain ints since C may pass anything.
+ */
+typedef enum FluxcoreAxis {
+  FLUXCORE_AXIS_X = 0,
+  FLUXCORE_AXIS_Y = 1,
+  FLUXCORE_AXIS_Z = 2,
+} FluxcoreAxis;
+
+/**
+ * Columns collected for `fluxcore_renderer_new` or `fluxcore_renderer_set_data`.
+ */
+typedef struct FluxcoreColumns FluxcoreColumns;
+
+typedef struct FluxcoreRenderer FluxcoreRenderer;
+
+/**
+ * Resolves OpenGL functions of the application's context, e.g. via `SDL_GL_GetProcAddress`.
+ */
+typedef const void *(*FluxcoreGetProcAddress)(void *user_data, const char *symbol);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Message of the last failed call on this thread. Valid until the next failing call.
+ */
+const char *fluxcore_last_error(void);
+
+struct FluxcoreColumns *fluxcore_columns_new(void);
+
+/**
+ * Copies `len` values as column `name`. NaN marks missing values, `is_time` columns hold seconds
+ * since 1970-01-01 UTC.
+ */
+int fluxcore_columns_add(struct FluxcoreColumns *columns,
+                         const char *name,
+                         const double *data,
+                         size_t len,
+                         bool is_time);
+
+/**
+ * Only needed for columns that are not passed to a renderer.
+ */
+void fluxcore_columns_free(struct FluxcoreColumns *columns);
+
+/**
+ * Creates a renderer with its own window, which stays hidden unless `visible` is set. `columns`
+ * are consumed, `name` is used for the window title and the bookmarks file. Returns `NULL` on
+ * errors.
+ */
+struct FluxcoreRenderer *fluxcore_renderer_new(struct FluxcoreColumns *columns,
+                                               const char *name,
+                                               uint32_t width,
+                                               uint32_t height,
+                                               bool visible);
+
+/**
+ * Creates a renderer that draws into the application's current OpenGL 3.3 core context, whose
+ * default framebuffer is `width` x `height` pixels. Returns `NULL` on errors.
+ */
+struct FluxcoreRenderer *fluxcore_renderer_new_gl(struct FluxcoreColumns *columns,
+                                                  const char *name,
+                                                  uint32_t width,
+                                                  uint32_t height,
+                                                  FluxcoreGetProcAddress get_proc_address,
+                                                  void *user_data);
+
+void fluxcore_renderer_free(struct FluxcoreRenderer *r);
+
+/**
+ * Replaces the data by `columns`, which are consumed. The view is kept if the column names are
+ * the same as before.
+ */
+int fluxcore_renderer_set_data(struct FluxcoreRenderer *r, struct FluxcoreColumns *columns);
+
+/**
+ * Runs a remote control method with parameters `params_json` (a JSON object, `NULL` for none).
+ * On success, `*result_json` is set to the JSON result if `result_json` is not `NULL`, which has
+ * to be freed with `fluxcore_string_free`.
+ */
+int fluxcore_renderer_command(struct FluxcoreRenderer *r,
+                              const char *method,
+                              const char *params_json,
+                              char **result_json);
+
+void fluxcore_string_free(char *s);
+
+/**
+ * Chooses the X, Y and Z columns by name, `NULL` keeps the current one.
+ */
+int fluxcore_renderer_set_columns(struct FluxcoreRenderer *r,
+                                  const char *x,
+                                  const char *y,
+                                  const char *z);
+
+/**
+ * Shows `[min, max]` of `axis`, one of `FluxcoreAxis`, in the units of the transformed column.
+ */
+int fluxcore_renderer_set_range(struct FluxcoreRenderer *r, int axis, double min, double max);
+
+int fluxcore_renderer_set_gamma(struct FluxcoreRenderer *r, float gamma);
+
+int fluxcore_renderer_set_point_size(struct FluxcoreRenderer *r, float size);
+
+/**
+ * Handles the input events since the last call and draws a frame, into the own window or the
+ * application's default framebuffer. Returns 1, 0 once the user quit, or -1 on errors.
+ */
+int fluxcore_renderer_draw(struct FluxcoreRenderer *r);
+
+/**
+ * Shows the own window and runs until the user closes it.
+ */
+int fluxcore_renderer_run(struct FluxcoreRenderer *r);
+
+/**
+ * Renders the current view offscreen into `out`, `width * height` 8 bit sRGB RGBA pixels with rows
+ * from top to bottom.
+ */
+int fluxcore_renderer_render_rgba(struct FluxcoreRenderer *r,
+                                  uint32_t width,
+                                  uint32_t height,
+                                  uint8_t *out);
+
+/**
+ * Renders the accumulation buffer into `out`, `width * height * 4` floats: luminance and Lab a/b
+ * sums and the point count, divided by the number of rows.
+ */
+int fluxcore_renderer_render_density(struct FluxcoreRenderer *r,
+                                     uint32_t width,
+                                     uint32_t height,
+                                     float *out);
+
+/**
+ * The application's framebuffer or the window was resized.
+ */
+int fluxcore_renderer_resize(struct FluxcoreRenderer *r, uint32_t width, uint32_t height);
+
+/**
+ * Key named as in the config file, e.g. `A`, `PageUp` or `LShift`; modifiers are keys of their own.
+ */
+int fluxcore_renderer_key(struct FluxcoreRenderer *r, const char *key, bool pressed);
+
+/**
+ * Text input (a Unicode code point), for the prompts.
+ */
+int fluxcore_renderer_char(struct FluxcoreRenderer *r, uint32_t codepoint);
+
+/**
+ * Mouse position in pixels from the top left corner.
+ */
+int fluxcore_renderer_mouse_move(struct FluxcoreRenderer *r, int32_t x, int32_t y);
+
+/**
+ * The left mouse button, which pans the view while pressed.
+ */
+int fluxcore_renderer_mouse_button(struct FluxcoreRenderer *r, bool pressed);
+
+/**
+ * Zooms around the mouse position, in lines of scrolling.
+ */
+int fluxcore_renderer_scroll(struct FluxcoreRenderer *r, float dx, float dy);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FLUXCORE_NG_H */
@@ -0,0 +1,51 @@
+
+out vec4 color;  // linear RGB
+
+uniform sampler2D cells;        // density and Z sums of every cell
+uniform sampler2D histogram;    // density of the 64 Z bins of every cell, 8x8 texels per cell
+uniform vec2      origin;       // position of the drawn surface in the binned image, in pixels
+uniform float     cell_pixels;  // area of a cell in pixels
+
+// Z where the histogram of `cell` reaches half of `counter`, linear within the bin
+float median(ivec2 cell, float counter) {
+    float wanted = 0.5 * counter;
+    float sum = 0.0;
+    for (int k = 0; k < 64; k++) {
+        float v = texelFetch(histogram, cell * 8 + ivec2(k % 8, k / 8), 0).r;
+        if (v > 0.0 && sum + v >= wanted) {
+            return (float(k) + (wanted - sum) / v) / 64.0;
+        }
+        sum += v;
+    }
+    return 1.0;
+}
+
+void main() {
+    ivec2 cell = cell_of(gl_FragCoord.xy + origin);
+    if (any(lessThan(cell, ivec2(0))) || any(greaterThanEqual(cell, ivec2(bin_cells)))) {
+        color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+    vec4 sums = texelFetch(cells, cell, 0);
+    float counter = sums.a;
+    if (counter <= 0.0) {
+        color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    float z;
+    if (stat > 6.5) {
+        z = median(cell, counter);
+    } else {
+        float categories = distinct(
+            texelFetch(categories0, cell, 0),
+            texelFetch(categories1, cell, 0),
+            texelFetch(categories2, cell, 0),
+            texelFetch(categories3, cell, 0)
+        );
+        z = stat_value(sums, texelFetch(extrema, cell, 0), categories);
+    }
+
+    // density per pixel, which does not depend on the cell size
+    color = vec4(tonemap(100.0 * pow(counter / cell_pixels, inv_gamma), z), 1.0);
+}
@@ -0,0 +1,26 @@
+// cell grid of the bins, shared by the point vertex shaders and the bins fragment shader
+
+uniform float bin_size;   // distance of the centers of neighbouring cells in pixels, 0 draws points
+uniform float bin_shape;  // 0 = squares, 1 = hexagons
+uniform vec2  bin_cells;  // columns and rows of the grid
+
+// column and row of the cell that pixel position p (from the bottom left corner of the image) is in
+ivec2 cell_of(vec2 p) {
+    if (bin_shape < 0.5) {
+        return ivec2(floor(p / bin_size));
+    }
+
+    // hexagons pointing upwards, rows 1.5 r apart with every odd row shifted by half a cell;
+    // even and odd rows are two lattices with a spacing of (size, 3 r), the nearer center wins
+    float r = bin_size / sqrt(3.0);
+    vec2 spacing = vec2(bin_size, 3.0 * r);
+    vec2 even = floor(p / spacing + 0.5);
+    vec2 odd  = floor((p - 0.5 * spacing) / spacing + 0.5);
+    vec2 d_even = p - even * spacing;
+    vec2 d_odd  = p - (odd + 0.5) * spacing;
+    if (dot(d_even, d_even) <= dot(d_odd, d_odd)) {
+        return ivec2(int(even.x), 2 * int(even.y));
+    } else {
+        return ivec2(int(odd.x), 2 * int(odd.y) + 1);
+    }
+}
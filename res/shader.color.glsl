@@ -0,0 +1,87 @@
+
+// tone mapping and the statistics of Z, shared by the texture and the bins fragment shaders
+
+uniform float     inv_gamma;
+uniform float     stat;            // index of stat::Stat
+uniform vec2      stat_range;      // sum or standard deviation of Z at the ends of the color map
+uniform vec3      stat_z;          // rows, scale and offset of the Z projection
+uniform float     category_count;  // distinct values of Z
+uniform sampler2D extrema;         // largest Z and negative smallest Z
+uniform sampler2D categories0;     // presence of the categories of Z, four per texture
+uniform sampler2D categories1;
+uniform sampler2D categories2;
+uniform sampler2D categories3;
+
+float f_inv(float t) {
+    float delta = 6.0 / 29.0;
+    if (t > delta) {
+        return t * t * t;
+    } else {
+        return 3 * delta * delta * (t - 4.0 / 29.0);
+    }
+}
+
+vec3 Lab2XYZ(vec3 Lab) {
+    // that's the D65 white point with the 2 degrees (CIE 1931) observer
+    const float Xn = 95.047;
+    const float Yn = 100.0;
+    const float Zn = 108.883;
+
+    float L = Lab.x;
+    float a = Lab.y;
+    float b = Lab.z;
+
+    float tmp = (L + 16.0) / 116.0;
+
+    return vec3(
+        Xn * f_inv(tmp + a / 500.0),
+        Yn * f_inv(tmp),
+        Zn * f_inv(tmp - b / 200.0)
+    );
+}
+
+vec3 XYZ2RGB(vec3 XYZ) {
+    // D65 as well
+    // with sRGB
+    const mat3 XYZ_to_RGB = mat3(
+         3.2404542, -1.5371385, -0.4985314,
+        -0.9692660,  1.8760108,  0.0415560,
+         0.0556434, -0.2040259,  1.0572252
+    );
+
+    return XYZ_to_RGB * XYZ;
+}
+
+// linear RGB of the lightness L in [0,100] and the position z in [0,1] on the color map
+vec3 tonemap(float L, float z) {
+    return XYZ2RGB(0.01 * Lab2XYZ(vec3(L, 256.0 * z - 128.0, 256.0 * z - 128.0)));  // [0,1] => [-128,128]
+}
+
+// number of categories present in all four textures
+float distinct(vec4 c0, vec4 c1, vec4 c2, vec4 c3) {
+    return dot(c0 + c1 + c2 + c3, vec4(1.0));
+}
+
+// position on the color map of the statistic, from the sums (Lab + counter, with the second moment
+// in place of b for the standard deviation), the extrema and the number of categories
+float stat_value(vec4 sums, vec4 extremes, float categories) {
+    float counter = sums.a;
+    float mean = sums.y / counter;
+    float width = max(stat_range.y - stat_range.x, 1e-30);
+    if (stat > 0.5 && stat < 1.5) {
+        return 0.5;
+    } else if (stat > 1.5 && stat < 2.5) {
+        return -extremes.y;
+    } else if (stat > 2.5 && stat < 3.5) {
+        return extremes.x;
+    } else if (stat > 3.5 && stat < 4.5) {
+        float sum = stat_z.x * (sums.y - stat_z.z * counter) / stat_z.y;
+        return (sum - stat_range.x) / width;
+    } else if (stat > 4.5 && stat < 5.5) {
+        float deviation = sqrt(max(sums.z / counter - mean * mean, 0.0)) / abs(stat_z.y);
+        return (deviation - stat_range.x) / width;
+    } else if (stat > 5.5 && stat < 6.5) {
+        return category_count > 1.5 ? (categories - 1.0) / (category_count - 1.0) : 0.5;
+    }
+    return mean;
+}
@@ -0,0 +1,22 @@
+
+// one (hi, lo) pair per row, relative to a per-column reference value
+uniform samplerBuffer col_x;
+uniform samplerBuffer col_y;
+uniform samplerBuffer col_z;
+
+// origin - reference, split the same way
+uniform vec2 offset_x;
+uniform vec2 offset_y;
+uniform vec2 offset_z;
+
+// fades out the trail of an animation, 1 otherwise
+uniform float frame_weight;
+
+float fetch(samplerBuffer col, vec2 offset) {
+    vec2 v = texelFetch(col, gl_VertexID).rg;
+    return (v.x - offset.x) + (v.y - offset.y);
+}
+
+void main() {
+    project(vec3(fetch(col_x, offset_x), fetch(col_y, offset_y), fetch(col_z, offset_z)), frame_weight);
+}
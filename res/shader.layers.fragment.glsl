@@ -0,0 +1,60 @@
+#version 140
+
+in vec2 v_tex_coords;
+
+out vec4 color;  // linear RGB, added up over the layers
+
+uniform float     inv_gamma;
+uniform sampler2D tex;         // accumulation of this layer
+uniform sampler2D other;       // accumulation of the layer it is compared to
+uniform vec3      layer_color;
+uniform float     mode;        // 0 = blend, 1 = difference, 2 = diverging
+uniform float     scale;       // diverging: 1 / largest difference of the counters
+
+float f_inv(float t) {
+    float delta = 6.0 / 29.0;
+    if (t > delta) {
+        return t * t * t;
+    } else {
+        return 3 * delta * delta * (t - 4.0 / 29.0);
+    }
+}
+
+// relative luminance of the tone mapped density, as the texture shader would show it
+float luminance(vec4 tdata) {
+    float counter = tdata.a;
+    if (counter <= 0.0) {
+        return 0.0;
+    }
+    float multiplier = pow(counter, inv_gamma) / counter;
+    float L = 100.0 * tdata.x * multiplier;
+    return f_inv((L + 16.0) / 116.0);
+}
+
+// dark in the middle like the background, the same stops as in layers.rs
+vec3 diverging(float v) {
+    const vec3 stops[5] = vec3[5](
+        vec3(0.55, 0.85, 1.00),
+        vec3(0.05, 0.30, 0.90),
+        vec3(0.00, 0.00, 0.00),
+        vec3(0.80, 0.15, 0.02),
+        vec3(1.00, 0.85, 0.45)
+    );
+    float x = clamp((v + 1.0) * 2.0, 0.0, 4.0);
+    int i = int(min(floor(x), 3.0));
+    return mix(stops[i], stops[i + 1], x - float(i));
+}
+
+void main() {
+    if (mode > 1.5) {
+        float d = (texture(tex, v_tex_coords).a - texture(other, v_tex_coords).a) * scale;
+        color = vec4(diverging(sign(d) * pow(min(abs(d), 1.0), inv_gamma)), 1.0);
+        return;
+    }
+
+    float y = luminance(texture(tex, v_tex_coords));
+    if (mode > 0.5) {
+        y = max(y - luminance(texture(other, v_tex_coords)), 0.0);
+    }
+    color = vec4(layer_color * y, 1.0);
+}
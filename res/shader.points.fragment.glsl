@@ -2,10 +2,15 @@
 
 in vec3  pointcolor;  // Lab color space
 in float atborder;
+in float pointweight;
+in float pointcategory;
 
 out vec4 color;       // Lab + counter
 
 uniform float inv_n;
+uniform float extrema;        // = bool, the largest Z and the negative smallest Z instead of sums
+uniform float moments;        // = bool, the second moment of Z in place of the third sum
+uniform float category_base;  // presence of the four categories from this one on, -1 for sums
 
 void main() {
     vec2  delta    = vec2(0.5, 0.5) - gl_PointCoord;
@@ -20,5 +25,24 @@ void main() {
     if (fade < 0.000001) {
         discard;
     }
-    color = vec4(pointcolor, 1.0) * fade * vec4(inv_n);
+    // pointcolor is scaled down at the border
+    float z = pointcolor.y / pointcolor.x;
+    if (extrema > 0.5) {
+        color = vec4(z, -z, 0.0, 0.0);
+        return;
+    }
+    if (category_base > -0.5) {
+        float c = floor(pointcategory - category_base + 0.5);
+        if (c < 0.0 || c > 3.0) {
+            discard;
+        }
+        color = vec4(equal(vec4(c), vec4(0.0, 1.0, 2.0, 3.0)));
+        return;
+    }
+
+    vec3 sums = pointcolor;
+    if (moments > 0.5) {
+        sums.z = z * pointcolor.y;
+    }
+    color = vec4(sums, 1.0) * fade * vec4(inv_n * pointweight);
 }
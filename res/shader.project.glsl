@@ -0,0 +1,89 @@
+// shared by all point vertex shaders, which call project() from their main(); follows the cell
+// grid of the bins
+
+out vec3  pointcolor;  // = Lab color space
+out float atborder;    // = bool
+out float pointweight;
+out float pointcategory;   // index of the category of Z, -1 without categories
+
+uniform mat4  matrix;
+uniform float pointsize;
+uniform float showborder;  // = bool
+
+uniform mat4  categories;      // Z of the categories of the distinct statistic, 16 at most
+uniform float category_count;
+
+uniform vec2  bin_image;   // size of the binned image in pixels
+uniform float bin_target;  // 0 = one texel per cell, 1 = a square of 8x8 texels for the Z histogram
+
+// moves the point onto the texel of its cell, or out of sight if it is not in the image
+void bin(float z) {
+    vec2 p = (gl_Position.xy * 0.5 + 0.5) * bin_image;
+    vec2 texel = vec2(cell_of(p));
+    vec2 size = bin_cells;
+    if (bin_target > 0.5) {
+        float k = clamp(floor(z * 64.0), 0.0, 63.0);
+        texel = texel * 8.0 + vec2(mod(k, 8.0), floor(k / 8.0));
+        size *= 8.0;
+    }
+
+    gl_PointSize = 1.0;
+    if (any(lessThan(p, vec2(0.0))) || any(greaterThanEqual(p, bin_image))) {
+        gl_Position = vec4(2.0, 2.0, 0.0, 1.0);
+    } else {
+        gl_Position = vec4((texel + 0.5) / size * 2.0 - 1.0, 0.0, 1.0);
+    }
+}
+
+void project(vec3 position, float weight) {
+    gl_PointSize     = pointsize;
+    vec4 pos_virtual = matrix * vec4(position, 1.0);
+    gl_Position      = vec4(pos_virtual.xy, 0.0, 1.0);
+    pointweight      = weight;
+
+    // the category with the nearest Z
+    pointcategory = -1.0;
+    float nearest = 1e30;
+    for (int i = 0; i < 16; i++) {
+        float d = abs(pos_virtual.z - categories[i / 4][i % 4]);
+        if (float(i) < category_count && d < nearest) {
+            nearest       = d;
+            pointcategory = float(i);
+        }
+    }
+
+    if (bin_size > 0.0) {
+        bin(pos_virtual.z);
+        atborder   = 0.0;
+        pointcolor = vec3(1.0, pos_virtual.z, pos_virtual.z);
+        return;
+    }
+
+    float alpha = 1.0;
+    atborder = 0.0;
+    if (showborder > 0.5) {
+        float dist = 0.0;
+        if (gl_Position.x > 1.0) {
+            dist += gl_Position.x;
+            gl_Position.x = 1.0;
+        } else if (gl_Position.x < -1.0) {
+            dist -= gl_Position.x;
+            gl_Position.x = -1.0;
+        }
+        if (gl_Position.y > 1.0) {
+            dist += gl_Position.y;
+            gl_Position.y = 1.0;
+        } else if (gl_Position.y < -1.0) {
+            dist -= gl_Position.y;
+            gl_Position.y= -1.0;
+        }
+        if (dist > 0.0) {
+            gl_PointSize /= dist;
+            alpha        = 1.0 / dist;
+            atborder     = 1.0;
+        }
+    }
+
+    float color_a_and_b = pos_virtual.z;
+    pointcolor = vec3(1.0, color_a_and_b, color_a_and_b) * alpha;
+}
@@ -0,0 +1,13 @@
+#version 140
+
+in vec2 v_tex_coords;
+in vec4 v_color;
+
+out vec4 color;
+
+uniform sampler2D tex;
+
+void main() {
+    float coverage = texture(tex, v_tex_coords).r;
+    color = vec4(v_color.rgb, v_color.a * coverage);
+}
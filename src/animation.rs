@@ -0,0 +1,188 @@
+use cfg;
+
+use data::{Column, Kind};
+
+use datetime::DateTime;
+
+use std::cmp;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+
+/// First value of every frame, ascending: the distinct values of the column, or equally wide bins
+/// if there are too many of them. NA values belong to no frame.
+fn frame_starts(column: &Column, mask: &[bool]) -> Vec<f64> {
+    let mut values = column.data.iter().zip(mask.iter()).filter(|&(v, m)| *m && !v.is_nan()).map(|(v, _)| *v).collect::<Vec<f64>>();
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    values.dedup();
+    if values.len() <= cfg::ANIMATION_FRAMES_MAX {
+        return values;
+    }
+
+    let (min, max) = (values[0], values[values.len() - 1]);
+    let count = cfg::ANIMATION_FRAMES_MAX;
+    (0..count).map(|i| min + (max - min) * (i as f64) / (count as f64)).collect()
+}
+
+fn format_value(v: f64, kind: Kind) -> String {
+    if kind == Kind::Time {
+        let dt = DateTime::from_timestamp(v);
+        format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second.floor())
+    } else {
+        format!("{}", v)
+    }
+}
+
+
+/// Rows grouped into frames along a column (e.g. a simulation step), of which a sliding window is
+/// shown at a time.
+pub struct Animation {
+    pub column: usize,
+    /// number of consecutive frames shown at once
+    pub window: usize,
+    /// first frame of the window
+    pub frame: usize,
+    pub playing: bool,
+    /// also show the frames before the window, fading out
+    pub trails: bool,
+    frames: Vec<f64>,
+    /// start of every frame in the rows ordered by frame, plus the end of the last one
+    offsets: Vec<usize>,
+    last_step: Instant,
+}
+
+impl Animation {
+    /// `COLUMN [WINDOW]`, where `WINDOW` is the number of frames shown at once (default 1).
+    pub fn parse(s: &str, columns: &[Column]) -> Result<Animation, String> {
+        let s = s.trim();
+        let (name, window) = match s.rfind(' ') {
+            Some(pos) => match s[pos + 1..].parse::<usize>() {
+                Ok(w) => (s[..pos].trim(), w),
+                Err(_) => (s, 1),
+            },
+            None => (s, 1),
+        };
+        if window == 0 {
+            return Err(String::from("the animation window needs at least one frame"));
+        }
+
+        match columns.iter().position(|c| c.name == name) {
+            Some(idx) => Ok(Animation {
+                column: idx,
+                window: window,
+                frame: 0,
+                playing: false,
+                trails: false,
+                frames: vec![],
+                offsets: vec![0],
+                last_step: Instant::now(),
+            }),
+            None => Err(format!("cannot animate unknown column '{}'", name)),
+        }
+    }
+
+    /// Groups the rows that pass `mask` into frames and returns their indices ordered by frame.
+    pub fn rows(&mut self, columns: &[Column], mask: &[bool]) -> Vec<u32> {
+        let column = &columns[self.column];
+        self.frames = frame_starts(column, mask);
+
+        let frame_of = |v: f64| {
+            match self.frames.binary_search_by(|f| f.partial_cmp(&v).unwrap()) {
+                Ok(i) => i,
+                Err(i) => i - 1,
+            }
+        };
+        let mut counts = vec![0; self.frames.len()];
+        for (v, _) in column.data.iter().zip(mask.iter()).filter(|&(v, m)| *m && !v.is_nan()) {
+            counts[frame_of(*v)] += 1;
+        }
+        let mut offsets = vec![0];
+        for c in counts {
+            let last = offsets[offsets.len() - 1];
+            offsets.push(last + c);
+        }
+
+        let mut next = offsets.clone();
+        let mut rows = vec![0; offsets[offsets.len() - 1]];
+        for (i, (v, _)) in column.data.iter().zip(mask.iter()).enumerate().filter(|&(_, (v, m))| *m && !v.is_nan()) {
+            let f = frame_of(*v);
+            rows[next[f]] = i as u32;
+            next[f] += 1;
+        }
+
+        self.offsets = offsets;
+        self.frame = cmp::min(self.frame, self.positions() - 1);
+        rows
+    }
+
+    /// Number of window positions, so that the window is always full.
+    pub fn positions(&self) -> usize {
+        self.frames.len().saturating_sub(self.window) + 1
+    }
+
+    /// Moves the window by `delta` frames, wrapping around at both ends.
+    pub fn step(&mut self, delta: isize) {
+        let positions = self.positions() as isize;
+        self.frame = (((self.frame as isize + delta) % positions + positions) % positions) as usize;
+        self.last_step = Instant::now();
+    }
+
+    /// Advances while playing, once per `cfg::ANIMATION_FRAME_MILLIS`. Returns whether the frame changed.
+    pub fn tick(&mut self) -> bool {
+        if self.playing && Instant::now().duration_since(self.last_step) >= Duration::from_millis(cfg::ANIMATION_FRAME_MILLIS) {
+            self.step(1);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn window_end(&self) -> usize {
+        cmp::min(self.frame + self.window, self.frames.len())
+    }
+
+    /// Number of rows in the window.
+    pub fn visible_rows(&self) -> usize {
+        self.offsets[self.window_end()] - self.offsets[self.frame]
+    }
+
+    /// Number of rows in the fullest window, which are drawn at once.
+    pub fn largest_window(&self) -> usize {
+        (0..self.positions()).map(|f| {
+            self.offsets[cmp::min(f + self.window, self.frames.len())] - self.offsets[f]
+        }).max().unwrap_or(0)
+    }
+
+    /// Ranges of the ordered rows to draw and their weights: the window, followed by the trail.
+    pub fn passes(&self) -> Vec<(Range<usize>, f32)> {
+        let mut passes = vec![(self.offsets[self.frame]..self.offsets[self.window_end()], 1.0)];
+        if self.trails {
+            let mut weight = 1.0;
+            for f in (self.frame.saturating_sub(cfg::ANIMATION_TRAIL_FRAMES)..self.frame).rev() {
+                weight *= cfg::ANIMATION_TRAIL_FADE;
+                passes.push((self.offsets[f]..self.offsets[f + 1], weight));
+            }
+        }
+        passes
+    }
+
+    /// E.g. `step = 40 (5/100)`, or `step in [40, 44] (5/96)` for wider windows.
+    pub fn label(&self, columns: &[Column]) -> String {
+        let column = &columns[self.column];
+        if self.frames.is_empty() {
+            return format!("{}: no frames", column.name);
+        }
+        let first = format_value(self.frames[self.frame], column.kind);
+        let values = if self.window_end() - self.frame > 1 {
+            format!("in [{}, {}]", first, format_value(self.frames[self.window_end() - 1], column.kind))
+        } else {
+            format!("= {}", first)
+        };
+        format!("{} {} ({}/{}){}", column.name, values, self.frame + 1, self.positions(), if self.playing { "" } else { " paused" })
+    }
+
+    /// The input that recreates this animation in the prompt.
+    pub fn to_string(&self, columns: &[Column]) -> String {
+        format!("{} {}", columns[self.column].name, self.window)
+    }
+}
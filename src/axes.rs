@@ -0,0 +1,185 @@
+use data::Kind;
+
+use datetime;
+use datetime::DateTime;
+
+use transform::Units;
+
+use std::f64;
+
+
+pub struct Tick {
+    /// position in (transformed) data units
+    pub pos: f64,
+    pub label: String,
+}
+
+
+/// Round step size (1, 2 or 5 times a power of 10) that splits `[min, max]` into at most ~`count` parts.
+fn nice_step(min: f64, max: f64, count: usize) -> f64 {
+    let raw = (max - min) / (count as f64);
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let normalized = raw / magnitude;
+    let factor = if normalized < 1.5 {
+        1.0
+    } else if normalized < 3.0 {
+        2.0
+    } else if normalized < 7.0 {
+        5.0
+    } else {
+        10.0
+    };
+    factor * magnitude
+}
+
+/// Nicely rounded values in `[min, max]`, together with their step size.
+pub fn nice_ticks(min: f64, max: f64, count: usize) -> (Vec<f64>, f64) {
+    if !(min < max) || !min.is_finite() || !max.is_finite() || count == 0 {
+        return (vec![], 0.0);
+    }
+
+    let step = nice_step(min, max, count);
+    if !(step > 0.0) {
+        return (vec![], 0.0);
+    }
+    let first = (min / step).ceil();
+    let last = (max / step).floor();
+    let values = (0..((last - first) as usize + 1)).map(|i| (first + (i as f64)) * step).collect();
+    (values, step)
+}
+
+/// Formats `v` with as many decimals as `step` requires.
+pub fn format_value(v: f64, step: f64) -> String {
+    let v = if v.abs() < step * 1e-3 { 0.0 } else { v };
+    if v != 0.0 && (v.abs() >= 1e6 || v.abs() < 1e-4) {
+        // enough mantissa digits to tell neighbouring ticks apart
+        let digits = f64::max(2.0, v.abs().log10().floor() - step.log10().floor()) as usize;
+        format!("{:.*e}", digits, v)
+    } else {
+        let decimals = f64::max(0.0, -step.log10().floor()) as usize;
+        format!("{:.*}", decimals, v)
+    }
+}
+
+// fixed-length steps for time axes, in seconds
+static TIME_STEPS: &'static [f64] = &[
+    1.0, 2.0, 5.0, 10.0, 15.0, 30.0,
+    60.0, 120.0, 300.0, 600.0, 900.0, 1800.0,
+    3600.0, 7200.0, 10800.0, 21600.0, 43200.0,
+    86400.0, 172800.0, 604800.0,
+];
+
+static MONTH_STEPS: &'static [i64] = &[1, 3, 6];
+
+static SECONDS_PER_MONTH: f64 = 30.44 * 86400.0;
+
+// 1970-01-05 was a Monday
+static FIRST_MONDAY: f64 = 4.0 * 86400.0;
+
+
+fn format_time(t: f64, step: f64) -> String {
+    let dt = DateTime::from_timestamp(t);
+    if step < 1.0 {
+        let decimals = f64::max(0.0, -step.log10().floor()) as usize;
+        format!("{:02}:{:02}:{:0w$.p$}", dt.hour, dt.minute, dt.second, w = decimals + 3, p = decimals)
+    } else if step < 60.0 {
+        format!("{:02}:{:02}:{:02}", dt.hour, dt.minute, dt.second.round())
+    } else if step < datetime::SECONDS_PER_DAY && (dt.hour, dt.minute) != (0, 0) {
+        format!("{:02}:{:02}", dt.hour, dt.minute)
+    } else {
+        format!("{:04}-{:02}-{:02}", dt.year, dt.month, dt.day)
+    }
+}
+
+fn month_start(index: i64) -> f64 {
+    let year = if index >= 0 { index / 12 } else { (index - 11) / 12 };
+    let month = (index - year * 12 + 1) as u32;
+    (datetime::days_from_civil(year, month, 1) as f64) * datetime::SECONDS_PER_DAY
+}
+
+/// Calendar-aware ticks for a time range in seconds since 1970: (sub)seconds, minutes, hours and days
+/// on fixed steps, months and years on the calendar.
+pub fn time_ticks(min: f64, max: f64, count: usize) -> Vec<(f64, String)> {
+    if !(min < max) || !min.is_finite() || !max.is_finite() || count == 0 {
+        return vec![];
+    }
+    let range = max - min;
+    let count = count as f64;
+
+    if range / count < 1.0 {
+        let (values, step) = nice_ticks(min, max, count as usize);
+        return values.into_iter().map(|t| (t, format_time(t, step))).collect();
+    }
+
+    if let Some(&step) = TIME_STEPS.iter().find(|&&s| range / s <= count) {
+        let base = if step == 604800.0 { FIRST_MONDAY } else { 0.0 };
+        let mut t = ((min - base) / step).ceil() * step + base;
+        let mut result = vec![];
+        while t <= max {
+            result.push((t, format_time(t, step)));
+            t += step;
+        }
+        return result;
+    }
+
+    let first = DateTime::from_timestamp(min);
+    if let Some(&step) = MONTH_STEPS.iter().find(|&&s| range / ((s as f64) * SECONDS_PER_MONTH) <= count) {
+        let mut index = first.year * 12 + (first.month as i64) - 1;
+        if month_start(index) < min {
+            index += 1;
+        }
+        index = ((index as f64) / (step as f64)).ceil() as i64 * step;
+        let mut result = vec![];
+        while month_start(index) <= max {
+            let dt = DateTime::from_timestamp(month_start(index));
+            result.push((month_start(index), format!("{:04}-{:02}", dt.year, dt.month)));
+            index += step;
+        }
+        return result;
+    }
+
+    let step = f64::max(1.0, nice_step(0.0, range / (SECONDS_PER_MONTH * 12.0), count as usize)) as i64;
+    let mut year = first.year;
+    if month_start(year * 12) < min {
+        year += 1;
+    }
+    year = ((year as f64) / (step as f64)).ceil() as i64 * step;
+    let mut result = vec![];
+    while month_start(year * 12) <= max {
+        result.push((month_start(year * 12), format!("{}", year)));
+        year += step;
+    }
+    result
+}
+
+/// Ticks for the displayed range `[min, max]`, labeled in the units of the original column.
+pub fn ticks(min: f64, max: f64, units: Units, kind: Kind, count: usize) -> Vec<Tick> {
+    match units {
+        Units::Linear { offset, scale } if kind == Kind::Time => {
+            time_ticks(min * scale + offset, max * scale + offset, count).into_iter().map(|(t, label)| {
+                Tick {
+                    pos: (t - offset) / scale,
+                    label: label,
+                }
+            }).collect()
+        },
+        Units::Linear { offset, scale } => {
+            let (values, step) = nice_ticks(min * scale + offset, max * scale + offset, count);
+            values.into_iter().map(|v| {
+                Tick {
+                    pos: (v - offset) / scale,
+                    label: format_value(v, step),
+                }
+            }).collect()
+        },
+        Units::Percentile => {
+            let (values, step) = nice_ticks(f64::max(min, 0.0) * 100.0, f64::min(max, 1.0) * 100.0, count);
+            values.into_iter().map(|v| {
+                Tick {
+                    pos: v / 100.0,
+                    label: format!("{}%", format_value(v, step)),
+                }
+            }).collect()
+        },
+    }
+}
@@ -0,0 +1,191 @@
+use cfg;
+
+
+/// Shape of the cells that points are binned into.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Shape {
+    Hexagon,
+    Square,
+}
+
+static SHAPES: &'static [(Shape, &'static str)] = &[
+    (Shape::Hexagon, "hexagon"),
+    (Shape::Square,  "square"),
+];
+
+impl Shape {
+    pub fn from_name(s: &str) -> Option<Shape> {
+        SHAPES.iter().find(|&&(_, name)| name == s).map(|&(shape, _)| shape)
+    }
+
+    pub fn name(&self) -> &'static str {
+        SHAPES.iter().find(|&&(s, _)| s == *self).unwrap().1
+    }
+}
+
+
+/// Every cell has a histogram of 8x8 Z bins for the median, in a square of texels. The shaders
+/// have the same numbers.
+pub static HISTOGRAM_SIDE: u32 = 8;
+
+
+/// Aggregation of the points of the view in cells of the screen, instead of drawing them as discs.
+#[derive(Clone, Debug)]
+pub struct Bins {
+    /// `None` draws the points
+    pub shape: Option<Shape>,
+    /// distance of the centers of neighbouring cells in window pixels
+    pub size: f32,
+}
+
+impl Bins {
+    pub fn new() -> Bins {
+        Bins {
+            shape: None,
+            size: cfg::BIN_SIZE_DEFAULT,
+        }
+    }
+
+    /// Cycles between points, hexagons and squares.
+    pub fn next_shape(&mut self) {
+        self.shape = match self.shape {
+            None => Some(SHAPES[0].0),
+            Some(shape) => {
+                let pos = SHAPES.iter().position(|&(s, _)| s == shape).unwrap();
+                SHAPES.get(pos + 1).map(|&(s, _)| s)
+            },
+        };
+    }
+
+    pub fn size_increase(&mut self) {
+        self.size = f32::min(self.size * cfg::BIN_SIZE_CHANGE, cfg::BIN_SIZE_MAX);
+    }
+
+    pub fn size_decrease(&mut self) {
+        self.size = f32::max(self.size / cfg::BIN_SIZE_CHANGE, cfg::BIN_SIZE_MIN);
+    }
+
+    pub fn label(&self) -> String {
+        match self.shape {
+            Some(shape) => format!("{} bins of {:.0} px", shape.name(), self.size),
+            None => String::from("points"),
+        }
+    }
+}
+
+
+/// Cells of `size` pixels covering an image of `width` x `height` pixels, from its bottom left
+/// corner. Squares are aligned with the corner. Hexagons point upwards, with a center in the corner
+/// and every odd row shifted by half a cell; the shader function `cell_of` has the same layout.
+pub struct CellGrid {
+    pub shape: Shape,
+    pub size: f32,
+    pub width: u32,
+    pub height: u32,
+    pub cols: u32,
+    pub rows: u32,
+}
+
+impl CellGrid {
+    pub fn new(shape: Shape, size: f32, width: u32, height: u32) -> CellGrid {
+        let (cols, rows) = match shape {
+            Shape::Square => (
+                ((width as f32) / size).ceil() as u32,
+                ((height as f32) / size).ceil() as u32,
+            ),
+            // cells on the edges stick out of the image by up to half a cell
+            Shape::Hexagon => (
+                ((width as f32) / size).ceil() as u32 + 1,
+                ((height as f32) / (0.5 * 3f32.sqrt() * size)).ceil() as u32 + 2,
+            ),
+        };
+        CellGrid {
+            shape: shape,
+            size: size,
+            width: width,
+            height: height,
+            cols: cols,
+            rows: rows,
+        }
+    }
+
+    /// Area of a cell in pixels.
+    pub fn cell_pixels(&self) -> f32 {
+        match self.shape {
+            Shape::Square => self.size * self.size,
+            Shape::Hexagon => 0.5 * 3f32.sqrt() * self.size * self.size,
+        }
+    }
+
+    /// Index of the shape in the shaders.
+    pub fn shape_index(&self) -> f32 {
+        match self.shape {
+            Shape::Square => 0.0,
+            Shape::Hexagon => 1.0,
+        }
+    }
+}
+
+
+/// What a pass over the points accumulates, in the pixels or in the cells.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Target {
+    /// density and Z sums
+    Sums,
+    /// density, Z sum and the second moment of Z in place of the third sum, for `Stat::Std`
+    Moments,
+    /// largest Z and negative smallest Z
+    Extrema,
+    /// density of every Z bin, only for cells
+    Histogram,
+    /// presence of the four categories of Z from the given one on
+    Categories(usize),
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycles_between_points_and_shapes() {
+        let mut bins = Bins::new();
+        assert_eq!(bins.label(), "points");
+        bins.next_shape();
+        assert_eq!(bins.shape, Some(Shape::Hexagon));
+        bins.next_shape();
+        assert_eq!(bins.shape, Some(Shape::Square));
+        bins.next_shape();
+        assert_eq!(bins.shape, None);
+        for &(shape, name) in SHAPES {
+            assert_eq!(Shape::from_name(name), Some(shape));
+            assert_eq!(shape.name(), name);
+        }
+        assert_eq!(Shape::from_name("circle"), None);
+    }
+
+    #[test]
+    fn keeps_the_size_within_its_limits() {
+        let mut bins = Bins::new();
+        for _ in 0..100 {
+            bins.size_increase();
+        }
+        assert_eq!(bins.size, cfg::BIN_SIZE_MAX);
+        for _ in 0..100 {
+            bins.size_decrease();
+        }
+        assert_eq!(bins.size, cfg::BIN_SIZE_MIN);
+    }
+
+    #[test]
+    fn covers_the_image_with_cells() {
+        let squares = CellGrid::new(Shape::Square, 10.0, 100, 95);
+        assert_eq!((squares.cols, squares.rows), (10, 10));
+        assert_eq!(squares.cell_pixels(), 100.0);
+
+        // rows of hexagons are sqrt(3)/2 cells apart, with half a cell sticking out on every edge
+        let hexagons = CellGrid::new(Shape::Hexagon, 10.0, 100, 95);
+        assert_eq!((hexagons.cols, hexagons.rows), (11, 13));
+        assert!((hexagons.cell_pixels() - 86.60254).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,162 @@
+extern crate csv;
+
+use transform::Transform;
+
+use std::path::Path;
+
+
+pub static SLOTS: usize = 9;
+
+
+#[derive(Clone)]
+pub struct AxisBookmark {
+    pub column: String,
+    pub transform: Transform,
+    /// displayed range, in transformed units
+    pub min: f64,
+    pub max: f64,
+}
+
+/// A named view, stored by column names and data ranges so it survives reordered or extra columns.
+#[derive(Clone)]
+pub struct Bookmark {
+    pub slot: usize,
+    pub name: String,
+    pub x: AxisBookmark,
+    pub y: AxisBookmark,
+    pub z: AxisBookmark,
+}
+
+
+pub struct Bookmarks {
+    fname: String,
+    entries: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    /// Bookmarks for `data_fname` are kept in `<data_fname>.bookmarks.csv`.
+    pub fn for_data_file(data_fname: &str) -> Bookmarks {
+        Bookmarks {
+            fname: format!("{}.bookmarks.csv", data_fname),
+            entries: vec![],
+        }
+    }
+
+    pub fn load(&mut self) -> Result<(), String> {
+        if !Path::new(&self.fname).exists() {
+            return Ok(());
+        }
+
+        let mut rdr = match csv::Reader::from_file(&self.fname) {
+            Ok(f) => f.has_headers(true),
+            Err(_) => {
+                return Err(format!("cannot open bookmark file {}!", self.fname));
+            }
+        };
+
+        let mut entries = vec![];
+        for (i, row) in rdr.records().enumerate() {
+            let row = match row {
+                Ok(r) => r,
+                Err(_) => {
+                    return Err(format!("cannot read row {} of bookmark file {}", i + 1, self.fname));
+                }
+            };
+            match parse_row(&row) {
+                Some(b) => entries.push(b),
+                None => {
+                    return Err(format!("row {} of bookmark file {} is malformed", i + 1, self.fname));
+                }
+            }
+        }
+
+        self.entries = entries;
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let mut wtr = match csv::Writer::from_file(&self.fname) {
+            Ok(w) => w,
+            Err(_) => {
+                return Err(format!("cannot write bookmark file {}!", self.fname));
+            }
+        };
+
+        let header = vec![
+            "slot", "name",
+            "x", "x_min", "x_max", "y", "y_min", "y_max", "z", "z_min", "z_max",
+            "x_transform", "y_transform", "z_transform",
+        ];
+        let mut rows = vec![header.iter().map(|s| s.to_string()).collect::<Vec<String>>()];
+        for b in &self.entries {
+            rows.push(vec![
+                b.slot.to_string(), b.name.clone(),
+                b.x.column.clone(), b.x.min.to_string(), b.x.max.to_string(),
+                b.y.column.clone(), b.y.min.to_string(), b.y.max.to_string(),
+                b.z.column.clone(), b.z.min.to_string(), b.z.max.to_string(),
+                b.x.transform.name().to_string(), b.y.transform.name().to_string(), b.z.transform.name().to_string(),
+            ]);
+        }
+
+        for row in rows {
+            if wtr.write(row.iter()).is_err() {
+                return Err(format!("cannot write bookmark file {}!", self.fname));
+            }
+        }
+        match wtr.flush() {
+            Ok(_) => Ok(()),
+            Err(_) => Err(format!("cannot write bookmark file {}!", self.fname)),
+        }
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&Bookmark> {
+        self.entries.iter().find(|b| b.slot == slot)
+    }
+
+    /// Replaces the bookmark in the same slot, if there is one.
+    pub fn set(&mut self, bookmark: Bookmark) {
+        self.entries.retain(|b| b.slot != bookmark.slot);
+        self.entries.push(bookmark);
+        self.entries.sort_by_key(|b| b.slot);
+    }
+}
+
+
+fn parse_axis(column: &str, min: &str, max: &str, transform: &str) -> Option<AxisBookmark> {
+    match (min.parse::<f64>(), max.parse::<f64>(), Transform::from_name(transform)) {
+        (Ok(min), Ok(max), Some(transform)) => Some(AxisBookmark {
+            column: String::from(column),
+            transform: transform,
+            min: min,
+            max: max,
+        }),
+        _ => None,
+    }
+}
+
+fn parse_row(row: &[String]) -> Option<Bookmark> {
+    if row.len() != 14 {
+        return None;
+    }
+
+    let slot = match row[0].parse::<usize>() {
+        Ok(s) if s >= 1 && s <= SLOTS => s,
+        _ => {
+            return None;
+        }
+    };
+
+    let x = parse_axis(&row[2], &row[3], &row[4], &row[11]);
+    let y = parse_axis(&row[5], &row[6], &row[7], &row[12]);
+    let z = parse_axis(&row[8], &row[9], &row[10], &row[13]);
+    match (x, y, z) {
+        (Some(x), Some(y), Some(z)) => Some(Bookmark {
+            slot: slot,
+            name: row[1].clone(),
+            x: x,
+            y: y,
+            z: z,
+        }),
+        _ => None,
+    }
+}
@@ -0,0 +1,437 @@
+//! Columnar binary cache of parsed CSV files, memory-mapped when the file is opened again.
+//!
+//! Layout (all integers are little-endian `u64`, strings are padded to 8 bytes):
+//!
+//! ```text
+//! magic, source size, source mtime (seconds, nanoseconds), parse options, rows, columns
+//! per column: name, kind, min, max
+//! per column: rows x f64
+//! ```
+
+extern crate memmap;
+
+use data;
+use data::{Column, Kind, Values};
+
+use datetime::TimeFormat;
+
+use self::memmap::{Mmap, Protection};
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+static MAGIC: &'static [u8] = b"FLUXCNG\x01";
+
+// values converted to bytes at once when writing
+static CHUNK_VALUES: usize = 1 << 16;
+
+
+/// Column values that live in a memory-mapped cache file.
+pub struct MappedValues {
+    map: Arc<Mmap>,
+    offset: usize,
+    len: usize,
+}
+
+impl MappedValues {
+    pub fn as_slice(&self) -> &[f64] {
+        // `offset` is a multiple of 8 and the mapping is page-aligned, so the values are aligned
+        unsafe {
+            slice::from_raw_parts(self.map.ptr().offset(self.offset as isize) as *const f64, self.len)
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    /// read the cache if it is up to date, write it otherwise
+    Use,
+    /// always parse the CSV file and overwrite the cache
+    Rebuild,
+    /// neither read nor write a cache
+    Off,
+}
+
+
+/// Identifies the source file and how it was parsed, a cache is only used if all of it matches.
+#[derive(PartialEq, Debug)]
+struct Source {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u64,
+    options: String,
+}
+
+impl Source {
+    fn new(fname: &str, time_formats: &[(String, TimeFormat)]) -> Result<Source, String> {
+        let metadata = match fs::metadata(fname) {
+            Ok(m) => m,
+            Err(e) => {
+                return Err(format!("cannot read metadata of {}: {}", fname, e));
+            }
+        };
+        let mtime = match metadata.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+            Some(d) => d,
+            None => {
+                return Err(format!("cannot read modification time of {}", fname));
+            }
+        };
+        let options = time_formats.iter().map(|&(ref name, ref format)| {
+            format!("{}={:?}", name, format)
+        }).collect::<Vec<String>>().join("\n");
+
+        Ok(Source {
+            size: metadata.len(),
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos() as u64,
+            options: options,
+        })
+    }
+}
+
+
+/// Next to the data file, or in `$XDG_CACHE_HOME/fluxcore_ng` (`~/.cache/fluxcore_ng`) if the data
+/// lives in a read-only directory.
+fn candidate_paths(fname: &str) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(format!("{}.fluxcache", fname))];
+
+    let base = match env::var_os("XDG_CACHE_HOME") {
+        Some(p) => Some(PathBuf::from(p)),
+        None    => env::home_dir().map(|h| h.join(".cache")),
+    };
+    let absolute = fs::canonicalize(fname).unwrap_or(PathBuf::from(fname));
+    if let Some(base) = base {
+        let flat = absolute.to_string_lossy().replace('/', "%").replace('\\', "%");
+        paths.push(base.join("fluxcore_ng").join(format!("{}.fluxcache", flat)));
+    }
+
+    paths
+}
+
+
+fn push_u64(buf: &mut Vec<u8>, v: u64) {
+    for i in 0..8 {
+        buf.push((v >> (8 * i)) as u8);
+    }
+}
+
+fn push_str(buf: &mut Vec<u8>, s: &str) {
+    push_u64(buf, s.len() as u64);
+    buf.extend_from_slice(s.as_bytes());
+    while buf.len() % 8 != 0 {
+        buf.push(0);
+    }
+}
+
+fn read_u64(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+    if *pos + 8 > bytes.len() {
+        return None;
+    }
+    let v = (0..8).fold(0u64, |acc, i| acc | ((bytes[*pos + i] as u64) << (8 * i)));
+    *pos += 8;
+    Some(v)
+}
+
+fn read_str(bytes: &[u8], pos: &mut usize) -> Option<String> {
+    let len = match read_u64(bytes, pos) {
+        Some(l) if (l as usize) <= bytes.len() - *pos => l as usize,
+        _ => {
+            return None;
+        }
+    };
+    let s = String::from_utf8_lossy(&bytes[*pos..*pos + len]).into_owned();
+    *pos += (len + 7) / 8 * 8;
+    Some(s)
+}
+
+
+fn write_file(path: &Path, source: &Source, columns: &[Column]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        if !dir.as_os_str().is_empty() && fs::create_dir_all(dir).is_err() {
+            return Err(format!("cannot create cache directory {}", dir.display()));
+        }
+    }
+
+    // written under a temporary name first, so that an interrupted write never leaves a broken cache
+    let tmp_path = PathBuf::from(format!("{}.tmp", path.display()));
+    let mut w = match File::create(&tmp_path) {
+        Ok(f) => BufWriter::new(f),
+        Err(_) => {
+            return Err(format!("cannot write cache file {}", tmp_path.display()));
+        }
+    };
+
+    let rows = if columns.is_empty() { 0 } else { columns[0].data.len() };
+    let mut header = vec![];
+    header.extend_from_slice(MAGIC);
+    push_u64(&mut header, source.size);
+    push_u64(&mut header, source.mtime_secs);
+    push_u64(&mut header, source.mtime_nanos);
+    push_str(&mut header, &source.options);
+    push_u64(&mut header, rows as u64);
+    push_u64(&mut header, columns.len() as u64);
+    for column in columns {
+        push_str(&mut header, &column.name);
+        push_u64(&mut header, if column.kind == Kind::Time { 1 } else { 0 });
+        push_u64(&mut header, column.min.to_bits());
+        push_u64(&mut header, column.max.to_bits());
+    }
+    let mut ok = w.write_all(&header).is_ok();
+
+    let mut chunk = Vec::with_capacity(CHUNK_VALUES * 8);
+    for column in columns {
+        for values in column.data.chunks(CHUNK_VALUES) {
+            chunk.clear();
+            for v in values {
+                push_u64(&mut chunk, v.to_bits());
+            }
+            ok = ok && w.write_all(&chunk).is_ok();
+        }
+    }
+    ok = ok && w.flush().is_ok();
+    drop(w);
+
+    if !ok || fs::rename(&tmp_path, path).is_err() {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(format!("cannot write cache file {}", path.display()));
+    }
+    Ok(())
+}
+
+/// `Ok(None)` if there is a cache file, but it belongs to another version of the source.
+fn read_file(path: &Path, source: &Source) -> Result<Option<Vec<Column>>, String> {
+    if cfg!(target_endian = "big") {
+        return Err(String::from("cache files can only be used on little-endian machines"));
+    }
+
+    let map = match Mmap::open_path(path, Protection::Read) {
+        Ok(m) => Arc::new(m),
+        Err(_) => {
+            return Err(format!("cannot map cache file {}", path.display()));
+        }
+    };
+    let malformed = || Err(format!("cache file {} is malformed", path.display()));
+
+    let (columns, offset, rows) = {
+        let bytes = unsafe { map.as_slice() };
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return malformed();
+        }
+        let mut pos = MAGIC.len();
+
+        let cached = match (read_u64(bytes, &mut pos), read_u64(bytes, &mut pos), read_u64(bytes, &mut pos), read_str(bytes, &mut pos)) {
+            (Some(size), Some(mtime_secs), Some(mtime_nanos), Some(options)) => Source {
+                size: size,
+                mtime_secs: mtime_secs,
+                mtime_nanos: mtime_nanos,
+                options: options,
+            },
+            _ => {
+                return malformed();
+            }
+        };
+        if cached != *source {
+            return Ok(None);
+        }
+
+        let (rows, m) = match (read_u64(bytes, &mut pos), read_u64(bytes, &mut pos)) {
+            (Some(rows), Some(m)) => (rows as usize, m as usize),
+            _ => {
+                return malformed();
+            }
+        };
+
+        let mut columns = vec![];
+        for _ in 0..m {
+            match (read_str(bytes, &mut pos), read_u64(bytes, &mut pos), read_u64(bytes, &mut pos), read_u64(bytes, &mut pos)) {
+                (Some(name), Some(kind), Some(min), Some(max)) => {
+                    columns.push((name, if kind == 1 { Kind::Time } else { Kind::Number }, f64::from_bits(min), f64::from_bits(max)));
+                },
+                _ => {
+                    return malformed();
+                }
+            }
+        }
+        match rows.checked_mul(m).and_then(|n| n.checked_mul(8)) {
+            Some(n) if n <= bytes.len() - pos => {},
+            _ => {
+                return malformed();
+            }
+        }
+        (columns, pos, rows)
+    };
+
+    Ok(Some(columns.into_iter().enumerate().map(|(j, (name, kind, min, max))| {
+        Column {
+            name: name,
+            kind: kind,
+            data: Values::Mapped(MappedValues {
+                map: map.clone(),
+                offset: offset + j * rows * 8,
+                len: rows,
+            }),
+            min: min,
+            max: max,
+        }
+    }).collect()))
+}
+
+
+/// Columns of `fname`, from its cache if `mode` allows and the cache is up to date. Problems with
+/// the cache are only warned about, the CSV file is parsed instead.
+pub fn columns_from_file(fname: &str, time_formats: &[(String, TimeFormat)], mode: Mode) -> Result<Vec<Column>, String> {
+    if mode == Mode::Off {
+        return data::columns_from_file(fname, time_formats);
+    }
+
+    let source = match Source::new(fname, time_formats) {
+        Ok(s) => s,
+        Err(s) => {
+            warn!("{}", s);
+            return data::columns_from_file(fname, time_formats);
+        }
+    };
+
+    let paths = candidate_paths(fname);
+    if mode == Mode::Use {
+        for path in paths.iter().filter(|p| p.exists()) {
+            match read_file(path, &source) {
+                Ok(Some(columns)) => {
+                    info!("read columns from cache file {}", path.display());
+                    return Ok(columns);
+                },
+                Ok(None) => info!("cache file {} is outdated", path.display()),
+                Err(s) => warn!("{}", s),
+            }
+        }
+    }
+
+    let columns = match data::columns_from_file(fname, time_formats) {
+        Ok(c) => c,
+        Err(s) => {
+            return Err(s);
+        }
+    };
+
+    for path in &paths {
+        match write_file(path, &source, &columns) {
+            Ok(()) => {
+                info!("wrote cache file {}", path.display());
+                break;
+            },
+            Err(s) => warn!("{}", s),
+        }
+    }
+
+    Ok(columns)
+}
+
+
+/// How data files are read, as given on the command line, to load further files at runtime.
+#[derive(Clone)]
+pub struct Loader {
+    pub time_formats: Vec<(String, TimeFormat)>,
+    pub mode: Mode,
+}
+
+impl Loader {
+    pub fn load(&self, fname: &str) -> Result<Vec<Column>, String> {
+        columns_from_file(fname, &self.time_formats, self.mode)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f64;
+    use std::process;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("fluxcore_ng_cache_test_{}_{}", process::id(), name))
+    }
+
+    fn columns() -> Vec<Column> {
+        let mut t = Column::from_data("t", vec![86400.0, f64::NAN, -1.5]);
+        t.kind = Kind::Time;
+        vec![Column::from_data("a b", vec![1.0, 2.0, 3.0]), t]
+    }
+
+    fn source() -> Source {
+        Source {
+            size: 1234,
+            mtime_secs: 1500000000,
+            mtime_nanos: 5,
+            options: String::from("t=Iso"),
+        }
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let path = temp_path("roundtrip");
+        write_file(&path, &source(), &columns()).unwrap();
+        let read = read_file(&path, &source()).unwrap().expect("cache was outdated");
+        fs::remove_file(&path).unwrap();
+
+        for (a, b) in read.iter().zip(columns().iter()) {
+            assert_eq!((&a.name, a.kind, a.min, a.max), (&b.name, b.kind, b.min, b.max));
+            assert_eq!(a.data.len(), b.data.len());
+            assert!(a.data.iter().zip(b.data.iter()).all(|(x, y)| x == y || (x.is_nan() && y.is_nan())));
+        }
+        assert_eq!(read.len(), 2);
+    }
+
+    #[test]
+    fn is_outdated_when_the_source_changes() {
+        let path = temp_path("outdated");
+        write_file(&path, &source(), &columns()).unwrap();
+        let changes = [
+            Source { size: 1235, ..source() },
+            Source { mtime_secs: 1500000001, ..source() },
+            Source { mtime_nanos: 6, ..source() },
+            Source { options: String::from("t=EpochSeconds"), ..source() },
+            Source { options: String::new(), ..source() },
+        ];
+        for changed in &changes {
+            assert!(read_file(&path, changed).unwrap().is_none(), "{:?} used the cache", changed);
+        }
+        assert!(read_file(&path, &source()).unwrap().is_some());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn describes_the_source_by_size_mtime_and_options() {
+        let path = temp_path("source.csv");
+        let fname = path.to_str().unwrap();
+        fs::write(&path, "a,b\n1,2\n").unwrap();
+        let plain = Source::new(fname, &[]).unwrap();
+        assert_eq!(plain.size, 8);
+        assert_eq!(plain.options, "");
+        let formats = [(String::from("b"), TimeFormat::EpochMillis)];
+        assert!(Source::new(fname, &formats).unwrap() != plain);
+        fs::write(&path, "a,b\n1,2\n3,4\n").unwrap();
+        assert_eq!(Source::new(fname, &[]).unwrap().size, 12);
+        fs::remove_file(&path).unwrap();
+        assert!(Source::new(fname, &[]).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let path = temp_path("truncated");
+        write_file(&path, &source(), &columns()).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        for &len in &[4, 40, bytes.len() - 8] {
+            fs::write(&path, &bytes[..len]).unwrap();
+            assert!(read_file(&path, &source()).is_err(), "{} bytes were accepted", len);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
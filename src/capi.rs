@@ -0,0 +1,626 @@
+//! C interface for embedding the renderer into other applications, see `include/fluxcore_ng.h`.
+//!
+//! Functions that can fail return 0 on success and -1 on errors, whose message is then available
+//! via `fluxcore_last_error`. All objects have to be used from the thread that created them.
+
+use bookmarks::Bookmarks;
+
+use data::{Column, Kind};
+
+use filter::Filter;
+
+use glium::SwapBuffersError;
+use glium::backend::{Backend, Context};
+use glium::debug::DebugCallbackBehavior;
+use glium::glutin;
+
+use image::Image;
+
+use json::Json;
+
+use keymap::Key;
+
+use renderer::Renderer;
+
+use screenshot;
+
+use settings;
+use settings::Settings;
+
+use std::cell::{Cell, RefCell};
+use std::char;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic;
+use std::ptr;
+use std::rc::Rc;
+use std::slice;
+
+
+/// Resolves OpenGL functions of the application's context, e.g. via `SDL_GL_GetProcAddress`.
+pub type FluxcoreGetProcAddress = extern "C" fn(user_data: *mut c_void, symbol: *const c_char) -> *const c_void;
+
+/// Named values of the `axis` arguments, which are plain ints since C may pass anything.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[allow(dead_code)] // only named in the header
+pub enum FluxcoreAxis {
+    X = 0,
+    Y = 1,
+    Z = 2,
+}
+
+/// Columns collected for `fluxcore_renderer_new` or `fluxcore_renderer_set_data`.
+pub struct FluxcoreColumns {
+    columns: Vec<Column>,
+}
+
+pub struct FluxcoreRenderer {
+    renderer: Renderer,
+    /// size of the application's framebuffer, `None` if the renderer has its own window
+    framebuffer: Option<Rc<Cell<(u32, u32)>>>,
+}
+
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_error(msg: &str) {
+    let msg = CString::new(msg.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = msg);
+}
+
+/// Runs `f`, turning errors and panics into the return value `error` and the last error message.
+fn guard<T, F>(error: T, f: F) -> T where F: FnOnce() -> Result<T, String> {
+    match panic::catch_unwind(panic::AssertUnwindSafe(f)) {
+        Ok(Ok(v)) => v,
+        Ok(Err(s)) => {
+            set_error(&s);
+            error
+        },
+        Err(_) => {
+            set_error("internal error, see the log for details");
+            error
+        },
+    }
+}
+
+unsafe fn string_arg(s: *const c_char, what: &str) -> Result<String, String> {
+    if s.is_null() {
+        return Err(format!("{} is NULL", what));
+    }
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Ok(String::from(s)),
+        Err(_) => Err(format!("{} is not valid UTF-8", what)),
+    }
+}
+
+/// `NULL` becomes `None`.
+unsafe fn optional_string_arg(s: *const c_char, what: &str) -> Result<Option<String>, String> {
+    if s.is_null() {
+        Ok(None)
+    } else {
+        string_arg(s, what).map(Some)
+    }
+}
+
+unsafe fn renderer_arg<'a>(r: *mut FluxcoreRenderer) -> Result<&'a mut FluxcoreRenderer, String> {
+    if r.is_null() {
+        Err(String::from("renderer is NULL"))
+    } else {
+        Ok(&mut *r)
+    }
+}
+
+/// Takes the columns out of `columns`, which is freed.
+unsafe fn take_columns(columns: *mut FluxcoreColumns) -> Result<Vec<Column>, String> {
+    if columns.is_null() {
+        return Err(String::from("columns are NULL"));
+    }
+    let columns = Box::from_raw(columns).columns;
+    if columns.len() < 2 {
+        return Err(String::from("we need at least 2 columns"));
+    }
+    if columns.iter().any(|c| c.data.len() != columns[0].data.len()) {
+        return Err(String::from("all columns need the same length"));
+    }
+    Ok(columns)
+}
+
+fn default_settings() -> Settings {
+    let result = match settings::default_path() {
+        Some(ref path) if path.exists() => Settings::from_file(&path.to_string_lossy()),
+        _ => Ok(Settings::new()),
+    };
+    match result {
+        Ok(s) => s,
+        Err(s) => {
+            warn!("{}", s);
+            Settings::new()
+        }
+    }
+}
+
+fn command(r: &mut FluxcoreRenderer, method: &str, params: Json) -> Result<c_int, String> {
+    match r.renderer.command(method, &params) {
+        Ok(_) => Ok(0),
+        Err(e) => Err(e.message),
+    }
+}
+
+
+/// OpenGL context of the embedding application, which makes it current and swaps the buffers.
+struct ExternalBackend {
+    get_proc_address: FluxcoreGetProcAddress,
+    user_data: *mut c_void,
+    framebuffer: Rc<Cell<(u32, u32)>>,
+}
+
+unsafe impl Backend for ExternalBackend {
+    fn swap_buffers(&self) -> Result<(), SwapBuffersError> {
+        Ok(())
+    }
+
+    unsafe fn get_proc_address(&self, symbol: &str) -> *const c_void {
+        let symbol = CString::new(symbol).unwrap();
+        (self.get_proc_address)(self.user_data, symbol.as_ptr())
+    }
+
+    fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+        self.framebuffer.get()
+    }
+
+    fn is_current(&self) -> bool {
+        true
+    }
+
+    unsafe fn make_current(&self) {
+    }
+}
+
+
+/// Message of the last failed call on this thread. Valid until the next failing call.
+#[no_mangle]
+pub extern "C" fn fluxcore_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn fluxcore_columns_new() -> *mut FluxcoreColumns {
+    Box::into_raw(Box::new(FluxcoreColumns { columns: vec![] }))
+}
+
+/// Copies `len` values as column `name`. NaN marks missing values, `is_time` columns hold seconds
+/// since 1970-01-01 UTC.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_columns_add(columns: *mut FluxcoreColumns, name: *const c_char, data: *const f64, len: usize, is_time: bool) -> c_int {
+    guard(-1, || {
+        if columns.is_null() || (data.is_null() && len > 0) {
+            return Err(String::from("columns or data are NULL"));
+        }
+        let name = match string_arg(name, "name") {
+            Ok(n) => n,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let values = if len > 0 { slice::from_raw_parts(data, len).to_vec() } else { vec![] };
+        let mut column = Column::from_data(&name, values);
+        if is_time {
+            column.kind = Kind::Time;
+        }
+        (*columns).columns.push(column);
+        Ok(0)
+    })
+}
+
+/// Only needed for columns that are not passed to a renderer.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_columns_free(columns: *mut FluxcoreColumns) {
+    if !columns.is_null() {
+        drop(Box::from_raw(columns));
+    }
+}
+
+/// Creates a renderer with its own window, which stays hidden unless `visible` is set. `columns`
+/// are consumed, `name` is used for the window title and the bookmarks file. Returns `NULL` on
+/// errors.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_new(columns: *mut FluxcoreColumns, name: *const c_char, width: u32, height: u32, visible: bool) -> *mut FluxcoreRenderer {
+    guard(ptr::null_mut(), || {
+        let columns = match take_columns(columns) {
+            Ok(c) => c,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let name = match string_arg(name, "name") {
+            Ok(n) => n,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let bookmarks = Bookmarks::for_data_file(&name);
+        match Renderer::new(width, height, columns, name, default_settings(), bookmarks, Filter::new(), visible) {
+            Ok(r) => Ok(Box::into_raw(Box::new(FluxcoreRenderer { renderer: r, framebuffer: None }))),
+            Err(s) => Err(s),
+        }
+    })
+}
+
+/// Creates a renderer that draws into the application's current OpenGL 3.3 core context, whose
+/// default framebuffer is `width` x `height` pixels. Returns `NULL` on errors.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_new_gl(columns: *mut FluxcoreColumns, name: *const c_char, width: u32, height: u32, get_proc_address: FluxcoreGetProcAddress, user_data: *mut c_void) -> *mut FluxcoreRenderer {
+    guard(ptr::null_mut(), || {
+        let columns = match take_columns(columns) {
+            Ok(c) => c,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let name = match string_arg(name, "name") {
+            Ok(n) => n,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let framebuffer = Rc::new(Cell::new((width, height)));
+        let backend = ExternalBackend {
+            get_proc_address: get_proc_address,
+            user_data: user_data,
+            framebuffer: framebuffer.clone(),
+        };
+        let context = match Context::new::<_, ()>(backend, false, DebugCallbackBehavior::Ignore) {
+            Ok(c) => c,
+            Err(_) => {
+                return Err(String::from("cannot use the OpenGL context, OpenGL 3.3 core is needed"));
+            }
+        };
+        let bookmarks = Bookmarks::for_data_file(&name);
+        match Renderer::embedded(context, width, height, columns, name, default_settings(), bookmarks, Filter::new()) {
+            Ok(r) => Ok(Box::into_raw(Box::new(FluxcoreRenderer { renderer: r, framebuffer: Some(framebuffer) }))),
+            Err(s) => Err(s),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_free(r: *mut FluxcoreRenderer) {
+    if !r.is_null() {
+        drop(Box::from_raw(r));
+    }
+}
+
+/// Replaces the data by `columns`, which are consumed. The view is kept if the column names are
+/// the same as before.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_set_data(r: *mut FluxcoreRenderer, columns: *mut FluxcoreColumns) -> c_int {
+    guard(-1, || {
+        let r = match renderer_arg(r) {
+            Ok(r) => r,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let columns = match take_columns(columns) {
+            Ok(c) => c,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let name = r.renderer.data_name();
+        r.renderer.replace_data(columns, &name);
+        Ok(0)
+    })
+}
+
+/// Runs a remote control method with parameters `params_json` (a JSON object, `NULL` for none).
+/// On success, `*result_json` is set to the JSON result if `result_json` is not `NULL`, which has
+/// to be freed with `fluxcore_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_command(r: *mut FluxcoreRenderer, method: *const c_char, params_json: *const c_char, result_json: *mut *mut c_char) -> c_int {
+    guard(-1, || {
+        let r = match renderer_arg(r) {
+            Ok(r) => r,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let method = match string_arg(method, "method") {
+            Ok(m) => m,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let params = match optional_string_arg(params_json, "params") {
+            Ok(Some(p)) => match Json::parse(&p) {
+                Ok(p) => p,
+                Err(s) => {
+                    return Err(s);
+                }
+            },
+            Ok(None) => Json::Object(vec![]),
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let result = match r.renderer.command(&method, &params) {
+            Ok(result) => result,
+            Err(e) => {
+                return Err(e.message);
+            }
+        };
+        if !result_json.is_null() {
+            *result_json = CString::new(result.to_string()).unwrap().into_raw();
+        }
+        Ok(0)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Chooses the X, Y and Z columns by name, `NULL` keeps the current one.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_set_columns(r: *mut FluxcoreRenderer, x: *const c_char, y: *const c_char, z: *const c_char) -> c_int {
+    guard(-1, || {
+        let r = match renderer_arg(r) {
+            Ok(r) => r,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let mut params = vec![];
+        for &(axis, name) in &[("x", x), ("y", y), ("z", z)] {
+            match optional_string_arg(name, axis) {
+                Ok(Some(n)) => params.push((axis, Json::String(n))),
+                Ok(None) => (),
+                Err(s) => {
+                    return Err(s);
+                }
+            }
+        }
+        command(r, "set_columns", Json::object(params))
+    })
+}
+
+/// Shows `[min, max]` of `axis`, one of `FluxcoreAxis`, in the units of the transformed column.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_set_range(r: *mut FluxcoreRenderer, axis: c_int, min: f64, max: f64) -> c_int {
+    guard(-1, || {
+        let r = match renderer_arg(r) {
+            Ok(r) => r,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let name = match axis {
+            0 => "x",
+            1 => "y",
+            2 => "z",
+            _ => {
+                return Err(format!("unknown axis {}", axis));
+            }
+        };
+        command(r, "set_view", Json::object(vec![(name, Json::Array(vec![Json::Number(min), Json::Number(max)]))]))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_set_gamma(r: *mut FluxcoreRenderer, gamma: f32) -> c_int {
+    guard(-1, || {
+        match renderer_arg(r) {
+            Ok(r) => command(r, "set_options", Json::object(vec![("gamma", Json::Number(gamma as f64))])),
+            Err(s) => Err(s),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_set_point_size(r: *mut FluxcoreRenderer, size: f32) -> c_int {
+    guard(-1, || {
+        match renderer_arg(r) {
+            Ok(r) => command(r, "set_options", Json::object(vec![("pointsize", Json::Number(size as f64))])),
+            Err(s) => Err(s),
+        }
+    })
+}
+
+/// Handles the input events since the last call and draws a frame, into the own window or the
+/// application's default framebuffer. Returns 1, 0 once the user quit, or -1 on errors.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_draw(r: *mut FluxcoreRenderer) -> c_int {
+    guard(-1, || {
+        match renderer_arg(r) {
+            Ok(r) => Ok(if r.renderer.run_once() { 1 } else { 0 }),
+            Err(s) => Err(s),
+        }
+    })
+}
+
+/// Shows the own window and runs until the user closes it.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_run(r: *mut FluxcoreRenderer) -> c_int {
+    guard(-1, || {
+        let r = match renderer_arg(r) {
+            Ok(r) => r,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        if r.framebuffer.is_some() {
+            return Err(String::from("embedded renderers are drawn with fluxcore_renderer_draw"));
+        }
+        r.renderer.show();
+        Ok(0)
+    })
+}
+
+fn render(r: *mut FluxcoreRenderer, width: u32, height: u32, raw: bool) -> Result<Image, String> {
+    let r = match unsafe { renderer_arg(r) } {
+        Ok(r) => r,
+        Err(s) => {
+            return Err(s);
+        }
+    };
+    if let Err(s) = screenshot::check_size(width, height) {
+        return Err(s);
+    }
+    let (accumulation, tonemapped) = r.renderer.render_image(width, height);
+    Ok(if raw { accumulation } else { tonemapped })
+}
+
+/// Renders the current view offscreen into `out`, `width * height` 8 bit sRGB RGBA pixels with rows
+/// from top to bottom.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_render_rgba(r: *mut FluxcoreRenderer, width: u32, height: u32, out: *mut u8) -> c_int {
+    guard(-1, || {
+        if out.is_null() {
+            return Err(String::from("out is NULL"));
+        }
+        let image = match render(r, width, height, false) {
+            Ok(i) => i,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let out = slice::from_raw_parts_mut(out, image.pixels.len() * 4);
+        for (dst, src) in out.chunks_mut(4).zip(image.to_srgb8().chunks(3)) {
+            dst[..3].copy_from_slice(src);
+            dst[3] = 255;
+        }
+        Ok(0)
+    })
+}
+
+/// Renders the accumulation buffer into `out`, `width * height * 4` floats: luminance and Lab a/b
+/// sums and the point count, divided by the number of rows.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_render_density(r: *mut FluxcoreRenderer, width: u32, height: u32, out: *mut f32) -> c_int {
+    guard(-1, || {
+        if out.is_null() {
+            return Err(String::from("out is NULL"));
+        }
+        let image = match render(r, width, height, true) {
+            Ok(i) => i,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let out = slice::from_raw_parts_mut(out, image.pixels.len() * 4);
+        for (dst, src) in out.chunks_mut(4).zip(image.pixels.iter()) {
+            dst.copy_from_slice(src);
+        }
+        Ok(0)
+    })
+}
+
+/// The application's framebuffer or the window was resized.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_resize(r: *mut FluxcoreRenderer, width: u32, height: u32) -> c_int {
+    guard(-1, || {
+        let r = match renderer_arg(r) {
+            Ok(r) => r,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        if let Some(ref framebuffer) = r.framebuffer {
+            framebuffer.set((width, height));
+        }
+        r.renderer.push_event(glutin::Event::Resized(width, height));
+        Ok(0)
+    })
+}
+
+/// Key named as in the config file, e.g. `A`, `PageUp` or `LShift`; modifiers are keys of their own.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_key(r: *mut FluxcoreRenderer, key: *const c_char, pressed: bool) -> c_int {
+    guard(-1, || {
+        let r = match renderer_arg(r) {
+            Ok(r) => r,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let code = match string_arg(key, "key").and_then(|k| Key::parse(&k)) {
+            Ok(k) => k.code,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let state = if pressed { glutin::ElementState::Pressed } else { glutin::ElementState::Released };
+        r.renderer.push_event(glutin::Event::KeyboardInput(state, 0, Some(code)));
+        Ok(0)
+    })
+}
+
+/// Text input (a Unicode code point), for the prompts.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_char(r: *mut FluxcoreRenderer, codepoint: u32) -> c_int {
+    guard(-1, || {
+        let r = match renderer_arg(r) {
+            Ok(r) => r,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        match char::from_u32(codepoint) {
+            Some(c) => {
+                r.renderer.push_event(glutin::Event::ReceivedCharacter(c));
+                Ok(0)
+            },
+            None => Err(format!("{} is not a Unicode code point", codepoint)),
+        }
+    })
+}
+
+/// Mouse position in pixels from the top left corner.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_mouse_move(r: *mut FluxcoreRenderer, x: i32, y: i32) -> c_int {
+    guard(-1, || {
+        match renderer_arg(r) {
+            Ok(r) => {
+                r.renderer.push_event(glutin::Event::MouseMoved(x, y));
+                Ok(0)
+            },
+            Err(s) => Err(s),
+        }
+    })
+}
+
+/// The left mouse button, which pans the view while pressed.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_mouse_button(r: *mut FluxcoreRenderer, pressed: bool) -> c_int {
+    guard(-1, || {
+        match renderer_arg(r) {
+            Ok(r) => {
+                let state = if pressed { glutin::ElementState::Pressed } else { glutin::ElementState::Released };
+                r.renderer.push_event(glutin::Event::MouseInput(state, glutin::MouseButton::Left));
+                Ok(0)
+            },
+            Err(s) => Err(s),
+        }
+    })
+}
+
+/// Zooms around the mouse position, in lines of scrolling.
+#[no_mangle]
+pub unsafe extern "C" fn fluxcore_renderer_scroll(r: *mut FluxcoreRenderer, dx: f32, dy: f32) -> c_int {
+    guard(-1, || {
+        match renderer_arg(r) {
+            Ok(r) => {
+                let delta = glutin::MouseScrollDelta::LineDelta(dx, dy);
+                r.renderer.push_event(glutin::Event::MouseWheel(delta, glutin::TouchPhase::Moved));
+                Ok(0)
+            },
+            Err(s) => Err(s),
+        }
+    })
+}
@@ -1,14 +1,54 @@
-pub static FRAME_MILLIS:       u64  = 50;
-pub static GAMMA_CHANGE:       f32  = 1.1;
-pub static GAMMA_DEFAULT:      f32  = 10.0;
-pub static GAMMA_MIN:          f32  = 1.0;
-pub static GAMMA_MAX:          f32  = 100.0;
-pub static LOWRES_FACTOR:      f32  = 0.2;
-pub static LOWRES_MILLIS:      u64  = 500;
-pub static POINTSIZE_CHANGE:   f32  = 1.1;
-pub static POINTSIZE_DEFAULT:  f32  = 10.0;
-pub static POINTSIZE_MIN:      f32  = 2.0;
-pub static POINTSIZE_MAX:      f32  = 100.0;
-pub static SCALE_MIN:          f32  = 0.00000001;
-pub static SCROLL_BASE:        f32  = 1.1;
-pub static SHOWBORDER_DEFAULT: bool = true;
+pub static ANIMATION_FRAMES_MAX:    usize = 1000;
+pub static ANIMATION_FRAME_MILLIS:  u64   = 200;
+pub static ANIMATION_TRAIL_FADE:    f32   = 0.5;
+pub static ANIMATION_TRAIL_FRAMES:  usize = 5;
+pub static BIN_SIZE_CHANGE:         f32   = 1.1;
+pub static BIN_SIZE_DEFAULT:        f32   = 12.0;
+pub static BIN_SIZE_MIN:            f32   = 4.0;
+pub static BIN_SIZE_MAX:            f32   = 200.0;
+pub static CATEGORIES_MAX:          usize = 16;
+pub static CLIP_HIGH_DEFAULT:       f64   = 99.0;
+pub static CLIP_LOW_DEFAULT:        f64   = 1.0;
+pub static COLORBAR_HEIGHT:         f32   = 200.0;
+pub static CONTOUR_CELL_PIXELS:     u32   = 4;
+pub static CONTOUR_LEVELS_DEFAULT:  &'static [f64] = &[50.0, 90.0, 99.0];
+pub static CONTOUR_SMOOTHING:       f32   = 1.5;
+pub static EXPORT_RASTER_SCALE:     u32   = 3;
+pub static EXPORT_TILE_SIZE:        u32   = 2048;
+pub static FRAME_MILLIS:            u64   = 50;
+pub static GAMMA_CHANGE:            f32   = 1.1;
+pub static GAMMA_DEFAULT:           f32   = 10.0;
+pub static GAMMA_MIN:               f32   = 1.0;
+pub static GAMMA_MAX:               f32   = 100.0;
+pub static GPU_COLUMNS_BYTES:       usize = 1 << 30;
+pub static HISTORY_COALESCE_MILLIS: u64   = 500;
+pub static HISTORY_DEPTH_DEFAULT:   usize = 100;
+pub static LOD_BORDER_LEVEL:        usize = 5;
+pub static LOD_CELL_PIXELS:         f64   = 1.0;
+pub static LOD_DEPTH:               usize = 12;
+pub static LOD_MARGIN:              f64   = 0.5;
+pub static LOD_MIN_POINTS:          usize = 1000000;
+pub static LOWRES_FACTOR:           f32   = 0.2;
+pub static LOWRES_MILLIS:           u64   = 500;
+pub static POINTSIZE_CHANGE:        f32   = 1.1;
+pub static POINTSIZE_DEFAULT:       f32   = 10.0;
+pub static POINTSIZE_MIN:           f32   = 2.0;
+pub static POINTSIZE_MAX:           f32   = 100.0;
+pub static REBASE_DISTANCE:         f64   = 100.0;
+pub static RPC_QUEUE_MAX:           usize = 256;
+pub static SCALE_MIN:               f64   = 1e-200;
+pub static SCREENSHOT_SIZE_DEFAULT: &'static str = "4000x3000";
+pub static SCROLL_BASE:             f64   = 1.1;
+pub static SERVE_PORT_DEFAULT:      u16   = 8000;
+pub static SERVE_REQUEST_MAX:       u64   = 8192;
+pub static SERVE_TIMEOUT_MILLIS:    u64   = 5000;
+pub static SERVE_WORKERS:           usize = 4;
+pub static SHOWAXES_DEFAULT:        bool  = true;
+pub static SHOWBORDER_DEFAULT:      bool  = true;
+pub static STATUS_MILLIS:           u64   = 3000;
+pub static TERMINAL_CELL_HEIGHT:    u32   = 20;
+pub static TERMINAL_CELL_WIDTH:     u32   = 10;
+pub static TERMINAL_QUERY_MILLIS:   u64   = 300;
+pub static TICK_SPACING:            f32   = 100.0;
+pub static TILE_SIZE:               u32   = 256;
+pub static TILE_ZOOM_MAX:           u32   = 30;
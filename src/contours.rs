@@ -0,0 +1,228 @@
+//! Contour lines of the smoothed point density, enclosing given shares of the points in the view.
+
+use cfg;
+
+
+/// Which contours are drawn over the points.
+#[derive(Clone, Debug)]
+pub struct Contours {
+    pub shown: bool,
+    /// percentages of the points in the view inside the lines, ascending
+    pub percents: Vec<f64>,
+}
+
+impl Contours {
+    pub fn new() -> Contours {
+        Contours {
+            shown: false,
+            percents: cfg::CONTOUR_LEVELS_DEFAULT.to_vec(),
+        }
+    }
+
+    /// Parses percentages separated by commas or spaces, e.g. `50,90,99`.
+    pub fn parse_percents(s: &str) -> Result<Vec<f64>, String> {
+        let mut percents = vec![];
+        for part in s.split(|c: char| c == ',' || c.is_whitespace()).filter(|p| !p.is_empty()) {
+            let number = if part.ends_with('%') { &part[..part.len() - 1] } else { part };
+            match number.parse::<f64>() {
+                Ok(p) if p > 0.0 && p < 100.0 => percents.push(p),
+                _ => {
+                    return Err(format!("contour level '{}' is not a percentage between 0 and 100", part));
+                }
+            }
+        }
+        if percents.is_empty() {
+            return Err(String::from("no contour levels given"));
+        }
+        percents.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        percents.dedup();
+        Ok(percents)
+    }
+
+    pub fn label(&self) -> String {
+        let percents = self.percents.iter().map(|p| format!("{}%", p)).collect::<Vec<String>>();
+        format!("contours at {}", percents.join(", "))
+    }
+}
+
+
+/// Contour line of one level as segments between points in grid coordinates, where the center of
+/// cell `(col, row)` is at `(col, row)`.
+pub struct Line {
+    pub percent: f64,
+    pub segments: Vec<[(f32, f32); 2]>,
+}
+
+
+/// Gaussian blur of the `width` x `height` grid `values` with a standard deviation of `sigma` cells.
+/// Mass that would leave the grid is lost, like the points outside of the view.
+pub fn smooth(values: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    let radius = (3.0 * sigma).ceil() as isize;
+    let kernel = (-radius..(radius + 1)).map(|d| {
+        let x = (d as f32) / sigma;
+        (-0.5 * x * x).exp()
+    }).collect::<Vec<f32>>();
+    let total = kernel.iter().fold(0.0, |acc, k| acc + k);
+    let kernel = kernel.iter().map(|k| k / total).collect::<Vec<f32>>();
+
+    let blur = |src: &[f32], step: usize, len: usize, lines: usize, stride: usize| {
+        let mut dst = vec![0.0; src.len()];
+        for line in 0..lines {
+            for i in 0..len {
+                let v = src[line * stride + i * step];
+                if v == 0.0 {
+                    continue;
+                }
+                for (k, w) in kernel.iter().enumerate() {
+                    let j = (i as isize) + (k as isize) - radius;
+                    if j >= 0 && (j as usize) < len {
+                        dst[line * stride + (j as usize) * step] += v * w;
+                    }
+                }
+            }
+        }
+        dst
+    };
+    let rows = blur(values, 1, width, height, width);
+    blur(&rows, width, height, width, 1)
+}
+
+/// Densities whose highest density regions hold the shares `percents` of the total density.
+pub fn thresholds(density: &[f32], percents: &[f64]) -> Vec<f32> {
+    let mut sorted = density.iter().cloned().filter(|&d| d > 0.0).collect::<Vec<f32>>();
+    sorted.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    let total = sorted.iter().fold(0.0, |acc, &d| acc + (d as f64));
+
+    percents.iter().map(|p| {
+        let wanted = total * p / 100.0;
+        let mut sum = 0.0;
+        for &d in &sorted {
+            sum += d as f64;
+            if sum >= wanted {
+                return d;
+            }
+        }
+        sorted.last().cloned().unwrap_or(0.0)
+    }).collect()
+}
+
+/// Marching squares over the `width` x `height` grid `density` at `level`. Saddles are resolved by
+/// the mean of the four corners.
+pub fn march(density: &[f32], width: usize, height: usize, level: f32) -> Vec<[(f32, f32); 2]> {
+    let mut segments = vec![];
+    if width < 2 || height < 2 {
+        return segments;
+    }
+    let at = |col: usize, row: usize| density[row * width + col];
+
+    for row in 0..(height - 1) {
+        for col in 0..(width - 1) {
+            // corners counter-clockwise from the bottom left
+            let v = [at(col, row), at(col + 1, row), at(col + 1, row + 1), at(col, row + 1)];
+            let case = v.iter().enumerate().fold(0, |acc, (i, &c)| if c >= level { acc | (1 << i) } else { acc });
+            if case == 0 || case == 15 {
+                continue;
+            }
+
+            // crossing on edge i, which goes from corner i to corner i + 1
+            let (x, y) = (col as f32, row as f32);
+            let corners = [(x, y), (x + 1.0, y), (x + 1.0, y + 1.0), (x, y + 1.0)];
+            let crossing = |i: usize| {
+                let j = (i + 1) % 4;
+                let t = if v[j] == v[i] { 0.5 } else { (level - v[i]) / (v[j] - v[i]) };
+                (corners[i].0 + t * (corners[j].0 - corners[i].0), corners[i].1 + t * (corners[j].1 - corners[i].1))
+            };
+            let center_inside = (v[0] + v[1] + v[2] + v[3]) / 4.0 >= level;
+
+            let edges: &[(usize, usize)] = match case {
+                1 | 14 => &[(3, 0)],
+                2 | 13 => &[(0, 1)],
+                3 | 12 => &[(3, 1)],
+                4 | 11 => &[(1, 2)],
+                6 | 9  => &[(0, 2)],
+                7 | 8  => &[(2, 3)],
+                5 => if center_inside { &[(3, 2), (0, 1)] } else { &[(3, 0), (1, 2)] },
+                _ => if center_inside { &[(3, 0), (1, 2)] } else { &[(0, 1), (2, 3)] },
+            };
+            for &(a, b) in edges {
+                segments.push([crossing(a), crossing(b)]);
+            }
+        }
+    }
+    segments
+}
+
+/// Contour lines of the raw `width` x `height` grid `counts` at `percents`, after smoothing.
+pub fn trace(counts: &[f32], width: usize, height: usize, percents: &[f64]) -> Vec<Line> {
+    let density = smooth(counts, width, height, cfg::CONTOUR_SMOOTHING);
+    percents.iter().zip(thresholds(&density, percents)).filter(|&(_, level)| level > 0.0).map(|(&p, level)| {
+        Line {
+            percent: p,
+            segments: march(&density, width, height, level),
+        }
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_percents() {
+        assert_eq!(Contours::parse_percents("90, 50 99% 50"), Ok(vec![50.0, 90.0, 99.0]));
+        for s in &["", " , ", "0", "100", "ninety", "-5"] {
+            assert!(Contours::parse_percents(s).is_err(), "{:?} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn keeps_the_mass_when_smoothing() {
+        let mut values = vec![0.0; 21 * 21];
+        values[10 * 21 + 10] = 1.0;
+        let smoothed = smooth(&values, 21, 21, 1.5);
+        assert!((smoothed.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!((smoothed[10 * 21 + 8] - smoothed[8 * 21 + 10]).abs() < 1e-7);
+        assert!(smoothed[10 * 21 + 10] > smoothed[10 * 21 + 11]);
+    }
+
+    #[test]
+    fn finds_the_densities_holding_the_shares() {
+        let density = [0.0, 2.0, 4.0, 1.0, 3.0];
+        assert_eq!(thresholds(&density, &[40.0, 50.0, 70.0, 95.0, 100.0]), vec![4.0, 3.0, 3.0, 1.0, 1.0]);
+        assert_eq!(thresholds(&[0.0; 4], &[50.0]), vec![0.0]);
+    }
+
+    #[test]
+    fn marches_around_a_peak() {
+        let density = [
+            0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0,
+        ];
+        let segments = march(&density, 3, 3, 0.5);
+        assert_eq!(segments.len(), 4);
+        // a closed diamond through the middles of the edges to the peak
+        let mut ends = segments.iter().flat_map(|s| s.iter().cloned()).collect::<Vec<(f32, f32)>>();
+        ends.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(ends, vec![(0.5, 1.0), (0.5, 1.0), (1.0, 0.5), (1.0, 0.5), (1.0, 1.5), (1.0, 1.5), (1.5, 1.0), (1.5, 1.0)]);
+        assert!(march(&density, 3, 3, 2.0).is_empty());
+    }
+
+    #[test]
+    fn resolves_saddles_by_the_mean() {
+        // the corners (0, 0) and (1, 1) are high, the mean of the cell is 0.5
+        let density = [1.0, 0.0, 0.0, 1.0];
+        let cut_off = |level: f32| {
+            let segments = march(&density, 2, 2, level);
+            assert_eq!(segments.len(), 2);
+            segments.iter().map(|s| {
+                let (x, y) = ((s[0].0 + s[1].0) / 2.0, (s[0].1 + s[1].1) / 2.0);
+                (x.round(), y.round())
+            }).collect::<Vec<(f32, f32)>>()
+        };
+        // the low corners are cut off if the center is inside, the high ones otherwise
+        assert_eq!(cut_off(0.4), vec![(0.0, 1.0), (1.0, 0.0)]);
+        assert_eq!(cut_off(0.6), vec![(0.0, 0.0), (1.0, 1.0)]);
+    }
+}
@@ -0,0 +1,165 @@
+//! CPU counterparts of the point and texture shaders, for output without OpenGL.
+
+use data::Point;
+use image::Image;
+
+use view::Projection;
+
+
+/// Accumulates the rows selected by `mask` like the point shaders do, as discs of `pointsize`
+/// pixels. Points of size 1 or less only add to the pixel their center falls into. Rows go from top
+/// to bottom.
+pub fn accumulate(x: &[f64], y: &[f64], z: &[f64], mask: &[bool], projection: &Projection, showborder: bool, pointsize: f32, width: u32, height: u32) -> Image {
+    let mut image = Image::new(width, height);
+    let n = mask.iter().filter(|m| **m).count();
+    if n == 0 || width == 0 || height == 0 {
+        return image;
+    }
+    let inv_n = 1.0 / (n as f32);
+
+    for i in 0..mask.len() {
+        if mask[i] {
+            splat(&mut image, [x[i], y[i], z[i]], inv_n, projection, showborder, pointsize);
+        }
+    }
+    image
+}
+
+/// Like `accumulate`, for points relative to `origin` that stand for `weight` of `n` rows each, e.g.
+/// the cells of a `Lod`.
+pub fn accumulate_points(points: &[Point], n: usize, origin: [f64; 3], projection: &Projection, pointsize: f32, width: u32, height: u32) -> Image {
+    let mut image = Image::new(width, height);
+    if n == 0 || width == 0 || height == 0 {
+        return image;
+    }
+    let inv_n = 1.0 / (n as f32);
+
+    for point in points {
+        let position = [
+            (point.position[0] as f64) + origin[0],
+            (point.position[1] as f64) + origin[1],
+            (point.position[2] as f64) + origin[2],
+        ];
+        splat(&mut image, position, point.weight * inv_n, projection, false, pointsize);
+    }
+    image
+}
+
+/// Adds a single point with the given weight to `image`.
+fn splat(image: &mut Image, position: [f64; 3], weight: f32, projection: &Projection, showborder: bool, pointsize: f32) {
+    let (width, height) = (image.width, image.height);
+    let p = projection;
+    let mut px = position[0] * p.scale_x + p.delta_x;
+    let mut py = position[1] * p.scale_y + p.delta_y;
+    let pz = (position[2] * p.scale_z + p.delta_z) as f32;
+    if px.is_nan() || py.is_nan() {
+        return;
+    }
+
+    let (mut alpha, mut size, mut atborder) = (1.0, pointsize as f64, false);
+    if showborder {
+        let mut dist = 0.0;
+        if px > 1.0 {
+            dist += px;
+            px = 1.0;
+        } else if px < -1.0 {
+            dist -= px;
+            px = -1.0;
+        }
+        if py > 1.0 {
+            dist += py;
+            py = 1.0;
+        } else if py < -1.0 {
+            dist -= py;
+            py = -1.0;
+        }
+        if dist > 0.0 {
+            alpha = (1.0 / dist) as f32;
+            size /= dist;
+            atborder = true;
+        }
+    }
+
+    // center in pixels from the top left corner
+    let cx = (px + 1.0) / 2.0 * (width as f64);
+    let cy = (1.0 - py) / 2.0 * (height as f64);
+    let value = [alpha * weight, pz * alpha * weight, pz * alpha * weight, weight];
+    if size <= 1.0 {
+        if atborder || (cx >= 0.0 && cx < (width as f64) && cy >= 0.0 && cy < (height as f64)) {
+            let col = (f64::max(cx, 0.0) as u32).min(width - 1);
+            let row = (f64::max(cy, 0.0) as u32).min(height - 1);
+            add(image, col, row, &value);
+        }
+        return;
+    }
+
+    // pixel centers within the disc, without its inner part at the border
+    let radius = size / 2.0;
+    let (col_min, col_max) = (f64::max(cx - radius, 0.0).floor(), f64::min(cx + radius, (width - 1) as f64).floor());
+    let (row_min, row_max) = (f64::max(cy - radius, 0.0).floor(), f64::min(cy + radius, (height - 1) as f64).floor());
+    if col_min > col_max || row_min > row_max {
+        return;
+    }
+    for row in (row_min as u32)..(row_max as u32 + 1) {
+        for col in (col_min as u32)..(col_max as u32 + 1) {
+            let dx = ((col as f64) + 0.5 - cx) / size;
+            let dy = ((row as f64) + 0.5 - cy) / size;
+            let d2 = dx * dx + dy * dy;
+            if d2 < 0.25 && !(atborder && d2 < 0.15) {
+                add(image, col, row, &value);
+            }
+        }
+    }
+}
+
+fn add(image: &mut Image, col: u32, row: u32, value: &[f32; 4]) {
+    let pixel = &mut image.pixels[(row as usize) * (image.width as usize) + (col as usize)];
+    for i in 0..4 {
+        pixel[i] += value[i];
+    }
+}
+
+
+fn f_inv(t: f32) -> f32 {
+    let delta = 6.0 / 29.0;
+    if t > delta {
+        t * t * t
+    } else {
+        3.0 * delta * delta * (t - 4.0 / 29.0)
+    }
+}
+
+/// Linear RGB of the accumulated Lab color, exactly like the texture shader. Empty pixels are black.
+fn tonemap_pixel(p: &[f32; 4], inv_gamma: f32) -> [f32; 4] {
+    let counter = p[3];
+    if counter <= 0.0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    let multiplier = counter.powf(inv_gamma) / counter;
+    let l = 100.0 * p[0] * multiplier;
+    let a = 256.0 * p[1] / counter - 128.0;
+    let b = 256.0 * p[2] / counter - 128.0;
+
+    // D65 white point, scaled by 0.01 as in the shader
+    let tmp = (l + 16.0) / 116.0;
+    let x = 0.95047 * f_inv(tmp + a / 500.0);
+    let y = 1.00000 * f_inv(tmp);
+    let z = 1.08883 * f_inv(tmp - b / 200.0);
+
+    // the shader's mat3 is filled column by column
+    [
+         3.2404542 * x - 0.9692660 * y + 0.0556434 * z,
+        -1.5371385 * x + 1.8760108 * y - 0.2040259 * z,
+        -0.4985314 * x + 0.0415560 * y + 1.0572252 * z,
+        1.0,
+    ]
+}
+
+pub fn tonemap(accumulation: &Image, gamma: f32) -> Image {
+    let inv_gamma = 1.0 / gamma;
+    Image {
+        width: accumulation.width,
+        height: accumulation.height,
+        pixels: accumulation.pixels.iter().map(|p| tonemap_pixel(p, inv_gamma)).collect(),
+    }
+}
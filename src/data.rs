@@ -1,18 +1,52 @@
 extern crate csv;
 
-use std::f32;
+use cache::MappedValues;
+
+use datetime::TimeFormat;
+
+use std::f64;
+use std::ops::Deref;
 
 #[derive(Clone, Copy)]
 pub struct Point {
     pub position: [f32; 3],
+    /// number of rows this point stands for
+    pub weight: f32,
+}
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    Number,
+    /// seconds since 1970-01-01 UTC
+    Time,
+}
+
+
+/// Values of a column, either parsed or mapped from a cache file.
+pub enum Values {
+    Owned(Vec<f64>),
+    Mapped(MappedValues),
+}
+
+impl Deref for Values {
+    type Target = [f64];
+
+    fn deref(&self) -> &[f64] {
+        match *self {
+            Values::Owned(ref v) => v,
+            Values::Mapped(ref m) => m.as_slice(),
+        }
+    }
 }
 
 
 pub struct Column {
     pub name: String,
-    pub data: Vec<f32>,
-    pub min: f32,
-    pub max: f32,
+    pub kind: Kind,
+    pub data: Values,
+    pub min: f64,
+    pub max: f64,
 }
 
 
@@ -20,16 +54,29 @@ impl Column {
     fn new(name: &str) -> Column {
         Column {
             name: String::from(name),
-            data: vec![],
-            min: f32::INFINITY,
-            max: f32::NEG_INFINITY,
+            kind: Kind::Number,
+            data: Values::Owned(vec![]),
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
         }
     }
 
-    fn push(&mut self, point: f32) {
-        self.data.push(point);
-        self.min = f32::min(self.min, point);
-        self.max = f32::max(self.max, point);
+    pub fn from_data(name: &str, data: Vec<f64>) -> Column {
+        let mut column = Column::new(name);
+        for &v in &data {
+            column.min = f64::min(column.min, v);
+            column.max = f64::max(column.max, v);
+        }
+        column.data = Values::Owned(data);
+        column
+    }
+
+    fn push(&mut self, point: f64) {
+        if let Values::Owned(ref mut v) = self.data {
+            v.push(point);
+        }
+        self.min = f64::min(self.min, point);
+        self.max = f64::max(self.max, point);
     }
 }
 
@@ -40,7 +87,28 @@ fn is_na_string(s: &str) -> bool {
 }
 
 
-pub fn columns_from_file(fname: &str) -> Result<Vec<Column>, String> {
+/// How the cells of one column are turned into numbers.
+enum CellParser {
+    /// decided by the first non-NA cell
+    Unknown,
+    Number,
+    Time(TimeFormat),
+}
+
+impl CellParser {
+    fn parse(&self, cell: &str) -> Option<f64> {
+        match *self {
+            CellParser::Unknown => None,
+            CellParser::Number => cell.parse::<f64>().ok(),
+            CellParser::Time(ref format) => format.parse(cell),
+        }
+    }
+}
+
+
+/// Reads all columns of `fname`. Columns listed in `time_formats` are parsed as date/time with the
+/// given format, other columns are detected as date/time if their first value is an ISO-8601 date.
+pub fn columns_from_file(fname: &str, time_formats: &[(String, TimeFormat)]) -> Result<Vec<Column>, String> {
     let mut rdr = match csv::Reader::from_file(fname) {
         Ok(f) => f.has_headers(true),
         Err(_) => {
@@ -58,6 +126,19 @@ pub fn columns_from_file(fname: &str) -> Result<Vec<Column>, String> {
         Column::new(name)
     }).collect::<Vec<Column>>();
 
+    let mut parsers = columns.iter().map(|_| CellParser::Unknown).collect::<Vec<CellParser>>();
+    for &(ref name, ref format) in time_formats {
+        match columns.iter().position(|c| c.name == *name) {
+            Some(j) => {
+                columns[j].kind = Kind::Time;
+                parsers[j] = CellParser::Time(format.clone());
+            },
+            None => {
+                return Err(format!("there is no column '{}' to parse as date/time", name));
+            }
+        }
+    }
+
     for (i, row) in rdr.records().enumerate() {
         let row = row.unwrap();
         if row.len() != m {
@@ -65,14 +146,27 @@ pub fn columns_from_file(fname: &str) -> Result<Vec<Column>, String> {
         }
 
         for (j, cell) in row.iter().enumerate() {
-            let value = if is_na_string(cell) {
-                f32::NAN
-            } else {
-                match cell.parse::<f32>() {
-                    Ok(v) => v,
-                    Err(_) => {
-                        return Err(format!("cannot parse column {} in row {}", j + 1, i + 1));
-                    }
+            if is_na_string(cell) {
+                columns[j].push(f64::NAN);
+                continue;
+            }
+
+            if let CellParser::Unknown = parsers[j] {
+                parsers[j] = if cell.parse::<f64>().is_ok() {
+                    CellParser::Number
+                } else if TimeFormat::Iso.parse(cell).is_some() {
+                    info!("column {} contains ISO-8601 dates", columns[j].name);
+                    columns[j].kind = Kind::Time;
+                    CellParser::Time(TimeFormat::Iso)
+                } else {
+                    CellParser::Number
+                };
+            }
+
+            let value = match parsers[j].parse(cell) {
+                Some(v) => v,
+                None => {
+                    return Err(format!("cannot parse column {} ('{}') in row {}: '{}'", j + 1, columns[j].name, i + 1, cell));
                 }
             };
             columns[j].push(value);
@@ -82,10 +176,15 @@ pub fn columns_from_file(fname: &str) -> Result<Vec<Column>, String> {
     Ok(columns)
 }
 
-pub fn points_from_columns(cols: &[Column], a: usize, b: usize, c: usize) -> Vec<Point> {
-    cols[a].data.iter().zip(cols[b].data.iter()).zip(cols[c].data.iter()).map(|((x, y), z)| {
-        Point {
-            position: [*x, *y, *z]
-        }
-    }).collect()
+/// Min and max of `col` over all rows selected by `mask`, or of the whole column if none is selected.
+pub fn column_range(col: &Column, mask: &[bool]) -> (f64, f64) {
+    let (min, max) = col.data.iter().zip(mask.iter()).filter(|&(_, m)| *m).fold(
+        (f64::INFINITY, f64::NEG_INFINITY),
+        |(min, max), (v, _)| (f64::min(min, *v), f64::max(max, *v))
+    );
+    if min <= max {
+        (min, max)
+    } else {
+        (col.min, col.max)
+    }
 }
@@ -0,0 +1,378 @@
+//! Date/time values are stored as (fractional) seconds since 1970-01-01 00:00:00 UTC.
+
+pub static SECONDS_PER_DAY: f64 = 86400.0;
+
+// tried in order, after replacing the first ' ' by 'T'
+static ISO_PATTERNS: &'static [&'static str] = &[
+    "%Y-%m-%dT%H:%M:%S%z",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M%z",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d",
+];
+
+static MONTHS: &'static [&'static str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+
+/// How the cells of a date/time column are written.
+#[derive(Clone, Debug)]
+pub enum TimeFormat {
+    /// ISO-8601 / RFC-3339, e.g. `2016-07-01T12:30:00.5+02:00`, `2016-07-01 12:30` or `2016-07-01`
+    Iso,
+    EpochSeconds,
+    EpochMillis,
+    /// strftime-like pattern, e.g. `%d.%m.%Y %H:%M`
+    Pattern(String),
+}
+
+impl TimeFormat {
+    /// `iso`, `epoch`, `epoch_ms`, or anything else as a strftime pattern.
+    pub fn from_name(s: &str) -> TimeFormat {
+        match s {
+            "iso" | "rfc3339"  => TimeFormat::Iso,
+            "epoch" | "epoch_s" => TimeFormat::EpochSeconds,
+            "epoch_ms"          => TimeFormat::EpochMillis,
+            _                   => TimeFormat::Pattern(String::from(s)),
+        }
+    }
+
+    pub fn parse(&self, s: &str) -> Option<f64> {
+        let s = s.trim();
+        match *self {
+            TimeFormat::Iso => parse_iso(s),
+            TimeFormat::EpochSeconds => s.parse::<f64>().ok(),
+            TimeFormat::EpochMillis => s.parse::<f64>().ok().map(|v| v / 1000.0),
+            TimeFormat::Pattern(ref p) => parse_pattern(p, s),
+        }
+    }
+}
+
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let mp = ((month as i64) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + (day as i64) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Number of days of `month` (1 to 12) in `year`.
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2              => 28,
+        4 | 6 | 9 | 11 => 30,
+        _              => 31,
+    }
+}
+
+/// Inverse of `days_from_civil`.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    (if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, month, day)
+}
+
+
+/// Broken-down UTC time.
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    /// including fractions of a second
+    pub second: f64,
+}
+
+impl DateTime {
+    pub fn from_timestamp(t: f64) -> DateTime {
+        let days = (t / SECONDS_PER_DAY).floor();
+        let seconds = t - days * SECONDS_PER_DAY;
+        let (year, month, day) = civil_from_days(days as i64);
+        let whole = seconds.floor() as u32;
+        DateTime {
+            year: year,
+            month: month,
+            day: day,
+            hour: whole / 3600,
+            minute: (whole / 60) % 60,
+            second: seconds - ((whole / 60 * 60) as f64),
+        }
+    }
+
+    pub fn to_timestamp(&self) -> f64 {
+        (days_from_civil(self.year, self.month, self.day) as f64) * SECONDS_PER_DAY
+            + ((self.hour * 3600 + self.minute * 60) as f64)
+            + self.second
+    }
+}
+
+
+fn parse_iso(s: &str) -> Option<f64> {
+    let s = s.to_uppercase().replacen(' ', "T", 1);
+    ISO_PATTERNS.iter().filter_map(|p| parse_pattern(p, &s)).next()
+}
+
+fn is_digit(c: u8) -> bool {
+    c >= b'0' && c <= b'9'
+}
+
+/// Reads between `min` and `max` digits.
+fn number(s: &[u8], pos: &mut usize, min: usize, max: usize) -> Option<i64> {
+    let start = *pos;
+    let mut value = 0;
+    while *pos < s.len() && *pos - start < max && is_digit(s[*pos]) {
+        value = value * 10 + ((s[*pos] - b'0') as i64);
+        *pos += 1;
+    }
+    if *pos - start >= min {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Reads the digits after a decimal separator as a fraction.
+fn fraction(s: &[u8], pos: &mut usize) -> Option<f64> {
+    let start = *pos;
+    let mut value = 0.0;
+    let mut scale = 1.0;
+    while *pos < s.len() && is_digit(s[*pos]) {
+        scale /= 10.0;
+        value += ((s[*pos] - b'0') as f64) * scale;
+        *pos += 1;
+    }
+    if *pos > start {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// `Z`, `+HH`, `+HHMM` or `+HH:MM`, in seconds east of UTC.
+fn utc_offset(s: &[u8], pos: &mut usize) -> Option<i64> {
+    if *pos >= s.len() {
+        return None;
+    }
+    let sign = match s[*pos] {
+        b'Z' => {
+            *pos += 1;
+            return Some(0);
+        },
+        b'+' => 1,
+        b'-' => -1,
+        _ => {
+            return None;
+        }
+    };
+    *pos += 1;
+    let hours = match number(s, pos, 2, 2) {
+        Some(h) => h,
+        None => {
+            return None;
+        }
+    };
+    if *pos < s.len() && s[*pos] == b':' {
+        *pos += 1;
+    }
+    let minutes = number(s, pos, 2, 2).unwrap_or(0);
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn month_name(s: &[u8], pos: &mut usize) -> Option<i64> {
+    if *pos + 3 > s.len() {
+        return None;
+    }
+    let name = String::from_utf8_lossy(&s[*pos..*pos + 3]).to_lowercase();
+    match MONTHS.iter().position(|m| *m == name) {
+        Some(i) => {
+            *pos += 3;
+            Some((i as i64) + 1)
+        },
+        None => None,
+    }
+}
+
+fn store(field: &mut i64, value: Option<i64>) -> bool {
+    match value {
+        Some(v) => {
+            *field = v;
+            true
+        },
+        None => false,
+    }
+}
+
+/// Supports `%Y %y %m %b %d %H %M %S %f %z %s %%`. `%S` accepts an optional fraction.
+fn parse_pattern(pattern: &str, s: &str) -> Option<f64> {
+    let p = pattern.as_bytes();
+    let s = s.as_bytes();
+    let mut pos = 0;
+
+    let (mut year, mut month, mut day) = (1970, 1, 1);
+    let (mut hour, mut minute, mut second) = (0, 0, 0);
+    let mut frac = 0.0;
+    let mut offset = 0;
+    let mut epoch = None;
+
+    let mut i = 0;
+    while i < p.len() {
+        if p[i] != b'%' || i + 1 == p.len() {
+            if pos >= s.len() || s[pos] != p[i] {
+                return None;
+            }
+            pos += 1;
+            i += 1;
+            continue;
+        }
+
+        let ok = match p[i + 1] {
+            b'Y' => store(&mut year, number(s, &mut pos, 4, 4)),
+            b'y' => store(&mut year, number(s, &mut pos, 2, 2).map(|y| if y < 69 { 2000 + y } else { 1900 + y })),
+            b'm' => store(&mut month, number(s, &mut pos, 1, 2)),
+            b'b' => store(&mut month, month_name(s, &mut pos)),
+            b'd' => store(&mut day, number(s, &mut pos, 1, 2)),
+            b'H' => store(&mut hour, number(s, &mut pos, 1, 2)),
+            b'M' => store(&mut minute, number(s, &mut pos, 2, 2)),
+            b'S' => {
+                let ok = store(&mut second, number(s, &mut pos, 2, 2));
+                if ok && pos + 1 < s.len() && (s[pos] == b'.' || s[pos] == b',') && is_digit(s[pos + 1]) {
+                    pos += 1;
+                    frac = fraction(s, &mut pos).unwrap_or(0.0);
+                }
+                ok
+            },
+            b'f' => {
+                match fraction(s, &mut pos) {
+                    Some(f) => {
+                        frac = f;
+                        true
+                    },
+                    None => false,
+                }
+            },
+            b'z' => store(&mut offset, utc_offset(s, &mut pos)),
+            b's' => {
+                let start = pos;
+                while pos < s.len() && (is_digit(s[pos]) || s[pos] == b'.' || (pos == start && s[pos] == b'-')) {
+                    pos += 1;
+                }
+                epoch = String::from_utf8_lossy(&s[start..pos]).parse::<f64>().ok();
+                epoch.is_some()
+            },
+            b'%' => {
+                let ok = pos < s.len() && s[pos] == b'%';
+                pos += 1;
+                ok
+            },
+            _ => false,
+        };
+        if !ok {
+            return None;
+        }
+        i += 2;
+    }
+
+    if pos != s.len() {
+        return None;
+    }
+    if let Some(t) = epoch {
+        return Some(t);
+    }
+    if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month as u32) as i64 || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let dt = DateTime {
+        year: year,
+        month: month as u32,
+        day: day as u32,
+        hour: hour as u32,
+        minute: minute as u32,
+        second: (second as f64) + frac,
+    };
+    Some(dt.to_timestamp() - (offset as f64))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_days_across_leap_years() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(days_from_civil(2000, 3, 1) - days_from_civil(2000, 2, 28), 2);
+        assert_eq!(days_from_civil(1900, 3, 1) - days_from_civil(1900, 2, 28), 1);
+        assert_eq!(days_from_civil(2016, 3, 1) - days_from_civil(2016, 2, 28), 2);
+        assert_eq!(days_from_civil(2017, 3, 1) - days_from_civil(2017, 2, 28), 1);
+        for &days in &[-719468, -146097, -1, 0, 1, 11016, 16860, 2932896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(days_from_civil(2016, 2, 29)), (2016, 2, 29));
+    }
+
+    #[test]
+    fn knows_the_month_lengths() {
+        let lengths = (1..13).map(|m| days_in_month(2017, m)).collect::<Vec<u32>>();
+        assert_eq!(lengths, vec![31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]);
+        for &(year, days) in &[(2016, 29), (2000, 29), (1900, 28), (2100, 28), (-4, 29), (-1, 28)] {
+            assert_eq!(days_in_month(year, 2), days, "February {}", year);
+        }
+        for days in 0..1000 {
+            let (year, month, day) = civil_from_days(days * 37);
+            assert!(day <= days_in_month(year, month));
+        }
+    }
+
+    #[test]
+    fn splits_timestamps_around_the_epoch() {
+        let dt = DateTime::from_timestamp(-0.5);
+        assert_eq!((dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second), (1969, 12, 31, 23, 59, 59.5));
+        let dt = DateTime::from_timestamp(0.0);
+        assert_eq!((dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second), (1970, 1, 1, 0, 0, 0.0));
+        let dt = DateTime::from_timestamp(951782400.0);
+        assert_eq!((dt.year, dt.month, dt.day), (2000, 2, 29));
+        assert_eq!(dt.to_timestamp(), 951782400.0);
+    }
+
+    #[test]
+    fn parses_iso() {
+        let iso = TimeFormat::Iso;
+        assert_eq!(iso.parse("1970-01-01"), Some(0.0));
+        assert_eq!(iso.parse("1969-12-31T23:59:59"), Some(-1.0));
+        assert_eq!(iso.parse("2016-02-29 12:30"), Some(1456749000.0));
+        assert_eq!(iso.parse("2016-07-01T12:30:00.5+02:00"), Some(1467369000.5));
+        assert_eq!(iso.parse("2016-07-01t10:30:00z"), Some(1467369000.0));
+        assert_eq!(iso.parse("2000-02-29"), Some(951782400.0));
+        for s in &["", "2016", "2016-13-01", "2016-00-10", "2016-01-00", "2016-01-32", "2017-02-29", "2017-02-30", "2016-02-30",
+                   "1900-02-29", "2016-04-31", "2016-11-31", "2016-07-01T24:00", "2016-07-01T12:30:00+", "2016-07-01x"] {
+            assert_eq!(iso.parse(s), None, "{:?} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn parses_patterns_and_epochs() {
+        assert_eq!(TimeFormat::from_name("%d.%m.%Y %H:%M").parse("29.02.2016 12:30"), Some(1456749000.0));
+        assert_eq!(TimeFormat::from_name("%d %b %y").parse("1 Jan 70"), Some(0.0));
+        assert_eq!(TimeFormat::from_name("%s").parse("-1.5"), Some(-1.5));
+        assert_eq!(TimeFormat::from_name("epoch_ms").parse("1500"), Some(1.5));
+        assert_eq!(TimeFormat::from_name("%d.%m.%Y").parse("29.02.16"), None);
+        assert_eq!(TimeFormat::from_name("%d.%m.%Y").parse("31.06.2016"), None);
+        assert_eq!(TimeFormat::from_name("%d %b %Y").parse("29 Feb 2015"), None);
+    }
+}
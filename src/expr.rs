@@ -0,0 +1,601 @@
+//! A small expression language for derived columns, e.g. `ratio = log(a) / b`.
+//!
+//! Supported are numbers, column references (plain identifiers or quoted with backticks, like
+//! `` `col name` ``), `+ - * / % ^`, comparisons, `&& || !`, `cond ? a : b` and the functions listed
+//! in `FUNCTIONS`. Comparisons and logic operators yield 1 or 0, NA values propagate.
+
+use data::Column;
+
+use std::f64;
+
+
+/// Deepest nesting of operators, parentheses and calls accepted, so that parsing and evaluating
+/// stay well within the stack of any thread.
+static DEPTH_MAX: usize = 256;
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+// longer operators first, so that `<=` is not taken for `<`
+static OPERATORS: &'static [&'static str] = &[
+    "<=", ">=", "==", "!=", "&&", "||",
+    "+", "-", "*", "/", "%", "^", "<", ">", "!", "?", ":",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars = s.chars().collect::<Vec<char>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_digit(10) || (c == '.' && i + 1 < chars.len() && chars[i + 1].is_digit(10)) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_digit(10) || chars[i] == '.') {
+                i += 1;
+            }
+            // exponent, e.g. 1e-3
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_digit(10) {
+                    i = j;
+                    while i < chars.len() && chars[i].is_digit(10) {
+                        i += 1;
+                    }
+                }
+            }
+            let literal = chars[start..i].iter().cloned().collect::<String>();
+            match literal.parse::<f64>() {
+                Ok(v) => tokens.push(Token::Number(v)),
+                Err(_) => {
+                    return Err(format!("invalid number '{}'", literal));
+                }
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().cloned().collect()));
+        } else if c == '`' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != '`' {
+                i += 1;
+            }
+            if i >= chars.len() {
+                return Err(String::from("unterminated `"));
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().cloned().collect()));
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c == ',' {
+            tokens.push(Token::Comma);
+            i += 1;
+        } else {
+            let at = |op: &str| op.chars().enumerate().all(|(k, o)| chars.get(i + k) == Some(&o));
+            match OPERATORS.iter().find(|op| at(op)) {
+                Some(op) => {
+                    tokens.push(Token::Op(*op));
+                    i += op.len();
+                },
+                None => {
+                    return Err(format!("unexpected character '{}'", c));
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+
+#[derive(Debug)]
+enum Node {
+    Constant(f64),
+    Column(usize),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+    Conditional(Box<Node>, Box<Node>, Box<Node>),
+    Call(&'static str, Vec<Node>),
+}
+
+// name, number of arguments (0 = at least one)
+static FUNCTIONS: &'static [(&'static str, usize)] = &[
+    ("abs", 1), ("sqrt", 1), ("exp", 1), ("ln", 1), ("log", 1), ("log2", 1), ("log10", 1),
+    ("sin", 1), ("cos", 1), ("tan", 1), ("asin", 1), ("acos", 1), ("atan", 1), ("atan2", 2),
+    ("floor", 1), ("ceil", 1), ("round", 1), ("pow", 2), ("min", 0), ("max", 0),
+    ("if", 3), ("isna", 1),
+];
+
+static CONSTANTS: &'static [(&'static str, f64)] = &[
+    ("pi", f64::consts::PI),
+    ("e",  f64::consts::E),
+    ("na", f64::NAN),
+];
+
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// levels of the expression tree above the current token
+    depth: usize,
+    columns: &'a [Column],
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn peek_op(&self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(&Token::Op(op)) if ops.contains(&op) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        match self.next() {
+            Some(ref t) if *t == token => Ok(()),
+            Some(t) => Err(format!("expected {:?} but found {:?}", token, t)),
+            None => Err(format!("expected {:?} but expression ended", token)),
+        }
+    }
+
+    /// Parses `f` one level deeper in the expression tree.
+    fn nested<F>(&mut self, f: F) -> Result<Node, String> where F: FnOnce(&mut Parser<'a>) -> Result<Node, String> {
+        if self.depth == DEPTH_MAX {
+            return Err(String::from("expression is nested too deeply"));
+        }
+        self.depth += 1;
+        let node = f(self);
+        self.depth -= 1;
+        node
+    }
+
+    fn expr(&mut self) -> Result<Node, String> {
+        self.nested(|p| p.conditional())
+    }
+
+    fn conditional(&mut self) -> Result<Node, String> {
+        let cond = match self.binary(0) {
+            Ok(n) => n,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        if self.peek_op(&["?"]).is_none() {
+            return Ok(cond);
+        }
+
+        self.pos += 1;
+        let a = match self.expr() {
+            Ok(n) => n,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        if let Err(s) = self.expect(Token::Op(":")) {
+            return Err(s);
+        }
+        let b = match self.expr() {
+            Ok(n) => n,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        Ok(Node::Conditional(Box::new(cond), Box::new(a), Box::new(b)))
+    }
+
+    /// Left associative binary operators, from lowest to highest precedence.
+    fn binary(&mut self, level: usize) -> Result<Node, String> {
+        let levels: [&[&'static str]; 5] = [
+            &["||"],
+            &["&&"],
+            &["<", "<=", ">", ">=", "==", "!="],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        if level >= levels.len() {
+            return self.unary();
+        }
+
+        let mut lhs = match self.binary(level + 1) {
+            Ok(n) => n,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        // every operator puts the terms so far one level deeper
+        let depth = self.depth;
+        while let Some(op) = self.peek_op(levels[level]) {
+            self.pos += 1;
+            let rhs = match self.nested(|p| p.binary(level + 1)) {
+                Ok(n) => n,
+                Err(s) => {
+                    return Err(s);
+                }
+            };
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+            self.depth += 1;
+        }
+        self.depth = depth;
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if let Some(op) = self.peek_op(&["-", "!"]) {
+            self.pos += 1;
+            return self.nested(|p| p.unary()).map(|n| Node::Unary(op, Box::new(n)));
+        }
+
+        let base = match self.atom() {
+            Ok(n) => n,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        if self.peek_op(&["^"]).is_some() {
+            // right associative and binds tighter than unary minus on its left: -2^2 = -4
+            self.pos += 1;
+            return self.nested(|p| p.unary()).map(|exponent| Node::Binary("^", Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Number(v)) => Ok(Node::Constant(v)),
+            Some(Token::LParen) => {
+                let n = match self.expr() {
+                    Ok(n) => n,
+                    Err(s) => {
+                        return Err(s);
+                    }
+                };
+                self.expect(Token::RParen).map(|_| n)
+            },
+            Some(Token::Ident(name)) => {
+                if self.peek() == Some(&Token::LParen) {
+                    self.pos += 1;
+                    self.call(&name)
+                } else {
+                    self.reference(&name)
+                }
+            },
+            Some(t) => Err(format!("unexpected {:?}", t)),
+            None => Err(String::from("unexpected end of expression")),
+        }
+    }
+
+    fn reference(&self, name: &str) -> Result<Node, String> {
+        if let Some(idx) = self.columns.iter().position(|c| c.name == name) {
+            return Ok(Node::Column(idx));
+        }
+        match CONSTANTS.iter().find(|&&(c, _)| c == name) {
+            Some(&(_, v)) => Ok(Node::Constant(v)),
+            None => Err(format!("unknown column '{}'", name)),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> Result<Node, String> {
+        let (fname, arity) = match FUNCTIONS.iter().find(|&&(f, _)| f == name) {
+            Some(&f) => f,
+            None => {
+                return Err(format!("unknown function '{}'", name));
+            }
+        };
+
+        let mut args = vec![];
+        if self.peek() != Some(&Token::RParen) {
+            loop {
+                match self.expr() {
+                    Ok(n) => args.push(n),
+                    Err(s) => {
+                        return Err(s);
+                    }
+                }
+                if self.peek() == Some(&Token::Comma) {
+                    self.pos += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+        if let Err(s) = self.expect(Token::RParen) {
+            return Err(s);
+        }
+
+        if (arity > 0 && args.len() != arity) || args.is_empty() {
+            return Err(format!("wrong number of arguments for {}()", fname));
+        }
+        Ok(Node::Call(fname, args))
+    }
+}
+
+
+fn bool_to_f64(b: bool) -> f64 {
+    if b { 1.0 } else { 0.0 }
+}
+
+fn apply_unary(op: &str, a: f64) -> f64 {
+    match op {
+        "-" => -a,
+        "!" => if a.is_nan() { a } else { bool_to_f64(a == 0.0) },
+        _   => unreachable!(),
+    }
+}
+
+fn apply_binary(op: &str, a: f64, b: f64) -> f64 {
+    // comparisons and logic would otherwise turn NA into 0
+    if a.is_nan() || b.is_nan() {
+        return f64::NAN;
+    }
+    match op {
+        "+"  => a + b,
+        "-"  => a - b,
+        "*"  => a * b,
+        "/"  => a / b,
+        "%"  => a % b,
+        "^"  => a.powf(b),
+        "<"  => bool_to_f64(a < b),
+        "<=" => bool_to_f64(a <= b),
+        ">"  => bool_to_f64(a > b),
+        ">=" => bool_to_f64(a >= b),
+        "==" => bool_to_f64(a == b),
+        "!=" => bool_to_f64(a != b),
+        "&&" => bool_to_f64(a != 0.0 && b != 0.0),
+        "||" => bool_to_f64(a != 0.0 || b != 0.0),
+        _    => unreachable!(),
+    }
+}
+
+fn apply_function(name: &str, args: &[f64]) -> f64 {
+    let a = args[0];
+    match name {
+        "abs"   => a.abs(),
+        "sqrt"  => a.sqrt(),
+        "exp"   => a.exp(),
+        "ln"    => a.ln(),
+        "log"   => a.ln(),
+        "log2"  => a.log2(),
+        "log10" => a.log10(),
+        "sin"   => a.sin(),
+        "cos"   => a.cos(),
+        "tan"   => a.tan(),
+        "asin"  => a.asin(),
+        "acos"  => a.acos(),
+        "atan"  => a.atan(),
+        "atan2" => a.atan2(args[1]),
+        "floor" => a.floor(),
+        "ceil"  => a.ceil(),
+        "round" => a.round(),
+        "pow"   => a.powf(args[1]),
+        // f64::min and f64::max would skip NA
+        "min"   => args.iter().fold(f64::INFINITY, |acc, &v| if acc.is_nan() || v.is_nan() { f64::NAN } else { acc.min(v) }),
+        "max"   => args.iter().fold(f64::NEG_INFINITY, |acc, &v| if acc.is_nan() || v.is_nan() { f64::NAN } else { acc.max(v) }),
+        "if"    => if a.is_nan() { a } else if a != 0.0 { args[1] } else { args[2] },
+        "isna"  => bool_to_f64(a.is_nan()),
+        _       => unreachable!(),
+    }
+}
+
+
+/// A parsed expression, with column references resolved to indices.
+pub struct Expr {
+    root: Node,
+}
+
+impl Expr {
+    pub fn parse(s: &str, columns: &[Column]) -> Result<Expr, String> {
+        let tokens = match tokenize(s) {
+            Ok(t) => t,
+            Err(e) => {
+                return Err(e);
+            }
+        };
+
+        let mut parser = Parser {
+            tokens: tokens,
+            pos: 0,
+            depth: 0,
+            columns: columns,
+        };
+        let root = match parser.expr() {
+            Ok(n) => n,
+            Err(e) => {
+                return Err(e);
+            }
+        };
+        if let Some(t) = parser.peek() {
+            return Err(format!("unexpected {:?} after end of expression", t));
+        }
+
+        Ok(Expr {
+            root: root,
+        })
+    }
+
+    /// Evaluates the expression for all rows at once.
+    pub fn eval(&self, columns: &[Column]) -> Vec<f64> {
+        let n = columns[0].data.len();
+        eval_node(&self.root, columns, n)
+    }
+}
+
+fn eval_node(node: &Node, columns: &[Column], n: usize) -> Vec<f64> {
+    match *node {
+        Node::Constant(v) => vec![v; n],
+        Node::Column(idx) => columns[idx].data.to_vec(),
+        Node::Unary(op, ref a) => {
+            let mut values = eval_node(a, columns, n);
+            for v in values.iter_mut() {
+                *v = apply_unary(op, *v);
+            }
+            values
+        },
+        Node::Binary(op, ref a, ref b) => {
+            let mut values = eval_node(a, columns, n);
+            let rhs = eval_node(b, columns, n);
+            for (v, w) in values.iter_mut().zip(rhs.iter()) {
+                *v = apply_binary(op, *v, *w);
+            }
+            values
+        },
+        Node::Conditional(ref cond, ref a, ref b) => {
+            let mut values = eval_node(cond, columns, n);
+            let lhs = eval_node(a, columns, n);
+            let rhs = eval_node(b, columns, n);
+            for (i, v) in values.iter_mut().enumerate() {
+                *v = apply_function("if", &[*v, lhs[i], rhs[i]]);
+            }
+            values
+        },
+        Node::Call(name, ref args) => {
+            let evaluated = args.iter().map(|a| eval_node(a, columns, n)).collect::<Vec<Vec<f64>>>();
+            let mut row = vec![0.0; evaluated.len()];
+            (0..n).map(|i| {
+                for (r, a) in row.iter_mut().zip(evaluated.iter()) {
+                    *r = a[i];
+                }
+                apply_function(name, &row)
+            }).collect()
+        },
+    }
+}
+
+
+/// Position of the `=` that separates name and expression in `name = expr`.
+fn find_assignment(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    (0..bytes.len()).find(|&i| {
+        bytes[i] == b'='
+            && (i + 1 >= bytes.len() || bytes[i + 1] != b'=')
+            && (i == 0 || !b"<>!=".contains(&bytes[i - 1]))
+    })
+}
+
+/// Builds a new column from a definition like `ratio = a / b`.
+pub fn derive_column(definition: &str, columns: &[Column]) -> Result<Column, String> {
+    let pos = match find_assignment(definition) {
+        Some(p) => p,
+        None => {
+            return Err(format!("derived column '{}' has to look like 'name = expression'", definition));
+        }
+    };
+
+    let name = definition[..pos].trim().trim_matches('`');
+    if name.is_empty() {
+        return Err(format!("derived column '{}' has no name", definition));
+    }
+    if columns.iter().any(|c| c.name == name) {
+        return Err(format!("column '{}' already exists", name));
+    }
+
+    match Expr::parse(&definition[pos + 1..], columns) {
+        Ok(expr) => Ok(Column::from_data(name, expr.eval(columns))),
+        Err(e) => Err(format!("cannot parse '{}': {}", definition, e)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns() -> Vec<Column> {
+        vec![
+            Column::from_data("a", vec![1.0, f64::NAN, 4.0]),
+            Column::from_data("b c", vec![2.0, 3.0, f64::NAN]),
+        ]
+    }
+
+    fn eval(s: &str) -> Vec<f64> {
+        let columns = columns();
+        Expr::parse(s, &columns).unwrap().eval(&columns)
+    }
+
+    /// Compares like `assert_eq`, but with NA equal to NA.
+    fn assert_values(s: &str, expected: &[f64]) {
+        let values = eval(s);
+        let same = values.len() == expected.len()
+            && values.iter().zip(expected).all(|(v, e)| (v.is_nan() && e.is_nan()) || v == e);
+        assert!(same, "{} gave {:?} instead of {:?}", s, values, expected);
+    }
+
+    #[test]
+    fn follows_precedence() {
+        assert_values("1 + 2 * 3 ^ 2", &[19.0; 3]);
+        assert_values("-2 ^ 2", &[-4.0; 3]);
+        assert_values("(1 + 2) * 3 % 4", &[1.0; 3]);
+        assert_values("1 < 2 && 2 <= 1 || !0", &[1.0; 3]);
+        assert_values("a * 2 + `b c`", &[4.0, f64::NAN, f64::NAN]);
+    }
+
+    #[test]
+    fn propagates_na() {
+        let na = f64::NAN;
+        assert_values("a > 0", &[1.0, na, 1.0]);
+        assert_values("!a", &[0.0, na, 0.0]);
+        assert_values("a == a", &[1.0, na, 1.0]);
+        assert_values("a ? 1 : 2", &[1.0, na, 1.0]);
+        assert_values("min(a, `b c`)", &[1.0, na, na]);
+        assert_values("max(`b c`, a, 10)", &[10.0, na, na]);
+        assert_values("isna(a)", &[0.0, 1.0, 0.0]);
+        assert_values("na + 1", &[na; 3]);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let columns = columns();
+        for s in &["", "1 +", "(1", "1)", "a b", "foo", "sqrt(1, 2)", "min()", "1 ? 2", "`a", "1 $ 2"] {
+            assert!(Expr::parse(s, &columns).is_err(), "{:?} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn limits_the_depth() {
+        let columns = columns();
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Expr::parse(&nested(DEPTH_MAX / 10), &columns).is_ok());
+        for s in &[nested(DEPTH_MAX), "-".repeat(DEPTH_MAX) + "1", "2^".repeat(DEPTH_MAX) + "2", "1".to_string() + &"+1".repeat(100000), "min(".repeat(DEPTH_MAX) + "1"] {
+            let error = Expr::parse(s, &columns).err().expect("deep expression was accepted");
+            assert!(error.contains("nested too deeply"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn derives_columns() {
+        let columns = columns();
+        let column = derive_column("ratio = a / `b c`", &columns).unwrap();
+        assert_eq!(column.name, "ratio");
+        assert_eq!(column.data[0], 0.5);
+        assert!(derive_column("a = 1", &columns).is_err());
+        assert!(derive_column("a == 1", &columns).is_err());
+        assert!(derive_column(" = 1", &columns).is_err());
+    }
+}
@@ -0,0 +1,283 @@
+//! Vector export of the current view: the density as embedded raster image, axes, ticks, labels
+//! and color bar as vector elements.
+
+use image;
+use image::Image;
+
+
+static MARGIN_LEFT:   f64 = 90.0;
+static MARGIN_RIGHT:  f64 = 130.0;
+static MARGIN_TOP:    f64 = 20.0;
+static MARGIN_BOTTOM: f64 = 55.0;
+static FONT_SIZE:     f64 = 11.0;
+static TICK_LENGTH:   f64 = 5.0;
+static BAR_GAP:       f64 = 20.0;
+static BAR_WIDTH:     f64 = 14.0;
+static BAR_STEPS:     usize = 64;
+/// Lab lightness of the color bar, the density only changes the lightness of the plot
+static BAR_LIGHTNESS: f32 = 70.0;
+
+
+pub struct Axis {
+    pub title: String,
+    /// position in `[0, 1]` (left to right, bottom to top) and label
+    pub ticks: Vec<(f64, String)>,
+}
+
+pub struct Figure {
+    /// size of the plot area in points
+    pub width: f64,
+    pub height: f64,
+    pub image: Image,
+    pub x: Axis,
+    pub y: Axis,
+    /// color bar
+    pub z: Axis,
+}
+
+
+#[derive(Clone, Copy, PartialEq)]
+enum Anchor {
+    Start,
+    Middle,
+    End,
+}
+
+/// Drawing primitives in page coordinates (points, origin top left).
+enum Element {
+    Image { x: f64, y: f64, w: f64, h: f64 },
+    Fill { x: f64, y: f64, w: f64, h: f64, color: [u8; 3] },
+    Frame { x: f64, y: f64, w: f64, h: f64 },
+    Line { x0: f64, y0: f64, x1: f64, y1: f64 },
+    /// `y` is the baseline, `vertical` text runs bottom to top
+    Text { x: f64, y: f64, anchor: Anchor, vertical: bool, text: String },
+}
+
+
+fn f_inv(t: f32) -> f32 {
+    let delta = 6.0 / 29.0;
+    if t > delta {
+        t * t * t
+    } else {
+        3.0 * delta * delta * (t - 4.0 / 29.0)
+    }
+}
+
+/// Color of points with Z at `z` in `[0, 1]`, with the same math as `shader.texture.fragment.glsl`.
+fn z_color(z: f64) -> [u8; 3] {
+    let a = 256.0 * (z as f32) - 128.0;
+    let tmp = (BAR_LIGHTNESS + 16.0) / 116.0;
+    let xyz = [
+        0.95047 * f_inv(tmp + a / 500.0),
+        1.0 * f_inv(tmp),
+        1.08883 * f_inv(tmp - a / 200.0),
+    ];
+    // columns of the GLSL mat3
+    let m = [
+        [ 3.2404542, -1.5371385, -0.4985314],
+        [-0.9692660,  1.8760108,  0.0415560],
+        [ 0.0556434, -0.2040259,  1.0572252],
+    ];
+    let mut rgb = [0u8; 3];
+    for i in 0..3 {
+        let linear = m[0][i] * xyz[0] + m[1][i] * xyz[1] + m[2][i] * xyz[2];
+        rgb[i] = (image::srgb_encode(linear) * 255.0).round() as u8;
+    }
+    rgb
+}
+
+
+impl Figure {
+    fn page_size(&self) -> (f64, f64) {
+        (MARGIN_LEFT + self.width + MARGIN_RIGHT, MARGIN_TOP + self.height + MARGIN_BOTTOM)
+    }
+
+    fn layout(&self) -> Vec<Element> {
+        let (page_width, page_height) = self.page_size();
+        let (left, top, w, h) = (MARGIN_LEFT, MARGIN_TOP, self.width, self.height);
+        let bottom = top + h;
+        let mut elements = vec![
+            Element::Image { x: left, y: top, w: w, h: h },
+            Element::Frame { x: left, y: top, w: w, h: h },
+        ];
+
+        for &(pos, ref label) in &self.x.ticks {
+            let x = left + pos * w;
+            elements.push(Element::Line { x0: x, y0: bottom, x1: x, y1: bottom + TICK_LENGTH });
+            elements.push(Element::Text { x: x, y: bottom + TICK_LENGTH + FONT_SIZE + 2.0, anchor: Anchor::Middle, vertical: false, text: label.clone() });
+        }
+        elements.push(Element::Text { x: left + w / 2.0, y: page_height - 12.0, anchor: Anchor::Middle, vertical: false, text: self.x.title.clone() });
+
+        for &(pos, ref label) in &self.y.ticks {
+            let y = top + (1.0 - pos) * h;
+            elements.push(Element::Line { x0: left - TICK_LENGTH, y0: y, x1: left, y1: y });
+            elements.push(Element::Text { x: left - TICK_LENGTH - 3.0, y: y + FONT_SIZE * 0.35, anchor: Anchor::End, vertical: false, text: label.clone() });
+        }
+        elements.push(Element::Text { x: 4.0 + FONT_SIZE, y: top + h / 2.0, anchor: Anchor::Middle, vertical: true, text: self.y.title.clone() });
+
+        let bar = left + w + BAR_GAP;
+        for i in 0..BAR_STEPS {
+            let step = h / (BAR_STEPS as f64);
+            let z = ((i as f64) + 0.5) / (BAR_STEPS as f64);
+            // slightly overlapping, so that no gaps show up between the steps
+            elements.push(Element::Fill { x: bar, y: bottom - ((i + 1) as f64) * step, w: BAR_WIDTH, h: step + 0.5, color: z_color(z) });
+        }
+        elements.push(Element::Frame { x: bar, y: top, w: BAR_WIDTH, h: h });
+        for &(pos, ref label) in &self.z.ticks {
+            let y = top + (1.0 - pos) * h;
+            elements.push(Element::Line { x0: bar + BAR_WIDTH, y0: y, x1: bar + BAR_WIDTH + TICK_LENGTH, y1: y });
+            elements.push(Element::Text { x: bar + BAR_WIDTH + TICK_LENGTH + 3.0, y: y + FONT_SIZE * 0.35, anchor: Anchor::Start, vertical: false, text: label.clone() });
+        }
+        elements.push(Element::Text { x: page_width - 6.0, y: top + h / 2.0, anchor: Anchor::Middle, vertical: true, text: self.z.title.clone() });
+
+        elements
+    }
+
+    pub fn to_svg(&self) -> String {
+        let (page_width, page_height) = self.page_size();
+        let png = image::encode_png(self.image.width, self.image.height, 8, &self.image.to_srgb8());
+
+        let mut svg = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" width=\"{}pt\" height=\"{}pt\" viewBox=\"0 0 {} {}\">\n<g font-family=\"Helvetica, Arial, sans-serif\" font-size=\"{}\" stroke-width=\"0.75\">\n",
+            page_width, page_height, page_width, page_height, FONT_SIZE
+        );
+        for element in self.layout() {
+            let line = match element {
+                Element::Image { x, y, w, h } => {
+                    format!("<image x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" preserveAspectRatio=\"none\" xlink:href=\"data:image/png;base64,{}\"/>", x, y, w, h, image::base64(&png))
+                },
+                Element::Fill { x, y, w, h, color } => {
+                    format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#{:02x}{:02x}{:02x}\"/>", x, y, w, h, color[0], color[1], color[2])
+                },
+                Element::Frame { x, y, w, h } => {
+                    format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"black\"/>", x, y, w, h)
+                },
+                Element::Line { x0, y0, x1, y1 } => {
+                    format!("<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"black\"/>", x0, y0, x1, y1)
+                },
+                Element::Text { x, y, anchor, vertical, text } => {
+                    let anchor = match anchor {
+                        Anchor::Start  => "start",
+                        Anchor::Middle => "middle",
+                        Anchor::End    => "end",
+                    };
+                    let rotate = if vertical { format!(" transform=\"rotate(-90 {} {})\"", x, y) } else { String::new() };
+                    format!("<text x=\"{}\" y=\"{}\" text-anchor=\"{}\"{}>{}</text>", x, y, anchor, rotate, xml_escape(&text))
+                },
+            };
+            svg.push_str(&line);
+            svg.push('\n');
+        }
+        svg.push_str("</g>\n</svg>\n");
+        svg
+    }
+
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let (page_width, page_height) = self.page_size();
+
+        // PDF coordinates start at the bottom left
+        let mut content = format!("{} w\n", 0.75);
+        for element in self.layout() {
+            let op = match element {
+                Element::Image { x, y, w, h } => format!("q {} 0 0 {} {} {} cm /Im1 Do Q", w, h, x, page_height - y - h),
+                Element::Fill { x, y, w, h, color } => {
+                    format!("{} {} {} rg {} {} {} {} re f", pdf_color(color[0]), pdf_color(color[1]), pdf_color(color[2]), x, page_height - y - h, w, h)
+                },
+                Element::Frame { x, y, w, h } => format!("{} {} {} {} re S", x, page_height - y - h, w, h),
+                Element::Line { x0, y0, x1, y1 } => format!("{} {} m {} {} l S", x0, page_height - y0, x1, page_height - y1),
+                Element::Text { x, y, anchor, vertical, text } => {
+                    let shift = match anchor {
+                        Anchor::Start  => 0.0,
+                        Anchor::Middle => text_width(&text) / 2.0,
+                        Anchor::End    => text_width(&text),
+                    };
+                    let matrix = if vertical {
+                        format!("0 1 -1 0 {} {}", x, page_height - y - shift)
+                    } else {
+                        format!("1 0 0 1 {} {}", x - shift, page_height - y)
+                    };
+                    format!("0 g BT /F1 {} Tf {} Tm ({}) Tj ET", FONT_SIZE, matrix, pdf_escape(&text))
+                },
+            };
+            content.push_str(&op);
+            content.push('\n');
+        }
+
+        let rgb = image::zlib(&self.image.to_srgb8());
+        let objects = vec![
+            String::from("<< /Type /Catalog /Pages 2 0 R >>").into_bytes(),
+            String::from("<< /Type /Pages /Kids [3 0 R] /Count 1 >>").into_bytes(),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 5 0 R >> /XObject << /Im1 6 0 R >> >> /Contents 4 0 R >>",
+                page_width, page_height
+            ).into_bytes(),
+            pdf_stream(String::new(), content.as_bytes()),
+            String::from("<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>").into_bytes(),
+            pdf_stream(
+                format!("/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode", self.image.width, self.image.height),
+                &rgb
+            ),
+        ];
+
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = vec![];
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            pdf.extend_from_slice(object);
+            pdf.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = pdf.len();
+        pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend_from_slice(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).as_bytes());
+        pdf
+    }
+}
+
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Only ASCII is supported by the standard font encoding, everything else becomes `?`.
+fn pdf_escape(s: &str) -> String {
+    s.chars().map(|c| {
+        match c {
+            '\\' | '(' | ')' => format!("\\{}", c),
+            c if c >= ' ' && c <= '~' => c.to_string(),
+            _ => String::from("?"),
+        }
+    }).collect()
+}
+
+/// Approximate width of Helvetica text, good enough to align labels.
+fn text_width(s: &str) -> f64 {
+    (s.chars().count() as f64) * FONT_SIZE * 0.55
+}
+
+fn pdf_color(c: u8) -> String {
+    format!("{:.3}", (c as f64) / 255.0)
+}
+
+fn pdf_stream(dict: String, data: &[u8]) -> Vec<u8> {
+    let mut stream = format!("<< {} /Length {} >>\nstream\n", dict, data.len()).into_bytes();
+    stream.extend_from_slice(data);
+    stream.extend_from_slice(b"\nendstream");
+    stream
+}
+
+
+/// Writes `figure` as SVG or PDF, depending on the extension of `fname`.
+pub fn save(figure: &Figure, fname: &str) -> Result<(), String> {
+    let lower = fname.to_lowercase();
+    if lower.ends_with(".svg") {
+        image::write_file(fname, figure.to_svg().as_bytes())
+    } else if lower.ends_with(".pdf") {
+        image::write_file(fname, &figure.to_pdf())
+    } else {
+        Err(format!("cannot export to {}, use .svg or .pdf", fname))
+    }
+}
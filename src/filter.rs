@@ -0,0 +1,246 @@
+use data::Column;
+
+use datetime::TimeFormat;
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+// the first entry of every operator is its canonical spelling
+static OPS: &'static [(&'static str, Op)] = &[
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("==", Op::Eq),
+    ("!=", Op::Ne),
+    ("<",  Op::Lt),
+    (">",  Op::Gt),
+    ("=",  Op::Eq),
+];
+
+impl Op {
+    fn symbol(&self) -> &'static str {
+        OPS.iter().find(|&&(_, op)| op == *self).unwrap().0
+    }
+
+    fn flip(&self) -> Op {
+        match *self {
+            Op::Lt => Op::Gt,
+            Op::Le => Op::Ge,
+            Op::Gt => Op::Lt,
+            Op::Ge => Op::Le,
+            Op::Eq => Op::Eq,
+            Op::Ne => Op::Ne,
+        }
+    }
+
+    fn eval(&self, a: f64, b: f64) -> bool {
+        match *self {
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+        }
+    }
+}
+
+
+/// `column <op> value`. NA values only pass `!=`.
+#[derive(Clone, Debug)]
+pub struct Predicate {
+    pub column: String,
+    pub op: Op,
+    pub value: f64,
+}
+
+impl Predicate {
+    pub fn to_string(&self) -> String {
+        format!("{}{}{}", self.column, self.op.symbol(), self.value)
+    }
+}
+
+
+/// Splits `s` at the first comparison operator.
+fn split_op(s: &str) -> Option<(&str, Op, &str)> {
+    let mut best: Option<(usize, &str, Op)> = None;
+    for &(symbol, op) in OPS {
+        if let Some(pos) = s.find(symbol) {
+            let better = match best {
+                Some((best_pos, best_symbol, _)) => pos < best_pos || (pos == best_pos && symbol.len() > best_symbol.len()),
+                None => true,
+            };
+            if better {
+                best = Some((pos, symbol, op));
+            }
+        }
+    }
+    best.map(|(pos, symbol, op)| (&s[..pos], op, &s[pos + symbol.len()..]))
+}
+
+/// A number, or an ISO-8601 date for date/time columns.
+fn parse_value(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(v) => Ok(v),
+        Err(_) => {
+            match TimeFormat::Iso.parse(s) {
+                Some(t) => Ok(t),
+                None    => Err(format!("'{}' is neither a number nor a date", s.trim())),
+            }
+        }
+    }
+}
+
+
+/// Conjunction of predicates, i.e. a row is shown if it passes all of them.
+#[derive(Clone)]
+pub struct Filter {
+    pub predicates: Vec<Predicate>,
+}
+
+impl Filter {
+    pub fn new() -> Filter {
+        Filter {
+            predicates: vec![],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.predicates.is_empty()
+    }
+
+    /// Adds `col>300`, `300<col`, or ranges like `10<=col<20` (as two predicates).
+    pub fn add(&mut self, s: &str) -> Result<(), String> {
+        let (lhs, op1, rest) = match split_op(s) {
+            Some(t) => t,
+            None => {
+                return Err(format!("filter '{}' has no comparison operator", s));
+            }
+        };
+
+        // (column, op, value) as written by the user
+        let mut parts = vec![];
+        match split_op(rest) {
+            Some((column, op2, rhs)) => {
+                // lo <op1> column <op2> hi
+                parts.push((column, op1.flip(), lhs));
+                parts.push((column, op2, rhs));
+            },
+            None => {
+                if parse_value(lhs).is_ok() {
+                    // value <op> column
+                    parts.push((rest, op1.flip(), lhs));
+                } else {
+                    // column <op> value
+                    parts.push((lhs, op1, rest));
+                }
+            },
+        }
+
+        let mut new = vec![];
+        for (column, op, value) in parts {
+            let column = column.trim();
+            if column.is_empty() {
+                return Err(format!("filter '{}' has no column", s));
+            }
+            match parse_value(value) {
+                Ok(v) => new.push(Predicate { column: String::from(column), op: op, value: v }),
+                Err(e) => {
+                    return Err(format!("filter '{}': {}", s, e));
+                }
+            }
+        }
+
+        self.predicates.extend(new);
+        Ok(())
+    }
+
+    /// Evaluates the filter for every row, fails if a predicate refers to an unknown column.
+    pub fn mask(&self, columns: &[Column]) -> Result<Vec<bool>, String> {
+        let n = columns[0].data.len();
+        let mut mask = vec![true; n];
+
+        for p in &self.predicates {
+            let column = match columns.iter().find(|c| c.name == p.column) {
+                Some(c) => c,
+                None => {
+                    return Err(format!("filter refers to unknown column '{}'", p.column));
+                }
+            };
+            for (m, v) in mask.iter_mut().zip(column.data.iter()) {
+                *m = *m && p.op.eval(*v, p.value);
+            }
+        }
+
+        Ok(mask)
+    }
+
+    pub fn to_string(&self) -> String {
+        self.predicates.iter().map(|p| p.to_string()).collect::<Vec<String>>().join(", ")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f64;
+
+    fn columns() -> Vec<Column> {
+        vec![
+            Column::from_data("a", vec![1.0, 2.0, f64::NAN, 4.0]),
+            Column::from_data("b", vec![10.0, f64::NAN, 30.0, 40.0]),
+        ]
+    }
+
+    fn mask(filters: &[&str]) -> Vec<bool> {
+        let mut filter = Filter::new();
+        for s in filters {
+            filter.add(s).unwrap();
+        }
+        filter.mask(&columns()).unwrap()
+    }
+
+    #[test]
+    fn parses_both_directions_and_ranges() {
+        let mut filter = Filter::new();
+        filter.add("a > 1").unwrap();
+        filter.add("3 >= b").unwrap();
+        filter.add("1 <= a < 4").unwrap();
+        assert_eq!(filter.to_string(), "a>1, b<=3, a>=1, a<4");
+        for s in &["a", "> 1", "a > x", "a >"] {
+            assert!(Filter::new().add(s).is_err(), "{:?} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn combines_predicates_into_a_mask() {
+        assert_eq!(mask(&[]), vec![true, true, true, true]);
+        assert_eq!(mask(&["a >= 2"]), vec![false, true, false, true]);
+        assert_eq!(mask(&["a >= 2", "b > 35"]), vec![false, false, false, true]);
+        assert_eq!(mask(&["2 <= a <= 3"]), vec![false, true, false, false]);
+    }
+
+    #[test]
+    fn passes_na_only_for_not_equal() {
+        assert_eq!(mask(&["a < 100"]), vec![true, true, false, true]);
+        assert_eq!(mask(&["a == 2"]), vec![false, true, false, false]);
+        assert_eq!(mask(&["a != 2"]), vec![true, false, true, true]);
+        assert_eq!(mask(&["b != 10"]), vec![false, true, true, true]);
+    }
+
+    #[test]
+    fn rejects_unknown_columns() {
+        let mut filter = Filter::new();
+        filter.add("c > 1").unwrap();
+        assert!(filter.mask(&columns()).is_err());
+    }
+}
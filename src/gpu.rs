@@ -0,0 +1,110 @@
+extern crate glium;
+
+use cfg;
+
+use data::Column;
+
+use glium::backend::Facade;
+use glium::texture::buffer_texture::{BufferTexture, BufferTextureType};
+
+use transform::Transform;
+
+use std::collections::HashMap;
+
+
+/// Splits `v` into a `f32` and the `f32` rounding error, together about twice as precise.
+fn split(v: f64) -> [f32; 2] {
+    let hi = v as f32;
+    [hi, (v - (hi as f64)) as f32]
+}
+
+
+/// A column as buffer texture of `(hi, lo)` pairs relative to a reference value, read by the vertex
+/// shader via `gl_VertexID`.
+pub struct GpuColumn {
+    pub texture: BufferTexture<(f32, f32)>,
+    reference: f64,
+    bytes: usize,
+}
+
+impl GpuColumn {
+    fn new<F>(facade: &F, column: &Column) -> GpuColumn where F: Facade {
+        let reference = if column.min.is_finite() && column.max.is_finite() {
+            (column.min + column.max) / 2.0
+        } else {
+            0.0
+        };
+        let data = column.data.iter().map(|v| {
+            let s = split(v - reference);
+            (s[0], s[1])
+        }).collect::<Vec<(f32, f32)>>();
+
+        GpuColumn {
+            texture: BufferTexture::new(facade, &data, BufferTextureType::Float).unwrap(),
+            reference: reference,
+            bytes: data.len() * 8,
+        }
+    }
+
+    /// `origin - reference`, split like the values, for the `offset_*` uniforms.
+    pub fn offset(&self, origin: f64) -> [f32; 2] {
+        split(origin - self.reference)
+    }
+}
+
+
+/// Columns (and their transforms) that were uploaded to the GPU. They stay there until those shown
+/// least recently have to make room under `cfg::GPU_COLUMNS_BYTES`.
+pub struct GpuColumns {
+    entries: HashMap<(usize, Transform), GpuColumn>,
+    /// keys from the least to the most recently shown
+    order: Vec<(usize, Transform)>,
+    bytes: usize,
+}
+
+impl GpuColumns {
+    pub fn new() -> GpuColumns {
+        GpuColumns {
+            entries: HashMap::new(),
+            order: vec![],
+            bytes: 0,
+        }
+    }
+
+    /// Uploads the columns shown together, each column `idx` with transform `t` applied, unless that
+    /// happened before. Frees the other columns shown least recently while all columns take more
+    /// than `cfg::GPU_COLUMNS_BYTES`.
+    pub fn prepare<F>(&mut self, facade: &F, shown: &[(usize, Transform, &Column)]) where F: Facade {
+        let keys = shown.iter().map(|&(idx, t, _)| (idx, t)).collect::<Vec<(usize, Transform)>>();
+        for &(idx, t, column) in shown {
+            self.order.retain(|key| *key != (idx, t));
+            self.order.push((idx, t));
+            if self.entries.contains_key(&(idx, t)) {
+                continue;
+            }
+
+            debug!("upload column {} ({}) to the GPU", column.name, t.name());
+            let entry = GpuColumn::new(facade, column);
+            self.bytes += entry.bytes;
+            self.entries.insert((idx, t), entry);
+        }
+
+        while self.bytes > cfg::GPU_COLUMNS_BYTES {
+            let key = match self.order.iter().position(|key| !keys.contains(key)) {
+                Some(pos) => self.order.remove(pos),
+                None => {
+                    break;
+                }
+            };
+            debug!("free column {} ({}) on the GPU", key.0, key.1.name());
+            if let Some(entry) = self.entries.remove(&key) {
+                self.bytes -= entry.bytes;
+            }
+        }
+    }
+
+    /// Needs a previous `prepare` for the same column and transform.
+    pub fn get(&self, idx: usize, t: Transform) -> &GpuColumn {
+        self.entries.get(&(idx, t)).expect("column was not uploaded")
+    }
+}
@@ -0,0 +1,121 @@
+//! Export of the accumulation buffer as data, for analysis outside of the viewer.
+
+extern crate csv;
+
+use image;
+use image::Image;
+
+
+/// The accumulation buffer of the current view together with the bin edges of its pixels.
+pub struct Grid {
+    /// luminance counts, Lab a and b sums and point counts, normalised by the number of rows
+    pub cells: Image,
+    /// `cells.width + 1` edges from left to right, in the units of the X column
+    pub x_edges: Vec<f64>,
+    /// `cells.height + 1` edges from bottom to top, in the units of the Y column
+    pub y_edges: Vec<f64>,
+}
+
+impl Grid {
+    /// Rows from bottom to top, i.e. in the order of `y_edges`.
+    fn rows_bottom_up(&self) -> Vec<&[[f32; 4]]> {
+        self.cells.pixels.chunks(self.cells.width as usize).rev().collect()
+    }
+}
+
+
+fn npy(descr: &str, shape: &[usize], data: &[u8]) -> Vec<u8> {
+    let shape = shape.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    let shape = if shape.len() == 1 { format!("{},", shape[0]) } else { shape.join(", ") };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': ({}), }}", descr, shape);
+    // magic, version and header length take 10 bytes, the data has to start 64 byte aligned
+    while (10 + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+
+    let mut out = b"\x93NUMPY\x01\x00".to_vec();
+    out.push(header.len() as u8);
+    out.push((header.len() >> 8) as u8);
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+fn le_f32(values: &[f32]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(values.len() * 4);
+    for v in values {
+        image::push_le32(&mut data, v.to_bits());
+    }
+    data
+}
+
+fn le_f64(values: &[f64]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(values.len() * 8);
+    for v in values {
+        image::push_le64(&mut data, v.to_bits());
+    }
+    data
+}
+
+/// `<stem>.npy` with shape `(height, width, 4)`, plus `<stem>.x_edges.npy` and `<stem>.y_edges.npy`.
+fn save_npy(grid: &Grid, fname: &str) -> Result<(), String> {
+    let stem = &fname[..fname.len() - 4];
+    let values = grid.rows_bottom_up().into_iter().flat_map(|row| row.iter()).flat_map(|p| p.iter().cloned()).collect::<Vec<f32>>();
+    let cells = npy("<f4", &[grid.cells.height as usize, grid.cells.width as usize, 4], &le_f32(&values));
+
+    let files = vec![
+        (String::from(fname), cells),
+        (format!("{}.x_edges.npy", stem), npy("<f8", &[grid.x_edges.len()], &le_f64(&grid.x_edges))),
+        (format!("{}.y_edges.npy", stem), npy("<f8", &[grid.y_edges.len()], &le_f64(&grid.y_edges))),
+    ];
+    for (name, data) in files {
+        if let Err(s) = image::write_file(&name, &data) {
+            return Err(s);
+        }
+    }
+    Ok(())
+}
+
+/// One row per cell, from the bottom left to the top right.
+fn save_csv(grid: &Grid, fname: &str) -> Result<(), String> {
+    let mut wtr = match csv::Writer::from_file(fname) {
+        Ok(w) => w,
+        Err(_) => {
+            return Err(format!("cannot write file {}!", fname));
+        }
+    };
+
+    let header = ["x_min", "x_max", "y_min", "y_max", "l_sum", "a_sum", "b_sum", "count"];
+    if wtr.write(header.iter()).is_err() {
+        return Err(format!("cannot write file {}!", fname));
+    }
+    for (i, row) in grid.rows_bottom_up().into_iter().enumerate() {
+        for (j, p) in row.iter().enumerate() {
+            let record = vec![
+                grid.x_edges[j].to_string(), grid.x_edges[j + 1].to_string(),
+                grid.y_edges[i].to_string(), grid.y_edges[i + 1].to_string(),
+                p[0].to_string(), p[1].to_string(), p[2].to_string(), p[3].to_string(),
+            ];
+            if wtr.write(record.iter()).is_err() {
+                return Err(format!("cannot write file {}!", fname));
+            }
+        }
+    }
+    match wtr.flush() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("cannot write file {}!", fname)),
+    }
+}
+
+/// Writes `grid` as NumPy arrays or CSV, depending on the extension of `fname`.
+pub fn save(grid: &Grid, fname: &str) -> Result<(), String> {
+    let lower = fname.to_lowercase();
+    if lower.ends_with(".npy") {
+        save_npy(grid, fname)
+    } else if lower.ends_with(".csv") {
+        save_csv(grid, fname)
+    } else {
+        Err(format!("cannot export the density grid to {}, use .npy or .csv", fname))
+    }
+}
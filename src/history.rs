@@ -0,0 +1,203 @@
+use cfg;
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+
+/// Continuous interactions whose intermediate states should end up as a single history entry.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Gesture {
+    Pan,
+    Zoom,
+}
+
+
+/// Linear undo/redo history. The entry at `position` is always the current state, recording a new
+/// state drops everything that could have been redone.
+pub struct History<T> {
+    entries: VecDeque<T>,
+    position: usize,
+    depth: usize,
+    last_gesture: Option<Gesture>,
+    last_record: Instant,
+}
+
+impl<T> History<T> where T: Clone {
+    pub fn new(initial: T, depth: usize) -> History<T> {
+        let mut entries = VecDeque::new();
+        entries.push_back(initial);
+
+        History {
+            entries: entries,
+            position: 0,
+            depth: depth,
+            last_gesture: None,
+            last_record: Instant::now(),
+        }
+    }
+
+    /// Forgets all entries, e.g. after loading another file.
+    pub fn reset(&mut self, initial: T) {
+        self.entries.clear();
+        self.entries.push_back(initial);
+        self.position = 0;
+        self.last_gesture = None;
+    }
+
+    pub fn current(&self) -> &T {
+        &self.entries[self.position]
+    }
+
+    /// Records `state` as the new current state. Consecutive records of the same gesture within
+    /// `HISTORY_COALESCE_MILLIS` replace each other.
+    pub fn record(&mut self, state: T, gesture: Option<Gesture>) {
+        let now = Instant::now();
+        let coalesce = gesture.is_some()
+            && gesture == self.last_gesture
+            && now.duration_since(self.last_record) < Duration::from_millis(cfg::HISTORY_COALESCE_MILLIS);
+
+        self.entries.truncate(self.position + 1);
+        if coalesce {
+            self.entries[self.position] = state;
+        } else {
+            self.entries.push_back(state);
+            self.position += 1;
+            while self.entries.len() > self.depth + 1 {
+                self.entries.pop_front();
+                self.position -= 1;
+            }
+        }
+
+        self.last_gesture = gesture;
+        self.last_record = now;
+    }
+
+    /// Ends the current gesture, so the next record always creates a new entry.
+    pub fn seal(&mut self) {
+        self.last_gesture = None;
+    }
+
+    pub fn undo(&mut self) -> Option<T> {
+        self.seal();
+        if self.position > 0 {
+            self.position -= 1;
+            Some(self.current().clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn redo(&mut self) -> Option<T> {
+        self.seal();
+        if self.position + 1 < self.entries.len() {
+            self.position += 1;
+            Some(self.current().clone())
+        } else {
+            None
+        }
+    }
+
+    /// Like `undo`, but skips all entries that `same` considers equal to the current one.
+    pub fn back<F>(&mut self, same: F) -> Option<T> where F: Fn(&T, &T) -> bool {
+        self.seal();
+        let mut pos = self.position;
+        while pos > 0 {
+            pos -= 1;
+            if !same(&self.entries[pos], &self.entries[self.position]) {
+                self.position = pos;
+                return Some(self.current().clone());
+            }
+        }
+        None
+    }
+
+    /// Like `redo`, but skips all entries that `same` considers equal to the current one.
+    pub fn forward<F>(&mut self, same: F) -> Option<T> where F: Fn(&T, &T) -> bool {
+        self.seal();
+        let mut pos = self.position;
+        while pos + 1 < self.entries.len() {
+            pos += 1;
+            if !same(&self.entries[pos], &self.entries[self.position]) {
+                self.position = pos;
+                return Some(self.current().clone());
+            }
+        }
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coalesces_gestures() {
+        let mut history = History::new(0, 10);
+        history.record(1, Some(Gesture::Pan));
+        history.record(2, Some(Gesture::Pan));
+        history.record(3, Some(Gesture::Zoom));
+        history.record(4, None);
+        history.record(5, None);
+        assert_eq!(history.undo(), Some(4));
+        assert_eq!(history.undo(), Some(3));
+        assert_eq!(history.undo(), Some(2));
+        assert_eq!(history.undo(), Some(0));
+        assert_eq!(history.undo(), None);
+    }
+
+    #[test]
+    fn seals_gestures() {
+        let mut history = History::new(0, 10);
+        history.record(1, Some(Gesture::Zoom));
+        history.seal();
+        history.record(2, Some(Gesture::Zoom));
+        assert_eq!(history.undo(), Some(1));
+        // undo also ends the gesture, the next record does not replace the state it went back to
+        history.record(3, Some(Gesture::Zoom));
+        assert_eq!(history.undo(), Some(1));
+        assert_eq!(history.redo(), Some(3));
+        assert_eq!(history.redo(), None);
+    }
+
+    #[test]
+    fn evicts_beyond_the_depth() {
+        let mut history = History::new(0, 3);
+        for i in 1..6 {
+            history.record(i, None);
+        }
+        assert_eq!(*history.current(), 5);
+        assert_eq!(history.undo(), Some(4));
+        assert_eq!(history.undo(), Some(3));
+        assert_eq!(history.undo(), Some(2));
+        assert_eq!(history.undo(), None);
+        assert_eq!(*history.current(), 2);
+    }
+
+    #[test]
+    fn drops_the_redo_entries_when_recording() {
+        let mut history = History::new(0, 10);
+        history.record(1, None);
+        history.record(2, None);
+        history.undo();
+        history.undo();
+        history.record(3, None);
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.undo(), Some(0));
+        assert_eq!(history.redo(), Some(3));
+    }
+
+    #[test]
+    fn skips_equal_entries_going_back_and_forward() {
+        let mut history = History::new(10, 10);
+        for &i in &[11, 20, 21, 22, 30] {
+            history.record(i, None);
+        }
+        let same = |a: &i32, b: &i32| a / 10 == b / 10;
+        assert_eq!(history.back(&same), Some(22));
+        assert_eq!(history.back(&same), Some(11));
+        assert_eq!(history.back(&same), None);
+        assert_eq!(history.forward(&same), Some(20));
+        assert_eq!(history.forward(&same), Some(30));
+    }
+}
@@ -0,0 +1,296 @@
+extern crate deflate;
+
+use std::fs::File;
+use std::io::Write;
+
+
+/// Rendered RGBA pixels, either tone mapped (linear RGB) or the raw accumulation buffer, rows from
+/// top to bottom.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Image {
+        Image {
+            width: width,
+            height: height,
+            pixels: vec![[0.0; 4]; (width as usize) * (height as usize)],
+        }
+    }
+
+    /// Copies a tile as read from OpenGL (rows from bottom to top) to `left`, `bottom` (from the
+    /// bottom left corner), without `margin` pixels on each side of the tile.
+    pub fn paste(&mut self, rows: &[Vec<(f32, f32, f32, f32)>], left: u32, bottom: u32, margin: u32) {
+        let tile_height = rows.len() as u32 - 2 * margin;
+        for r in 0..tile_height {
+            let src = &rows[(r + margin) as usize];
+            let y = self.height - 1 - (bottom + r);
+            let start = (y as usize) * (self.width as usize) + (left as usize);
+            let tile_width = src.len() as u32 - 2 * margin;
+            for c in 0..tile_width {
+                let (red, green, blue, alpha) = src[(c + margin) as usize];
+                self.pixels[start + (c as usize)] = [red, green, blue, alpha];
+            }
+        }
+    }
+
+    /// 8 bit sRGB, 3 bytes per pixel.
+    pub fn to_srgb8(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.pixels.len() * 3);
+        for p in &self.pixels {
+            for &c in &p[..3] {
+                data.push((srgb_encode(c) * 255.0).round() as u8);
+            }
+        }
+        data
+    }
+
+    /// 16 bit sRGB, 6 bytes per pixel in big-endian order.
+    pub fn to_srgb16(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.pixels.len() * 6);
+        for p in &self.pixels {
+            for &c in &p[..3] {
+                let v = (srgb_encode(c) * 65535.0).round() as u16;
+                data.push((v >> 8) as u8);
+                data.push(v as u8);
+            }
+        }
+        data
+    }
+}
+
+
+/// Linear to sRGB transfer function, clamped to `[0, 1]`.
+pub fn srgb_encode(c: f32) -> f32 {
+    let c = if c > 0.0 { f32::min(c, 1.0) } else { 0.0 };
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+
+fn crc32(data: &[&[u8]]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for part in data {
+        for &byte in *part {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+            }
+        }
+    }
+    !crc
+}
+
+fn push_be32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&[(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]);
+}
+
+fn push_chunk(buf: &mut Vec<u8>, name: &[u8], data: &[u8]) {
+    push_be32(buf, data.len() as u32);
+    buf.extend_from_slice(name);
+    buf.extend_from_slice(data);
+    push_be32(buf, crc32(&[name, data]));
+}
+
+/// zlib-compressed `data`, as used by PNG and PDF.
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    deflate::deflate_bytes_zlib(data)
+}
+
+/// PNG file of RGB pixels with 8 or 16 bits per channel, rows from top to bottom.
+pub fn encode_png(width: u32, height: u32, bit_depth: u8, rgb: &[u8]) -> Vec<u8> {
+    let stride = (width as usize) * 3 * (bit_depth as usize / 8);
+    let mut filtered = Vec::with_capacity((stride + 1) * (height as usize));
+    for row in rgb.chunks(stride) {
+        // filter type 0 (none) per row
+        filtered.push(0);
+        filtered.extend_from_slice(row);
+    }
+
+    let mut header = vec![];
+    push_be32(&mut header, width);
+    push_be32(&mut header, height);
+    // color type 2 (RGB), default compression, filtering and no interlacing
+    header.extend_from_slice(&[bit_depth, 2, 0, 0, 0]);
+
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+    push_chunk(&mut png, b"IHDR", &header);
+    push_chunk(&mut png, b"IDAT", &zlib(&filtered));
+    push_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+
+pub fn push_le16(buf: &mut Vec<u8>, v: u16) {
+    buf.extend_from_slice(&[v as u8, (v >> 8) as u8]);
+}
+
+pub fn push_le32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+}
+
+pub fn push_le64(buf: &mut Vec<u8>, v: u64) {
+    push_le32(buf, v as u32);
+    push_le32(buf, (v >> 32) as u32);
+}
+
+/// Standard base64 with padding.
+pub fn base64(data: &[u8]) -> String {
+    let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let v = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | (b[2] as u32);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(alphabet[((v >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn push_attribute(buf: &mut Vec<u8>, name: &str, ty: &str, value: &[u8]) {
+    buf.extend_from_slice(name.as_bytes());
+    buf.push(0);
+    buf.extend_from_slice(ty.as_bytes());
+    buf.push(0);
+    push_le32(buf, value.len() as u32);
+    buf.extend_from_slice(value);
+}
+
+/// Uncompressed single-part scanline OpenEXR file with the RGBA channels as 32 bit floats.
+pub fn encode_exr(image: &Image) -> Vec<u8> {
+    // channels have to be sorted by name
+    let channels = [("A", 3), ("B", 2), ("G", 1), ("R", 0)];
+
+    let mut chlist = vec![];
+    for &(name, _) in &channels {
+        chlist.extend_from_slice(name.as_bytes());
+        chlist.push(0);
+        // FLOAT, not linear, 3 reserved bytes, no subsampling
+        push_le32(&mut chlist, 2);
+        chlist.extend_from_slice(&[0, 0, 0, 0]);
+        push_le32(&mut chlist, 1);
+        push_le32(&mut chlist, 1);
+    }
+    chlist.push(0);
+
+    let mut window = vec![];
+    for &v in &[0, 0, image.width - 1, image.height - 1] {
+        push_le32(&mut window, v);
+    }
+
+    let mut exr = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    push_attribute(&mut exr, "channels", "chlist", &chlist);
+    push_attribute(&mut exr, "compression", "compression", &[0]);
+    push_attribute(&mut exr, "dataWindow", "box2i", &window);
+    push_attribute(&mut exr, "displayWindow", "box2i", &window);
+    push_attribute(&mut exr, "lineOrder", "lineOrder", &[0]);
+    push_attribute(&mut exr, "pixelAspectRatio", "float", &[0, 0, 0x80, 0x3f]);
+    push_attribute(&mut exr, "screenWindowCenter", "v2f", &[0; 8]);
+    push_attribute(&mut exr, "screenWindowWidth", "float", &[0, 0, 0x80, 0x3f]);
+    exr.push(0);
+
+    // offset table, one scanline per block
+    let width = image.width as usize;
+    let block_size = 8 + width * channels.len() * 4;
+    let first_block = exr.len() + 8 * (image.height as usize);
+    for y in 0..(image.height as usize) {
+        let offset = (first_block + y * block_size) as u64;
+        push_le32(&mut exr, offset as u32);
+        push_le32(&mut exr, (offset >> 32) as u32);
+    }
+
+    for (y, row) in image.pixels.chunks(width).enumerate() {
+        push_le32(&mut exr, y as u32);
+        push_le32(&mut exr, (block_size - 8) as u32);
+        for &(_, c) in &channels {
+            for p in row {
+                push_le32(&mut exr, p[c].to_bits());
+            }
+        }
+    }
+    exr
+}
+
+/// Uncompressed little-endian TIFF file with the RGBA channels as 32 bit floats.
+pub fn encode_tiff(image: &Image) -> Result<Vec<u8>, String> {
+    let data_size = (image.pixels.len() as u64) * 16;
+    if data_size > 0xffffffff - 1024 {
+        return Err(String::from("image is too large for a TIFF file"));
+    }
+
+    // tag, type (3: SHORT, 4: LONG), count, value or offset; sorted by tag
+    let entries: Vec<(u16, u16, u32, u32)> = vec![
+        (256, 4, 1, image.width),       // ImageWidth
+        (257, 4, 1, image.height),      // ImageLength
+        (258, 3, 4, 0),                 // BitsPerSample, offset filled in below
+        (259, 3, 1, 1),                 // Compression: none
+        (262, 3, 1, 2),                 // PhotometricInterpretation: RGB
+        (273, 4, 1, 0),                 // StripOffsets, filled in below
+        (277, 3, 1, 4),                 // SamplesPerPixel
+        (278, 4, 1, image.height),      // RowsPerStrip
+        (279, 4, 1, data_size as u32),  // StripByteCounts
+        (284, 3, 1, 1),                 // PlanarConfiguration: chunky
+        (338, 3, 1, 0),                 // ExtraSamples: unspecified
+        (339, 3, 4, 0),                 // SampleFormat, offset filled in below
+    ];
+    let bits_offset = 8 + 2 + 12 * (entries.len() as u32) + 4;
+    let format_offset = bits_offset + 8;
+    let data_offset = format_offset + 8;
+
+    let mut tiff = vec![b'I', b'I', 42, 0];
+    push_le32(&mut tiff, 8);
+    push_le16(&mut tiff, entries.len() as u16);
+    for &(tag, ty, count, value) in &entries {
+        let value = match tag {
+            258 => bits_offset,
+            273 => data_offset,
+            339 => format_offset,
+            _   => value,
+        };
+        push_le16(&mut tiff, tag);
+        push_le16(&mut tiff, ty);
+        push_le32(&mut tiff, count);
+        push_le32(&mut tiff, value);
+    }
+    push_le32(&mut tiff, 0);
+    for _ in 0..4 {
+        push_le16(&mut tiff, 32);
+    }
+    for _ in 0..4 {
+        // IEEE floating point
+        push_le16(&mut tiff, 3);
+    }
+
+    tiff.reserve(data_size as usize);
+    for p in &image.pixels {
+        for &c in p {
+            push_le32(&mut tiff, c.to_bits());
+        }
+    }
+    Ok(tiff)
+}
+
+
+pub fn write_file(fname: &str, data: &[u8]) -> Result<(), String> {
+    let mut file = match File::create(fname) {
+        Ok(f) => f,
+        Err(_) => {
+            return Err(format!("cannot write file {}!", fname));
+        }
+    };
+    match file.write_all(data) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("cannot write file {}!", fname)),
+    }
+}
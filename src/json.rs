@@ -0,0 +1,422 @@
+//! Minimal JSON values, as needed for the remote control protocol.
+
+use std::char;
+
+
+/// Deepest nesting of arrays and objects accepted, well within the stack of any thread.
+static DEPTH_MAX: usize = 128;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// members in the order they were written
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(s: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            s: s.as_bytes(),
+            pos: 0,
+            depth: 0,
+        };
+        let value = match parser.value() {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(format!("invalid JSON at byte {}: {}", parser.pos, e));
+            }
+        };
+        parser.whitespace();
+        if parser.pos < parser.s.len() {
+            return Err(format!("invalid JSON at byte {}: trailing characters", parser.pos));
+        }
+        Ok(value)
+    }
+
+    /// Object with the given members.
+    pub fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(members.into_iter().map(|(k, v)| (String::from(k), v)).collect())
+    }
+
+    pub fn string(s: &str) -> Json {
+        Json::String(String::from(s))
+    }
+
+    /// Member `key` of an object, `None` for other values.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref members) => members.iter().find(|&&(ref k, _)| k == key).map(|&(_, ref v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match *self {
+            Json::Array(ref values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Compact, single line representation. Non-finite numbers become `null`.
+    pub fn to_string(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out);
+        out
+    }
+
+    fn write(&self, out: &mut String) {
+        match *self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(if b { "true" } else { "false" }),
+            Json::Number(v) if v.is_finite() => out.push_str(&format!("{}", v)),
+            Json::Number(_) => out.push_str("null"),
+            Json::String(ref s) => write_string(s, out),
+            Json::Array(ref values) => {
+                out.push('[');
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    v.write(out);
+                }
+                out.push(']');
+            },
+            Json::Object(ref members) => {
+                out.push('{');
+                for (i, &(ref k, ref v)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_string(k, out);
+                    out.push(':');
+                    v.write(out);
+                }
+                out.push('}');
+            },
+        }
+    }
+}
+
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"'  => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+    /// arrays and objects around the current position
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn whitespace(&mut self) {
+        while self.pos < self.s.len() && (self.s[self.pos] == b' ' || self.s[self.pos] == b'\t' || self.s[self.pos] == b'\n' || self.s[self.pos] == b'\r') {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        if self.s[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(())
+        } else {
+            Err(format!("expected {}", word))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.whitespace();
+        match self.peek() {
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') | Some(b'{') if self.depth == DEPTH_MAX => Err(String::from("nested too deeply")),
+            Some(b'[') => {
+                self.depth += 1;
+                let array = self.array();
+                self.depth -= 1;
+                array
+            },
+            Some(b'{') => {
+                self.depth += 1;
+                let object = self.object();
+                self.depth -= 1;
+                object
+            },
+            Some(c) if c == b'-' || (c >= b'0' && c <= b'9') => self.number(),
+            Some(_) => Err(String::from("unexpected character")),
+            None => Err(String::from("unexpected end")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if (c >= b'0' && c <= b'9') || c == b'-' || c == b'+' || c == b'.' || c == b'e' || c == b'E' {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        // the slice only contains ASCII characters
+        match String::from_utf8_lossy(&self.s[start..self.pos]).parse::<f64>() {
+            Ok(v) => Ok(Json::Number(v)),
+            Err(_) => {
+                self.pos = start;
+                Err(String::from("invalid number"))
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        if self.pos + 4 > self.s.len() {
+            return Err(String::from("unexpected end"));
+        }
+        let digits = String::from_utf8_lossy(&self.s[self.pos..self.pos + 4]).into_owned();
+        match u32::from_str_radix(&digits, 16) {
+            Ok(v) => {
+                self.pos += 4;
+                Ok(v)
+            },
+            Err(_) => Err(String::from("invalid unicode escape")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        // opening quote
+        self.pos += 1;
+        let mut bytes = vec![];
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => {
+                    return Err(String::from("unterminated string"));
+                }
+            };
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.peek() {
+                        Some(e) => e,
+                        None => {
+                            return Err(String::from("unterminated string"));
+                        }
+                    };
+                    self.pos += 1;
+                    let decoded = match escaped {
+                        b'"'  => '"',
+                        b'\\' => '\\',
+                        b'/'  => '/',
+                        b'b'  => '\u{8}',
+                        b'f'  => '\u{c}',
+                        b'n'  => '\n',
+                        b'r'  => '\r',
+                        b't'  => '\t',
+                        b'u'  => {
+                            let mut code = match self.hex4() {
+                                Ok(v) => v,
+                                Err(e) => {
+                                    return Err(e);
+                                }
+                            };
+                            // surrogate pair
+                            if code >= 0xd800 && code < 0xdc00 && self.s[self.pos..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                match self.hex4() {
+                                    Ok(low) if low >= 0xdc00 && low < 0xe000 => {
+                                        code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                                    },
+                                    _ => {
+                                        return Err(String::from("invalid surrogate pair"));
+                                    }
+                                }
+                            }
+                            match char::from_u32(code) {
+                                Some(c) => c,
+                                None => {
+                                    return Err(String::from("invalid unicode escape"));
+                                }
+                            }
+                        },
+                        _ => {
+                            return Err(String::from("invalid escape"));
+                        }
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(decoded.encode_utf8(&mut buf).as_bytes());
+                },
+                c => bytes.push(c),
+            }
+        }
+        match String::from_utf8(bytes) {
+            Ok(s) => Ok(s),
+            Err(_) => Err(String::from("invalid UTF-8 in string")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.pos += 1;
+        let mut values = vec![];
+        self.whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            match self.value() {
+                Ok(v) => values.push(v),
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+            self.whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(values));
+                },
+                _ => {
+                    return Err(String::from("expected , or ]"));
+                }
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.pos += 1;
+        let mut members = vec![];
+        self.whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(String::from("expected member name"));
+            }
+            let key = match self.string() {
+                Ok(k) => k,
+                Err(e) => {
+                    return Err(e);
+                }
+            };
+            self.whitespace();
+            if self.peek() != Some(b':') {
+                return Err(String::from("expected :"));
+            }
+            self.pos += 1;
+            match self.value() {
+                Ok(v) => members.push((key, v)),
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+            self.whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                },
+                _ => {
+                    return Err(String::from("expected , or }"));
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_values() {
+        let value = Json::parse(r#" {"a": [1, -2.5e3, true, null], "b": {"c": "d"}} "#).unwrap();
+        assert_eq!(value.get("a").and_then(|a| a.as_array()).map(|a| a.len()), Some(4));
+        assert_eq!(value.get("a").unwrap().as_array().unwrap()[1], Json::Number(-2500.0));
+        assert_eq!(value.get("b").and_then(|b| b.get("c")).and_then(|c| c.as_str()), Some("d"));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for s in &["", "{", "[1,]", "[1 2]", "{\"a\" 1}", "{1: 2}", "tru", "\"abc", "\"\\x\"", "1 2", "-", "[1]]"] {
+            assert!(Json::parse(s).is_err(), "{:?} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn decodes_escapes_and_surrogate_pairs() {
+        assert_eq!(Json::parse(r#""a\"\\\/\n\u00e9""#), Ok(Json::string("a\"\\/\n\u{e9}")));
+        assert_eq!(Json::parse(r#""\ud83d\ude00""#), Ok(Json::string("\u{1f600}")));
+        assert!(Json::parse(r#""\ud83d\u0041""#).is_err());
+        assert!(Json::parse(r#""\ud83d""#).is_err());
+        assert!(Json::parse(r#""\u12""#).is_err());
+    }
+
+    #[test]
+    fn limits_the_depth() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Json::parse(&nested(DEPTH_MAX)).is_ok());
+        let error = Json::parse(&nested(DEPTH_MAX + 1)).unwrap_err();
+        assert!(error.contains("nested too deeply"), "{}", error);
+        assert!(Json::parse(&"[".repeat(100000)).is_err());
+    }
+
+    #[test]
+    fn writes_what_it_reads() {
+        let value = Json::object(vec![
+            ("s", Json::string("tab\t\"quote\" \u{1f600}")),
+            ("n", Json::Array(vec![Json::Number(1.0), Json::Number(-0.5), Json::Null, Json::Bool(false)])),
+        ]);
+        assert_eq!(Json::parse(&value.to_string()), Ok(value));
+    }
+}
@@ -0,0 +1,433 @@
+extern crate glium;
+
+use bookmarks;
+
+use layers;
+
+use glium::glutin::VirtualKeyCode;
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Quit,
+    ToggleBorder,
+    ToggleHelp,
+    ToggleAxes,
+    PointsizeIncrease,
+    PointsizeDecrease,
+    GammaIncrease,
+    GammaDecrease,
+    Reset,
+    XPrev,
+    XNext,
+    YPrev,
+    YNext,
+    ZPrev,
+    ZNext,
+    TransformX,
+    TransformY,
+    TransformZ,
+    Undo,
+    Redo,
+    Back,
+    Forward,
+    AddFilter,
+    ClearFilter,
+    DeriveColumn,
+    ExportFigure,
+    Screenshot,
+    ExportGrid,
+    Animate,
+    AnimationPlay,
+    AnimationNext,
+    AnimationPrev,
+    AnimationTrails,
+    ExportAnimation,
+    Binning,
+    ZStat,
+    SplitLayers,
+    LayerComposite,
+    Contours,
+    ContourLevels,
+    ToggleLayer(usize),
+    RecallBookmark(usize),
+    StoreBookmark(usize),
+}
+
+static ACTIONS: &'static [(Action, &'static str, &'static str)] = &[
+    (Action::Quit,              "quit",               "quit"),
+    (Action::ToggleBorder,      "toggle_border",      "show/hide points outside the view at the border"),
+    (Action::ToggleHelp,        "toggle_help",        "show/hide this help"),
+    (Action::ToggleAxes,        "toggle_axes",        "show/hide axis labels"),
+    (Action::PointsizeIncrease, "pointsize_increase", "increase point size (cell size of bins)"),
+    (Action::PointsizeDecrease, "pointsize_decrease", "decrease point size (cell size of bins)"),
+    (Action::GammaIncrease,     "gamma_increase",     "increase gamma"),
+    (Action::GammaDecrease,     "gamma_decrease",     "decrease gamma"),
+    (Action::Reset,             "reset",              "reset view and user settings"),
+    (Action::XPrev,             "x_prev",             "previous column on X axis"),
+    (Action::XNext,             "x_next",             "next column on X axis"),
+    (Action::YPrev,             "y_prev",             "previous column on Y axis"),
+    (Action::YNext,             "y_next",             "next column on Y axis"),
+    (Action::ZPrev,             "z_prev",             "previous column for Z (color)"),
+    (Action::ZNext,             "z_next",             "next column for Z (color)"),
+    (Action::TransformX,        "x_transform",        "cycle X transform (identity, zscore, robust, rank, clip)"),
+    (Action::TransformY,        "y_transform",        "cycle Y transform"),
+    (Action::TransformZ,        "z_transform",        "cycle Z transform"),
+    (Action::Undo,              "undo",               "undo last view change"),
+    (Action::Redo,              "redo",               "redo view change"),
+    (Action::Back,              "back",               "go back to previous view (skips gamma/point size changes)"),
+    (Action::Forward,           "forward",            "go forward to next view (skips gamma/point size changes)"),
+    (Action::AddFilter,         "add_filter",         "add a row filter"),
+    (Action::ClearFilter,       "clear_filter",       "remove all row filters"),
+    (Action::DeriveColumn,      "derive_column",      "add a column computed from an expression"),
+    (Action::ExportFigure,      "export_figure",      "export the view as SVG or PDF"),
+    (Action::Screenshot,        "screenshot",         "save a high-resolution image of the view"),
+    (Action::ExportGrid,        "export_grid",        "export the density grid as NumPy or CSV data"),
+    (Action::Animate,           "animate",            "animate along a column, or stop the animation"),
+    (Action::AnimationPlay,     "animation_play",     "play/pause the animation"),
+    (Action::AnimationNext,     "animation_next",     "show the next animation frame"),
+    (Action::AnimationPrev,     "animation_prev",     "show the previous animation frame"),
+    (Action::AnimationTrails,   "animation_trails",   "toggle fading trails of previous frames"),
+    (Action::ExportAnimation,   "export_animation",   "export all animation frames as PNG series, Y4M or GIF"),
+    (Action::Binning,           "binning",            "cycle between points, hexagonal and square bins"),
+    (Action::ZStat,             "z_stat",             "cycle the statistic of Z that colors pixels and bins (mean, count, min, max, sum, std, distinct, median for bins)"),
+    (Action::SplitLayers,       "split_layers",       "split the rows into layers by a column, or merge them again"),
+    (Action::LayerComposite,    "layer_composite",    "cycle how layers are combined (blend, difference, diverging)"),
+    (Action::Contours,          "contours",           "toggle density contour lines"),
+    (Action::ContourLevels,     "contour_levels",     "set the contour levels in percent of the points in the view"),
+];
+
+impl Action {
+    pub fn all() -> Vec<Action> {
+        let mut actions = ACTIONS.iter().map(|&(a, _, _)| a).collect::<Vec<Action>>();
+        for layer in 1..(layers::SLOTS + 1) {
+            actions.push(Action::ToggleLayer(layer));
+        }
+        for slot in 1..(bookmarks::SLOTS + 1) {
+            actions.push(Action::RecallBookmark(slot));
+        }
+        for slot in 1..(bookmarks::SLOTS + 1) {
+            actions.push(Action::StoreBookmark(slot));
+        }
+        actions
+    }
+
+    pub fn from_name(s: &str) -> Option<Action> {
+        Action::all().into_iter().find(|a| a.name() == s)
+    }
+
+    pub fn name(&self) -> String {
+        match *self {
+            Action::ToggleLayer(layer)   => format!("toggle_layer_{}", layer),
+            Action::RecallBookmark(slot) => format!("recall_bookmark_{}", slot),
+            Action::StoreBookmark(slot)  => format!("store_bookmark_{}", slot),
+            _ => String::from(ACTIONS.iter().find(|&&(a, _, _)| a == *self).unwrap().1),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match *self {
+            Action::ToggleLayer(layer)   => format!("show/hide layer {}", layer),
+            Action::RecallBookmark(slot) => format!("recall bookmark {}", slot),
+            Action::StoreBookmark(slot)  => format!("store current view as bookmark {}", slot),
+            _ => String::from(ACTIONS.iter().find(|&&(a, _, _)| a == *self).unwrap().2),
+        }
+    }
+}
+
+
+macro_rules! key_codes {
+    ($($name:ident),*) => {
+        fn code_from_name(s: &str) -> Option<VirtualKeyCode> {
+            let lower = s.to_lowercase();
+            $(
+                if lower == stringify!($name).to_lowercase() {
+                    return Some(VirtualKeyCode::$name);
+                }
+            )*
+            None
+        }
+
+        fn code_to_name(code: VirtualKeyCode) -> &'static str {
+            match code {
+                $(VirtualKeyCode::$name => stringify!($name),)*
+                _ => "???",
+            }
+        }
+    }
+}
+
+key_codes!(
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Escape, Space, Return, Back, Tab, Insert, Delete, Home, End, PageUp, PageDown,
+    Left, Right, Up, Down, LShift, RShift, LControl, RControl,
+    Add, Subtract, Minus, Equals, Comma, Period, Slash, Backslash, Semicolon, Apostrophe,
+    LBracket, RBracket, Grave,
+    NavigateBackward, NavigateForward, WebBack, WebForward
+);
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Key {
+    pub code: VirtualKeyCode,
+    pub ctrl: bool,
+    pub shift: bool,
+}
+
+impl Key {
+    pub fn new(code: VirtualKeyCode) -> Key {
+        Key {
+            code: code,
+            ctrl: false,
+            shift: false,
+        }
+    }
+
+    fn with_ctrl(mut self) -> Key {
+        self.ctrl = true;
+        self
+    }
+
+    fn with_shift(mut self) -> Key {
+        self.shift = true;
+        self
+    }
+
+    /// Parses key descriptions like `J`, `PageUp`, `1`, `Ctrl+Shift+Z` or `Ctrl++`, where a trailing
+    /// `+` is the `Add` key.
+    pub fn parse(s: &str) -> Result<Key, String> {
+        let s = s.trim();
+        let (modifiers, name) = if s.ends_with('+') {
+            let rest = &s[..s.len() - 1];
+            if !rest.is_empty() && !rest.ends_with('+') {
+                return Err(format!("missing key after '{}'", s));
+            }
+            (&rest[..rest.len().saturating_sub(1)], "Add")
+        } else {
+            match s.rfind('+') {
+                Some(pos) => (&s[..pos], &s[pos + 1..]),
+                None      => ("", s),
+            }
+        };
+        let name = name.trim();
+
+        let code = match code_from_name(name).or_else(|| code_from_name(&format!("Key{}", name))) {
+            Some(c) => c,
+            None => {
+                return Err(format!("unknown key '{}'", name));
+            }
+        };
+        let mut key = Key::new(code);
+
+        for modifier in modifiers.split('+').map(|p| p.trim()).filter(|_| !modifiers.is_empty()) {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => key.ctrl = true,
+                "shift"            => key.shift = true,
+                _ => {
+                    return Err(format!("unknown modifier '{}'", modifier));
+                }
+            }
+        }
+
+        Ok(key)
+    }
+
+    pub fn to_string(&self) -> String {
+        let mut s = String::new();
+        if self.ctrl {
+            s.push_str("Ctrl+");
+        }
+        if self.shift {
+            s.push_str("Shift+");
+        }
+        s.push_str(code_to_name(self.code));
+        s
+    }
+}
+
+
+pub struct Keymap {
+    bindings: Vec<(Key, Action)>,
+    /// characters like `?`, whose keys depend on the keyboard layout
+    char_bindings: Vec<(char, Action)>,
+}
+
+impl Keymap {
+    pub fn new() -> Keymap {
+        Keymap {
+            bindings: vec![],
+            char_bindings: vec![],
+        }
+    }
+
+    pub fn default() -> Keymap {
+        let mut keymap = Keymap::new();
+        keymap.bind(Key::new(VirtualKeyCode::Escape),                      Action::Quit);
+        keymap.bind(Key::new(VirtualKeyCode::Q),                           Action::Quit);
+        keymap.bind(Key::new(VirtualKeyCode::B),                           Action::ToggleBorder);
+        keymap.bind(Key::new(VirtualKeyCode::F1),                          Action::ToggleHelp);
+        keymap.bind(Key::new(VirtualKeyCode::A),                           Action::ToggleAxes);
+        keymap.bind(Key::new(VirtualKeyCode::J),                           Action::PointsizeIncrease);
+        keymap.bind(Key::new(VirtualKeyCode::K),                           Action::PointsizeDecrease);
+        keymap.bind(Key::new(VirtualKeyCode::N),                           Action::GammaIncrease);
+        keymap.bind(Key::new(VirtualKeyCode::M),                           Action::GammaDecrease);
+        keymap.bind(Key::new(VirtualKeyCode::R),                           Action::Reset);
+        keymap.bind(Key::new(VirtualKeyCode::Left),                        Action::XPrev);
+        keymap.bind(Key::new(VirtualKeyCode::Right),                       Action::XNext);
+        keymap.bind(Key::new(VirtualKeyCode::Up),                          Action::YPrev);
+        keymap.bind(Key::new(VirtualKeyCode::Down),                        Action::YNext);
+        keymap.bind(Key::new(VirtualKeyCode::PageUp),                      Action::ZPrev);
+        keymap.bind(Key::new(VirtualKeyCode::PageDown),                    Action::ZNext);
+        keymap.bind(Key::new(VirtualKeyCode::X),                           Action::TransformX);
+        keymap.bind(Key::new(VirtualKeyCode::Y),                           Action::TransformY);
+        keymap.bind(Key::new(VirtualKeyCode::Z),                           Action::TransformZ);
+        keymap.bind(Key::new(VirtualKeyCode::Z).with_ctrl(),               Action::Undo);
+        keymap.bind(Key::new(VirtualKeyCode::U),                           Action::Undo);
+        keymap.bind(Key::new(VirtualKeyCode::Y).with_ctrl(),               Action::Redo);
+        keymap.bind(Key::new(VirtualKeyCode::Z).with_ctrl().with_shift(),  Action::Redo);
+        keymap.bind(Key::new(VirtualKeyCode::R).with_ctrl(),               Action::Redo);
+        keymap.bind(Key::new(VirtualKeyCode::Back),                        Action::Back);
+        keymap.bind(Key::new(VirtualKeyCode::NavigateBackward),            Action::Back);
+        keymap.bind(Key::new(VirtualKeyCode::WebBack),                     Action::Back);
+        keymap.bind(Key::new(VirtualKeyCode::Back).with_shift(),           Action::Forward);
+        keymap.bind(Key::new(VirtualKeyCode::NavigateForward),             Action::Forward);
+        keymap.bind(Key::new(VirtualKeyCode::WebForward),                  Action::Forward);
+        keymap.bind(Key::new(VirtualKeyCode::F),                           Action::AddFilter);
+        keymap.bind(Key::new(VirtualKeyCode::F).with_shift(),              Action::ClearFilter);
+        keymap.bind(Key::new(VirtualKeyCode::D),                           Action::DeriveColumn);
+        keymap.bind(Key::new(VirtualKeyCode::E),                           Action::ExportFigure);
+        keymap.bind(Key::new(VirtualKeyCode::S),                           Action::Screenshot);
+        keymap.bind(Key::new(VirtualKeyCode::G),                           Action::ExportGrid);
+        keymap.bind(Key::new(VirtualKeyCode::P).with_shift(),              Action::Animate);
+        keymap.bind(Key::new(VirtualKeyCode::P),                           Action::AnimationPlay);
+        keymap.bind(Key::new(VirtualKeyCode::Period),                      Action::AnimationNext);
+        keymap.bind(Key::new(VirtualKeyCode::Comma),                       Action::AnimationPrev);
+        keymap.bind(Key::new(VirtualKeyCode::T),                           Action::AnimationTrails);
+        keymap.bind(Key::new(VirtualKeyCode::V),                           Action::ExportAnimation);
+        keymap.bind(Key::new(VirtualKeyCode::H),                           Action::Binning);
+        keymap.bind(Key::new(VirtualKeyCode::Z).with_shift(),              Action::ZStat);
+        keymap.bind(Key::new(VirtualKeyCode::L),                           Action::SplitLayers);
+        keymap.bind(Key::new(VirtualKeyCode::C),                           Action::LayerComposite);
+        keymap.bind(Key::new(VirtualKeyCode::O),                           Action::Contours);
+        keymap.bind(Key::new(VirtualKeyCode::O).with_shift(),              Action::ContourLevels);
+
+        let slot_keys = [
+            VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3,
+            VirtualKeyCode::Key4, VirtualKeyCode::Key5, VirtualKeyCode::Key6,
+            VirtualKeyCode::Key7, VirtualKeyCode::Key8, VirtualKeyCode::Key9,
+        ];
+        for (i, code) in slot_keys.iter().enumerate() {
+            keymap.bind(Key::new(*code),              Action::RecallBookmark(i + 1));
+            keymap.bind(Key::new(*code).with_ctrl(),  Action::StoreBookmark(i + 1));
+            keymap.bind(Key::new(*code).with_shift(), Action::ToggleLayer(i + 1));
+        }
+        keymap.bind_char('?', Action::ToggleHelp);
+        keymap
+    }
+
+    /// Binds `key` to `action`, replacing any previous binding of that key.
+    pub fn bind(&mut self, key: Key, action: Action) {
+        self.unbind(&key);
+        self.bindings.push((key, action));
+    }
+
+    pub fn unbind(&mut self, key: &Key) {
+        self.bindings.retain(|&(k, _)| k != *key);
+    }
+
+    /// Binds the character `c`, replacing any previous binding of it.
+    pub fn bind_char(&mut self, c: char, action: Action) {
+        self.unbind_char(c);
+        self.char_bindings.push((c, action));
+    }
+
+    pub fn unbind_char(&mut self, c: char) {
+        self.char_bindings.retain(|&(b, _)| b != c);
+    }
+
+    pub fn unbind_all(&mut self) {
+        self.bindings.clear();
+        self.char_bindings.clear();
+    }
+
+    /// Bindings without Shift match with Shift as well, unless that is bound separately.
+    pub fn lookup(&self, key: &Key) -> Option<Action> {
+        let find = |key: &Key| self.bindings.iter().find(|&&(k, _)| k == *key).map(|&(_, a)| a);
+        match find(key) {
+            Some(action) => Some(action),
+            None if key.shift => find(&Key { shift: false, ..*key }),
+            None => None,
+        }
+    }
+
+    pub fn lookup_char(&self, c: char) -> Option<Action> {
+        self.char_bindings.iter().find(|&&(b, _)| b == c).map(|&(_, a)| a)
+    }
+
+    pub fn keys_for(&self, action: Action) -> Vec<Key> {
+        self.bindings.iter().filter(|&&(_, a)| a == action).map(|&(k, _)| k).collect()
+    }
+
+    pub fn chars_for(&self, action: Action) -> Vec<char> {
+        self.char_bindings.iter().filter(|&&(_, a)| a == action).map(|&(c, _)| c).collect()
+    }
+
+    /// One line per action that has at least one key bound, in the order of `Action::all`.
+    pub fn help_lines(&self) -> Vec<String> {
+        let entries = Action::all().into_iter().filter_map(|action| {
+            let mut keys = self.keys_for(action).iter().map(|k| k.to_string()).collect::<Vec<String>>();
+            keys.extend(self.chars_for(action).iter().map(|c| c.to_string()));
+            if keys.is_empty() {
+                None
+            } else {
+                let keys_str = keys.join(", ");
+                Some((keys_str, action.description()))
+            }
+        }).collect::<Vec<(String, String)>>();
+
+        let width = entries.iter().map(|&(ref k, _)| k.len()).max().unwrap_or(0);
+        entries.iter().map(|&(ref k, ref d)| {
+            format!("{:width$}  {}", k, d, width = width)
+        }).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(Key::parse("J"), Ok(Key::new(VirtualKeyCode::J)));
+        assert_eq!(Key::parse("pageup"), Ok(Key::new(VirtualKeyCode::PageUp)));
+        assert_eq!(Key::parse("1"), Ok(Key::new(VirtualKeyCode::Key1)));
+        assert_eq!(Key::parse("Ctrl+Shift+Z"), Ok(Key::new(VirtualKeyCode::Z).with_ctrl().with_shift()));
+        assert_eq!(Key::parse("control + j"), Ok(Key::new(VirtualKeyCode::J).with_ctrl()));
+        assert_eq!(Key::parse("+"), Ok(Key::new(VirtualKeyCode::Add)));
+        assert_eq!(Key::parse("Ctrl++"), Ok(Key::new(VirtualKeyCode::Add).with_ctrl()));
+        for s in &["", "Ctrl+", "Ctrl+Nope", "Alt+J", "J+Ctrl", "Ctrl+J+"] {
+            assert!(Key::parse(s).is_err(), "{:?} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn prints_what_it_parses() {
+        for key in &[Key::new(VirtualKeyCode::F1), Key::new(VirtualKeyCode::Back).with_shift(), Key::new(VirtualKeyCode::Y).with_ctrl()] {
+            assert_eq!(Key::parse(&key.to_string()), Ok(*key));
+        }
+    }
+
+    #[test]
+    fn falls_back_to_the_key_without_shift() {
+        let mut keymap = Keymap::new();
+        keymap.bind(Key::new(VirtualKeyCode::J), Action::PointsizeIncrease);
+        assert_eq!(keymap.lookup(&Key::new(VirtualKeyCode::J).with_shift()), Some(Action::PointsizeIncrease));
+        keymap.bind(Key::new(VirtualKeyCode::J).with_shift(), Action::Quit);
+        assert_eq!(keymap.lookup(&Key::new(VirtualKeyCode::J).with_shift()), Some(Action::Quit));
+        assert_eq!(keymap.lookup(&Key::new(VirtualKeyCode::J).with_ctrl()), None);
+    }
+}
@@ -0,0 +1,212 @@
+use data::Column;
+
+use std::ops::Range;
+use std::path::Path;
+
+
+/// Number of layers, each with a color of its own and a key to show/hide it.
+pub static SLOTS: usize = 9;
+
+/// Linear RGB color of every layer.
+static COLORS: [[f32; 3]; 9] = [
+    [1.00, 0.45, 0.05],  // orange
+    [0.10, 0.50, 1.00],  // sky blue
+    [0.20, 0.90, 0.20],  // green
+    [0.90, 0.10, 0.80],  // magenta
+    [0.90, 0.80, 0.10],  // yellow
+    [0.10, 0.90, 0.90],  // cyan
+    [1.00, 0.15, 0.10],  // red
+    [0.50, 0.30, 1.00],  // violet
+    [0.80, 0.80, 0.80],  // gray
+];
+
+/// Stops of the diverging color map from -1 to 1, in linear RGB. It is dark in the middle, where
+/// both layers are equally dense, like the background. The layers shader has the same stops.
+static DIVERGING: [[f32; 3]; 5] = [
+    [0.55, 0.85, 1.00],
+    [0.05, 0.30, 0.90],
+    [0.00, 0.00, 0.00],
+    [0.80, 0.15, 0.02],
+    [1.00, 0.85, 0.45],
+];
+
+/// Color of `v` in `[-1, 1]` on the diverging color map.
+pub fn diverging_color(v: f32) -> [f32; 3] {
+    let x = f32::min(f32::max((v + 1.0) * 2.0, 0.0), 4.0);
+    let i = f32::min(x.floor(), 3.0) as usize;
+    let t = x - (i as f32);
+    let (a, b) = (DIVERGING[i], DIVERGING[i + 1]);
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+
+/// How the layers are combined on screen.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Composite {
+    /// the colored densities of all visible layers add up
+    Blend,
+    /// the first two visible layers, in the color of the denser one
+    Difference,
+    /// density difference of the first two visible layers on a diverging color map, scaled
+    /// symmetrically to the largest difference in the view
+    Diverging,
+}
+
+static COMPOSITES: &'static [(Composite, &'static str)] = &[
+    (Composite::Blend,      "blend"),
+    (Composite::Difference, "difference"),
+    (Composite::Diverging,  "diverging"),
+];
+
+impl Composite {
+    pub fn next(&self) -> Composite {
+        let pos = COMPOSITES.iter().position(|&(c, _)| c == *self).unwrap();
+        COMPOSITES[(pos + 1) % COMPOSITES.len()].0
+    }
+
+    pub fn name(&self) -> &'static str {
+        COMPOSITES.iter().find(|&&(c, _)| c == *self).unwrap().1
+    }
+}
+
+
+/// Rows split into layers by the values of a column, e.g. the file they came from. Every layer is
+/// accumulated on its own, normalised by its own number of rows, and drawn in its own color.
+pub struct Layers {
+    pub column: usize,
+    /// value of every layer, ascending
+    pub values: Vec<f64>,
+    pub names: Vec<String>,
+    pub visible: Vec<bool>,
+    pub composite: Composite,
+}
+
+impl Layers {
+    pub fn new(column: usize, values: Vec<f64>, names: Vec<String>) -> Layers {
+        let count = values.len();
+        Layers {
+            column: column,
+            values: values,
+            names: names,
+            visible: vec![true; count],
+            composite: Composite::Blend,
+        }
+    }
+
+    /// One layer per distinct value of column `idx`, e.g. of a boolean column (0 or 1) derived from
+    /// an expression. NA values belong to no layer.
+    pub fn split(columns: &[Column], idx: usize) -> Result<Layers, String> {
+        let column = &columns[idx];
+        let mut values = vec![];
+        for &v in column.data.iter().filter(|v| !v.is_nan()) {
+            if !values.contains(&v) {
+                if values.len() == SLOTS {
+                    return Err(format!("cannot split by {}, it has more than {} distinct values", column.name, SLOTS));
+                }
+                values.push(v);
+            }
+        }
+        if values.len() < 2 {
+            return Err(format!("cannot split by {}, it needs at least two distinct values", column.name));
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let names = values.iter().map(|v| format!("{}={}", column.name, v)).collect();
+        Ok(Layers::new(idx, values, names))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn color(&self, layer: usize) -> [f32; 3] {
+        COLORS[layer % COLORS.len()]
+    }
+
+    /// Layers that are drawn, in the order of compositing.
+    pub fn shown(&self) -> Vec<usize> {
+        let visible = (0..self.len()).filter(|&l| self.visible[l]);
+        match self.composite {
+            Composite::Blend => visible.collect(),
+            Composite::Difference | Composite::Diverging => visible.take(2).collect(),
+        }
+    }
+
+    /// Returns the indices of the rows that pass `mask` ordered by layer, and the range of every
+    /// layer in them. Rows with other values belong to no layer.
+    pub fn rows(&self, columns: &[Column], mask: &[bool]) -> (Vec<u32>, Vec<Range<usize>>) {
+        let column = &columns[self.column];
+        let layer_of = |v: f64| self.values.iter().position(|&l| l == v);
+
+        let mut counts = vec![0; self.len()];
+        for (v, _) in column.data.iter().zip(mask.iter()).filter(|&(_, m)| *m) {
+            if let Some(l) = layer_of(*v) {
+                counts[l] += 1;
+            }
+        }
+        let mut ranges = vec![];
+        let mut start = 0;
+        for c in counts {
+            ranges.push(start..(start + c));
+            start += c;
+        }
+
+        let mut next = ranges.iter().map(|r| r.start).collect::<Vec<usize>>();
+        let mut rows = vec![0; start];
+        for (i, (v, _)) in column.data.iter().zip(mask.iter()).enumerate().filter(|&(_, (_, m))| *m) {
+            if let Some(l) = layer_of(*v) {
+                rows[next[l]] = i as u32;
+                next[l] += 1;
+            }
+        }
+        (rows, ranges)
+    }
+}
+
+
+/// Name of a layer for file `fname`, without the directories.
+pub fn file_layer_name(fname: &str) -> String {
+    match Path::new(fname).file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => String::from(fname),
+    }
+}
+
+/// Concatenates the columns of several files, matched by name. Columns that are missing in one of
+/// the files (or have another kind there) are left out. A further column, `file` unless that name
+/// is taken, holds the index of the file every row came from.
+pub fn merge(datasets: Vec<Vec<Column>>) -> Result<Vec<Column>, String> {
+    let names = datasets[0].iter().filter(|c| {
+        datasets.iter().all(|columns| columns.iter().any(|other| other.name == c.name && other.kind == c.kind))
+    }).map(|c| c.name.clone()).collect::<Vec<String>>();
+    if names.is_empty() {
+        return Err(String::from("the files have no columns in common"));
+    }
+    for columns in &datasets {
+        for c in columns.iter().filter(|c| !names.contains(&c.name)) {
+            warn!("column {} is not in all files, leaving it out", c.name);
+        }
+    }
+
+    let mut merged = names.iter().map(|name| {
+        let mut data = vec![];
+        for columns in &datasets {
+            let column = columns.iter().find(|c| c.name == *name).unwrap();
+            data.extend_from_slice(&column.data);
+        }
+        let mut column = Column::from_data(name, data);
+        column.kind = datasets[0].iter().find(|c| c.name == *name).unwrap().kind;
+        column
+    }).collect::<Vec<Column>>();
+
+    let mut file_name = String::from("file");
+    while names.contains(&file_name) {
+        file_name.insert(0, '_');
+    }
+    let mut files = vec![];
+    for (i, columns) in datasets.iter().enumerate() {
+        let rows = columns.get(0).map(|c| c.data.len()).unwrap_or(0);
+        files.extend(vec![i as f64; rows]);
+    }
+    merged.push(Column::from_data(&file_name, files));
+    Ok(merged)
+}
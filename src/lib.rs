@@ -0,0 +1,47 @@
+//! Fast density plots of large tables. The viewer binary and the language bindings share
+//! everything in here.
+
+#[macro_use] extern crate glium;
+#[macro_use] extern crate log;
+// the PyO3 macros refer to `::core`, which needs to be in the crate root with edition 2015
+#[cfg(feature = "python")] extern crate core;
+#[cfg(feature = "python")] extern crate numpy;
+#[cfg(feature = "python")] extern crate pyo3;
+
+mod animation;
+mod axes;
+pub mod bins;
+pub mod bookmarks;
+#[cfg(feature = "capi")]
+mod capi;
+pub mod cache;
+pub mod cfg;
+pub mod contours;
+pub mod data;
+mod cpu;
+pub mod datetime;
+pub mod expr;
+mod figure;
+pub mod filter;
+mod gpu;
+mod grid;
+mod history;
+pub mod image;
+pub mod json;
+mod keymap;
+pub mod layers;
+mod lod;
+#[cfg(feature = "python")]
+mod python;
+pub mod renderer;
+mod res;
+pub mod rpc;
+pub mod screenshot;
+pub mod serve;
+pub mod settings;
+pub mod stat;
+pub mod terminal;
+mod text;
+mod transform;
+mod video;
+mod view;
@@ -0,0 +1,334 @@
+use data::{Column, Point};
+
+use std::cmp;
+use std::f64;
+
+
+/// Axis-aligned rectangle in data coordinates.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rect {
+    pub x_min: f64,
+    pub x_max: f64,
+    pub y_min: f64,
+    pub y_max: f64,
+}
+
+impl Rect {
+    pub fn contains(&self, other: &Rect) -> bool {
+        self.x_min <= other.x_min && other.x_max <= self.x_max && self.y_min <= other.y_min && other.y_max <= self.y_max
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x_min <= other.x_max && other.x_min <= self.x_max && self.y_min <= other.y_max && other.y_min <= self.y_max
+    }
+
+    /// Grows the rectangle by `factor` times its size on every side.
+    pub fn expand(&self, factor: f64) -> Rect {
+        let dx = (self.x_max - self.x_min) * factor;
+        let dy = (self.y_max - self.y_min) * factor;
+        Rect {
+            x_min: self.x_min - dx,
+            x_max: self.x_max + dx,
+            y_min: self.y_min - dy,
+            y_max: self.y_max + dy,
+        }
+    }
+}
+
+
+// bit interleaving for Morton codes, so that the 4 children of a cell have the codes `4 * parent + i`
+fn spread(v: u32) -> u32 {
+    let mut v = v & 0x0000ffff;
+    v = (v | (v << 8)) & 0x00ff00ff;
+    v = (v | (v << 4)) & 0x0f0f0f0f;
+    v = (v | (v << 2)) & 0x33333333;
+    (v | (v << 1)) & 0x55555555
+}
+
+fn compact(v: u32) -> u32 {
+    let mut v = v & 0x55555555;
+    v = (v | (v >> 1)) & 0x33333333;
+    v = (v | (v >> 2)) & 0x0f0f0f0f;
+    v = (v | (v >> 4)) & 0x00ff00ff;
+    (v | (v >> 8)) & 0x0000ffff
+}
+
+
+struct Cell {
+    code: u32,
+    count: u32,
+    z_sum: f64,
+    /// index of the first row of this cell in `Lod::rows`
+    first: usize,
+}
+
+
+/// Quadtree of binned counts and Z sums over the bounding box of all selected points. Level `l` has
+/// up to `4^l` cells; the rows of every cell are stored contiguously, so the finest level doubles as
+/// a spatial index for raw points.
+pub struct Lod {
+    bounds: Rect,
+    levels: Vec<Vec<Cell>>,
+    rows: Vec<u32>,
+}
+
+impl Lod {
+    /// `depth` is the finest level (at most 16). Returns `None` if there are no finite points.
+    pub fn build(x: &Column, y: &Column, z: &Column, mask: &[bool], depth: usize) -> Option<Lod> {
+        let selected = |i: usize| mask[i] && x.data[i].is_finite() && y.data[i].is_finite() && z.data[i].is_finite();
+
+        let mut bounds = Rect {
+            x_min: f64::INFINITY,
+            x_max: f64::NEG_INFINITY,
+            y_min: f64::INFINITY,
+            y_max: f64::NEG_INFINITY,
+        };
+        for i in (0..mask.len()).filter(|&i| selected(i)) {
+            bounds.x_min = f64::min(bounds.x_min, x.data[i]);
+            bounds.x_max = f64::max(bounds.x_max, x.data[i]);
+            bounds.y_min = f64::min(bounds.y_min, y.data[i]);
+            bounds.y_max = f64::max(bounds.y_max, y.data[i]);
+        }
+        if !(bounds.x_min <= bounds.x_max) {
+            return None;
+        }
+
+        // counting sort of all rows by their cell on the finest level
+        let side = 1u32 << depth;
+        let width = if bounds.x_max > bounds.x_min { bounds.x_max - bounds.x_min } else { 1.0 };
+        let height = if bounds.y_max > bounds.y_min { bounds.y_max - bounds.y_min } else { 1.0 };
+        let cell_of = |i: usize| {
+            let ix = ((x.data[i] - bounds.x_min) / width * (side as f64)) as u32;
+            let iy = ((y.data[i] - bounds.y_min) / height * (side as f64)) as u32;
+            spread(cmp::min(ix, side - 1)) | (spread(cmp::min(iy, side - 1)) << 1)
+        };
+
+        // `starts[c]` is the offset of the first row of cell `c` once all rows are placed. It is
+        // used as the insertion point of cell `c - 1` while placing them, so no copy is needed, and
+        // holds `u32`s like `rows`, since it has `4^depth` entries
+        let cells = (side as usize) * (side as usize);
+        let mut starts = vec![0u32; cells + 2];
+        for i in (0..mask.len()).filter(|&i| selected(i)) {
+            starts[cell_of(i) as usize + 2] += 1;
+        }
+        for c in 2..starts.len() {
+            starts[c] += starts[c - 1];
+        }
+        let mut rows = vec![0u32; starts[cells + 1] as usize];
+        for i in (0..mask.len()).filter(|&i| selected(i)) {
+            let c = cell_of(i) as usize;
+            rows[starts[c + 1] as usize] = i as u32;
+            starts[c + 1] += 1;
+        }
+
+        let finest = (0..cells).filter(|&c| starts[c + 1] > starts[c]).map(|c| {
+            let (first, end) = (starts[c] as usize, starts[c + 1] as usize);
+            Cell {
+                code: c as u32,
+                count: (end - first) as u32,
+                z_sum: rows[first..end].iter().fold(0.0, |acc, &r| acc + z.data[r as usize]),
+                first: first,
+            }
+        }).collect::<Vec<Cell>>();
+
+        // coarser levels merge runs of siblings, which are adjacent because of the Morton order
+        let mut levels = vec![finest];
+        while levels.len() <= depth {
+            let coarser = {
+                let finer = &levels[levels.len() - 1];
+                let mut coarser: Vec<Cell> = vec![];
+                for cell in finer {
+                    let code = cell.code >> 2;
+                    let merge = match coarser.last() {
+                        Some(last) => last.code == code,
+                        None => false,
+                    };
+                    if merge {
+                        let last = coarser.last_mut().unwrap();
+                        last.count += cell.count;
+                        last.z_sum += cell.z_sum;
+                    } else {
+                        coarser.push(Cell { code: code, count: cell.count, z_sum: cell.z_sum, first: cell.first });
+                    }
+                }
+                coarser
+            };
+            levels.push(coarser);
+        }
+        levels.reverse();
+
+        debug!("built LOD with {} levels, {} cells on the finest level", levels.len(), levels[depth].len());
+        Some(Lod {
+            bounds: bounds,
+            levels: levels,
+            rows: rows,
+        })
+    }
+
+    fn depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    fn cell_size(&self, level: usize) -> (f64, f64) {
+        let side = (1u32 << level) as f64;
+        ((self.bounds.x_max - self.bounds.x_min) / side, (self.bounds.y_max - self.bounds.y_min) / side)
+    }
+
+    fn cell_rect(&self, level: usize, code: u32) -> Rect {
+        let (w, h) = self.cell_size(level);
+        let x_min = self.bounds.x_min + (compact(code) as f64) * w;
+        let y_min = self.bounds.y_min + (compact(code >> 1) as f64) * h;
+        Rect {
+            x_min: x_min,
+            x_max: x_min + w,
+            y_min: y_min,
+            y_max: y_min + h,
+        }
+    }
+
+    /// Coarsest level whose cells are at most `max_width` x `max_height` large, `None` if even the
+    /// finest level is too coarse.
+    pub fn level_for(&self, max_width: f64, max_height: f64) -> Option<usize> {
+        (0..(self.depth() + 1)).find(|&l| {
+            let (w, h) = self.cell_size(l);
+            w <= max_width && h <= max_height
+        })
+    }
+
+    fn cell_point(&self, level: usize, cell: &Cell, origin: [f64; 3]) -> Point {
+        let rect = self.cell_rect(level, cell.code);
+        let x = (rect.x_min + rect.x_max) / 2.0;
+        let y = (rect.y_min + rect.y_max) / 2.0;
+        let z = cell.z_sum / (cell.count as f64);
+        Point {
+            position: [(x - origin[0]) as f32, (y - origin[1]) as f32, (z - origin[2]) as f32],
+            weight: cell.count as f32,
+        }
+    }
+
+    /// Like `points_in`, plus cells of `border_level` outside of `view`, so that points at the border
+    /// stay visible.
+    pub fn points(&self, level: Option<usize>, view: &Rect, border_level: usize, x: &Column, y: &Column, z: &Column, origin: [f64; 3]) -> Vec<Point> {
+        let mut points = self.points_in(level, view, x, y, z, origin);
+        let border_level = cmp::min(border_level, self.depth());
+        for cell in self.levels[border_level].iter().filter(|c| !view.intersects(&self.cell_rect(border_level, c.code))) {
+            points.push(self.cell_point(border_level, cell, origin));
+        }
+        points
+    }
+
+    /// Points relative to `origin` for everything in `view`: one per cell of `level`, or the raw rows
+    /// if `level` is `None`.
+    pub fn points_in(&self, level: Option<usize>, view: &Rect, x: &Column, y: &Column, z: &Column, origin: [f64; 3]) -> Vec<Point> {
+        let mut points = vec![];
+
+        match level {
+            Some(l) => {
+                for cell in self.levels[l].iter().filter(|c| view.intersects(&self.cell_rect(l, c.code))) {
+                    points.push(self.cell_point(l, cell, origin));
+                }
+            },
+            None => {
+                let l = self.depth();
+                for cell in self.levels[l].iter().filter(|c| view.intersects(&self.cell_rect(l, c.code))) {
+                    for &r in &self.rows[cell.first..(cell.first + cell.count as usize)] {
+                        let r = r as usize;
+                        points.push(Point {
+                            position: [(x.data[r] - origin[0]) as f32, (y.data[r] - origin[1]) as f32, (z.data[r] - origin[2]) as f32],
+                            weight: 1.0,
+                        });
+                    }
+                }
+            },
+        }
+        points
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pseudo random columns x, y and z with some NA and masked rows.
+    fn columns(n: usize) -> (Column, Column, Column, Vec<bool>) {
+        let mut state = 12345u64;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) as f64) / ((1u64 << 31) as f64)
+        };
+        let mut data = vec![vec![], vec![], vec![]];
+        for i in 0..n {
+            data[0].push(if i % 17 == 0 { f64::NAN } else { next() * 10.0 - 5.0 });
+            data[1].push(next().powi(2) * 3.0);
+            data[2].push(next());
+        }
+        let mask = (0..n).map(|i| i % 5 != 0).collect();
+        let z = Column::from_data("z", data.pop().unwrap());
+        let y = Column::from_data("y", data.pop().unwrap());
+        let x = Column::from_data("x", data.pop().unwrap());
+        (x, y, z, mask)
+    }
+
+    fn everything() -> Rect {
+        Rect { x_min: -10.0, x_max: 10.0, y_min: -10.0, y_max: 10.0 }
+    }
+
+    #[test]
+    fn counts_and_sums_the_selected_rows_on_every_level() {
+        let (x, y, z, mask) = columns(5000);
+        let lod = Lod::build(&x, &y, &z, &mask, 4).unwrap();
+        let selected = (0..5000).filter(|&i| mask[i] && !x.data[i].is_nan()).collect::<Vec<usize>>();
+        let z_sum = selected.iter().fold(0.0, |acc, &i| acc + z.data[i]);
+
+        for l in 0..(lod.depth() + 1) {
+            let cells = &lod.levels[l];
+            assert!(cells.len() <= 1 << (2 * l));
+            assert_eq!(cells.iter().map(|c| c.count as usize).sum::<usize>(), selected.len());
+            assert!((cells.iter().map(|c| c.z_sum).sum::<f64>() - z_sum).abs() < 1e-6);
+            let points = lod.points(Some(l), &everything(), 0, &x, &y, &z, [0.0; 3]);
+            assert_eq!(points.len(), cells.len());
+            assert_eq!(points.iter().map(|p| p.weight as usize).sum::<usize>(), selected.len());
+        }
+    }
+
+    #[test]
+    fn puts_every_row_into_its_cell() {
+        let (x, y, z, mask) = columns(5000);
+        let lod = Lod::build(&x, &y, &z, &mask, 3).unwrap();
+        let l = lod.depth();
+        let mut rows = vec![];
+        for cell in &lod.levels[l] {
+            let rect = lod.cell_rect(l, cell.code);
+            let cell_rows = &lod.rows[cell.first..(cell.first + cell.count as usize)];
+            let z_sum = cell_rows.iter().fold(0.0, |acc, &r| acc + z.data[r as usize]);
+            assert!((cell.z_sum - z_sum).abs() < 1e-9);
+            for &r in cell_rows {
+                let (px, py) = (x.data[r as usize], y.data[r as usize]);
+                assert!(rect.x_min <= px && px <= rect.x_max && rect.y_min <= py && py <= rect.y_max);
+            }
+            rows.extend_from_slice(cell_rows);
+        }
+        rows.sort();
+        let expected = (0..5000).filter(|&i| mask[i] && !x.data[i].is_nan()).map(|i| i as u32).collect::<Vec<u32>>();
+        assert_eq!(rows, expected);
+        assert_eq!(lod.points(None, &everything(), 0, &x, &y, &z, [0.0; 3]).len(), expected.len());
+    }
+
+    #[test]
+    fn chooses_the_coarsest_level_fine_enough() {
+        let x = Column::from_data("x", vec![0.0, 16.0]);
+        let y = Column::from_data("y", vec![0.0, 8.0]);
+        let lod = Lod::build(&x, &y, &y, &[true, true], 4).unwrap();
+        assert_eq!(lod.level_for(16.0, 8.0), Some(0));
+        assert_eq!(lod.level_for(4.0, 8.0), Some(2));
+        assert_eq!(lod.level_for(1.0, 1.0), Some(4));
+        assert_eq!(lod.level_for(0.5, 1.0), None);
+    }
+
+    #[test]
+    fn needs_finite_points() {
+        let x = Column::from_data("x", vec![f64::NAN, 1.0]);
+        assert!(Lod::build(&x, &x, &x, &[true, false], 4).is_none());
+    }
+}
@@ -1,15 +1,24 @@
 extern crate clap;
 extern crate env_logger;
-#[macro_use] extern crate glium;
+extern crate fluxcore_ng;
 #[macro_use] extern crate log;
 
-mod cfg;
-mod data;
-mod renderer;
-mod res;
-
 use clap::{Arg, App};
-use renderer::Renderer;
+use fluxcore_ng::{cache, cfg, expr, layers, settings};
+use fluxcore_ng::bins::{Bins, Shape};
+use fluxcore_ng::bookmarks::Bookmarks;
+use fluxcore_ng::contours::Contours;
+use fluxcore_ng::datetime::TimeFormat;
+use fluxcore_ng::filter::Filter;
+use fluxcore_ng::layers::Layers;
+use fluxcore_ng::renderer::Renderer;
+use fluxcore_ng::rpc::Server;
+use fluxcore_ng::screenshot::Screenshot;
+use fluxcore_ng::serve::TileServer;
+use fluxcore_ng::settings::Settings;
+use fluxcore_ng::stat::Stat;
+use fluxcore_ng::terminal;
+use fluxcore_ng::terminal::Terminal;
 
 fn is_uint_and_geq_100(s: String) -> Result<(), String> {
     match s.parse::<u32>() {
@@ -26,12 +35,27 @@ fn is_uint_and_geq_100(s: String) -> Result<(), String> {
     }
 }
 
+fn is_positive_number(s: String) -> Result<(), String> {
+    match s.parse::<f32>() {
+        Ok(v) if v > 0.0 => Ok(()),
+        _ => Err(String::from("Not a positive number")),
+    }
+}
+
+fn is_port(s: String) -> Result<(), String> {
+    match s.parse::<u16>() {
+        Ok(p) if p > 0 => Ok(()),
+        _ => Err(String::from("Not a port number")),
+    }
+}
+
 
 fn main() {
     env_logger::init().unwrap();
     info!("that's fluxcore...booting up!");
 
     info!("parse command line args");
+    let stat_names = Stat::names();
     let matches = App::new("fluxcore_ng")
         .version("???")
         .author("Marco Neumann")
@@ -46,25 +70,317 @@ fn main() {
              .long("height")
              .default_value("600")
              .validator(is_uint_and_geq_100))
+        .arg(Arg::with_name("config")
+             .short("c")
+             .long("config")
+             .takes_value(true)
+             .value_name("CONFIG"))
+        .arg(Arg::with_name("derive")
+             .long("derive")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("DEFINITION")
+             .help("add a derived column, e.g. 'ratio = a / b'"))
+        .arg(Arg::with_name("filter")
+             .long("filter")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("FILTER")
+             .help("only show rows matching FILTER, e.g. 'temp>300' or '0<=x<1'"))
+        .arg(Arg::with_name("time")
+             .long("time")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("COLUMN[=FORMAT]")
+             .help("parse COLUMN as date/time; FORMAT is iso (default), epoch, epoch_ms or a strftime pattern like '%d.%m.%Y %H:%M'"))
+        .arg(Arg::with_name("no-cache")
+             .long("no-cache")
+             .conflicts_with("rebuild-cache")
+             .help("neither read nor write the binary cache of FILE"))
+        .arg(Arg::with_name("rebuild-cache")
+             .long("rebuild-cache")
+             .help("parse FILE even if it is cached and overwrite the cache"))
+        .arg(Arg::with_name("screenshot")
+             .long("screenshot")
+             .takes_value(true)
+             .value_name("IMAGE")
+             .help("save a screenshot of the initial view to IMAGE (.png, .exr or .tiff) and exit"))
+        .arg(Arg::with_name("screenshot-size")
+             .long("screenshot-size")
+             .takes_value(true)
+             .value_name("WIDTHxHEIGHT")
+             .requires("screenshot")
+             .help("size of the screenshot, independent of the window size (default: 4000x3000)"))
+        .arg(Arg::with_name("png16")
+             .long("png16")
+             .requires("screenshot")
+             .help("save the screenshot as 16 bit instead of 8 bit PNG"))
+        .arg(Arg::with_name("rpc")
+             .long("rpc")
+             .takes_value(true)
+             .value_name("SOCKET|PORT")
+             .conflicts_with("screenshot")
+             .help("accept JSON-RPC requests on a Unix domain socket or a localhost TCP port"))
+        .arg(Arg::with_name("terminal")
+             .long("terminal")
+             .takes_value(true)
+             .min_values(0)
+             .require_equals(true)
+             .possible_values(&["auto", "blocks", "braille", "sixel", "kitty"])
+             .value_name("MODE")
+             .conflicts_with_all(&["screenshot", "rpc"])
+             .help("draw into the terminal instead of a window, as blocks, braille, sixel or kitty graphics (default: auto)"))
+        .arg(Arg::with_name("serve")
+             .long("serve")
+             .takes_value(true)
+             .min_values(0)
+             .require_equals(true)
+             .value_name("PORT")
+             .validator(is_port)
+             .conflicts_with_all(&["screenshot", "rpc", "terminal"])
+             .help("serve the plot as map tiles for a browser on a port of localhost (default: 8000)"))
+        .arg(Arg::with_name("split")
+             .long("split")
+             .takes_value(true)
+             .value_name("COLUMN")
+             .conflicts_with_all(&["terminal", "serve"])
+             .help("split the rows into colored layers by the distinct values of COLUMN"))
+        .arg(Arg::with_name("bins")
+             .long("bins")
+             .takes_value(true)
+             .possible_values(&["hexagon", "square"])
+             .value_name("SHAPE")
+             .conflicts_with_all(&["terminal", "serve", "split"])
+             .help("aggregate the points in hexagonal or square cells of the screen"))
+        .arg(Arg::with_name("bin-size")
+             .long("bin-size")
+             .takes_value(true)
+             .value_name("PIXELS")
+             .requires("bins")
+             .validator(is_positive_number)
+             .help("distance of the centers of neighbouring cells in window pixels"))
+        .arg(Arg::with_name("stat")
+             .long("stat")
+             .takes_value(true)
+             .possible_values(&stat_names)
+             .value_name("STAT")
+             .conflicts_with_all(&["terminal", "serve"])
+             .help("statistic of Z that colors the pixels or bins (default: mean); median needs --bins"))
+        .arg(Arg::with_name("contours")
+             .long("contours")
+             .takes_value(true)
+             .min_values(0)
+             .require_equals(true)
+             .value_name("PERCENTS")
+             .conflicts_with_all(&["terminal", "serve"])
+             .help("draw density contours enclosing these percentages of the points in the view (default: 50,90,99)"))
         .arg(Arg::with_name("file")
              .required(true)
              .index(1)
-             .value_name("FILE"))
+             .multiple(true)
+             .value_name("FILE")
+             .help("data to show; several files with the same columns are overlaid as layers"))
         .get_matches();
     let width = matches.value_of("width").unwrap().parse::<u32>().unwrap();
     let height = matches.value_of("height").unwrap().parse::<u32>().unwrap();
-    let file = String::from(matches.value_of("file").unwrap());
+    let files = matches.values_of("file").unwrap().map(String::from).collect::<Vec<String>>();
+    let file = files[0].clone();
+    let cache_mode = if matches.is_present("no-cache") {
+        cache::Mode::Off
+    } else if matches.is_present("rebuild-cache") {
+        cache::Mode::Rebuild
+    } else {
+        cache::Mode::Use
+    };
+
+    let screenshot = match matches.value_of("screenshot") {
+        Some(fname) => {
+            let size = matches.value_of("screenshot-size").unwrap_or(cfg::SCREENSHOT_SIZE_DEFAULT);
+            match Screenshot::new(size, fname, matches.is_present("png16")) {
+                Ok(s) => Some(s),
+                Err(s) => {
+                    error!("{}", s);
+                    return;
+                }
+            }
+        },
+        None => None,
+    };
+
+    let mut filter = Filter::new();
+    for f in matches.values_of("filter").into_iter().flat_map(|v| v) {
+        if let Err(s) = filter.add(f) {
+            error!("{}", s);
+            return;
+        }
+    }
+
+    info!("read settings");
+    let settings_result = match matches.value_of("config") {
+        Some(fname) => Settings::from_file(fname),
+        None => {
+            match settings::default_path() {
+                Some(ref path) if path.exists() => Settings::from_file(&path.to_string_lossy()),
+                _ => Ok(Settings::new()),
+            }
+        }
+    };
+    let settings = match settings_result {
+        Ok(s) => s,
+        Err(s) => {
+            error!("{}", s);
+            return;
+        }
+    };
+
+    let time_formats = matches.values_of("time").into_iter().flat_map(|v| v).map(|t| {
+        match t.find('=') {
+            Some(pos) => (String::from(&t[..pos]), TimeFormat::from_name(&t[pos + 1..])),
+            None      => (String::from(t), TimeFormat::Iso),
+        }
+    }).collect::<Vec<(String, TimeFormat)>>();
+
+    let loader = cache::Loader {
+        time_formats: time_formats,
+        mode: cache_mode,
+    };
 
     info!("read data from file");
-    let columns = match data::columns_from_file(&file) {
-        Ok(c) => c,
+    let mut datasets = vec![];
+    for f in &files {
+        match loader.load(f) {
+            Ok(c) => datasets.push(c),
+            Err(s) => {
+                error!("{}", s);
+                return;
+            }
+        }
+    }
+    let mut columns = if datasets.len() == 1 {
+        datasets.pop().unwrap()
+    } else {
+        match layers::merge(datasets) {
+            Ok(c) => c,
+            Err(s) => {
+                error!("{}", s);
+                return;
+            }
+        }
+    };
+    // the merged columns end with the file of every row
+    let file_column = if files.len() > 1 { Some(columns.len() - 1) } else { None };
+
+    for definition in matches.values_of("derive").into_iter().flat_map(|v| v) {
+        match expr::derive_column(definition, &columns) {
+            Ok(c) => columns.push(c),
+            Err(s) => {
+                error!("{}", s);
+                return;
+            }
+        }
+    }
+
+    let mut bookmarks = Bookmarks::for_data_file(&file);
+    if let Err(s) = bookmarks.load() {
+        warn!("{}", s);
+    }
+
+    if matches.is_present("terminal") {
+        let mode = terminal::Mode::from_name(matches.value_of("terminal").unwrap_or("auto")).unwrap();
+        let result = match Terminal::new(columns, file, settings, bookmarks, filter, mode) {
+            Ok(mut t) => t.run_forever(),
+            Err(s) => Err(s),
+        };
+        if let Err(s) = result {
+            error!("{}", s);
+        }
+        info!("shutting down");
+        return;
+    }
+
+    if matches.is_present("serve") {
+        let port = match matches.value_of("serve") {
+            Some(p) => p.parse::<u16>().unwrap(),
+            None => cfg::SERVE_PORT_DEFAULT,
+        };
+        let result = match TileServer::new(columns, file, settings, filter) {
+            Ok(s) => s.run_forever(port),
+            Err(s) => Err(s),
+        };
+        if let Err(s) = result {
+            error!("{}", s);
+        }
+        return;
+    }
+
+    let mut r = match Renderer::new(width, height, columns, file, settings, bookmarks, filter, screenshot.is_none()) {
+        Ok(r) => r,
         Err(s) => {
             error!("{}", s);
             return;
         }
     };
 
-    let mut r = Renderer::new(width, height, columns, file);
+    if let Some(idx) = file_column {
+        let values = (0..files.len()).map(|i| i as f64).collect();
+        let names = files.iter().map(|f| layers::file_layer_name(f)).collect();
+        r.set_layers(Layers::new(idx, values, names));
+    }
+    if let Some(shape) = matches.value_of("bins") {
+        let mut bins = Bins::new();
+        bins.shape = Shape::from_name(shape);
+        if let Some(size) = matches.value_of("bin-size") {
+            bins.size = f32::min(f32::max(size.parse::<f32>().unwrap(), cfg::BIN_SIZE_MIN), cfg::BIN_SIZE_MAX);
+        }
+        if let Err(s) = r.set_bins(bins) {
+            error!("{}", s);
+            return;
+        }
+    }
+    if let Some(stat) = matches.value_of("stat") {
+        r.set_stat(Stat::from_name(stat).unwrap());
+    }
+    if matches.is_present("contours") {
+        let mut contours = Contours::new();
+        contours.shown = true;
+        if let Some(s) = matches.value_of("contours") {
+            match Contours::parse_percents(s) {
+                Ok(percents) => contours.percents = percents,
+                Err(s) => {
+                    error!("{}", s);
+                    return;
+                }
+            }
+        }
+        r.set_contours(contours);
+    }
+    if let Some(name) = matches.value_of("split") {
+        if let Err(s) = r.split_layers(name) {
+            error!("{}", s);
+            return;
+        }
+    }
+
+    if let Some(s) = screenshot {
+        match r.screenshot(&s) {
+            Ok(_)  => info!("saved screenshot to {}", s.fname),
+            Err(s) => error!("{}", s),
+        }
+        return;
+    }
+
+    if let Some(endpoint) = matches.value_of("rpc") {
+        match Server::start(endpoint) {
+            Ok(server) => r.enable_rpc(server, loader),
+            Err(s) => {
+                error!("{}", s);
+                return;
+            }
+        }
+    }
     r.run_forever();
 
     info!("shutting down");
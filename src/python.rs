@@ -0,0 +1,256 @@
+//! Python extension module `fluxcore_ng`: loads data files and renders plots into NumPy arrays,
+//! optionally in an interactive window.
+
+use bookmarks::Bookmarks;
+
+use cache;
+use cache::Loader;
+
+use data::{Column, Kind};
+
+use datetime::TimeFormat;
+
+use filter::Filter;
+
+use image::Image;
+
+use json::Json;
+
+use numpy::{PyArray1, PyArrayMethods, PyReadonlyArray1};
+
+use pyo3::exceptions::{PyRuntimeError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyList, PyTuple};
+use pyo3::IntoPyObjectExt;
+
+use renderer::Renderer;
+
+use rpc;
+
+use screenshot;
+
+use settings;
+use settings::Settings;
+
+use std::collections::HashMap;
+use std::f64;
+
+
+/// Integral numbers become `int`, so that e.g. row counts can be used as indices.
+fn to_python(py: Python, value: &Json) -> PyResult<Py<PyAny>> {
+    match *value {
+        Json::Null => Ok(py.None()),
+        Json::Bool(b) => b.into_py_any(py),
+        Json::Number(v) if v.fract() == 0.0 && v.abs() < 9007199254740992.0 => (v as i64).into_py_any(py),
+        Json::Number(v) => v.into_py_any(py),
+        Json::String(ref s) => s.as_str().into_py_any(py),
+        Json::Array(ref values) => {
+            let list = PyList::empty(py);
+            for v in values {
+                list.append(to_python(py, v)?)?;
+            }
+            list.into_py_any(py)
+        },
+        Json::Object(ref members) => {
+            let dict = PyDict::new(py);
+            for &(ref k, ref v) in members {
+                dict.set_item(k, to_python(py, v)?)?;
+            }
+            dict.into_py_any(py)
+        },
+    }
+}
+
+fn from_python(value: &Bound<PyAny>) -> PyResult<Json> {
+    if value.is_none() {
+        Ok(Json::Null)
+    } else if let Ok(b) = value.cast::<PyBool>() {
+        Ok(Json::Bool(b.is_true()))
+    } else if let Ok(s) = value.extract::<String>() {
+        Ok(Json::String(s))
+    } else if let Ok(v) = value.extract::<f64>() {
+        Ok(Json::Number(v))
+    } else if let Ok(dict) = value.cast::<PyDict>() {
+        let mut members = vec![];
+        for (k, v) in dict.iter() {
+            members.push((k.str()?.to_string(), from_python(&v)?));
+        }
+        Ok(Json::Object(members))
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        let mut values = vec![];
+        for v in value.try_iter()? {
+            values.push(from_python(&v?)?);
+        }
+        Ok(Json::Array(values))
+    } else {
+        Err(PyTypeError::new_err(format!("cannot pass {} to the renderer", value.get_type().name()?)))
+    }
+}
+
+fn to_pyerr(e: rpc::Error) -> PyErr {
+    if e.code == rpc::FAILED {
+        PyRuntimeError::new_err(e.message)
+    } else {
+        PyValueError::new_err(e.message)
+    }
+}
+
+
+/// Converts a NumPy array, a pandas Series or a list. `datetime64` values become time columns.
+fn column_from_python(name: &str, values: &Bound<PyAny>) -> PyResult<Column> {
+    let py = values.py();
+    let np = py.import("numpy")?;
+    let array = np.call_method1("asarray", (values,))?;
+    let invalid = || PyValueError::new_err(format!("column '{}' has to be one-dimensional and numeric", name));
+
+    if array.getattr("dtype")?.getattr("kind")?.extract::<String>()? == "M" {
+        let nanos = array.call_method1("astype", ("datetime64[ns]",))?.call_method1("view", ("int64",))?;
+        let nanos = nanos.extract::<PyReadonlyArray1<i64>>().map_err(|_| invalid())?;
+        // NaT is the smallest 64 bit integer
+        let data = nanos.as_array().iter().map(|&v| if v == i64::min_value() { f64::NAN } else { (v as f64) / 1e9 }).collect();
+        let mut column = Column::from_data(name, data);
+        column.kind = Kind::Time;
+        return Ok(column);
+    }
+
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("dtype", "float64")?;
+    let floats = np.call_method("asarray", (array,), Some(&kwargs)).map_err(|_| invalid())?;
+    let floats = floats.extract::<PyReadonlyArray1<f64>>().map_err(|_| invalid())?;
+    Ok(Column::from_data(name, floats.as_array().to_vec()))
+}
+
+fn column_to_python<'py>(py: Python<'py>, column: &Column) -> PyResult<Bound<'py, PyAny>> {
+    if column.kind == Kind::Time {
+        let nanos = column.data.iter().map(|&v| if v.is_nan() { i64::min_value() } else { (v * 1e9).round() as i64 }).collect::<Vec<i64>>();
+        PyArray1::from_vec(py, nanos).call_method1("view", ("datetime64[ns]",))
+    } else {
+        Ok(PyArray1::from_slice(py, &column.data).into_any())
+    }
+}
+
+fn settings_from_file(config: Option<&str>) -> Result<Settings, String> {
+    match config {
+        Some(fname) => Settings::from_file(fname),
+        None => {
+            match settings::default_path() {
+                Some(ref path) if path.exists() => Settings::from_file(&path.to_string_lossy()),
+                _ => Ok(Settings::new()),
+            }
+        }
+    }
+}
+
+fn image_to_python<'py>(py: Python<'py>, image: &Image, raw: bool) -> PyResult<Bound<'py, PyAny>> {
+    let (width, height) = (image.width as usize, image.height as usize);
+    if raw {
+        let values = image.pixels.iter().flat_map(|p| p.iter().cloned()).collect::<Vec<f32>>();
+        Ok(PyArray1::from_vec(py, values).reshape([height, width, 4])?.into_any())
+    } else {
+        Ok(PyArray1::from_vec(py, image.to_srgb8()).reshape([height, width, 3])?.into_any())
+    }
+}
+
+
+/// Reads a CSV file into a dict of column name to array, like the viewer does. `time` maps column
+/// names to `iso`, `epoch`, `epoch_ms` or a strftime pattern; time columns become `datetime64[ns]`.
+#[pyfunction]
+#[pyo3(signature = (path, time=None, cache=true))]
+fn load<'py>(py: Python<'py>, path: &str, time: Option<HashMap<String, String>>, cache: bool) -> PyResult<Bound<'py, PyDict>> {
+    let loader = Loader {
+        time_formats: time.unwrap_or_default().into_iter().map(|(c, f)| (c, TimeFormat::from_name(&f))).collect(),
+        mode: if cache { cache::Mode::Use } else { cache::Mode::Off },
+    };
+    let columns = loader.load(path).map_err(PyRuntimeError::new_err)?;
+    let dict = PyDict::new(py);
+    for column in &columns {
+        dict.set_item(&column.name, column_to_python(py, column)?)?;
+    }
+    Ok(dict)
+}
+
+
+/// Renderer for `data`, a dict of arrays or a pandas DataFrame. It needs an OpenGL 3.3 capable
+/// display, but its window stays hidden unless `visible` is set or `show()` is called.
+#[pyclass(unsendable)]
+struct Viewer {
+    renderer: Renderer,
+}
+
+#[pymethods]
+impl Viewer {
+    #[new]
+    #[pyo3(signature = (data, width=800, height=600, filter=None, name="python", config=None, visible=false))]
+    fn new(data: &Bound<PyAny>, width: u32, height: u32, filter: Option<Vec<String>>, name: &str, config: Option<&str>, visible: bool) -> PyResult<Viewer> {
+        // dicts and DataFrames both yield (name, values) pairs
+        let mut columns = vec![];
+        for item in data.call_method0("items")?.try_iter()? {
+            let (column_name, values) = item?.extract::<(Bound<PyAny>, Bound<PyAny>)>()?;
+            columns.push(column_from_python(&column_name.str()?.to_string(), &values)?);
+        }
+        if columns.len() < 2 {
+            return Err(PyValueError::new_err("we need at least 2 columns"));
+        }
+        if columns.iter().any(|c| c.data.len() != columns[0].data.len()) {
+            return Err(PyValueError::new_err("all columns need the same length"));
+        }
+
+        let mut f = Filter::new();
+        for s in filter.unwrap_or_default() {
+            f.add(&s).map_err(PyValueError::new_err)?;
+        }
+        let settings = settings_from_file(config).map_err(PyValueError::new_err)?;
+        let mut bookmarks = Bookmarks::for_data_file(name);
+        if let Err(s) = bookmarks.load() {
+            warn!("{}", s);
+        }
+        match Renderer::new(width, height, columns, String::from(name), settings, bookmarks, f, visible) {
+            Ok(r) => Ok(Viewer { renderer: r }),
+            Err(s) => Err(PyRuntimeError::new_err(s)),
+        }
+    }
+
+    /// Runs a remote control method, e.g. `call("set_columns", x="temp", ty="rank")`, and returns
+    /// its result.
+    #[pyo3(signature = (method, **params))]
+    fn call(&mut self, py: Python, method: &str, params: Option<&Bound<PyDict>>) -> PyResult<Py<PyAny>> {
+        let params = match params {
+            Some(p) => from_python(p.as_any())?,
+            None => Json::Object(vec![]),
+        };
+        let result = self.renderer.command(method, &params).map_err(to_pyerr)?;
+        to_python(py, &result)
+    }
+
+    /// Columns, view and filter, as returned by every method of `call`.
+    fn state(&mut self, py: Python) -> PyResult<Py<PyAny>> {
+        self.call(py, "get_state", None)
+    }
+
+    /// Renders the current view, by default at the window size. Returns the tone mapped image as
+    /// `uint8` array of shape `(height, width, 3)`, or with `raw` the accumulation buffer as
+    /// `float32` array of shape `(height, width, 4)`. Rows are ordered from top to bottom.
+    #[pyo3(signature = (width=None, height=None, raw=false))]
+    fn render<'py>(&mut self, py: Python<'py>, width: Option<u32>, height: Option<u32>, raw: bool) -> PyResult<Bound<'py, PyAny>> {
+        let size = self.renderer.window_size();
+        let (width, height) = (width.unwrap_or(size.0), height.unwrap_or(size.1));
+        if let Err(s) = screenshot::check_size(width, height) {
+            return Err(PyValueError::new_err(s));
+        }
+        let (accumulation, tonemapped) = self.renderer.render_image(width, height);
+        image_to_python(py, if raw { &accumulation } else { &tonemapped }, raw)
+    }
+
+    /// Opens the interactive window and returns once it is closed (`Q`). The view is kept.
+    fn show(&mut self) {
+        self.renderer.show();
+    }
+}
+
+
+#[pymodule]
+fn fluxcore_ng(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(self::load, m)?)?;
+    m.add_class::<Viewer>()?;
+    Ok(())
+}
@@ -1,19 +1,79 @@
 extern crate glium;
 extern crate log;
 
+use animation::Animation;
+
+use axes;
+
+use bins;
+use bins::{Bins, CellGrid, Shape, Target};
+
+use contours;
+use contours::Contours;
+
+use bookmarks::Bookmarks;
+
+use cache::Loader;
+
 use cfg;
 
-use glium::{DisplayBuild, Surface};
-use glium::backend::Facade;
+use glium::{DisplayBuild, Frame, Surface};
+use glium::backend::{Context, Facade};
 use glium::backend::glutin_backend::GlutinFacade;
 use glium::glutin;
 
-use data;
-use data::{Column, Point};
+use data::{Column, Kind, Point};
+
+use figure;
+use figure::Figure;
+
+use filter::Filter;
+
+use gpu::GpuColumns;
+
+use grid;
+use grid::Grid;
+
+use history::Gesture;
+
+use image::Image;
+
+use json::Json;
+
+use keymap::{Action, Key, Keymap};
+
+use layers;
+use layers::{Composite, Layers};
+
+use lod::{Lod, Rect};
 
 use res;
 
+use rpc;
+use rpc::Server;
+
+use screenshot::Screenshot;
+
+use settings::Settings;
+
+use stat;
+use stat::Stat;
+
+use text;
+use text::{TextBatch, TextRenderer};
+
+use transform::Transform;
+
+use video::VideoWriter;
+
+use view;
+use view::{ColumnState, Projection, Prompt, View};
+
+use std::cmp;
 use std::f32;
+use std::mem;
+use std::ops::Range;
+use std::rc::Rc;
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -24,7 +84,7 @@ struct TextureVertex {
     tex_coords: [f32; 2],
 }
 
-implement_vertex!(Point, position);
+implement_vertex!(Point, position, weight);
 implement_vertex!(TextureVertex, position, tex_coords);
 
 
@@ -52,144 +112,136 @@ fn build_renderable_texture<F>(facade: &F, window_dims: &WindowDims) -> glium::T
     ).unwrap()
 }
 
-
-struct Projection {
-    scale_x: f32,
-    scale_y: f32,
-    scale_z: f32,
-    delta_x: f32,
-    delta_y: f32,
-    delta_z: f32,
-}
-
-impl Projection {
-    fn new() -> Projection {
-        Projection {
-            scale_x: 1.0,
-            scale_y: 1.0,
-            scale_z: 1.0,
-            delta_x: 0.0,
-            delta_y: 0.0,
-            delta_z: 0.0,
-        }
-    }
-
-    fn adjust_x(&mut self, min: f32, max: f32) {
-        if (max - min).abs() > f32::EPSILON {
-            self.scale_x = 2.0 / (max - min);
-        }
-        self.delta_x = -1.0 - min * self.scale_x;
-        debug!("adjust x projection: data_range=[{}, {}] scale={} delta={}", min, max, self.scale_x, self.delta_x);
+/// Adds up everything drawn, for accumulating points and compositing layers.
+fn additive_blend() -> glium::Blend {
+    glium::Blend {
+        color: glium::BlendingFunction::Addition {
+            source: glium::LinearBlendingFactor::One,
+            destination: glium::LinearBlendingFactor::One,
+        },
+        alpha: glium::BlendingFunction::Addition {
+            source: glium::LinearBlendingFactor::One,
+            destination: glium::LinearBlendingFactor::One
+        },
+        constant_value: (0.0, 0.0, 0.0, 0.0)
     }
+}
 
-    fn adjust_y(&mut self, min: f32, max: f32) {
-        if (max - min).abs() > f32::EPSILON {
-            self.scale_y = 2.0 / (max - min);
-        }
-        self.delta_y = -1.0 - min * self.scale_y;
-        debug!("adjust y projection: data_range=[{}, {}] scale={} delta={}", min, max, self.scale_y, self.delta_y);
+/// Keeps the largest value of every channel, for the extrema of the bins.
+fn max_blend() -> glium::Blend {
+    glium::Blend {
+        color: glium::BlendingFunction::Max,
+        alpha: glium::BlendingFunction::Max,
+        constant_value: (0.0, 0.0, 0.0, 0.0)
     }
+}
 
-    fn adjust_z(&mut self, min: f32, max: f32) {
-        if (max - min).abs() > f32::EPSILON {
-            self.scale_z = 1.0 / (max - min);
+/// Largest difference of the counters of two accumulation textures of size `dims`, in units of the
+/// rows spread evenly over all pixels. `margin` pixels at the edges, where the border markers pile
+/// up, are left out.
+fn max_difference(a: &glium::Texture2d, b: &glium::Texture2d, dims: &WindowDims, margin: u32) -> f32 {
+    let rect = glium::Rect {
+        left: 0,
+        bottom: 0,
+        width: dims.width,
+        height: dims.height,
+    };
+    let read = |t: &glium::Texture2d| -> Vec<Vec<(f32, f32, f32, f32)>> {
+        t.main_level().first_layer().into_image(None).unwrap().raw_read(&rect)
+    };
+    let (rows_a, rows_b) = (read(a), read(b));
+    let pixels = (dims.width * dims.height) as f32;
+    let mut max = 0.0;
+    for row in (margin as usize)..(dims.height.saturating_sub(margin) as usize) {
+        for col in (margin as usize)..(dims.width.saturating_sub(margin) as usize) {
+            max = f32::max(max, (rows_a[row][col].3 - rows_b[row][col].3).abs() * pixels);
         }
-        self.delta_z = -min * self.scale_z;
-        debug!("adjust z projection: data_range=[{}, {}] scale={} delta={}", min, max, self.scale_z, self.delta_z);
-    }
-
-    fn move_x(&mut self, dx: i32, width: u32) {
-        self.delta_x += 2.0 * (dx as f32) / (width as f32);
-    }
-
-    fn move_y(&mut self, dy: i32, height: u32) {
-        self.delta_y -= 2.0 * (dy as f32) / (height as f32);
-    }
-
-    fn scroll_x(&mut self, dx: f32, posx: u32, width: u32) {
-        let posx_relative = 2.0 * (posx as f32) / (width as f32) - 1.0;
-        let scale_x_old = self.scale_x;
-        let factor_x = cfg::SCROLL_BASE.powf(dx);
-        self.scale_x = f32::max(cfg::SCALE_MIN, self.scale_x * factor_x);
-        self.delta_x += (scale_x_old - self.scale_x) * (posx_relative - self.delta_x) / scale_x_old;
-    }
-
-    fn scroll_y(&mut self, dy: f32, posy: u32, height: u32) {
-        let posy_relative = -(2.0 * (posy as f32) / (height as f32) - 1.0);
-        let scale_y_old = self.scale_y;
-        let factor_y = cfg::SCROLL_BASE.powf(dy);
-        self.scale_y = f32::max(cfg::SCALE_MIN, self.scale_y * factor_y);
-        self.delta_y += (scale_y_old - self.scale_y) * (posy_relative - self.delta_y) / scale_y_old;
-    }
-
-    fn get_matrix(&self) -> [[f32; 4]; 4] {
-        [
-            [self.scale_x, 0.0         , 0.0         , 0.0],
-            [0.0         , self.scale_y, 0.0         , 0.0],
-            [0.0         , 0.0         , self.scale_z, 0.0],
-            [self.delta_x, self.delta_y, self.delta_z, 1.0],
-        ]
     }
+    max
 }
 
-struct ColumnState {
-    x: usize,
-    y: usize,
-    z: usize,
+/// Accumulation of the points for the statistics of Z that need more than the sums, in pixels or
+/// cells of `width` x `height`.
+struct StatTextures {
+    stat: Stat,
+    width: u32,
+    height: u32,
+    /// largest Z and negative smallest Z, 1x1 unless needed
+    extrema: glium::Texture2d,
+    /// presence of the categories of Z, four per texture as in the shaders; 1x1 unless needed
+    categories: Vec<glium::Texture2d>,
 }
 
-impl ColumnState {
-    fn new(m: usize) -> ColumnState {
-        ColumnState {
-            x: 0,
-            y: 1,
-            z: if m > 2 { 2 } else { 1 },
-        }
-    }
-
-    fn x_prev(&mut self, m: usize) {
-        if self.x == 0 {
-            self.x = m;
-        }
-        self.x -= 1;
-    }
-
-    fn y_prev(&mut self, m: usize) {
-        if self.y == 0 {
-            self.y = m;
-        }
-        self.y -= 1;
-    }
-
-    fn z_prev(&mut self, m: usize) {
-        if self.z == 0 {
-            self.z = m;
-        }
-        self.z -= 1;
+fn build_stat_textures<F>(facade: &F, stat: Stat, width: u32, height: u32) -> StatTextures where F: Facade {
+    let unused = WindowDims { width: 1, height: 1 };
+    let dims = WindowDims { width: width, height: height };
+    let extrema = build_renderable_texture(facade, if stat == Stat::Min || stat == Stat::Max { &dims } else { &unused });
+    let categories = (0..(cfg::CATEGORIES_MAX / 4)).map(|_| {
+        let d = if stat == Stat::Distinct { &dims } else { &unused };
+        glium::Texture2d::empty_with_format(
+            facade,
+            glium::texture::UncompressedFloatFormat::U8U8U8U8,
+            glium::texture::MipmapsOption::NoMipmap,
+            d.width,
+            d.height
+        ).unwrap()
+    }).collect();
+    StatTextures {
+        stat: stat,
+        width: width,
+        height: height,
+        extrema: extrema,
+        categories: categories,
     }
+}
 
-    fn x_next(&mut self, m: usize) {
-        self.x += 1;
-        if self.x >= m {
-            self.x = 0;
+/// Smallest and largest sum or standard deviation of Z over the texels of `sums` with points,
+/// leaving out `margin` texels at the edges. `z` are the rows, scale and offset of the Z projection,
+/// like the uniform of the shaders.
+fn stat_range(sums: &glium::Texture2d, width: u32, height: u32, margin: u32, stat: Stat, z: [f32; 3]) -> (f32, f32) {
+    let rect = glium::Rect {
+        left: 0,
+        bottom: 0,
+        width: width,
+        height: height,
+    };
+    let rows: Vec<Vec<(f32, f32, f32, f32)>> = sums.main_level().first_layer().into_image(None).unwrap().raw_read(&rect);
+    let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+    for row in (margin as usize)..(height.saturating_sub(margin) as usize) {
+        for col in (margin as usize)..(width.saturating_sub(margin) as usize) {
+            let (_, g, b, counter) = rows[row][col];
+            if counter <= 0.0 {
+                continue;
+            }
+            let value = if stat == Stat::Sum {
+                z[0] * (g - z[2] * counter) / z[1]
+            } else {
+                let mean = g / counter;
+                f32::max(b / counter - mean * mean, 0.0).sqrt() / z[1].abs()
+            };
+            min = f32::min(min, value);
+            max = f32::max(max, value);
         }
     }
-
-    fn y_next(&mut self, m: usize) {
-        self.y += 1;
-        if self.y >= m {
-            self.y = 0;
-        }
+    if min > max {
+        (0.0, 0.0)
+    } else if stat == Stat::Std {
+        (0.0, max)
+    } else {
+        (min, max)
     }
+}
 
-    fn z_next(&mut self, m: usize) {
-        self.z += 1;
-        if self.z >= m {
-            self.z = 0;
-        }
-    }
+/// Points accumulated in the cells of `grid`, see `bins::Target`.
+struct BinTextures {
+    grid: CellGrid,
+    cells: glium::Texture2d,
+    stats: StatTextures,
+    /// 1x1 unless the bins show the median Z
+    histogram: glium::Texture2d,
 }
 
+
 struct MouseState {
     x: u32,
     y: u32,
@@ -206,92 +258,128 @@ impl MouseState {
     }
 }
 
-struct UserState {
-    gamma: f32,
-    pointsize: f32,
-    showborder: bool,
+struct ModifierState {
+    ctrl: bool,
+    shift: bool,
 }
 
-impl UserState {
-    fn new() -> UserState {
-        UserState {
-            gamma:      cfg::GAMMA_DEFAULT,
-            pointsize:  cfg::POINTSIZE_DEFAULT,
-            showborder: cfg::SHOWBORDER_DEFAULT,
+impl ModifierState {
+    fn new() -> ModifierState {
+        ModifierState {
+            ctrl: false,
+            shift: false,
         }
     }
 
-    fn reset(&mut self) {
-        self.gamma      = cfg::GAMMA_DEFAULT;
-        self.pointsize  = cfg::POINTSIZE_DEFAULT;
-        self.showborder = cfg::SHOWBORDER_DEFAULT;
-    }
-
-    fn showborder_toggle(&mut self) {
-        self.showborder = !self.showborder;
-    }
-
-    fn pointsize_increase(&mut self) {
-        self.pointsize = f32::min(self.pointsize * cfg::POINTSIZE_CHANGE, cfg::POINTSIZE_MAX);
-    }
-
-    fn pointsize_decrease(&mut self) {
-        self.pointsize = f32::max(self.pointsize / cfg::POINTSIZE_CHANGE, cfg::POINTSIZE_MIN);
+    fn update(&mut self, state: glutin::ElementState, code: glutin::VirtualKeyCode) {
+        let pressed = state == glutin::ElementState::Pressed;
+        match code {
+            glutin::VirtualKeyCode::LControl | glutin::VirtualKeyCode::RControl => self.ctrl = pressed,
+            glutin::VirtualKeyCode::LShift | glutin::VirtualKeyCode::RShift     => self.shift = pressed,
+            _ => ()
+        }
     }
 
-    fn gamma_increase(&mut self) {
-        self.gamma = f32::min(self.gamma * cfg::GAMMA_CHANGE, cfg::GAMMA_MAX);
-
+    fn key(&self, code: glutin::VirtualKeyCode) -> Key {
+        Key {
+            code: code,
+            ctrl: self.ctrl,
+            shift: self.shift,
+        }
     }
+}
 
-    fn gamma_decrease(&mut self) {
-        self.gamma = f32::max(self.gamma / cfg::GAMMA_CHANGE, cfg::GAMMA_MIN);
-    }
+enum PromptPurpose {
+    BookmarkName(usize),
+    Filter,
+    SplitLayers,
+    ContourLevels,
+    DeriveColumn,
+    ExportFigure,
+    Screenshot,
+    ExportGrid,
+    Animate,
+    ExportAnimation,
 }
 
 pub struct Renderer {
     window_dims: WindowDims,
-    columns: Vec<Column>,
-    column_state: ColumnState,
+    fname: String,
+    view: View,
+    /// data coordinates that the uploaded points are relative to
+    origin: [f64; 3],
+    /// aggregation of the current columns, only for large datasets
+    lod: Option<Lod>,
+    /// LOD level (`None` for raw points) and region of the uploaded points
+    lod_view: Option<(Option<usize>, Rect)>,
+    /// rows are shown frame by frame, only without LOD
+    animation: Option<Animation>,
+    /// rows split by the values of a column, e.g. the file they came from
+    layers: Option<Layers>,
+    /// range of every layer in `row_indices`, empty unless the layers are drawn one by one
+    layer_rows: Vec<Range<usize>>,
+    /// largest difference of the diverging composite in the view, relative to the rows spread
+    /// evenly over it
+    diverging_scale: f32,
+    bins: Bins,
+    /// accumulation of the bins of the window, `None` while the points are drawn
+    bin_textures: Option<BinTextures>,
+    stat: Stat,
+    /// sum or standard deviation of Z at the ends of the color map, those in the view
+    stat_range: (f32, f32),
+    /// distinct values of Z for `Stat::Distinct`, found when needed
+    z_categories: Option<Result<Vec<f64>, String>>,
+    contours: Contours,
+    /// contours of the last drawn view, in window pixels from the top left corner
+    contour_lines: Vec<contours::Line>,
     n: usize,
-    m: usize,
-    display: GlutinFacade,
-    user_state: UserState,
-    projection: Projection,
+    /// OpenGL context, either of `window` or of the application the renderer is embedded into
+    context: Rc<Context>,
+    window: Option<GlutinFacade>,
+    /// input events passed on by an embedding application
+    pending_events: Vec<glutin::Event>,
     mouse_state: MouseState,
+    modifier_state: ModifierState,
+    keymap: Keymap,
+    show_help: bool,
+    prompt: Option<Prompt<PromptPurpose>>,
+    /// the character of the key that opened the prompt, which arrives after the key, is not input
+    swallow_char: bool,
+    status: Option<(String, Instant)>,
     last_frame: Instant,
     redraw: bool,
+    /// skip the low resolution preview for the next redraw, e.g. for animation frames
+    redraw_full: bool,
     lowres: bool,
     lowres_start: Instant,
     vertex_buffer_points: glium::VertexBuffer<Point>,
+    /// selected rows, drawn from `gpu_columns` when there is no LOD
+    row_indices: glium::IndexBuffer<u32>,
+    gpu_columns: GpuColumns,
     vertex_buffer_texture: glium::VertexBuffer<TextureVertex>,
     indices_points: glium::index::NoIndices,
     indices_texture: glium::index::NoIndices,
     texture_lowres: glium::Texture2d,
     texture_std: glium::Texture2d,
+    stat_lowres: StatTextures,
+    stat_std: StatTextures,
+    /// full and low resolution accumulation of every layer
+    layer_textures: Vec<(glium::Texture2d, glium::Texture2d)>,
     program_points: glium::Program,
+    program_columns: glium::Program,
     program_texture: glium::Program,
+    program_layers: glium::Program,
+    program_bins: glium::Program,
+    text_renderer: TextRenderer,
+    /// remote control, with the options to load further files
+    rpc: Option<(Server, Loader)>,
 }
 
 impl Renderer {
-    pub fn new(width: u32, height: u32, columns: Vec<Column>, fname: String) -> Renderer {
+    /// A hidden window (`visible` false) is enough for offscreen rendering.
+    pub fn new(width: u32, height: u32, columns: Vec<Column>, fname: String, settings: Settings, bookmarks: Bookmarks, filter: Filter, visible: bool) -> Result<Renderer, String> {
         info!("set up OpenGL stuff");
 
-        let window_dims = WindowDims{width: width, height: height};
-
-        let m = columns.len();
-        let column_state = ColumnState::new(m);
-        let points = data::points_from_columns(&columns, column_state.x, column_state.y, column_state.z);
-
-        let vertices_texture = vec![
-            TextureVertex { position: [-1.0, -1.0], tex_coords: [0.0, 0.0] },
-            TextureVertex { position: [ 1.0,  1.0], tex_coords: [1.0, 1.0] },
-            TextureVertex { position: [-1.0,  1.0], tex_coords: [0.0, 1.0] },
-            TextureVertex { position: [-1.0, -1.0], tex_coords: [0.0, 0.0] },
-            TextureVertex { position: [ 1.0, -1.0], tex_coords: [1.0, 0.0] },
-            TextureVertex { position: [ 1.0,  1.0], tex_coords: [1.0, 1.0] },
-        ];
-
         let display = glutin::WindowBuilder::new()
             .with_dimensions(width, height)
             .with_gl(glutin::GlRequest::Specific(
@@ -300,9 +388,39 @@ impl Renderer {
             ))
             .with_srgb(Some(true))
             .with_gl_profile(glutin::GlProfile::Core)
-            .with_title(format!("fluxcore_ng - {}", fname))
+            .with_title(format!("fluxcore_ng - {}", &fname))
+            .with_visibility(visible)
             .build_glium()
             .unwrap();
+        let context = display.get_context().clone();
+
+        Renderer::with_context(context, Some(display), width, height, columns, fname, settings, bookmarks, filter)
+    }
+
+    /// Draws into the OpenGL context of another application, which passes on the input events
+    /// via `push_event` and calls `run_once` for every frame.
+    pub fn embedded(context: Rc<Context>, width: u32, height: u32, columns: Vec<Column>, fname: String, settings: Settings, bookmarks: Bookmarks, filter: Filter) -> Result<Renderer, String> {
+        Renderer::with_context(context, None, width, height, columns, fname, settings, bookmarks, filter)
+    }
+
+    fn with_context(context: Rc<Context>, window: Option<GlutinFacade>, width: u32, height: u32, columns: Vec<Column>, fname: String, settings: Settings, bookmarks: Bookmarks, filter: Filter) -> Result<Renderer, String> {
+        let view = match View::new(columns, filter, bookmarks, &settings) {
+            Ok(v) => v,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+
+        let window_dims = WindowDims{width: width, height: height};
+
+        let vertices_texture = vec![
+            TextureVertex { position: [-1.0, -1.0], tex_coords: [0.0, 0.0] },
+            TextureVertex { position: [ 1.0,  1.0], tex_coords: [1.0, 1.0] },
+            TextureVertex { position: [-1.0,  1.0], tex_coords: [0.0, 1.0] },
+            TextureVertex { position: [-1.0, -1.0], tex_coords: [0.0, 0.0] },
+            TextureVertex { position: [ 1.0, -1.0], tex_coords: [1.0, 0.0] },
+            TextureVertex { position: [ 1.0,  1.0], tex_coords: [1.0, 1.0] },
+        ];
 
         let source_code_points = glium::program::ProgramCreationInput::SourceCode {
             fragment_shader: res::FRAGMENT_SHADER_POINTS_SRC,
@@ -315,42 +433,87 @@ impl Renderer {
             vertex_shader: res::VERTEX_SHADER_POINTS_SRC,
         };
 
-        let mut projection = Projection::new();
-        projection.adjust_x(columns[column_state.x].min, columns[column_state.x].max);
-        projection.adjust_y(columns[column_state.y].min, columns[column_state.y].max);
-        projection.adjust_z(columns[column_state.z].min, columns[column_state.z].max);
-
-
-        let vertex_buffer_points  = glium::VertexBuffer::new(&display, &points).unwrap();
-        let vertex_buffer_texture = glium::VertexBuffer::new(&display, &vertices_texture).unwrap();
-        let texture_std           = build_renderable_texture(&display, &window_dims);
-        let texture_lowres        = build_renderable_texture(&display, &window_dims.to_lowres());
-        let program_points        = glium::Program::new(&display, source_code_points).unwrap();
-        let program_texture       = glium::Program::from_source(&display, res::VERTEX_SHADER_TEXTURE_SRC, res::FRAGMENT_SHADER_TEXTURE_SRC, None).unwrap();
+        let source_code_columns = glium::program::ProgramCreationInput::SourceCode {
+            fragment_shader: res::FRAGMENT_SHADER_POINTS_SRC,
+            geometry_shader: None,
+            outputs_srgb: false,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
+            transform_feedback_varyings: None,
+            uses_point_size: true,
+            vertex_shader: res::VERTEX_SHADER_COLUMNS_SRC,
+        };
 
-        Renderer {
+        let vertex_buffer_points  = glium::VertexBuffer::new(&context, &[]).unwrap();
+        let row_indices           = glium::IndexBuffer::new(&context, glium::index::PrimitiveType::Points, &[]).unwrap();
+        let vertex_buffer_texture = glium::VertexBuffer::new(&context, &vertices_texture).unwrap();
+        let texture_std           = build_renderable_texture(&context, &window_dims);
+        let texture_lowres        = build_renderable_texture(&context, &window_dims.to_lowres());
+        let stat_std              = build_stat_textures(&context, Stat::Mean, window_dims.width, window_dims.height);
+        let stat_lowres           = build_stat_textures(&context, Stat::Mean, window_dims.to_lowres().width, window_dims.to_lowres().height);
+        let program_points        = glium::Program::new(&context, source_code_points).unwrap();
+        let program_columns       = glium::Program::new(&context, source_code_columns).unwrap();
+        let program_texture       = glium::Program::from_source(&context, res::VERTEX_SHADER_TEXTURE_SRC, res::FRAGMENT_SHADER_TEXTURE_SRC, None).unwrap();
+        let program_layers        = glium::Program::from_source(&context, res::VERTEX_SHADER_TEXTURE_SRC, res::FRAGMENT_SHADER_LAYERS_SRC, None).unwrap();
+        let program_bins          = glium::Program::from_source(&context, res::VERTEX_SHADER_TEXTURE_SRC, res::FRAGMENT_SHADER_BINS_SRC, None).unwrap();
+        let text_renderer         = TextRenderer::new(&context);
+
+        let mut renderer = Renderer {
             window_dims: window_dims,
-            columns: columns,
-            column_state: column_state,
-            n: points.len(),
-            m: m,
-            display: display,
-            user_state: UserState::new(),
-            projection: projection,
+            fname: fname,
+            view: view,
+            origin: [0.0, 0.0, 0.0],
+            lod: None,
+            lod_view: None,
+            animation: None,
+            layers: None,
+            layer_rows: vec![],
+            diverging_scale: 0.0,
+            bins: Bins::new(),
+            bin_textures: None,
+            stat: Stat::Mean,
+            stat_range: (0.0, 0.0),
+            z_categories: None,
+            contours: Contours::new(),
+            contour_lines: vec![],
+            n: 0,
+            context: context,
+            window: window,
+            pending_events: vec![],
             mouse_state: MouseState::new(),
+            modifier_state: ModifierState::new(),
+            keymap: settings.keymap,
+            show_help: false,
+            prompt: None,
+            swallow_char: false,
+            status: None,
             last_frame: Instant::now(),
             redraw: true,
+            redraw_full: false,
             lowres: false,
             lowres_start: Instant::now(),
             vertex_buffer_points: vertex_buffer_points,
+            row_indices: row_indices,
+            gpu_columns: GpuColumns::new(),
             vertex_buffer_texture: vertex_buffer_texture,
             indices_points: glium::index::NoIndices(glium::index::PrimitiveType::Points),
             indices_texture: glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
             texture_std: texture_std,
             texture_lowres: texture_lowres,
+            stat_std: stat_std,
+            stat_lowres: stat_lowres,
+            layer_textures: vec![],
             program_points: program_points,
+            program_columns: program_columns,
             program_texture: program_texture,
-        }
+            program_layers: program_layers,
+            program_bins: program_bins,
+            text_renderer: text_renderer,
+            rpc: None,
+        };
+        renderer.update_rows();
+        renderer.update_geometry();
+        Ok(renderer)
     }
 
     pub fn run_forever(&mut self) {
@@ -360,6 +523,101 @@ impl Renderer {
             if !next {
                 break;
             }
+            self.throttle();
+        }
+    }
+
+    /// The file or name the data came from.
+    pub fn data_name(&self) -> String {
+        self.fname.clone()
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        (self.window_dims.width, self.window_dims.height)
+    }
+
+    /// Shows a window created hidden and runs until it is closed.
+    pub fn show(&mut self) {
+        if let Some(window) = self.window.as_ref().and_then(|w| w.get_window()) {
+            window.show();
+        }
+        self.redraw = true;
+        self.run_forever();
+        if let Some(window) = self.window.as_ref().and_then(|w| w.get_window()) {
+            window.hide();
+        }
+    }
+
+    /// Queues an input event for the next `run_once`, for renderers without a window of their own.
+    pub fn push_event(&mut self, event: glutin::Event) {
+        self.pending_events.push(event);
+    }
+
+    /// Accepts remote control requests from `server` while running.
+    pub fn enable_rpc(&mut self, server: Server, loader: Loader) {
+        self.rpc = Some((server, loader));
+    }
+
+    /// Draws the rows split into `layers`, each in its own color. Stops a running animation.
+    pub fn set_layers(&mut self, layers: Layers) {
+        self.animation = None;
+        self.bins.shape = None;
+        let stat = self.stat;
+        self.set_stat(stat);
+        self.layers = Some(layers);
+        self.update_rows();
+        self.update_lod();
+        self.redraw = true;
+    }
+
+    /// Aggregates the points in `bins`, which do not work together with layers.
+    pub fn set_bins(&mut self, bins: Bins) -> Result<(), String> {
+        if bins.shape.is_some() && self.layers.is_some() {
+            return Err(String::from("bins do not work with layers, merge them first"));
+        }
+        self.bins = bins;
+        let stat = self.stat;
+        self.set_stat(stat);
+        Ok(())
+    }
+
+    /// Colors the pixels or bins by `stat` of Z. The median needs bins and falls back to the mean
+    /// without them.
+    pub fn set_stat(&mut self, stat: Stat) {
+        self.stat = if stat == Stat::Median && self.bins.shape.is_none() { Stat::Mean } else { stat };
+        self.redraw = true;
+    }
+
+    /// Draws the density `contours` over the points, or not.
+    pub fn set_contours(&mut self, contours: Contours) {
+        self.contours = contours;
+        self.redraw = true;
+    }
+
+    /// Splits the rows into layers by the distinct values of column `name`, or draws them together
+    /// again for an empty `name`.
+    pub fn split_layers(&mut self, name: &str) -> Result<(), String> {
+        if name.is_empty() {
+            self.layers = None;
+            self.update_rows();
+            self.update_lod();
+            self.redraw = true;
+            self.set_status(String::from("removed layers"));
+            return Ok(());
+        }
+        let idx = match self.view.columns.iter().position(|c| c.name == name) {
+            Some(i) => i,
+            None => {
+                return Err(format!("cannot split by unknown column '{}'", name));
+            }
+        };
+        match Layers::split(&self.view.columns, idx) {
+            Ok(layers) => {
+                self.set_status(format!("split into {} layers by {}", layers.len(), name));
+                self.set_layers(layers);
+                Ok(())
+            },
+            Err(s) => Err(s),
         }
     }
 
@@ -369,50 +627,415 @@ impl Renderer {
 
         let mut rebuild_points = false;
         let mut exit = false;
+        let view = self.view.view_state();
         self.handle_events(&mut rebuild_points, &mut exit);
         if exit {
             return false;
         }
+        if self.rpc.is_some() {
+            if self.view.view_state() != view {
+                self.rpc_notify("view_changed");
+            }
+            self.handle_rpc(&mut rebuild_points, &mut exit);
+            if exit {
+                return false;
+            }
+        }
+
+        let next_frame = match self.animation {
+            Some(ref mut a) => a.tick(),
+            None => false,
+        };
+        if next_frame {
+            self.redraw = true;
+            self.redraw_full = true;
+        }
 
         if rebuild_points {
             self.update_geometry();
+        } else if self.points_stale() {
+            self.upload_points();
         }
 
-        self.throttle();
-
         true
     }
 
-    fn render_to_textures(&mut self) {
+    /// Draws either the LOD points or the selected rows straight from the GPU columns, only those of
+    /// `layer` if given, accumulating `target`. With `cells`, every point lands on the texel of its
+    /// cell instead.
+    fn draw_points<S>(&self, surface: &mut S, projection: &Projection, pointsize: f32, showborder: bool, layer: Option<usize>, cells: Option<&CellGrid>, target: Target) where S: Surface {
+        if self.n == 0 {
+            return;
+        }
+
         let params_points = glium::DrawParameters {
-            blend: glium::Blend {
-                color: glium::BlendingFunction::Addition {
-                    source: glium::LinearBlendingFactor::One,
-                    destination: glium::LinearBlendingFactor::One,
-                },
-                alpha: glium::BlendingFunction::Addition {
-                    source: glium::LinearBlendingFactor::One,
-                    destination: glium::LinearBlendingFactor::One
-                },
-                constant_value: (0.0, 0.0, 0.0, 0.0)
+            blend: match target {
+                Target::Extrema | Target::Categories(_) => max_blend(),
+                _ => additive_blend(),
             },
             .. Default::default()
         };
-
-        if self.redraw {
-            self.texture_lowres.as_surface().clear_color(0.0, 0.0, 0.0, 0.0);
-            self.texture_lowres.as_surface().draw(
+        let showborder = if showborder && cells.is_none() { 1f32 } else { 0f32 };
+        let (bin_size, bin_shape, bin_image, bin_cells) = match cells {
+            Some(grid) => (grid.size, grid.shape_index(), [grid.width as f32, grid.height as f32], [grid.cols as f32, grid.rows as f32]),
+            None => (0.0, 0.0, [0.0, 0.0], [0.0, 0.0]),
+        };
+        let bin_target = if target == Target::Histogram { 1f32 } else { 0f32 };
+        let extrema = if target == Target::Extrema { 1f32 } else { 0f32 };
+        let moments = if target == Target::Moments { 1f32 } else { 0f32 };
+        let category_base = if let Target::Categories(base) = target { base as f32 } else { -1f32 };
+        let (categories, category_count) = self.category_uniforms(projection);
+
+        if self.lod.is_some() {
+            surface.draw(
                 &self.vertex_buffer_points,
                 &self.indices_points,
                 &self.program_points,
                 &uniform! {
-                    matrix: self.projection.get_matrix(),
+                    matrix: projection.get_matrix(&self.origin),
                     inv_n:     1.0 / (self.n as f32),
-                    pointsize: self.user_state.pointsize * cfg::LOWRES_FACTOR,
-                    showborder: if self.user_state.showborder { 1f32 } else { 0f32 },
+                    pointsize: pointsize,
+                    showborder: showborder,
+                    bin_size: bin_size,
+                    bin_shape: bin_shape,
+                    bin_image: bin_image,
+                    bin_cells: bin_cells,
+                    bin_target: bin_target,
+                    extrema: extrema,
+                    moments: moments,
+                    categories: categories,
+                    category_count: category_count,
+                    category_base: category_base,
                 },
                 &params_points
             ).unwrap();
+        } else {
+            let cs = &self.view.column_state;
+            let x = self.gpu_columns.get(cs.x, cs.tx);
+            let y = self.gpu_columns.get(cs.y, cs.ty);
+            let z = self.gpu_columns.get(cs.z, cs.tz);
+            // an animation draws ranges of the rows ordered by frame, with their own weights
+            // every layer is normalised by its own number of rows
+            let (passes, n) = match (&self.animation, layer) {
+                (&Some(ref a), _) => (a.passes().into_iter().map(|(rows, weight)| (Some(rows), weight)).collect(), cmp::max(a.visible_rows(), 1)),
+                (&None, Some(l)) => (vec![(Some(self.layer_rows[l].clone()), 1.0)], cmp::max(self.layer_rows[l].len(), 1)),
+                (&None, None) => (vec![(None, 1.0)], self.n),
+            };
+            for (rows, weight) in passes {
+                let indices: glium::index::IndicesSource = match rows {
+                    Some(ref r) if r.start == r.end => continue,
+                    Some(r) => self.row_indices.slice(r).unwrap().into(),
+                    None => (&self.row_indices).into(),
+                };
+                surface.draw(
+                    glium::vertex::EmptyVertexAttributes { len: self.view.mask.len() },
+                    indices,
+                    &self.program_columns,
+                    &uniform! {
+                        matrix: projection.get_matrix(&self.origin),
+                        inv_n:     1.0 / (n as f32),
+                        pointsize: pointsize,
+                        showborder: showborder,
+                        frame_weight: weight as f32,
+                        col_x: &x.texture,
+                        col_y: &y.texture,
+                        col_z: &z.texture,
+                        offset_x: x.offset(self.origin[0]),
+                        offset_y: y.offset(self.origin[1]),
+                        offset_z: z.offset(self.origin[2]),
+                        bin_size: bin_size,
+                        bin_shape: bin_shape,
+                        bin_image: bin_image,
+                        bin_cells: bin_cells,
+                        bin_target: bin_target,
+                        extrema: extrema,
+                        moments: moments,
+                        categories: categories,
+                        category_count: category_count,
+                        category_base: category_base,
+                    },
+                    &params_points
+                ).unwrap();
+            }
+        }
+    }
+
+    /// Accumulates the points into the full or low resolution texture, or into those of every layer.
+    fn draw_textures(&mut self, lowres: bool) {
+        self.contour_lines = if self.contours.shown { self.trace_contours() } else { vec![] };
+
+        if self.stat == Stat::Distinct && self.z_categories.is_none() {
+            let cs = &self.view.column_state;
+            let (z, _) = self.view.transform_cache.get(&self.view.columns, cs.z, cs.tz);
+            let categories = stat::categories(z, &self.view.mask);
+            if let Err(ref s) = categories {
+                let msg = s.clone();
+                self.set_status(msg);
+            }
+            self.z_categories = Some(categories);
+        }
+
+        if let Some(shape) = self.bins.shape {
+            let (width, height) = (self.window_dims.width, self.window_dims.height);
+            let textures = self.accumulate_bins(shape, width, height, self.bins.size);
+            if self.stat.is_scaled() {
+                let grid = &textures.grid;
+                self.stat_range = stat_range(&textures.cells, grid.cols, grid.rows, 0, self.stat, self.stat_z());
+            }
+            self.bin_textures = Some(textures);
+            return;
+        }
+        self.bin_textures = None;
+
+        let pointsize = if lowres { self.view.user_state.pointsize * cfg::LOWRES_FACTOR } else { self.view.user_state.pointsize };
+        if self.layer_rows.is_empty() {
+            let dims = if lowres { self.window_dims.to_lowres() } else { WindowDims { width: self.window_dims.width, height: self.window_dims.height } };
+            {
+                let stats = if lowres { &self.stat_lowres } else { &self.stat_std };
+                if stats.stat != self.stat || stats.width != dims.width || stats.height != dims.height {
+                    let rebuilt = build_stat_textures(&self.context, self.stat, dims.width, dims.height);
+                    if lowres { self.stat_lowres = rebuilt; } else { self.stat_std = rebuilt; }
+                }
+            }
+            {
+                let mut surface = if lowres { self.texture_lowres.as_surface() } else { self.texture_std.as_surface() };
+                surface.clear_color(0.0, 0.0, 0.0, 0.0);
+                self.draw_points(&mut surface, &self.view.projection, pointsize, self.view.user_state.showborder, None, None, self.sums_target());
+            }
+            let stats = if lowres { &self.stat_lowres } else { &self.stat_std };
+            self.accumulate_stats(stats, &self.view.projection, pointsize, self.view.user_state.showborder, None);
+            if self.stat.is_scaled() {
+                let margin = if self.view.user_state.showborder { (pointsize / 2.0).ceil() as u32 + 1 } else { 0 };
+                let sums = if lowres { &self.texture_lowres } else { &self.texture_std };
+                self.stat_range = stat_range(sums, dims.width, dims.height, margin, self.stat, self.stat_z());
+            }
+        } else {
+            for (l, &(ref texture_std, ref texture_lowres)) in self.layer_textures.iter().enumerate() {
+                let mut surface = if lowres { texture_lowres.as_surface() } else { texture_std.as_surface() };
+                surface.clear_color(0.0, 0.0, 0.0, 0.0);
+                self.draw_points(&mut surface, &self.view.projection, pointsize, self.view.user_state.showborder, Some(l), None, Target::Sums);
+            }
+        }
+
+        if let Some((a, b)) = self.diverging_layers() {
+            let dims = if lowres { self.window_dims.to_lowres() } else { WindowDims { width: self.window_dims.width, height: self.window_dims.height } };
+            let margin = if self.view.user_state.showborder { (pointsize / 2.0).ceil() as u32 + 1 } else { 0 };
+            let (texture_a, texture_b) = if lowres {
+                (&self.layer_textures[a].1, &self.layer_textures[b].1)
+            } else {
+                (&self.layer_textures[a].0, &self.layer_textures[b].0)
+            };
+            self.diverging_scale = max_difference(texture_a, texture_b, &dims, margin);
+        }
+    }
+
+    /// The two layers compared by the diverging composite, if it is shown.
+    fn diverging_layers(&self) -> Option<(usize, usize)> {
+        match self.layers {
+            Some(ref layers) if layers.composite == Composite::Diverging && !self.layer_rows.is_empty() => {
+                let shown = layers.shown();
+                if shown.len() == 2 { Some((shown[0], shown[1])) } else { None }
+            },
+            _ => None,
+        }
+    }
+
+    /// Contours of the view, from the points accumulated at a single texel each into a grid of
+    /// `cfg::CONTOUR_CELL_PIXELS` window pixels per cell.
+    fn trace_contours(&self) -> Vec<contours::Line> {
+        let (width, height) = (self.window_dims.width, self.window_dims.height);
+        let dims = WindowDims {
+            width: cmp::max(1, width / cfg::CONTOUR_CELL_PIXELS),
+            height: cmp::max(1, height / cfg::CONTOUR_CELL_PIXELS),
+        };
+        let texture = build_renderable_texture(&self.context, &dims);
+        {
+            let mut surface = texture.as_surface();
+            surface.clear_color(0.0, 0.0, 0.0, 0.0);
+            self.draw_points(&mut surface, &self.view.projection, 1.0, false, None, None, Target::Sums);
+        }
+        let rect = glium::Rect {
+            left: 0,
+            bottom: 0,
+            width: dims.width,
+            height: dims.height,
+        };
+        let rows: Vec<Vec<(f32, f32, f32, f32)>> = texture.main_level().first_layer().into_image(None).unwrap().raw_read(&rect);
+        let counts = rows.iter().flat_map(|row| row.iter().map(|p| p.3)).collect::<Vec<f32>>();
+
+        // rows of the grid go from bottom to top
+        let (sx, sy) = ((width as f32) / (dims.width as f32), (height as f32) / (dims.height as f32));
+        let to_window = |p: (f32, f32)| ((p.0 + 0.5) * sx, (height as f32) - (p.1 + 0.5) * sy);
+        contours::trace(&counts, dims.width as usize, dims.height as usize, &self.contours.percents).into_iter().map(|line| {
+            contours::Line {
+                percent: line.percent,
+                segments: line.segments.iter().map(|s| [to_window(s[0]), to_window(s[1])]).collect(),
+            }
+        }).collect()
+    }
+
+    /// Accumulates the points of the view in cells of `size` pixels of a `width` x `height` image,
+    /// as far as the statistic of Z needs them.
+    fn accumulate_bins(&self, shape: Shape, width: u32, height: u32, size: f32) -> BinTextures {
+        let grid = CellGrid::new(shape, size, width, height);
+        let grid_dims = WindowDims { width: grid.cols, height: grid.rows };
+
+        let cells = build_renderable_texture(&self.context, &grid_dims);
+        {
+            let mut surface = cells.as_surface();
+            surface.clear_color(0.0, 0.0, 0.0, 0.0);
+            self.draw_points(&mut surface, &self.view.projection, 1.0, false, None, Some(&grid), self.sums_target());
+        }
+
+        let stats = build_stat_textures(&self.context, self.stat, grid.cols, grid.rows);
+        self.accumulate_stats(&stats, &self.view.projection, 1.0, false, Some(&grid));
+
+        let histogram = if self.stat == Stat::Median {
+            // a single channel keeps the histograms of small cells affordable
+            let texture = glium::Texture2d::empty_with_format(
+                &self.context,
+                glium::texture::UncompressedFloatFormat::F32,
+                glium::texture::MipmapsOption::NoMipmap,
+                grid.cols * bins::HISTOGRAM_SIDE,
+                grid.rows * bins::HISTOGRAM_SIDE
+            ).unwrap();
+            {
+                let mut surface = texture.as_surface();
+                surface.clear_color(0.0, 0.0, 0.0, 0.0);
+                self.draw_points(&mut surface, &self.view.projection, 1.0, false, None, Some(&grid), Target::Histogram);
+            }
+            texture
+        } else {
+            build_renderable_texture(&self.context, &WindowDims { width: 1, height: 1 })
+        };
+
+        BinTextures {
+            grid: grid,
+            cells: cells,
+            stats: stats,
+            histogram: histogram,
+        }
+    }
+
+    /// Accumulates what the statistic of `textures` needs besides the sums, in pixels or in `cells`.
+    fn accumulate_stats(&self, textures: &StatTextures, projection: &Projection, pointsize: f32, showborder: bool, cells: Option<&CellGrid>) {
+        match textures.stat {
+            Stat::Min | Stat::Max => {
+                let mut surface = textures.extrema.as_surface();
+                surface.clear_color(-f32::MAX, -f32::MAX, 0.0, 0.0);
+                self.draw_points(&mut surface, projection, pointsize, showborder, None, cells, Target::Extrema);
+            },
+            Stat::Distinct => {
+                for (i, texture) in textures.categories.iter().enumerate() {
+                    let mut surface = texture.as_surface();
+                    surface.clear_color(0.0, 0.0, 0.0, 0.0);
+                    self.draw_points(&mut surface, projection, pointsize, showborder, None, cells, Target::Categories(4 * i));
+                }
+            },
+            _ => (),
+        }
+    }
+
+    /// Projected Z of the categories for the vertex shaders, none unless Z is colored by the number
+    /// of distinct values.
+    fn category_uniforms(&self, projection: &Projection) -> ([[f32; 4]; 4], f32) {
+        let mut uniforms = [[0f32; 4]; 4];
+        match (self.stat, &self.z_categories) {
+            (Stat::Distinct, &Some(Ok(ref values))) => {
+                for (i, v) in values.iter().enumerate() {
+                    uniforms[i / 4][i % 4] = (v * projection.scale_z + projection.delta_z) as f32;
+                }
+                (uniforms, values.len() as f32)
+            },
+            _ => (uniforms, 0.0),
+        }
+    }
+
+    /// What the tone mapping of the statistic needs accumulated.
+    fn sums_target(&self) -> Target {
+        if self.stat == Stat::Std { Target::Moments } else { Target::Sums }
+    }
+
+    /// Rows, scale and offset of the Z projection, to turn the sums back into data units.
+    fn stat_z(&self) -> [f32; 3] {
+        let rows = match self.animation {
+            Some(ref a) => cmp::max(a.visible_rows(), 1),
+            None => self.n,
+        };
+        [rows as f32, self.view.projection.scale_z as f32, self.view.projection.delta_z as f32]
+    }
+
+    /// Colors every pixel of `surface` like its cell, with the surface at `origin` of the binned
+    /// image.
+    fn draw_bins<S>(&self, surface: &mut S, bins: &BinTextures, origin: [f32; 2]) where S: Surface {
+        let grid = &bins.grid;
+        let stats = &bins.stats;
+        surface.draw(
+            &self.vertex_buffer_texture,
+            &self.indices_texture,
+            &self.program_bins,
+            &uniform! {
+                inv_gamma:      (1.0 / self.view.user_state.gamma) as f32,
+                cells:          &bins.cells,
+                histogram:      &bins.histogram,
+                stat:           stats.stat.index() as f32,
+                stat_range:     [self.stat_range.0, self.stat_range.1],
+                stat_z:         self.stat_z(),
+                category_count: self.category_uniforms(&self.view.projection).1,
+                extrema:        &stats.extrema,
+                categories0:    &stats.categories[0],
+                categories1:    &stats.categories[1],
+                categories2:    &stats.categories[2],
+                categories3:    &stats.categories[3],
+                origin:         origin,
+                cell_pixels:    grid.cell_pixels(),
+                bin_size:       grid.size,
+                bin_shape:      grid.shape_index(),
+                bin_cells:      [grid.cols as f32, grid.rows as f32],
+            },
+            &Default::default()
+        ).unwrap();
+    }
+
+    /// Tone maps the accumulated `sums` onto `surface`, colored by the statistic of `stats`.
+    fn draw_tonemapped<S>(&self, surface: &mut S, sums: &glium::Texture2d, stats: &StatTextures) where S: Surface {
+        let sampler = glium::uniforms::Sampler::new(sums)
+            .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp);
+        // interpolating the extrema or categories would mix in the empty pixels
+        let nearest = |t| glium::uniforms::Sampler::new(t)
+            .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
+            .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp);
+        surface.draw(
+            &self.vertex_buffer_texture,
+            &self.indices_texture,
+            &self.program_texture,
+            &uniform! {
+                inv_gamma:      (1.0 / self.view.user_state.gamma) as f32,
+                tex:            sampler,
+                stat:           stats.stat.index() as f32,
+                stat_range:     [self.stat_range.0, self.stat_range.1],
+                stat_z:         self.stat_z(),
+                category_count: self.category_uniforms(&self.view.projection).1,
+                extrema:        nearest(&stats.extrema),
+                categories0:    nearest(&stats.categories[0]),
+                categories1:    nearest(&stats.categories[1]),
+                categories2:    nearest(&stats.categories[2]),
+                categories3:    nearest(&stats.categories[3]),
+            },
+            &Default::default()
+        ).unwrap();
+    }
+
+    fn render_to_textures(&mut self) {
+        // the cells are small, bins need no low resolution preview
+        if self.redraw && (self.redraw_full || self.bins.shape.is_some()) {
+            self.draw_textures(false);
+
+            self.redraw = false;
+            self.redraw_full = false;
+            self.lowres = false;
+        } else if self.redraw {
+            self.draw_textures(true);
 
             self.redraw = false;
             self.lowres = true;
@@ -421,119 +1044,258 @@ impl Renderer {
         let lowres_now   = Instant::now();
         let lowres_delta = lowres_now.duration_since(self.lowres_start);
         if self.lowres && lowres_delta > Duration::from_millis(cfg::LOWRES_MILLIS) {
-            self.texture_std.as_surface().clear_color(0.0, 0.0, 0.0, 0.0);
-            self.texture_std.as_surface().draw(
-                &self.vertex_buffer_points,
-                &self.indices_points,
-                &self.program_points,
-                &uniform! {
-                    matrix: self.projection.get_matrix(),
-                    inv_n:     1.0 / (self.n as f32),
-                    pointsize: self.user_state.pointsize,
-                    showborder: if self.user_state.showborder { 1f32 } else { 0f32 },
-                },
-                &params_points
-            ).unwrap();
+            self.draw_textures(false);
             self.lowres = false;
         }
     }
 
     fn render_to_screen(&mut self) {
-        let mut target = self.display.draw();
-        {
-            let sampler = glium::uniforms::Sampler::new(if self.lowres { &self.texture_lowres } else {&self.texture_std})
-                .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp);
-            target.draw(
-                &self.vertex_buffer_texture,
-                &self.indices_texture,
-                &self.program_texture,
-                &uniform! {
-                    inv_gamma: (1.0 / self.user_state.gamma) as f32,
-                    tex:       sampler,
-                },
-                &Default::default()
-            ).unwrap();
+        let mut target = Frame::new(self.context.clone(), self.context.get_framebuffer_dimensions());
+        if let Some(ref bins) = self.bin_textures {
+            self.draw_bins(&mut target, bins, [0.0, 0.0]);
+        } else if !self.layer_rows.is_empty() {
+            let textures = self.layer_textures.iter().map(|&(ref texture_std, ref texture_lowres)| {
+                if self.lowres { texture_lowres } else { texture_std }
+            }).collect::<Vec<&glium::Texture2d>>();
+            let dims = if self.lowres { self.window_dims.to_lowres() } else { WindowDims { width: self.window_dims.width, height: self.window_dims.height } };
+            target.clear_color(0.0, 0.0, 0.0, 1.0);
+            self.draw_layers(&mut target, &textures, &dims, &self.view.projection);
+        } else {
+            if self.lowres {
+                self.draw_tonemapped(&mut target, &self.texture_lowres, &self.stat_lowres);
+            } else {
+                self.draw_tonemapped(&mut target, &self.texture_std, &self.stat_std);
+            }
+        }
+        let mut batch = TextBatch::new(self.window_dims.width, self.window_dims.height);
+        self.push_contours(&mut batch);
+        let mut bottom_line = (self.window_dims.height as f32) - 42.0;
+        if self.view.user_state.showaxes {
+            self.push_axes(&mut batch);
+            bottom_line -= (text::GLYPH_HEIGHT as f32) + 8.0;
         }
+        if self.show_help {
+            batch.push_panel(10.0, 10.0, &self.keymap.help_lines());
+        } else if let Some(ref a) = self.animation {
+            batch.push_panel(10.0, 10.0, &[a.label(&self.view.columns)]);
+        } else if !self.layer_rows.is_empty() {
+            self.push_legend(&mut batch);
+        } else if self.stat != Stat::Mean {
+            batch.push_panel(10.0, 10.0, &[self.stat_label()]);
+        }
+        if let Some((a, b)) = self.diverging_layers() {
+            self.push_colorbar(&mut batch, a, b);
+        }
+        if let Some(ref prompt) = self.prompt {
+            batch.push_panel(10.0, bottom_line, &[prompt.to_string()]);
+        } else if let Some((ref msg, since)) = self.status {
+            if Instant::now().duration_since(since) < Duration::from_millis(cfg::STATUS_MILLIS) {
+                batch.push_panel(10.0, bottom_line, &[msg.clone()]);
+            }
+        }
+        self.text_renderer.draw(&self.context, &mut target, &batch);
         target.finish().unwrap();
     }
 
-    fn handle_events(&mut self, rebuild_points: &mut bool, exit: &mut bool) {
-        for ev in self.display.poll_events() {
-            match ev {
-                glutin::Event::Closed => {
-                    *exit = true;
-                    return;
-                },
-                glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(code)) => {
-                    match code {
-                        glutin::VirtualKeyCode::Escape | glutin::VirtualKeyCode::Q => {
-                            *exit = true;
-                            return;
-                        }
-                        glutin::VirtualKeyCode::B => {
-                            self.user_state.showborder_toggle();
-                            self.redraw = true;
-                        },
-                        glutin::VirtualKeyCode::J => {
-                            self.user_state.pointsize_increase();
-                            self.redraw = true;
-                        },
-                        glutin::VirtualKeyCode::K => {
-                            self.user_state.pointsize_decrease();
-                            self.redraw = true;
-                        },
-                        glutin::VirtualKeyCode::N => {
-                            self.user_state.gamma_increase();
-                            self.redraw = true;
-                        },
-                        glutin::VirtualKeyCode::M => {
-                            self.user_state.gamma_decrease();
-                            self.redraw = true;
-                        },
-                        glutin::VirtualKeyCode::R => {
-                            self.projection.adjust_x(self.columns[self.column_state.x].min, self.columns[self.column_state.x].max);
-                            self.projection.adjust_y(self.columns[self.column_state.y].min, self.columns[self.column_state.y].max);
-                            self.projection.adjust_z(self.columns[self.column_state.z].min, self.columns[self.column_state.z].max);
-                            self.user_state.reset();
-                            self.redraw = true;
-                        },
-                        glutin::VirtualKeyCode::Left => {
-                            self.column_state.x_prev(self.m);
-                            *rebuild_points = true;
-                            self.projection.adjust_x(self.columns[self.column_state.x].min, self.columns[self.column_state.x].max);
-                            self.redraw = true;
-                        },
-                        glutin::VirtualKeyCode::Right => {
-                            self.column_state.x_next(self.m);
-                            *rebuild_points = true;
-                            self.projection.adjust_x(self.columns[self.column_state.x].min, self.columns[self.column_state.x].max);
-                            self.redraw = true;
-                        },
-                        glutin::VirtualKeyCode::Up => {
-                            self.column_state.y_prev(self.m);
-                            *rebuild_points = true;
-                            self.projection.adjust_y(self.columns[self.column_state.y].min, self.columns[self.column_state.y].max);
-                            self.redraw = true;
-                        },
-                        glutin::VirtualKeyCode::Down => {
-                            self.column_state.y_next(self.m);
-                            *rebuild_points = true;
-                            self.projection.adjust_y(self.columns[self.column_state.y].min, self.columns[self.column_state.y].max);
-                            self.redraw = true;
-                        },
-                        glutin::VirtualKeyCode::PageUp => {
-                            self.column_state.z_prev(self.m);
-                            *rebuild_points = true;
-                            self.projection.adjust_z(self.columns[self.column_state.z].min, self.columns[self.column_state.z].max);
-                            self.redraw = true;
-                        },
-                        glutin::VirtualKeyCode::PageDown => {
-                            self.column_state.z_next(self.m);
-                            *rebuild_points = true;
-                            self.projection.adjust_z(self.columns[self.column_state.z].min, self.columns[self.column_state.z].max);
-                            self.redraw = true;
-                        },
-                        _ => ()
+    /// Contour lines with their percentage at the top of each.
+    fn push_contours(&self, batch: &mut TextBatch) {
+        let color = [1.0, 1.0, 1.0, 0.8];
+        for line in &self.contour_lines {
+            for s in &line.segments {
+                batch.push_line(s[0].0, s[0].1, s[1].0, s[1].1, 1.5, color);
+            }
+            let top = line.segments.iter().map(|s| s[0]).fold(None, |acc: Option<(f32, f32)>, p| {
+                match acc {
+                    Some(a) if a.1 <= p.1 => Some(a),
+                    _ => Some(p),
+                }
+            });
+            if let Some((x, y)) = top {
+                let label = format!("{}%", line.percent);
+                let label_width = text::text_width(&label);
+                let (lx, ly) = (x - label_width / 2.0, y - (text::GLYPH_HEIGHT as f32) - 2.0);
+                batch.push_box(lx - 2.0, ly - 1.0, lx + label_width + 2.0, ly + (text::GLYPH_HEIGHT as f32) + 1.0, [0.0, 0.0, 0.0, 0.5]);
+                batch.push_text(lx, ly, &label, color);
+            }
+        }
+    }
+
+    /// What colors the pixels or bins, with the values at the ends of the color map where they depend
+    /// on the view.
+    fn stat_label(&self) -> String {
+        let z = &self.view.columns[self.view.column_state.z].name;
+        match (self.stat, &self.z_categories) {
+            (Stat::Count, _) => String::from("count only"),
+            (Stat::Distinct, &Some(Ok(ref values))) => format!("distinct values of {}: 1 .. {}", z, values.len()),
+            (stat, _) if stat.is_scaled() => {
+                let (lo, hi) = (self.stat_range.0 as f64, self.stat_range.1 as f64);
+                let step = f64::max((hi - lo).abs(), f64::max(hi.abs(), 1e-30)) / 10.0;
+                format!("{} of {}: {} .. {}", stat.name(), z, axes::format_value(lo, step), axes::format_value(hi, step))
+            },
+            (stat, _) => format!("{} of {}", stat.name(), z),
+        }
+    }
+
+    /// Composites the accumulation `textures` of the layers, of size `dims` and showing `projection`.
+    fn draw_layers<S>(&self, surface: &mut S, textures: &[&glium::Texture2d], dims: &WindowDims, projection: &Projection) where S: Surface {
+        let layers = match self.layers {
+            Some(ref l) => l,
+            None => {
+                return;
+            }
+        };
+        let params_layers = glium::DrawParameters {
+            blend: additive_blend(),
+            .. Default::default()
+        };
+        let shown = layers.shown();
+        // pairs of a layer and the one it is compared to; differences need two layers, a single
+        // one is shown as it is
+        let (mode, passes) = match layers.composite {
+            Composite::Difference if shown.len() == 2 => (1, vec![(shown[0], shown[1]), (shown[1], shown[0])]),
+            Composite::Diverging if shown.len() == 2 => (2, vec![(shown[0], shown[1])]),
+            _ => (0, shown.iter().map(|&l| (l, l)).collect()),
+        };
+
+        // the scale refers to all pixels of the view, of which an exported tile may only show a part
+        let ((x_min, x_max), (y_min, y_max)) = (projection.range_x(), projection.range_y());
+        let ((view_x_min, view_x_max), (view_y_min, view_y_max)) = (self.view.projection.range_x(), self.view.projection.range_y());
+        let pixels = ((dims.width * dims.height) as f64) * (view_x_max - view_x_min) / (x_max - x_min) * (view_y_max - view_y_min) / (y_max - y_min);
+        let scale = if self.diverging_scale > 0.0 { (pixels as f32) / self.diverging_scale } else { 0.0 };
+
+        for (l, other) in passes {
+            let sampler = |t| glium::uniforms::Sampler::new(t).wrap_function(glium::uniforms::SamplerWrapFunction::Clamp);
+            surface.draw(
+                &self.vertex_buffer_texture,
+                &self.indices_texture,
+                &self.program_layers,
+                &uniform! {
+                    inv_gamma:   (1.0 / self.view.user_state.gamma) as f32,
+                    tex:         sampler(textures[l]),
+                    other:       sampler(textures[other]),
+                    layer_color: layers.color(l),
+                    mode:        mode as f32,
+                    scale:       scale,
+                },
+                &params_layers
+            ).unwrap();
+        }
+    }
+
+    /// Name of every layer in its color, those that are not shown dimmed, and how they are combined.
+    fn push_legend(&self, batch: &mut TextBatch) {
+        let layers = match self.layers {
+            Some(ref l) => l,
+            None => {
+                return;
+            }
+        };
+        let gh = text::GLYPH_HEIGHT as f32;
+        let pad = 8.0;
+        let shown = layers.shown();
+        let lines = (0..layers.len()).map(|l| format!("{} {}", l + 1, layers.names[l])).collect::<Vec<String>>();
+        let composite = format!("layers: {}", layers.composite.name());
+        let width = lines.iter().chain(Some(&composite)).map(|l| text::text_width(l)).fold(0.0, f32::max);
+        batch.push_box(10.0, 10.0, 10.0 + width + 2.0 * pad, 10.0 + ((lines.len() + 1) as f32) * gh + 2.0 * pad, [0.0, 0.0, 0.0, 0.75]);
+        for (l, line) in lines.iter().enumerate() {
+            let c = layers.color(l);
+            let alpha = if shown.contains(&l) { 1.0 } else { 0.35 };
+            batch.push_text(10.0 + pad, 10.0 + pad + (l as f32) * gh, line, [c[0], c[1], c[2], alpha]);
+        }
+        batch.push_text(10.0 + pad, 10.0 + pad + (lines.len() as f32) * gh, &composite, [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    /// Color bar of the diverging composite below the column names, labelled with the differences
+    /// relative to the rows spread evenly over the view. Layer `a` is denser towards the top.
+    fn push_colorbar(&self, batch: &mut TextBatch, a: usize, b: usize) {
+        let layers = match self.layers {
+            Some(ref l) => l,
+            None => {
+                return;
+            }
+        };
+        let width = self.window_dims.width as f32;
+        let gh = text::GLYPH_HEIGHT as f32;
+        let background = [0.0, 0.0, 0.0, 0.5];
+        let foreground = [1.0, 1.0, 1.0, 0.9];
+        let (bar_width, height, steps) = (16.0, cfg::COLORBAR_HEIGHT, 64);
+        let (left, top) = (width - bar_width - 10.0, 2.0 * gh + 24.0);
+
+        for i in 0..steps {
+            let v = 1.0 - 2.0 * ((i as f32) + 0.5) / (steps as f32);
+            let c = layers::diverging_color(v);
+            let y = top + height * (i as f32) / (steps as f32);
+            batch.push_box(left, y, left + bar_width, y + height / (steps as f32) + 0.5, [c[0], c[1], c[2], 1.0]);
+        }
+
+        // the color map is applied after the gamma correction
+        for &v in &[1.0f64, 0.5, 0.0, -0.5, -1.0] {
+            let d = v.signum() * v.abs().powf(self.view.user_state.gamma as f64) * (self.diverging_scale as f64);
+            let label = if d == 0.0 {
+                String::from("0")
+            } else {
+                format!("{}{}", if d > 0.0 { "+" } else { "" }, axes::format_value(d, d.abs() / 100.0))
+            };
+            let label_width = text::text_width(&label);
+            let y = top + height * ((1.0 - v) / 2.0) as f32 - gh / 2.0;
+            batch.push_box(left - label_width - 10.0, y - 1.0, left - 2.0, y + gh + 1.0, background);
+            batch.push_text(left - label_width - 6.0, y, &label, foreground);
+        }
+
+        for &(name, y) in &[(&layers.names[a], top - gh - 4.0), (&layers.names[b], top + height + 4.0)] {
+            let name_width = text::text_width(name);
+            let x = width - name_width - 10.0;
+            batch.push_box(x - 4.0, y - 1.0, x + name_width + 4.0, y + gh + 1.0, background);
+            batch.push_text(x, y, name, foreground);
+        }
+    }
+
+    fn handle_events(&mut self, rebuild_points: &mut bool, exit: &mut bool) {
+        let mut events = mem::replace(&mut self.pending_events, vec![]);
+        if let Some(ref window) = self.window {
+            events.extend(window.poll_events());
+        }
+        for ev in events {
+            match ev {
+                glutin::Event::Closed => {
+                    *exit = true;
+                    return;
+                },
+                glutin::Event::KeyboardInput(state, _, Some(code)) => {
+                    self.modifier_state.update(state, code);
+                    if state == glutin::ElementState::Pressed {
+                        // keys without a character, e.g. function keys, are followed by the next key
+                        self.swallow_char = false;
+                    }
+                    if state == glutin::ElementState::Pressed && self.prompt.is_some() {
+                        self.handle_prompt_key(code, rebuild_points);
+                    } else if state == glutin::ElementState::Pressed {
+                        let key = self.modifier_state.key(code);
+                        if let Some(action) = self.keymap.lookup(&key) {
+                            self.view.record_history();
+                            self.view.gesture = None;
+                            self.apply_action(action, rebuild_points, exit);
+                            if *exit {
+                                return;
+                            }
+                            self.swallow_char = self.prompt.is_some();
+                        }
+                    }
+                },
+                glutin::Event::ReceivedCharacter(c) => {
+                    if self.swallow_char {
+                        self.swallow_char = false;
+                    } else if let Some(ref mut prompt) = self.prompt {
+                        if !c.is_control() {
+                            prompt.input.push(c);
+                        }
+                    } else if let Some(action) = self.keymap.lookup_char(c) {
+                        self.view.record_history();
+                        self.view.gesture = None;
+                        self.apply_action(action, rebuild_points, exit);
+                        if *exit {
+                            return;
+                        }
                     }
                 },
                 glutin::Event::MouseInput(glutin::ElementState::Pressed, glutin::MouseButton::Left) => {
@@ -541,43 +1303,1089 @@ impl Renderer {
                 },
                 glutin::Event::MouseInput(glutin::ElementState::Released, glutin::MouseButton::Left) => {
                     self.mouse_state.down = false;
+                    self.view.record_history();
+                    self.view.history.seal();
                 },
                 glutin::Event::MouseMoved(posx, posy) => {
                     if self.mouse_state.down {
                         let dx = posx - (self.mouse_state.x as i32);
                         let dy = posy - (self.mouse_state.y as i32);
-                        self.projection.move_x(dx, self.window_dims.width);
-                        self.projection.move_y(dy, self.window_dims.height);
+                        self.view.projection.move_x(dx, self.window_dims.width);
+                        self.view.projection.move_y(dy, self.window_dims.height);
+                        self.view.gesture = Some(Gesture::Pan);
                         self.redraw = true;
                     }
                     self.mouse_state.x = posx as u32;
                     self.mouse_state.y = posy as u32;
                 },
                 glutin::Event::MouseWheel(glutin::MouseScrollDelta::LineDelta(dx, dy), glutin::TouchPhase::Moved) => {
-                    self.projection.scroll_x(dx, self.mouse_state.x, self.window_dims.width);
-                    self.projection.scroll_y(dy, self.mouse_state.y, self.window_dims.height);
+                    self.view.projection.scroll_x(dx, self.mouse_state.x, self.window_dims.width);
+                    self.view.projection.scroll_y(dy, self.mouse_state.y, self.window_dims.height);
+                    self.view.gesture = Some(Gesture::Zoom);
                     self.redraw = true;
                 },
                 glutin::Event::Resized(w, h) => {
                     self.window_dims.width = w;
                     self.window_dims.height = h;
-                    self.texture_std    = build_renderable_texture(&self.display, &self.window_dims);
-                    self.texture_lowres = build_renderable_texture(&self.display, &self.window_dims.to_lowres());
+                    self.texture_std    = build_renderable_texture(&self.context, &self.window_dims);
+                    self.texture_lowres = build_renderable_texture(&self.context, &self.window_dims.to_lowres());
+                    self.stat_std       = build_stat_textures(&self.context, self.stat, self.window_dims.width, self.window_dims.height);
+                    self.stat_lowres    = build_stat_textures(&self.context, self.stat, self.window_dims.to_lowres().width, self.window_dims.to_lowres().height);
+                    self.build_layer_textures();
                     self.redraw = true;
                 },
                 _ => ()
             }
         }
+
+        self.view.record_history();
     }
 
-    fn update_geometry(&mut self) {
-        let points = data::points_from_columns(
-            &self.columns,
-            self.column_state.x,
-            self.column_state.y,
-            self.column_state.z
+    fn handle_prompt_key(&mut self, code: glutin::VirtualKeyCode, rebuild_points: &mut bool) {
+        if let Some(prompt) = view::prompt_key(&mut self.prompt, code) {
+            self.submit_prompt(prompt, rebuild_points);
+        }
+    }
+
+    fn submit_prompt(&mut self, prompt: Prompt<PromptPurpose>, rebuild_points: &mut bool) {
+        match prompt.purpose {
+            PromptPurpose::BookmarkName(slot) => {
+                match self.view.store_bookmark(slot, &prompt.input) {
+                    Ok(msg) => self.set_status(msg),
+                    Err(s)  => self.set_status(s),
+                }
+            },
+            PromptPurpose::Filter => {
+                let mut filter = self.view.filter.clone();
+                let result = match filter.add(&prompt.input) {
+                    Ok(_)  => self.set_filter(filter, rebuild_points),
+                    Err(s) => Err(s),
+                };
+                if let Err(s) = result {
+                    self.set_status(s);
+                }
+            },
+            PromptPurpose::SplitLayers => {
+                if let Err(s) = self.split_layers(prompt.input.trim()) {
+                    self.set_status(s);
+                }
+            },
+            PromptPurpose::ContourLevels => {
+                match Contours::parse_percents(&prompt.input) {
+                    Ok(percents) => {
+                        let mut contours = self.contours.clone();
+                        contours.shown = true;
+                        contours.percents = percents;
+                        self.set_status(contours.label());
+                        self.set_contours(contours);
+                    },
+                    Err(s) => self.set_status(s),
+                }
+            },
+            PromptPurpose::DeriveColumn => {
+                match self.view.derive_column(&prompt.input) {
+                    Ok(msg) => self.set_status(msg),
+                    Err(s)  => self.set_status(s),
+                }
+            },
+            PromptPurpose::ExportFigure => {
+                let fname = prompt.input.trim();
+                match self.export_figure(fname) {
+                    Ok(_)  => self.set_status(format!("exported view to {}", fname)),
+                    Err(s) => self.set_status(s),
+                }
+            },
+            PromptPurpose::Screenshot => {
+                let result = match Screenshot::parse(&prompt.input) {
+                    Ok(s) => self.screenshot(&s).map(|_| format!("saved {}x{} screenshot to {}", s.width, s.height, s.fname)),
+                    Err(s) => Err(s),
+                };
+                match result {
+                    Ok(msg) => self.set_status(msg),
+                    Err(s)  => self.set_status(s),
+                }
+            },
+            PromptPurpose::ExportGrid => {
+                let fname = prompt.input.trim();
+                match self.export_grid(fname) {
+                    Ok(_)  => self.set_status(format!("exported density grid to {}", fname)),
+                    Err(s) => self.set_status(s),
+                }
+            },
+            PromptPurpose::Animate => {
+                self.set_animation(prompt.input.trim());
+            },
+            PromptPurpose::ExportAnimation => {
+                self.export_animation(prompt.input.trim());
+            },
+        }
+    }
+
+    /// Applies `filter` and autoscales all axes to the remaining rows.
+    fn set_filter(&mut self, filter: Filter, rebuild_points: &mut bool) -> Result<(), String> {
+        let msg = match self.view.set_filter(filter) {
+            Ok(m) => m,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        self.update_rows();
+        *rebuild_points = true;
+        self.redraw = true;
+        self.set_status(msg);
+        Ok(())
+    }
+
+    fn column_title(&self, idx: usize, t: Transform) -> String {
+        match t {
+            Transform::Identity => self.view.columns[idx].name.clone(),
+            _ => format!("{} ({})", self.view.columns[idx].name, t.name()),
+        }
+    }
+
+    fn axis_title(&self, axis: &str, idx: usize, t: Transform) -> String {
+        format!("{}: {}", axis, self.column_title(idx, t))
+    }
+
+    /// Tick labels along the bottom (X) and left (Y) border, column names in the top right corner.
+    fn push_axes(&self, batch: &mut TextBatch) {
+        let width = self.window_dims.width as f32;
+        let height = self.window_dims.height as f32;
+        let gh = text::GLYPH_HEIGHT as f32;
+        let background = [0.0, 0.0, 0.0, 0.5];
+        let foreground = [1.0, 1.0, 1.0, 0.9];
+
+        let (x_min, x_max) = self.view.projection.range_x();
+        let (_, x_units) = self.view.transform_cache.get(&self.view.columns, self.view.column_state.x, self.view.column_state.tx);
+        let x_kind = self.view.columns[self.view.column_state.x].kind;
+        for tick in axes::ticks(x_min, x_max, x_units, x_kind, (width / cfg::TICK_SPACING) as usize) {
+            let px = ((self.view.projection.scale_x * tick.pos + self.view.projection.delta_x + 1.0) / 2.0) as f32 * width;
+            let label_width = text::text_width(&tick.label);
+            let label_x = f32::max(0.0, f32::min(px - label_width / 2.0, width - label_width));
+            batch.push_box(px, height - 4.0, px + 1.0, height, foreground);
+            batch.push_box(label_x - 2.0, height - gh - 6.0, label_x + label_width + 2.0, height - 4.0, background);
+            batch.push_text(label_x, height - gh - 5.0, &tick.label, foreground);
+        }
+
+        let (y_min, y_max) = self.view.projection.range_y();
+        let (_, y_units) = self.view.transform_cache.get(&self.view.columns, self.view.column_state.y, self.view.column_state.ty);
+        let y_kind = self.view.columns[self.view.column_state.y].kind;
+        for tick in axes::ticks(y_min, y_max, y_units, y_kind, (height / cfg::TICK_SPACING) as usize) {
+            let py = ((1.0 - self.view.projection.scale_y * tick.pos - self.view.projection.delta_y) / 2.0) as f32 * height;
+            let label_width = text::text_width(&tick.label);
+            let label_y = f32::max(0.0, f32::min(py - gh / 2.0, height - gh));
+            batch.push_box(0.0, py, 4.0, py + 1.0, foreground);
+            batch.push_box(4.0, label_y - 1.0, label_width + 8.0, label_y + gh + 1.0, background);
+            batch.push_text(6.0, label_y, &tick.label, foreground);
+        }
+
+        let title = format!(
+            "{}  {}  {}",
+            self.axis_title("x", self.view.column_state.x, self.view.column_state.tx),
+            self.axis_title("y", self.view.column_state.y, self.view.column_state.ty),
+            self.axis_title("z", self.view.column_state.z, self.view.column_state.tz)
         );
-        self.vertex_buffer_points = glium::VertexBuffer::new(&self.display, &points).unwrap();
+        let title_width = text::text_width(&title);
+        batch.push_box(width - title_width - 12.0, 0.0, width, gh + 8.0, background);
+        batch.push_text(width - title_width - 6.0, 4.0, &title, foreground);
+    }
+
+    /// Title and ticks of an axis for export, `scale` and `delta` map data units to `[0, 1]`.
+    fn figure_axis(&self, idx: usize, t: Transform, range: (f64, f64), count: usize, scale: f64, delta: f64) -> figure::Axis {
+        let (_, units) = self.view.transform_cache.get(&self.view.columns, idx, t);
+        let ticks = axes::ticks(range.0, range.1, units, self.view.columns[idx].kind, count).into_iter().map(|tick| {
+            (tick.pos * scale + delta, tick.label)
+        }).collect();
+        figure::Axis {
+            title: self.column_title(idx, t),
+            ticks: ticks,
+        }
+    }
+
+    /// Renders one tile with the viewport `dims` and returns the accumulation buffer (of all layers
+    /// together) and the tone mapped image, as rows from bottom to top. The image shows `bins`
+    /// instead of the points if given, with the tile at a pixel position of the binned image.
+    fn render_tile(&self, dims: &WindowDims, projection: &Projection, pointsize: f32, showborder: bool, bins: Option<(&BinTextures, [f32; 2])>) -> (Vec<Vec<(f32, f32, f32, f32)>>, Vec<Vec<(f32, f32, f32, f32)>>) {
+        let accumulation = build_renderable_texture(&self.context, dims);
+        {
+            let mut surface = accumulation.as_surface();
+            surface.clear_color(0.0, 0.0, 0.0, 0.0);
+            self.draw_points(&mut surface, projection, pointsize, showborder, None, None, Target::Sums);
+        }
+
+        let tonemapped = build_renderable_texture(&self.context, dims);
+        if let Some((bins, origin)) = bins {
+            let mut surface = tonemapped.as_surface();
+            self.draw_bins(&mut surface, bins, origin);
+        } else if !self.layer_rows.is_empty() {
+            let textures = (0..self.layer_rows.len()).map(|l| {
+                let texture = build_renderable_texture(&self.context, dims);
+                {
+                    let mut surface = texture.as_surface();
+                    surface.clear_color(0.0, 0.0, 0.0, 0.0);
+                    self.draw_points(&mut surface, projection, pointsize, showborder, Some(l), None, Target::Sums);
+                }
+                texture
+            }).collect::<Vec<glium::Texture2d>>();
+            let mut surface = tonemapped.as_surface();
+            surface.clear_color(0.0, 0.0, 0.0, 1.0);
+            self.draw_layers(&mut surface, &textures.iter().collect::<Vec<&glium::Texture2d>>(), dims, projection);
+        } else {
+            let stats = build_stat_textures(&self.context, self.stat, dims.width, dims.height);
+            self.accumulate_stats(&stats, projection, pointsize, showborder, None);
+            // the returned accumulation buffer keeps the plain sums
+            let moments = if self.sums_target() == Target::Moments {
+                let texture = build_renderable_texture(&self.context, dims);
+                {
+                    let mut surface = texture.as_surface();
+                    surface.clear_color(0.0, 0.0, 0.0, 0.0);
+                    self.draw_points(&mut surface, projection, pointsize, showborder, None, None, Target::Moments);
+                }
+                Some(texture)
+            } else {
+                None
+            };
+            let mut surface = tonemapped.as_surface();
+            self.draw_tonemapped(&mut surface, moments.as_ref().unwrap_or(&accumulation), &stats);
+        }
+
+        let rect = glium::Rect {
+            left: 0,
+            bottom: 0,
+            width: dims.width,
+            height: dims.height,
+        };
+        (
+            accumulation.main_level().first_layer().into_image(None).unwrap().raw_read(&rect),
+            tonemapped.main_level().first_layer().into_image(None).unwrap().raw_read(&rect),
+        )
+    }
+
+    /// Renders the current view into `width` x `height` pixels with the point size scaled
+    /// accordingly, in tiles if that is more than the GPU can handle at once. Returns the
+    /// accumulation buffer and the tone mapped image. Tiled images have no border markers.
+    pub fn render_image(&mut self, width: u32, height: u32) -> (Image, Image) {
+        let pointsize = self.view.user_state.pointsize * (width as f32) / (self.window_dims.width as f32);
+        // tiles overlap by the point radius, so that points on the edges are not cut off
+        let margin = (pointsize / 2.0).ceil() as u32 + 1;
+        let (max_width, max_height) = self.context.get_max_viewport_dimensions();
+        let tile_width = cmp::max(1, cmp::min(cfg::EXPORT_TILE_SIZE, max_width).saturating_sub(2 * margin));
+        let tile_height = cmp::max(1, cmp::min(cfg::EXPORT_TILE_SIZE, max_height).saturating_sub(2 * margin));
+        let tiles_x = (width + tile_width - 1) / tile_width;
+        let tiles_y = (height + tile_height - 1) / tile_height;
+        let showborder = self.view.user_state.showborder && tiles_x * tiles_y == 1;
+        debug!("render {}x{} image in {}x{} tiles", width, height, tiles_x, tiles_y);
+
+        // the LOD level has to resolve the pixels of the image, not of the window
+        let lod_points = match self.lod {
+            Some(ref lod) => Some((self.lod_level(lod, width, height), self.view_rect())),
+            None => None,
+        }.map(|(level, view)| self.lod_points(level, &view));
+        let window_points = match lod_points {
+            Some(points) => Some(mem::replace(&mut self.vertex_buffer_points, glium::VertexBuffer::new(&self.context, &points).unwrap())),
+            None => None,
+        };
+
+        // the cells are laid out over the whole image, across the tiles
+        let bin_size = self.bins.size * (width as f32) / (self.window_dims.width as f32);
+        let bins = self.bins.shape.map(|shape| self.accumulate_bins(shape, width, height, bin_size));
+
+        let mut accumulation = Image::new(width, height);
+        let mut tonemapped = Image::new(width, height);
+        for ty in 0..tiles_y {
+            for tx in 0..tiles_x {
+                let (left, bottom) = (tx * tile_width, ty * tile_height);
+                let dims = WindowDims {
+                    width: cmp::min(tile_width, width - left) + 2 * margin,
+                    height: cmp::min(tile_height, height - bottom) + 2 * margin,
+                };
+                let x0 = ((left as f64) - (margin as f64)) / (width as f64);
+                let y0 = ((bottom as f64) - (margin as f64)) / (height as f64);
+                let projection = self.view.projection.crop(
+                    x0,
+                    x0 + (dims.width as f64) / (width as f64),
+                    y0,
+                    y0 + (dims.height as f64) / (height as f64)
+                );
+
+                let origin = [(left as f32) - (margin as f32), (bottom as f32) - (margin as f32)];
+                let (acc_rows, tone_rows) = self.render_tile(&dims, &projection, pointsize, showborder, bins.as_ref().map(|b| (b, origin)));
+                accumulation.paste(&acc_rows, left, bottom, margin);
+                tonemapped.paste(&tone_rows, left, bottom, margin);
+            }
+        }
+
+        if let Some(points) = window_points {
+            self.vertex_buffer_points = points;
+        }
+        (accumulation, tonemapped)
+    }
+
+    /// Renders and saves `s`, independent of the window size.
+    pub fn screenshot(&mut self, s: &Screenshot) -> Result<(), String> {
+        info!("render {}x{} screenshot", s.width, s.height);
+        let (accumulation, tonemapped) = self.render_image(s.width, s.height);
+        s.save(&tonemapped, &accumulation)
+    }
+
+    fn export_figure(&mut self, fname: &str) -> Result<(), String> {
+        let (width, height) = (self.window_dims.width, self.window_dims.height);
+        let cs = self.view.column_state.clone();
+        let p = self.view.projection.clone();
+        let figure = Figure {
+            width: width as f64,
+            height: height as f64,
+            image: self.render_image(width * cfg::EXPORT_RASTER_SCALE, height * cfg::EXPORT_RASTER_SCALE).1,
+            x: self.figure_axis(cs.x, cs.tx, p.range_x(), (width as f32 / cfg::TICK_SPACING) as usize, p.scale_x / 2.0, (p.delta_x + 1.0) / 2.0),
+            y: self.figure_axis(cs.y, cs.ty, p.range_y(), (height as f32 / cfg::TICK_SPACING) as usize, p.scale_y / 2.0, (p.delta_y + 1.0) / 2.0),
+            z: self.figure_axis(cs.z, cs.tz, p.range_z(), (height as f32 / cfg::TICK_SPACING) as usize, p.scale_z, p.delta_z),
+        };
+        figure::save(&figure, fname)
+    }
+
+    /// Saves the accumulation buffer of the window (without border markers) with the bin edges of
+    /// its pixels.
+    fn export_grid(&mut self, fname: &str) -> Result<(), String> {
+        let (width, height) = (self.window_dims.width, self.window_dims.height);
+        let (rows, _) = self.render_tile(&self.window_dims, &self.view.projection, self.view.user_state.pointsize, false, None);
+        let mut cells = Image::new(width, height);
+        cells.paste(&rows, 0, 0, 0);
+
+        let edges = |idx: usize, t: Transform, range: (f64, f64), count: u32| {
+            let (_, units) = self.view.transform_cache.get(&self.view.columns, idx, t);
+            (0..(count + 1)).map(|i| {
+                units.to_original(range.0 + (range.1 - range.0) * (i as f64) / (count as f64))
+            }).collect::<Vec<f64>>()
+        };
+        let grid = Grid {
+            cells: cells,
+            x_edges: edges(self.view.column_state.x, self.view.column_state.tx, self.view.projection.range_x(), width),
+            y_edges: edges(self.view.column_state.y, self.view.column_state.ty, self.view.projection.range_y(), height),
+        };
+        grid::save(&grid, fname)
+    }
+
+    /// Starts an animation as parsed by `Animation::parse`, or stops it for an empty `s`.
+    fn set_animation(&mut self, s: &str) {
+        if s.is_empty() {
+            self.animation = None;
+            self.set_status(String::from("stopped animation"));
+        } else if !self.layer_rows.is_empty() {
+            self.set_status(String::from("animations are not available with layers"));
+            return;
+        } else {
+            match Animation::parse(s, &self.view.columns) {
+                Ok(a)  => self.animation = Some(a),
+                Err(s) => {
+                    self.set_status(s);
+                    return;
+                }
+            }
+        }
+        self.update_rows();
+        self.update_lod();
+        self.redraw = true;
+    }
+
+    /// Renders every window position of the animation at the window size and writes them to `fname`.
+    fn export_animation(&mut self, fname: &str) {
+        let (frame, positions) = match self.animation {
+            Some(ref a) => (a.frame, a.positions()),
+            None => {
+                self.set_status(String::from("no animation, choose a column first"));
+                return;
+            }
+        };
+
+        let (width, height) = (self.window_dims.width, self.window_dims.height);
+        let mut video = match VideoWriter::create(fname, width, height, cfg::ANIMATION_FRAME_MILLIS) {
+            Ok(v) => v,
+            Err(s) => {
+                self.set_status(s);
+                return;
+            }
+        };
+        info!("export {} animation frames to {}", positions, fname);
+        let mut result = Ok(());
+        for i in 0..positions {
+            if let Some(ref mut a) = self.animation {
+                a.frame = i;
+            }
+            let (_, tonemapped) = self.render_image(width, height);
+            result = video.push(&tonemapped);
+            if result.is_err() {
+                break;
+            }
+        }
+        if let Some(ref mut a) = self.animation {
+            a.frame = frame;
+        }
+
+        match result.and_then(|_| video.finish()) {
+            Ok(count) => self.set_status(format!("exported {} frames to {}", count, fname)),
+            Err(s) => self.set_status(s),
+        }
+    }
+
+    /// Replaces the data by the columns of `fname`. The filter is kept if it still applies.
+    fn load_file(&mut self, fname: &str, rebuild_points: &mut bool) -> Result<(), String> {
+        let columns = match self.rpc {
+            Some((_, ref loader)) => loader.load(fname),
+            None => Err(String::from("loading files is only possible via remote control")),
+        };
+        match columns {
+            Ok(c) => {
+                self.set_data(c, fname, rebuild_points);
+                self.set_status(format!("loaded {} ({} rows)", fname, self.view.mask.len()));
+                Ok(())
+            },
+            Err(s) => Err(s),
+        }
+    }
+
+    /// Replaces the data, e.g. by new measurements of an embedding application. See `set_data`.
+    pub fn replace_data(&mut self, columns: Vec<Column>, name: &str) {
+        let mut rebuild_points = false;
+        self.set_data(columns, name, &mut rebuild_points);
+        self.update_geometry();
+    }
+
+    /// Replaces the data by `columns`, named `name` (e.g. the file). The filter is kept if it still
+    /// applies, the chosen columns and the view are kept if the column names did not change.
+    fn set_data(&mut self, columns: Vec<Column>, name: &str, rebuild_points: &mut bool) {
+        let same_columns = columns.len() == self.view.columns.len() && columns.iter().zip(self.view.columns.iter()).all(|(a, b)| a.name == b.name);
+        self.view.mask = match self.view.filter.mask(&columns) {
+            Ok(m) => m,
+            Err(_) => {
+                self.view.filter = Filter::new();
+                vec![true; columns[0].data.len()]
+            }
+        };
+        self.view.columns = columns;
+        self.view.transform_cache.clear();
+        self.gpu_columns = GpuColumns::new();
+        if !same_columns {
+            self.view.column_state = ColumnState::new(self.view.columns.len());
+            self.animation = None;
+            self.layers = None;
+        }
+        if name != self.fname {
+            self.fname = String::from(name);
+            self.view.bookmarks = Bookmarks::for_data_file(name);
+            if let Err(s) = self.view.bookmarks.load() {
+                warn!("{}", s);
+            }
+            if let Some(window) = self.window.as_ref().and_then(|w| w.get_window()) {
+                window.set_title(&format!("fluxcore_ng - {}", name));
+            }
+        }
+
+        self.update_rows();
+        if !same_columns {
+            self.view.autoscale_x();
+            self.view.autoscale_y();
+            self.view.autoscale_z();
+            let view = self.view.view_state();
+            self.view.history.reset(view);
+        }
+        *rebuild_points = true;
+        self.redraw = true;
+    }
+
+    /// Columns, view and selection, as reported to remote control clients.
+    fn state_json(&self) -> Json {
+        let axis = |idx: usize, t: Transform, range: (f64, f64)| {
+            Json::object(vec![
+                ("column", Json::string(&self.view.columns[idx].name)),
+                ("transform", Json::string(t.name())),
+                ("min", Json::Number(range.0)),
+                ("max", Json::Number(range.1)),
+            ])
+        };
+        let cs = &self.view.column_state;
+        Json::object(vec![
+            ("file", Json::string(&self.fname)),
+            ("columns", Json::Array(self.view.columns.iter().map(|c| Json::string(&c.name)).collect())),
+            ("x", axis(cs.x, cs.tx, self.view.projection.range_x())),
+            ("y", axis(cs.y, cs.ty, self.view.projection.range_y())),
+            ("z", axis(cs.z, cs.tz, self.view.projection.range_z())),
+            ("filter", Json::Array(self.view.filter.predicates.iter().map(|p| Json::String(p.to_string())).collect())),
+            ("gamma", Json::Number(self.view.user_state.gamma as f64)),
+            ("pointsize", Json::Number(self.view.user_state.pointsize as f64)),
+            ("rows", Json::Number(self.view.mask.len() as f64)),
+            ("selected", Json::Number(self.n as f64)),
+            ("animation", match self.animation {
+                Some(ref a) => Json::String(a.label(&self.view.columns)),
+                None => Json::Null,
+            }),
+            ("stat", Json::string(self.stat.name())),
+            ("contours", if self.contours.shown {
+                Json::Array(self.contours.percents.iter().map(|&p| Json::Number(p)).collect())
+            } else {
+                Json::Null
+            }),
+            ("bins", match self.bins.shape {
+                Some(shape) => Json::object(vec![
+                    ("shape", Json::string(shape.name())),
+                    ("size", Json::Number(self.bins.size as f64)),
+                ]),
+                None => Json::Null,
+            }),
+            ("layers", match self.layers {
+                Some(ref layers) => Json::object(vec![
+                    ("column", Json::string(&self.view.columns[layers.column].name)),
+                    ("names", Json::Array(layers.names.iter().map(|n| Json::string(n)).collect())),
+                    ("visible", Json::Array(layers.visible.iter().map(|&v| Json::Bool(v)).collect())),
+                    ("composite", Json::string(layers.composite.name())),
+                    ("diverging_scale", Json::Number(self.diverging_scale as f64)),
+                ]),
+                None => Json::Null,
+            }),
+        ])
+    }
+
+    fn rpc_notify(&self, method: &str) {
+        if let Some((ref server, _)) = self.rpc {
+            server.notify(method, self.state_json());
+        }
+    }
+
+    fn handle_rpc(&mut self, rebuild_points: &mut bool, exit: &mut bool) {
+        let requests = match self.rpc {
+            Some((ref server, _)) => server.poll(),
+            None => vec![],
+        };
+        for request in requests {
+            // requests that render need the columns chosen by earlier ones
+            if *rebuild_points {
+                self.update_geometry();
+                *rebuild_points = false;
+            }
+            debug!("remote control: {} {}", request.method, request.params.to_string());
+            let result = self.rpc_call(&request.method, &request.params, rebuild_points, exit);
+            if let Some((ref server, _)) = self.rpc {
+                server.respond(&request, result);
+            }
+            self.view.record_history();
+            if *exit {
+                return;
+            }
+        }
+    }
+
+    /// Carries out a remote control request without a server, e.g. from language bindings.
+    pub fn command(&mut self, method: &str, params: &Json) -> Result<Json, rpc::Error> {
+        let mut rebuild_points = false;
+        let mut exit = false;
+        let result = self.rpc_call(method, params, &mut rebuild_points, &mut exit);
+        self.view.record_history();
+        if rebuild_points {
+            self.update_geometry();
+        }
+        result
+    }
+
+    fn rpc_call(&mut self, method: &str, params: &Json, rebuild_points: &mut bool, exit: &mut bool) -> Result<Json, rpc::Error> {
+        let file = params.get("file").and_then(|f| f.as_str()).map(String::from);
+        let result = match method {
+            "get_state" => Ok(()),
+            "list_columns" => {
+                return Ok(Json::Array(self.view.columns.iter().map(|c| {
+                    Json::object(vec![
+                        ("name", Json::string(&c.name)),
+                        ("kind", Json::string(if c.kind == Kind::Time { "time" } else { "number" })),
+                        ("min", Json::Number(c.min)),
+                        ("max", Json::Number(c.max)),
+                    ])
+                }).collect()));
+            },
+            "action" => {
+                match params.get("name").and_then(|n| n.as_str()).and_then(Action::from_name) {
+                    Some(action) => {
+                        self.view.gesture = None;
+                        self.apply_action(action, rebuild_points, exit);
+                        Ok(())
+                    },
+                    None => {
+                        return Err(rpc::Error::invalid_params(String::from("'name' has to be the name of an action")));
+                    }
+                }
+            },
+            "set_columns" => {
+                return self.rpc_set_columns(params, rebuild_points);
+            },
+            "set_view" => {
+                return self.rpc_set_view(params);
+            },
+            "set_options" => {
+                return self.rpc_set_options(params);
+            },
+            "set_filter" => {
+                let mut filter = Filter::new();
+                for f in params.get("filter").and_then(|f| f.as_array()).unwrap_or(&[]) {
+                    match f.as_str().map(|s| filter.add(s)) {
+                        Some(Ok(_)) => (),
+                        Some(Err(s)) => {
+                            return Err(rpc::Error::invalid_params(s));
+                        },
+                        None => {
+                            return Err(rpc::Error::invalid_params(String::from("'filter' has to be a list of strings")));
+                        }
+                    }
+                }
+                self.set_filter(filter, rebuild_points)
+            },
+            "load" | "screenshot" | "export_figure" | "export_grid" if file.is_none() => {
+                return Err(rpc::Error::invalid_params(String::from("'file' is missing")));
+            },
+            "load" => self.load_file(&file.unwrap(), rebuild_points),
+            "screenshot" => {
+                let size = params.get("size").and_then(|s| s.as_str()).unwrap_or(cfg::SCREENSHOT_SIZE_DEFAULT);
+                let png16 = params.get("png16").and_then(|p| p.as_bool()).unwrap_or(false);
+                match Screenshot::new(size, &file.unwrap(), png16) {
+                    Ok(s) => self.screenshot(&s),
+                    Err(s) => {
+                        return Err(rpc::Error::invalid_params(s));
+                    }
+                }
+            },
+            "export_figure" => self.export_figure(&file.unwrap()),
+            "export_grid" => self.export_grid(&file.unwrap()),
+            _ => {
+                return Err(rpc::Error::new(rpc::METHOD_NOT_FOUND, format!("unknown method '{}'", method)));
+            }
+        };
+        match result {
+            Ok(_)  => Ok(self.state_json()),
+            Err(s) => Err(rpc::Error::failed(s)),
+        }
+    }
+
+    /// `x`, `y` and `z` are column names, `tx`, `ty` and `tz` transform names; all optional.
+    fn rpc_set_columns(&mut self, params: &Json, rebuild_points: &mut bool) -> Result<Json, rpc::Error> {
+        let old = self.view.column_state.clone();
+        let mut cs = old.clone();
+        {
+            let mut axes = [("x", "tx", &mut cs.x, &mut cs.tx), ("y", "ty", &mut cs.y, &mut cs.ty), ("z", "tz", &mut cs.z, &mut cs.tz)];
+            for entry in axes.iter_mut() {
+                if let Some(name) = params.get(entry.0) {
+                    match name.as_str().and_then(|n| self.view.columns.iter().position(|c| c.name == n)) {
+                        Some(idx) => *entry.2 = idx,
+                        None => {
+                            return Err(rpc::Error::invalid_params(format!("'{}' has to be the name of a column", entry.0)));
+                        }
+                    }
+                }
+                if let Some(name) = params.get(entry.1) {
+                    match name.as_str().and_then(Transform::from_name) {
+                        Some(t) => *entry.3 = t,
+                        None => {
+                            return Err(rpc::Error::invalid_params(format!("'{}' has to be the name of a transform", entry.1)));
+                        }
+                    }
+                }
+            }
+        }
+
+        self.view.column_state = cs.clone();
+        if cs.x != old.x || cs.tx != old.tx {
+            self.view.autoscale_x();
+        }
+        if cs.y != old.y || cs.ty != old.ty {
+            self.view.autoscale_y();
+        }
+        if cs.z != old.z || cs.tz != old.tz {
+            self.view.autoscale_z();
+        }
+        if cs != old {
+            *rebuild_points = true;
+            self.redraw = true;
+        }
+        Ok(self.state_json())
+    }
+
+    /// `x`, `y` and `z` are optional `[min, max]` ranges in the units of the (transformed) columns.
+    fn rpc_set_view(&mut self, params: &Json) -> Result<Json, rpc::Error> {
+        let mut ranges = vec![];
+        for &axis in &["x", "y", "z"] {
+            let range = match params.get(axis).map(|r| r.as_array().unwrap_or(&[])) {
+                Some(r) if r.len() == 2 && r[0].as_f64().is_some() && r[1].as_f64().is_some() => Some((r[0].as_f64().unwrap(), r[1].as_f64().unwrap())),
+                Some(_) => {
+                    return Err(rpc::Error::invalid_params(format!("'{}' has to be [min, max]", axis)));
+                },
+                None => None,
+            };
+            ranges.push(range);
+        }
+
+        if let Some((min, max)) = ranges[0] {
+            self.view.projection.adjust_x(min, max);
+        }
+        if let Some((min, max)) = ranges[1] {
+            self.view.projection.adjust_y(min, max);
+        }
+        if let Some((min, max)) = ranges[2] {
+            self.view.projection.adjust_z(min, max);
+        }
+        self.redraw = true;
+        Ok(self.state_json())
+    }
+
+    /// `gamma` and `pointsize` are numbers, `border` and `axes` booleans; all optional.
+    fn rpc_set_options(&mut self, params: &Json) -> Result<Json, rpc::Error> {
+        let mut user = self.view.user_state.clone();
+        {
+            let mut numbers = [("gamma", &mut user.gamma, cfg::GAMMA_MIN, cfg::GAMMA_MAX), ("pointsize", &mut user.pointsize, cfg::POINTSIZE_MIN, cfg::POINTSIZE_MAX)];
+            for entry in numbers.iter_mut() {
+                match params.get(entry.0).map(|v| v.as_f64()) {
+                    Some(Some(v)) if v > 0.0 => *entry.1 = f32::min(f32::max(v as f32, entry.2), entry.3),
+                    Some(_) => {
+                        return Err(rpc::Error::invalid_params(format!("'{}' has to be a positive number", entry.0)));
+                    },
+                    None => (),
+                }
+            }
+            let mut flags = [("border", &mut user.showborder), ("axes", &mut user.showaxes)];
+            for entry in flags.iter_mut() {
+                match params.get(entry.0).map(|v| v.as_bool()) {
+                    Some(Some(b)) => *entry.1 = b,
+                    Some(None) => {
+                        return Err(rpc::Error::invalid_params(format!("'{}' has to be true or false", entry.0)));
+                    },
+                    None => (),
+                }
+            }
+        }
+        self.view.user_state = user;
+        self.redraw = true;
+        Ok(self.state_json())
+    }
+
+    fn set_status(&mut self, msg: String) {
+        info!("{}", msg);
+        self.status = Some((msg, Instant::now()));
+    }
+
+    fn apply_action(&mut self, action: Action, rebuild_points: &mut bool, exit: &mut bool) {
+        debug!("action: {}", action.name());
+        if let Some(change) = self.view.apply_action(action) {
+            if change.columns {
+                *rebuild_points = true;
+            }
+            if let Some(msg) = change.status {
+                self.set_status(msg);
+            }
+            self.redraw = true;
+            return;
+        }
+        match action {
+            Action::Quit => {
+                *exit = true;
+            },
+            Action::ToggleHelp => {
+                self.show_help = !self.show_help;
+            },
+            Action::PointsizeIncrease => {
+                if self.bins.shape.is_some() {
+                    self.bins.size_increase();
+                } else {
+                    self.view.user_state.pointsize_increase();
+                }
+                self.redraw = true;
+            },
+            Action::PointsizeDecrease => {
+                if self.bins.shape.is_some() {
+                    self.bins.size_decrease();
+                } else {
+                    self.view.user_state.pointsize_decrease();
+                }
+                self.redraw = true;
+            },
+            Action::AddFilter => {
+                self.prompt = Some(Prompt::new(PromptPurpose::Filter, "add filter (e.g. temp>300 or 0<=x<1)"));
+            },
+            Action::ClearFilter => {
+                if let Err(s) = self.set_filter(Filter::new(), rebuild_points) {
+                    self.set_status(s);
+                }
+            },
+            Action::DeriveColumn => {
+                self.prompt = Some(Prompt::new(PromptPurpose::DeriveColumn, "derive column (e.g. ratio = a / b)"));
+            },
+            Action::ExportFigure => {
+                let mut prompt = Prompt::new(PromptPurpose::ExportFigure, "export view to (.svg or .pdf)");
+                prompt.input = format!("{}.svg", self.fname);
+                self.prompt = Some(prompt);
+            },
+            Action::Screenshot => {
+                let mut prompt = Prompt::new(PromptPurpose::Screenshot, "screenshot (WIDTHxHEIGHT FILE [16], FILE .png, .exr or .tiff)");
+                prompt.input = format!("{} {}.png", cfg::SCREENSHOT_SIZE_DEFAULT, self.fname);
+                self.prompt = Some(prompt);
+            },
+            Action::ExportGrid => {
+                let mut prompt = Prompt::new(PromptPurpose::ExportGrid, "export density grid to (.npy or .csv)");
+                prompt.input = format!("{}.density.npy", self.fname);
+                self.prompt = Some(prompt);
+            },
+            Action::Animate => {
+                let mut prompt = Prompt::new(PromptPurpose::Animate, "animate column (NAME [FRAMES PER WINDOW], empty to stop)");
+                if let Some(ref a) = self.animation {
+                    prompt.input = a.to_string(&self.view.columns);
+                }
+                self.prompt = Some(prompt);
+            },
+            Action::AnimationPlay | Action::AnimationNext | Action::AnimationPrev | Action::AnimationTrails => {
+                match self.animation {
+                    Some(ref mut a) => {
+                        match action {
+                            Action::AnimationPlay   => a.playing = !a.playing,
+                            Action::AnimationNext   => a.step(1),
+                            Action::AnimationPrev   => a.step(-1),
+                            _                       => a.trails = !a.trails,
+                        }
+                        self.redraw = true;
+                        self.redraw_full = true;
+                    },
+                    None => {
+                        self.set_status(String::from("no animation, choose a column first"));
+                    },
+                }
+            },
+            Action::ExportAnimation => {
+                if self.animation.is_some() {
+                    let mut prompt = Prompt::new(PromptPurpose::ExportAnimation, "export animation to (.png series, .y4m or .gif)");
+                    prompt.input = format!("{}.gif", self.fname);
+                    self.prompt = Some(prompt);
+                } else {
+                    self.set_status(String::from("no animation, choose a column first"));
+                }
+            },
+            Action::Contours => {
+                let mut contours = self.contours.clone();
+                contours.shown = !contours.shown;
+                let msg = if contours.shown { contours.label() } else { String::from("contours off") };
+                self.set_status(msg);
+                self.set_contours(contours);
+            },
+            Action::ContourLevels => {
+                let mut prompt = Prompt::new(PromptPurpose::ContourLevels, "contour levels in percent of the points (e.g. 50 90 99)");
+                prompt.input = self.contours.percents.iter().map(|p| p.to_string()).collect::<Vec<String>>().join(" ");
+                self.prompt = Some(prompt);
+            },
+            Action::LayerComposite => {
+                let msg = match self.layers {
+                    Some(ref mut layers) => {
+                        layers.composite = layers.composite.next();
+                        format!("layers: {}", layers.composite.name())
+                    },
+                    None => String::from("no layers, open several files or split by a column"),
+                };
+                self.set_status(msg);
+                self.redraw = true;
+            },
+            Action::ToggleLayer(layer) => {
+                let msg = match self.layers {
+                    Some(ref mut layers) if layer <= layers.len() => {
+                        layers.visible[layer - 1] = !layers.visible[layer - 1];
+                        format!("layer {} ({}) {}", layer, layers.names[layer - 1], if layers.visible[layer - 1] { "shown" } else { "hidden" })
+                    },
+                    _ => format!("there is no layer {}", layer),
+                };
+                self.set_status(msg);
+                self.redraw = true;
+            },
+            Action::Binning => {
+                let mut bins = self.bins.clone();
+                bins.next_shape();
+                let msg = bins.label();
+                match self.set_bins(bins) {
+                    Ok(_)  => self.set_status(msg),
+                    Err(s) => self.set_status(s),
+                }
+            },
+            Action::ZStat => {
+                let stat = self.stat.next(self.bins.shape.is_some());
+                self.set_stat(stat);
+                let msg = format!("colored by the {} of {}", self.stat.name(), self.view.columns[self.view.column_state.z].name);
+                self.set_status(msg);
+            },
+            Action::SplitLayers => {
+                let mut prompt = Prompt::new(PromptPurpose::SplitLayers, "split into layers by column (NAME, empty to merge them)");
+                if let Some(ref layers) = self.layers {
+                    prompt.input = self.view.columns[layers.column].name.clone();
+                }
+                self.prompt = Some(prompt);
+            },
+            Action::StoreBookmark(slot) => {
+                let label = format!("name for bookmark {}", slot);
+                self.prompt = Some(Prompt::new(PromptPurpose::BookmarkName(slot), &label));
+            },
+            // carried out by the view above
+            _ => (),
+        }
+    }
+
+    /// Uploads the indices of the selected rows, unless the dataset is large enough for the LOD.
+    /// An animation gets them ordered by frame and layers get them ordered by layer, whatever the
+    /// size of the dataset, as long as every window of frames or layer stays below that size.
+    fn update_rows(&mut self) {
+        self.n = self.view.mask.iter().filter(|m| **m).count();
+        self.layer_rows = vec![];
+        let mut rows = vec![];
+        let stop = match self.animation {
+            Some(ref mut a) => {
+                rows = a.rows(&self.view.columns, &self.view.mask);
+                a.largest_window() >= cfg::LOD_MIN_POINTS
+            },
+            None => false,
+        };
+        if stop {
+            self.animation = None;
+            self.set_status(format!("stopped animation, its windows need fewer than {} rows", cfg::LOD_MIN_POINTS));
+        }
+        let merge = match (&self.animation, &self.layers) {
+            (&None, &Some(ref layers)) => {
+                let (layer_rows, ranges) = layers.rows(&self.view.columns, &self.view.mask);
+                rows = layer_rows;
+                self.layer_rows = ranges;
+                self.layer_rows.iter().any(|r| r.len() >= cfg::LOD_MIN_POINTS)
+            },
+            _ => false,
+        };
+        if merge {
+            self.layers = None;
+            self.layer_rows = vec![];
+            self.set_status(format!("merged the layers, every layer needs fewer than {} rows", cfg::LOD_MIN_POINTS));
+        }
+        if self.animation.is_none() && self.layers.is_none() {
+            rows = if self.n < cfg::LOD_MIN_POINTS {
+                (0..self.view.mask.len()).filter(|&i| self.view.mask[i]).map(|i| i as u32).collect::<Vec<u32>>()
+            } else {
+                vec![]
+            };
+        }
+        self.row_indices = glium::IndexBuffer::new(&self.context, glium::index::PrimitiveType::Points, &rows).unwrap();
+        if self.layer_textures.len() != self.layer_rows.len() {
+            self.build_layer_textures();
+        }
+    }
+
+    /// One full and one low resolution texture per layer that is drawn on its own.
+    fn build_layer_textures(&mut self) {
+        self.layer_textures = self.layer_rows.iter().map(|_| {
+            (build_renderable_texture(&self.context, &self.window_dims), build_renderable_texture(&self.context, &self.window_dims.to_lowres()))
+        }).collect();
+    }
+
+    /// Whether the rows are aggregated, which an animation draws frame by frame and layers draw
+    /// layer by layer instead.
+    fn needs_lod(&self) -> bool {
+        self.n >= cfg::LOD_MIN_POINTS && self.animation.is_none() && self.layers.is_none()
+    }
+
+    /// Rebuilds the geometry after an animation or layers were started or stopped on a dataset large
+    /// enough for the LOD.
+    fn update_lod(&mut self) {
+        if self.lod.is_some() != self.needs_lod() {
+            self.update_geometry();
+        }
+    }
+
+    /// Prepares the current columns: datasets below `cfg::LOD_MIN_POINTS` rows, animations and layers
+    /// are drawn from GPU columns, which stay uploaded, larger ones get a LOD aggregation, built again
+    /// for every change.
+    fn update_geometry(&mut self) {
+        self.z_categories = None;
+        let cs = self.view.column_state.clone();
+        self.view.transform_cache.prepare(&self.view.columns, cs.x, cs.tx);
+        self.view.transform_cache.prepare(&self.view.columns, cs.y, cs.ty);
+        self.view.transform_cache.prepare(&self.view.columns, cs.z, cs.tz);
+
+        if !self.needs_lod() {
+            let (cache, columns) = (&self.view.transform_cache, &self.view.columns);
+            let shown = [(cs.x, cs.tx), (cs.y, cs.ty), (cs.z, cs.tz)].iter().map(|&(idx, t)| {
+                (idx, t, cache.get(columns, idx, t).0)
+            }).collect::<Vec<(usize, Transform, &Column)>>();
+            self.gpu_columns.prepare(&self.context, &shown);
+        }
+
+        self.lod = if self.needs_lod() {
+            info!("aggregate {} points", self.n);
+            let (x, _) = self.view.transform_cache.get(&self.view.columns, cs.x, cs.tx);
+            let (y, _) = self.view.transform_cache.get(&self.view.columns, cs.y, cs.ty);
+            let (z, _) = self.view.transform_cache.get(&self.view.columns, cs.z, cs.tz);
+            Lod::build(x, y, z, &self.view.mask, cfg::LOD_DEPTH)
+        } else {
+            None
+        };
+        self.upload_points();
+    }
+
+    fn view_rect(&self) -> Rect {
+        let (x_min, x_max) = self.view.projection.range_x();
+        let (y_min, y_max) = self.view.projection.range_y();
+        Rect {
+            x_min: x_min,
+            x_max: x_max,
+            y_min: y_min,
+            y_max: y_max,
+        }
+    }
+
+    /// Coarsest LOD level that still resolves single pixels of a `width` x `height` image of the view,
+    /// `None` for raw points.
+    fn lod_level(&self, lod: &Lod, width: u32, height: u32) -> Option<usize> {
+        let view = self.view_rect();
+        let pixel_width = (view.x_max - view.x_min) / (width as f64);
+        let pixel_height = (view.y_max - view.y_min) / (height as f64);
+        lod.level_for(pixel_width * cfg::LOD_CELL_PIXELS, pixel_height * cfg::LOD_CELL_PIXELS)
+    }
+
+    /// Whether the uploaded points are too far from the view center to be precise, or do not match
+    /// the LOD level and region of the current view.
+    fn points_stale(&self) -> bool {
+        if self.view.projection.distance(&self.origin) > cfg::REBASE_DISTANCE {
+            return true;
+        }
+        match (&self.lod, &self.lod_view) {
+            (&Some(ref lod), &Some((level, ref region))) => {
+                self.lod_level(lod, self.window_dims.width, self.window_dims.height) != level || !region.contains(&self.view_rect())
+            },
+            _ => false,
+        }
+    }
+
+    /// LOD points of `level` in `region`, relative to the origin; empty if there is no LOD.
+    fn lod_points(&self, level: Option<usize>, region: &Rect) -> Vec<Point> {
+        let cs = &self.view.column_state;
+        let (x, _) = self.view.transform_cache.get(&self.view.columns, cs.x, cs.tx);
+        let (y, _) = self.view.transform_cache.get(&self.view.columns, cs.y, cs.ty);
+        let (z, _) = self.view.transform_cache.get(&self.view.columns, cs.z, cs.tz);
+        match self.lod {
+            Some(ref lod) => lod.points(level, region, cfg::LOD_BORDER_LEVEL, x, y, z, self.origin),
+            None => vec![],
+        }
+    }
+
+    /// Moves the origin to the view center and, if there is a LOD, uploads the points of the current
+    /// level and region relative to it.
+    fn upload_points(&mut self) {
+        self.origin = self.view.projection.center();
+        self.lod_view = match self.lod {
+            Some(ref lod) => {
+                let level = self.lod_level(lod, self.window_dims.width, self.window_dims.height);
+                debug!("upload LOD level {:?}", level);
+                Some((level, self.view_rect().expand(cfg::LOD_MARGIN)))
+            },
+            None => None,
+        };
+
+        let points = match self.lod_view {
+            Some((level, ref region)) => self.lod_points(level, region),
+            None => vec![],
+        };
+        self.vertex_buffer_points = glium::VertexBuffer::new(&self.context, &points).unwrap();
+        self.redraw = true;
     }
 
     fn throttle(&mut self) {
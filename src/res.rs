@@ -1,4 +1,12 @@
-pub static VERTEX_SHADER_POINTS_SRC:    &'static str = include_str!("../res/shader.points.vertex.glsl");
+// the shared parts (cells, project, color) have no #version line of their own
+pub static VERTEX_SHADER_POINTS_SRC:    &'static str = concat!("#version 140\n", include_str!("../res/shader.cells.glsl"), include_str!("../res/shader.project.glsl"), include_str!("../res/shader.points.vertex.glsl"));
+pub static VERTEX_SHADER_COLUMNS_SRC:   &'static str = concat!("#version 140\n", include_str!("../res/shader.cells.glsl"), include_str!("../res/shader.project.glsl"), include_str!("../res/shader.columns.vertex.glsl"));
 pub static FRAGMENT_SHADER_POINTS_SRC:  &'static str = include_str!("../res/shader.points.fragment.glsl");
 pub static VERTEX_SHADER_TEXTURE_SRC:   &'static str = include_str!("../res/shader.texture.vertex.glsl");
-pub static FRAGMENT_SHADER_TEXTURE_SRC: &'static str = include_str!("../res/shader.texture.fragment.glsl");
+pub static FRAGMENT_SHADER_TEXTURE_SRC: &'static str = concat!("#version 140\n", include_str!("../res/shader.color.glsl"), include_str!("../res/shader.texture.fragment.glsl"));
+pub static FRAGMENT_SHADER_LAYERS_SRC:  &'static str = include_str!("../res/shader.layers.fragment.glsl");
+pub static FRAGMENT_SHADER_BINS_SRC:    &'static str = concat!("#version 140\n", include_str!("../res/shader.cells.glsl"), include_str!("../res/shader.color.glsl"), include_str!("../res/shader.bins.fragment.glsl"));
+pub static VERTEX_SHADER_TEXT_SRC:      &'static str = include_str!("../res/shader.text.vertex.glsl");
+pub static FRAGMENT_SHADER_TEXT_SRC:    &'static str = include_str!("../res/shader.text.fragment.glsl");
+pub static FONT_BITMAP:                 &'static [u8] = include_bytes!("../res/font.bin");
+pub static SERVE_PAGE:                  &'static str = include_str!("../res/serve.html");
@@ -0,0 +1,291 @@
+//! JSON-RPC 2.0 server for remote control, one request or notification per line. Connections are
+//! handled in background threads, the renderer polls the requests from its main loop.
+
+use cfg;
+use json::Json;
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::thread;
+
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+
+pub static PARSE_ERROR: i64 = -32700;
+pub static INVALID_REQUEST: i64 = -32600;
+pub static METHOD_NOT_FOUND: i64 = -32601;
+pub static INVALID_PARAMS: i64 = -32602;
+/// the request was valid, but could not be carried out
+pub static FAILED: i64 = -32000;
+
+
+pub struct Error {
+    pub code: i64,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(code: i64, message: String) -> Error {
+        Error {
+            code: code,
+            message: message,
+        }
+    }
+
+    pub fn invalid_params(message: String) -> Error {
+        Error::new(INVALID_PARAMS, message)
+    }
+
+    pub fn failed(message: String) -> Error {
+        Error::new(FAILED, message)
+    }
+}
+
+
+pub struct Request {
+    /// `None` for notifications, which get no response
+    pub id: Option<Json>,
+    pub method: String,
+    pub params: Json,
+    client: usize,
+}
+
+
+/// Queues of the lines to write to every client, each written by a thread of its own.
+type Clients = Arc<Mutex<Vec<(usize, SyncSender<String>)>>>;
+
+/// Queues `message` without blocking the renderer.
+fn send(clients: &Clients, client: Option<usize>, message: &Json) {
+    let line = format!("{}\n", message.to_string());
+    let mut clients = clients.lock().unwrap();
+    let mut failed = vec![];
+    for &(id, ref queue) in clients.iter() {
+        if client.is_none() || client == Some(id) {
+            if queue.try_send(line.clone()).is_err() {
+                failed.push(id);
+            }
+        }
+    }
+    // drop clients that stopped reading or cannot be written to anymore, which also ends their
+    // writer threads
+    for id in &failed {
+        debug!("dropping remote control client {}", id);
+    }
+    clients.retain(|&(id, _)| !failed.contains(&id));
+}
+
+fn write_lines(mut writer: Box<dyn Write + Send>, lines: Receiver<String>) {
+    for line in lines {
+        if writer.write_all(line.as_bytes()).and_then(|_| writer.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+fn error_response(id: Json, code: i64, message: &str) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::string("2.0")),
+        ("id", id),
+        ("error", Json::object(vec![
+            ("code", Json::Number(code as f64)),
+            ("message", Json::string(message)),
+        ])),
+    ])
+}
+
+/// Turns a line into a request, or the error response for it.
+fn parse_request(line: &str, client: usize) -> Result<Request, Json> {
+    let message = match Json::parse(line) {
+        Ok(m) => m,
+        Err(s) => {
+            return Err(error_response(Json::Null, PARSE_ERROR, &s));
+        }
+    };
+    let id = message.get("id").cloned();
+    let method = match message.get("method").and_then(|m| m.as_str()) {
+        Some(m) => String::from(m),
+        None => {
+            return Err(error_response(id.unwrap_or(Json::Null), INVALID_REQUEST, "request has no method"));
+        }
+    };
+    Ok(Request {
+        id: id,
+        method: method,
+        params: message.get("params").cloned().unwrap_or(Json::Object(vec![])),
+        client: client,
+    })
+}
+
+/// Reads requests from one connection until it is closed.
+fn serve_connection<R>(stream: R, client: usize, requests: Sender<Request>, clients: Clients) where R: Read {
+    debug!("remote control client {} connected", client);
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_request(&line, client) {
+            Ok(request) => {
+                if requests.send(request).is_err() {
+                    break;
+                }
+            },
+            Err(response) => send(&clients, Some(client), &response),
+        }
+    }
+    clients.lock().unwrap().retain(|&(id, _)| id != client);
+    debug!("remote control client {} disconnected", client);
+}
+
+/// Registers a new connection and reads from it in its own thread.
+fn accept<S>(stream: S, writer: Box<dyn Write + Send>, next_client: &mut usize, requests: &Sender<Request>, clients: &Clients) where S: Read + Send + 'static {
+    let client = *next_client;
+    *next_client += 1;
+    let (queue, lines) = sync_channel(cfg::RPC_QUEUE_MAX);
+    clients.lock().unwrap().push((client, queue));
+    thread::spawn(move || write_lines(writer, lines));
+    let (requests, clients) = (requests.clone(), clients.clone());
+    thread::spawn(move || serve_connection(stream, client, requests, clients));
+}
+
+
+pub struct Server {
+    requests: Receiver<Request>,
+    clients: Clients,
+    /// path of the Unix domain socket, removed again when the server is dropped
+    socket_path: Option<String>,
+}
+
+impl Server {
+    /// Listens on `127.0.0.1:<endpoint>` if `endpoint` is a port number, on the Unix domain socket
+    /// at path `endpoint` otherwise.
+    pub fn start(endpoint: &str) -> Result<Server, String> {
+        let (tx, rx) = channel();
+        let clients: Clients = Arc::new(Mutex::new(vec![]));
+
+        let socket_path = match endpoint.parse::<u16>() {
+            Ok(port) => {
+                let listener = match TcpListener::bind(("127.0.0.1", port)) {
+                    Ok(l) => l,
+                    Err(e) => {
+                        return Err(format!("cannot listen on port {}: {}", port, e));
+                    }
+                };
+                let clients = clients.clone();
+                thread::spawn(move || {
+                    let mut next_client = 0;
+                    for stream in listener.incoming() {
+                        if let Ok(stream) = stream {
+                            if let Ok(writer) = stream.try_clone() {
+                                accept(stream, Box::new(writer), &mut next_client, &tx, &clients);
+                            }
+                        }
+                    }
+                });
+                None
+            },
+            Err(_) => {
+                match listen_unix(endpoint, tx, clients.clone()) {
+                    Ok(_) => Some(String::from(endpoint)),
+                    Err(s) => {
+                        return Err(s);
+                    }
+                }
+            },
+        };
+
+        info!("remote control listening on {}", endpoint);
+        Ok(Server {
+            requests: rx,
+            clients: clients,
+            socket_path: socket_path,
+        })
+    }
+
+    /// Requests received since the last call.
+    pub fn poll(&self) -> Vec<Request> {
+        self.requests.try_iter().collect()
+    }
+
+    pub fn respond(&self, request: &Request, result: Result<Json, Error>) {
+        let id = match request.id {
+            Some(ref id) => id.clone(),
+            None => {
+                return;
+            }
+        };
+        let response = match result {
+            Ok(value) => Json::object(vec![
+                ("jsonrpc", Json::string("2.0")),
+                ("id", id),
+                ("result", value),
+            ]),
+            Err(e) => error_response(id, e.code, &e.message),
+        };
+        send(&self.clients, Some(request.client), &response);
+    }
+
+    /// Sends a notification to all connected clients.
+    pub fn notify(&self, method: &str, params: Json) {
+        let notification = Json::object(vec![
+            ("jsonrpc", Json::string("2.0")),
+            ("method", Json::string(method)),
+            ("params", params),
+        ]);
+        send(&self.clients, None, &notification);
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        if let Some(ref path) = self.socket_path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+
+#[cfg(unix)]
+fn listen_unix(path: &str, requests: Sender<Request>, clients: Clients) -> Result<(), String> {
+    // a socket file that nobody listens on is left over from an earlier run, anything else at the
+    // path belongs to the user
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(format!("cannot listen on socket {}: path exists and is not a socket", path));
+        }
+        if UnixStream::connect(path).is_err() {
+            let _ = fs::remove_file(path);
+        }
+    }
+    let listener = match UnixListener::bind(path) {
+        Ok(l) => l,
+        Err(e) => {
+            return Err(format!("cannot listen on socket {}: {}", path, e));
+        }
+    };
+    thread::spawn(move || {
+        let mut next_client = 0;
+        for stream in listener.incoming() {
+            if let Ok(stream) = stream {
+                if let Ok(writer) = stream.try_clone() {
+                    accept(stream, Box::new(writer), &mut next_client, &requests, &clients);
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn listen_unix(path: &str, _: Sender<Request>, _: Clients) -> Result<(), String> {
+    Err(format!("cannot listen on {}, only TCP ports are supported on this platform", path))
+}
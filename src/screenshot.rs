@@ -0,0 +1,113 @@
+use image;
+use image::Image;
+
+
+/// Largest supported width and height.
+static MAX_SIZE: u32 = 32768;
+/// Largest supported number of pixels, as the accumulation buffer and the tone mapped image are
+/// both kept in memory as floats (32 bytes per pixel together).
+static MAX_PIXELS: u64 = 256 * 1024 * 1024;
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Png8,
+    Png16,
+    /// raw accumulation buffer as OpenEXR
+    Exr,
+    /// raw accumulation buffer as TIFF
+    Tiff,
+}
+
+
+/// An image of the current view that is rendered offscreen, independent of the window size.
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub fname: String,
+    pub format: Format,
+}
+
+impl Screenshot {
+    /// `size` is `WIDTHxHEIGHT`, the format follows from the extension of `fname` (`.png`, `.exr`,
+    /// `.tif` or `.tiff`); `png16` selects 16 bit instead of 8 bit PNGs.
+    pub fn new(size: &str, fname: &str, png16: bool) -> Result<Screenshot, String> {
+        let (width, height) = match parse_size(size) {
+            Some(s) => s,
+            None => {
+                return Err(format!("'{}' is not a valid size, use WIDTHxHEIGHT with at most {} pixels each", size, MAX_SIZE));
+            }
+        };
+        if let Err(s) = check_size(width, height) {
+            return Err(s);
+        }
+
+        let lower = fname.to_lowercase();
+        let format = if lower.ends_with(".png") {
+            if png16 { Format::Png16 } else { Format::Png8 }
+        } else if lower.ends_with(".exr") {
+            Format::Exr
+        } else if lower.ends_with(".tif") || lower.ends_with(".tiff") {
+            Format::Tiff
+        } else {
+            return Err(format!("cannot save a screenshot as {}, use .png, .exr or .tiff", fname));
+        };
+
+        Ok(Screenshot {
+            width: width,
+            height: height,
+            fname: String::from(fname),
+            format: format,
+        })
+    }
+
+    /// `WIDTHxHEIGHT FILE`, optionally followed by `16` for a 16 bit PNG.
+    pub fn parse(s: &str) -> Result<Screenshot, String> {
+        let parts = s.split_whitespace().collect::<Vec<&str>>();
+        match parts.len() {
+            2 => Screenshot::new(parts[0], parts[1], false),
+            3 if parts[2] == "16" => Screenshot::new(parts[0], parts[1], true),
+            3 if parts[2] == "8" => Screenshot::new(parts[0], parts[1], false),
+            _ => Err(format!("'{}' is not a valid screenshot, use WIDTHxHEIGHT FILE [16]", s)),
+        }
+    }
+
+    /// Writes the tone mapped image as PNG, or the raw accumulation buffer (Lab sums and point
+    /// count as RGBA) as EXR or TIFF.
+    pub fn save(&self, tonemapped: &Image, accumulation: &Image) -> Result<(), String> {
+        let data = match self.format {
+            Format::Png8  => image::encode_png(self.width, self.height, 8, &tonemapped.to_srgb8()),
+            Format::Png16 => image::encode_png(self.width, self.height, 16, &tonemapped.to_srgb16()),
+            Format::Exr   => image::encode_exr(accumulation),
+            Format::Tiff  => {
+                match image::encode_tiff(accumulation) {
+                    Ok(d) => d,
+                    Err(s) => {
+                        return Err(s);
+                    }
+                }
+            },
+        };
+        image::write_file(&self.fname, &data)
+    }
+}
+
+
+/// Whether an image of `width` x `height` pixels can be rendered, for every way of rendering one.
+pub fn check_size(width: u32, height: u32) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        Err(String::from("the image needs at least one pixel"))
+    } else if (width as u64) * (height as u64) > MAX_PIXELS {
+        Err(format!("{}x{} is too large, images can have at most {} megapixels", width, height, MAX_PIXELS / (1024 * 1024)))
+    } else {
+        Ok(())
+    }
+}
+
+fn parse_size(s: &str) -> Option<(u32, u32)> {
+    let mut parts = s.splitn(2, 'x');
+    match (parts.next().map(|w| w.parse::<u32>()), parts.next().map(|h| h.parse::<u32>())) {
+        (Some(Ok(w)), Some(Ok(h))) if w > 0 && h > 0 && w <= MAX_SIZE && h <= MAX_SIZE => Some((w, h)),
+        _ => None,
+    }
+}
@@ -0,0 +1,492 @@
+//! HTTP server for looking at the data in a browser: a small page that shows map-style tiles of the
+//! density plot, rendered on the CPU on demand by a few worker threads. From `LOD_MIN_POINTS`
+//! selected rows on, tiles are drawn from a `Lod`, so that every tile only touches its own cells.
+//!
+//! - `/`: the page
+//! - `/info`: name, row count and columns, as JSON
+//! - `/extent?<params>`: X and Y range of the whole plot, as JSON
+//! - `/tiles/<zoom>/<x>/<y>.png?<params>`: tile `x`, `y` (from the top left) of `2^zoom` tiles per
+//!   axis
+//!
+//! The parameters are the columns `x`, `y` and `z` by name, their transforms `tx`, `ty` and `tz`,
+//! `gamma`, `pointsize` and any number of `filter`s, which add to the filter given on start.
+
+use cfg;
+use cpu;
+use data;
+use data::Column;
+use filter::Filter;
+use image;
+use image::Image;
+use json::Json;
+use lod::{Lod, Rect};
+use res;
+use settings::Settings;
+use transform::{Transform, TransformCache};
+use view::{ColumnState, Projection};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+
+/// What a tile or extent request asks for.
+struct Params {
+    columns: ColumnState,
+    gamma: f32,
+    pointsize: f32,
+    filters: Vec<String>,
+}
+
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: Vec<u8>) -> Response {
+        Response {
+            status: "200 OK",
+            content_type: content_type,
+            body: body,
+        }
+    }
+
+    fn error(status: &'static str, message: String) -> Response {
+        Response {
+            status: status,
+            content_type: "text/plain; charset=utf-8",
+            body: message.into_bytes(),
+        }
+    }
+
+    fn json(value: Json) -> Response {
+        Response::ok("application/json", value.to_string().into_bytes())
+    }
+}
+
+
+/// Decodes `%XX` escapes and `+` for spaces.
+fn url_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match u8::from_str_radix(&String::from_utf8_lossy(&bytes[i + 1..i + 3]), 16) {
+                    Ok(b) => {
+                        out.push(b);
+                        i += 2;
+                    },
+                    Err(_) => out.push(b'%'),
+                }
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query.split('&').filter(|p| !p.is_empty()).map(|p| {
+        match p.find('=') {
+            Some(pos) => (url_decode(&p[..pos]), url_decode(&p[pos + 1..])),
+            None      => (url_decode(p), String::new()),
+        }
+    }).collect()
+}
+
+
+/// Answers the connections of `streams` until the server stops.
+fn serve_connections(server: Arc<TileServer>, streams: Arc<Mutex<Receiver<TcpStream>>>) {
+    loop {
+        let stream = match streams.lock().unwrap().recv() {
+            Ok(s) => s,
+            Err(_) => {
+                return;
+            }
+        };
+        handle_connection(&server, stream);
+    }
+}
+
+/// Answers a single GET request and closes the connection.
+fn handle_connection(server: &TileServer, stream: TcpStream) {
+    let timeout = Some(Duration::from_millis(cfg::SERVE_TIMEOUT_MILLIS));
+    if stream.set_read_timeout(timeout).and_then(|_| stream.set_write_timeout(timeout)).is_err() {
+        return;
+    }
+    // the request line and the headers together are bounded
+    let mut reader = BufReader::new((&stream).take(cfg::SERVE_REQUEST_MAX));
+    let mut request_line = String::new();
+    match reader.read_line(&mut request_line) {
+        Ok(n) if n > 0 => (),
+        _ => {
+            return;
+        }
+    }
+    // headers are not needed
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(n) if n > 0 && !line.trim().is_empty() => (),
+            _ => break,
+        }
+    }
+
+    let response = if request_line.ends_with('\n') {
+        server.respond(&request_line)
+    } else {
+        Response::error("414 URI Too Long", String::from("request line too long"))
+    };
+
+    // tiles only change with their parameters, which are part of the URL
+    let cache = if response.content_type == "image/png" { "max-age=3600" } else { "no-cache" };
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: {}\r\nConnection: close\r\n\r\n",
+        response.status, response.content_type, response.body.len(), cache
+    );
+    let mut stream = &stream;
+    let _ = stream.write_all(header.as_bytes()).and_then(|_| stream.write_all(&response.body));
+}
+
+
+/// Everything the tiles of one choice of columns and filters are drawn from. It is shared with the
+/// workers, so drawing does not lock the server.
+struct Scene {
+    columns: ColumnState,
+    filters: Vec<String>,
+    x: Column,
+    y: Column,
+    z: Column,
+    mask: Vec<bool>,
+    /// number of selected rows
+    n: usize,
+    extent: ((f64, f64), (f64, f64)),
+    z_range: (f64, f64),
+    lod: Option<Lod>,
+}
+
+impl Scene {
+    fn new(params: &Params, x: Column, y: Column, z: Column, mask: Vec<bool>) -> Scene {
+        let n = mask.iter().filter(|m| **m).count();
+        let extent = (data::column_range(&x, &mask), data::column_range(&y, &mask));
+        let z_range = data::column_range(&z, &mask);
+        let lod = if n >= cfg::LOD_MIN_POINTS { Lod::build(&x, &y, &z, &mask, cfg::LOD_DEPTH) } else { None };
+        Scene {
+            columns: params.columns.clone(),
+            filters: params.filters.clone(),
+            x: x,
+            y: y,
+            z: z,
+            mask: mask,
+            n: n,
+            extent: extent,
+            z_range: z_range,
+            lod: lod,
+        }
+    }
+
+    fn render_tile(&self, params: &Params, zoom: u32, tile_x: u32, tile_y: u32) -> Vec<u8> {
+        let tonemapped = cpu::tonemap(&self.accumulate_tile(params, zoom, tile_x, tile_y), params.gamma);
+        image::encode_png(cfg::TILE_SIZE, cfg::TILE_SIZE, 8, &tonemapped.to_srgb8())
+    }
+
+    fn accumulate_tile(&self, params: &Params, zoom: u32, tile_x: u32, tile_y: u32) -> Image {
+        // tiles are numbered from the top left, like map tiles
+        let ((x_min, x_max), (y_min, y_max)) = self.extent;
+        let tiles = (1u64 << zoom) as f64;
+        let (width, height) = ((x_max - x_min) / tiles, (y_max - y_min) / tiles);
+        let tile = Rect {
+            x_min: x_min + width * (tile_x as f64),
+            x_max: x_min + width * ((tile_x + 1) as f64),
+            y_min: y_max - height * ((tile_y + 1) as f64),
+            y_max: y_max - height * (tile_y as f64),
+        };
+        let mut projection = Projection::new();
+        projection.adjust_x(tile.x_min, tile.x_max);
+        projection.adjust_y(tile.y_min, tile.y_max);
+        projection.adjust_z(self.z_range.0, self.z_range.1);
+
+        let size = cfg::TILE_SIZE;
+        match self.lod {
+            Some(ref lod) => {
+                // cells of about a pixel, or the raw rows once the finest cells are larger than that;
+                // points less than their radius away from the tile still cover some of its pixels
+                let pixel = (width / (size as f64), height / (size as f64));
+                let level = lod.level_for(pixel.0 * cfg::LOD_CELL_PIXELS, pixel.1 * cfg::LOD_CELL_PIXELS);
+                let view = tile.expand((params.pointsize as f64) / 2.0 / (size as f64));
+                let origin = [(tile.x_min + tile.x_max) / 2.0, (tile.y_min + tile.y_max) / 2.0, self.z_range.0];
+                let points = lod.points_in(level, &view, &self.x, &self.y, &self.z, origin);
+                cpu::accumulate_points(&points, self.n, origin, &projection, params.pointsize, size, size)
+            },
+            None => cpu::accumulate(&self.x.data, &self.y.data, &self.z.data, &self.mask, &projection, false, params.pointsize, size, size),
+        }
+    }
+}
+
+
+struct State {
+    transform_cache: TransformCache,
+    /// scene of the last request
+    scene: Option<Arc<Scene>>,
+}
+
+
+pub struct TileServer {
+    fname: String,
+    columns: Vec<Column>,
+    /// filter given on start, which every request adds to
+    filter: Filter,
+    /// locked while the scene of a request is looked up or built, not while its tiles are drawn
+    state: Mutex<State>,
+}
+
+impl TileServer {
+    pub fn new(columns: Vec<Column>, fname: String, settings: Settings, filter: Filter) -> Result<TileServer, String> {
+        if let Err(s) = filter.mask(&columns) {
+            return Err(s);
+        }
+        Ok(TileServer {
+            fname: fname,
+            columns: columns,
+            filter: filter,
+            state: Mutex::new(State {
+                transform_cache: TransformCache::new(settings.clip_low / 100.0, settings.clip_high / 100.0),
+                scene: None,
+            }),
+        })
+    }
+
+    /// Serves on `127.0.0.1:<port>` until the process is stopped.
+    pub fn run_forever(self, port: u16) -> Result<(), String> {
+        let address = format!("127.0.0.1:{}", port);
+        let listener = match TcpListener::bind(address.as_str()) {
+            Ok(l) => l,
+            Err(e) => {
+                return Err(format!("cannot listen on {}: {}", address, e));
+            }
+        };
+        info!("serving {} on http://{}/", self.fname, address);
+
+        // idle or slow connections only hold up their own worker
+        let server = Arc::new(self);
+        let (tx, rx) = channel();
+        let rx = Arc::new(Mutex::new(rx));
+        for _ in 0..cfg::SERVE_WORKERS {
+            let (server, rx) = (server.clone(), rx.clone());
+            thread::spawn(move || serve_connections(server, rx));
+        }
+        for stream in listener.incoming() {
+            if let Ok(stream) = stream {
+                if tx.send(stream).is_err() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn respond(&self, request_line: &str) -> Response {        let parts = request_line.split_whitespace().collect::<Vec<&str>>();
+        match (parts.get(0), parts.get(1)) {
+            (Some(&"GET"), Some(target)) => {
+                let (path, query) = match target.find('?') {
+                    Some(pos) => (&target[..pos], &target[pos + 1..]),
+                    None      => (*target, ""),
+                };
+                debug!("GET {}", target);
+                self.route(path, &parse_query(query))
+            },
+            (Some(_), Some(_)) => Response::error("405 Method Not Allowed", String::from("only GET is supported")),
+            _ => Response::error("400 Bad Request", String::from("invalid request")),
+        }
+    }
+
+    fn route(&self, path: &str, query: &[(String, String)]) -> Response {
+        let segments = path.trim_matches('/').split('/').collect::<Vec<&str>>();
+        match segments.as_slice() {
+            [""] => Response::ok("text/html; charset=utf-8", res::SERVE_PAGE.as_bytes().to_vec()),
+            ["info"] => Response::json(self.info()),
+            ["extent"] => {
+                match self.parse_params(query).and_then(|p| self.scene(&p)).map(|s| s.extent) {
+                    Ok(((x_min, x_max), (y_min, y_max))) => Response::json(Json::object(vec![
+                        ("x", Json::Array(vec![Json::Number(x_min), Json::Number(x_max)])),
+                        ("y", Json::Array(vec![Json::Number(y_min), Json::Number(y_max)])),
+                    ])),
+                    Err(s) => Response::error("400 Bad Request", s),
+                }
+            },
+            ["tiles", zoom, x, y] if y.ends_with(".png") => {
+                let coords = (zoom.parse::<u32>(), x.parse::<u32>(), y[..y.len() - 4].parse::<u32>());
+                let (zoom, x, y) = match coords {
+                    (Ok(zoom), Ok(x), Ok(y)) if zoom <= cfg::TILE_ZOOM_MAX && x < (1 << zoom) && y < (1 << zoom) => (zoom, x, y),
+                    _ => {
+                        return Response::error("404 Not Found", format!("there is no tile {}", path));
+                    }
+                };
+                let params = match self.parse_params(query) {
+                    Ok(p) => p,
+                    Err(s) => {
+                        return Response::error("400 Bad Request", s);
+                    }
+                };
+                match self.scene(&params).map(|s| s.render_tile(&params, zoom, x, y)) {
+                    Ok(png) => Response::ok("image/png", png),
+                    Err(s) => Response::error("400 Bad Request", s),
+                }
+            },
+            _ => Response::error("404 Not Found", format!("{} not found", path)),
+        }
+    }
+
+    fn info(&self) -> Json {
+        let defaults = ColumnState::new(self.columns.len());
+        let name = |idx: usize| Json::string(&self.columns[idx].name);
+        let mut transforms = vec![Transform::Identity];
+        while transforms[transforms.len() - 1].next() != Transform::Identity {
+            let t = transforms[transforms.len() - 1].next();
+            transforms.push(t);
+        }
+        Json::object(vec![
+            ("name", Json::string(&self.fname)),
+            ("rows", Json::Number(self.columns[0].data.len() as f64)),
+            ("columns", Json::Array(self.columns.iter().map(|c| Json::string(&c.name)).collect())),
+            ("transforms", Json::Array(transforms.iter().map(|t| Json::string(t.name())).collect())),
+            ("x", name(defaults.x)),
+            ("y", name(defaults.y)),
+            ("z", name(defaults.z)),
+            ("filter", Json::string(&self.filter.to_string())),
+            ("gamma", Json::Number(cfg::GAMMA_DEFAULT as f64)),
+            ("pointsize", Json::Number(cfg::POINTSIZE_DEFAULT as f64)),
+            ("tile_size", Json::Number(cfg::TILE_SIZE as f64)),
+            ("max_zoom", Json::Number(cfg::TILE_ZOOM_MAX as f64)),
+        ])
+    }
+
+    fn parse_params(&self, query: &[(String, String)]) -> Result<Params, String> {
+        let mut params = Params {
+            columns: ColumnState::new(self.columns.len()),
+            gamma: cfg::GAMMA_DEFAULT,
+            pointsize: cfg::POINTSIZE_DEFAULT,
+            filters: vec![],
+        };
+        for &(ref key, ref value) in query {
+            let column = || self.columns.iter().position(|c| c.name == *value).ok_or(format!("unknown column '{}'", value));
+            let transform = || Transform::from_name(value).ok_or(format!("unknown transform '{}'", value));
+            let number = || value.parse::<f32>().map_err(|_| format!("{} has to be a number", key));
+            let result = match key.as_str() {
+                "x"  => column().map(|c| params.columns.x = c),
+                "y"  => column().map(|c| params.columns.y = c),
+                "z"  => column().map(|c| params.columns.z = c),
+                "tx" => transform().map(|t| params.columns.tx = t),
+                "ty" => transform().map(|t| params.columns.ty = t),
+                "tz" => transform().map(|t| params.columns.tz = t),
+                "gamma"     => number().map(|v| params.gamma = v.max(cfg::GAMMA_MIN).min(cfg::GAMMA_MAX)),
+                "pointsize" => number().map(|v| params.pointsize = v.max(cfg::POINTSIZE_MIN).min(cfg::POINTSIZE_MAX)),
+                "filter" if !value.trim().is_empty() => Ok(params.filters.push(value.clone())),
+                _ => Ok(()),
+            };
+            if let Err(s) = result {
+                return Err(s);
+            }
+        }
+        Ok(params)
+    }
+
+    /// Scene of `params`, built unless it is the one of the last request. The transformed columns
+    /// are copied, so that the scene does not borrow from the transform cache.
+    fn scene(&self, params: &Params) -> Result<Arc<Scene>, String> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(ref scene) = state.scene {
+            if scene.columns == params.columns && scene.filters == params.filters {
+                return Ok(scene.clone());
+            }
+        }
+
+        let mut filter = self.filter.clone();
+        for f in &params.filters {
+            if let Err(s) = filter.add(f) {
+                return Err(s);
+            }
+        }
+        let mask = match filter.mask(&self.columns) {
+            Ok(m) => m,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+
+        let cs = &params.columns;
+        let transform_cache = &mut state.transform_cache;
+        let mut copy = |idx: usize, t: Transform| {
+            transform_cache.prepare(&self.columns, idx, t);
+            let (c, _) = transform_cache.get(&self.columns, idx, t);
+            Column::from_data(&c.name, c.data.to_vec())
+        };
+        let (x, y, z) = (copy(cs.x, cs.tx), copy(cs.y, cs.ty), copy(cs.z, cs.tz));
+        let scene = Arc::new(Scene::new(params, x, y, z, mask));
+        state.scene = Some(scene.clone());
+        Ok(scene)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> Params {
+        Params {
+            columns: ColumnState::new(3),
+            gamma: cfg::GAMMA_DEFAULT,
+            pointsize: 1.0,
+            filters: vec![],
+        }
+    }
+
+    /// Scene of some scattered rows, with a LOD of `depth` levels whatever the number of rows.
+    fn scene(depth: Option<usize>) -> Scene {
+        let n = 3000;
+        let x = (0..n).map(|i| ((i * 7919) % 1009) as f64 / 100.0).collect::<Vec<f64>>();
+        let y = (0..n).map(|i| ((i * i) % 997) as f64 * x[i]).collect::<Vec<f64>>();
+        let z = (0..n).map(|i| (i % 10) as f64).collect::<Vec<f64>>();
+        let mask = (0..n).map(|i| i % 3 != 0).collect();
+        let mut scene = Scene::new(&params(), Column::from_data("x", x), Column::from_data("y", y), Column::from_data("z", z), mask);
+        scene.lod = depth.and_then(|d| Lod::build(&scene.x, &scene.y, &scene.z, &scene.mask, d));
+        scene
+    }
+
+    fn weight(image: &Image) -> f32 {
+        image.pixels.iter().map(|p| p[3]).sum()
+    }
+
+    #[test]
+    fn draws_the_same_rows_with_and_without_a_lod() {
+        let raw = scene(None);
+        // rows on the upper X and lower Y edge fall outside of the raw tiles, but not cell centers
+        let ((_, x_max), (y_min, _)) = raw.extent;
+        let edge = (0..raw.mask.len()).filter(|&i| raw.mask[i] && (raw.x.data[i] == x_max || raw.y.data[i] == y_min)).count();
+        let tolerance = ((edge as f32) + 0.5) / (raw.n as f32);
+        for depth in &[4, 8] {
+            let binned = scene(Some(*depth));
+            assert!(binned.lod.is_some());
+            for zoom in 0..3 {
+                for tile_x in 0..(1 << zoom) {
+                    for tile_y in 0..(1 << zoom) {
+                        let expected = weight(&raw.accumulate_tile(&params(), zoom, tile_x, tile_y));
+                        let actual = weight(&binned.accumulate_tile(&params(), zoom, tile_x, tile_y));
+                        assert!((expected - actual).abs() < tolerance, "depth {}, tile {}/{}/{}: {} != {}", depth, zoom, tile_x, tile_y, actual, expected);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use cfg;
+
+use keymap::{Action, Key, Keymap};
+
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+
+/// User settings, read from a config file with one directive per line:
+///
+/// ```text
+/// # a word starting with '#' begins a comment, unless it is the key of bind or unbind
+/// bind  <key> <action>    e.g. `bind Ctrl+J pointsize_increase`, `bind Ctrl++ zoom_in` or `bind # toggle_help`
+/// unbind <key>            or `unbind all` to start from an empty keymap
+/// set <name> <value>      e.g. `set history_depth 500` or `set clip_high 99.5`
+/// ```
+pub struct Settings {
+    pub keymap: Keymap,
+    pub history_depth: usize,
+    /// percentiles used by the clip transform
+    pub clip_low: f64,
+    pub clip_high: f64,
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings {
+            keymap: Keymap::default(),
+            history_depth: cfg::HISTORY_DEPTH_DEFAULT,
+            clip_low: cfg::CLIP_LOW_DEFAULT,
+            clip_high: cfg::CLIP_HIGH_DEFAULT,
+        }
+    }
+
+    pub fn from_file(fname: &str) -> Result<Settings, String> {
+        let f = match File::open(fname) {
+            Ok(f) => f,
+            Err(_) => {
+                return Err(format!("cannot open config file {}!", fname));
+            }
+        };
+
+        let mut settings = Settings::new();
+        for (i, line) in BufReader::new(f).lines().enumerate() {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => {
+                    return Err(format!("cannot read line {} of config file {}", i + 1, fname));
+                }
+            };
+            if let Err(s) = settings.apply_line(&line) {
+                return Err(format!("{}:{}: {}", fname, i + 1, s));
+            }
+        }
+
+        Ok(settings)
+    }
+
+    fn apply_line(&mut self, line: &str) -> Result<(), String> {
+        let mut tokens = line.split_whitespace().collect::<Vec<&str>>();
+        // `#` is a key of its own after bind and unbind
+        let keyword = tokens.first().map_or(false, |&t| t == "bind" || t == "unbind");
+        if let Some(pos) = tokens.iter().enumerate().position(|(i, t)| t.starts_with('#') && !(i == 1 && keyword)) {
+            tokens.truncate(pos);
+        }
+        if tokens.is_empty() {
+            return Ok(());
+        }
+
+        match (tokens[0], tokens.len()) {
+            ("bind", 3) => {
+                let action = match Action::from_name(tokens[2]) {
+                    Some(a) => a,
+                    None => {
+                        return Err(format!("unknown action '{}'", tokens[2]));
+                    }
+                };
+                if let Some(c) = bound_char(tokens[1]) {
+                    self.keymap.bind_char(c, action);
+                } else {
+                    match Key::parse(tokens[1]) {
+                        Ok(key) => self.keymap.bind(key, action),
+                        Err(s)  => {
+                            return Err(s);
+                        }
+                    }
+                }
+            },
+            ("unbind", 2) => {
+                if tokens[1] == "all" {
+                    self.keymap.unbind_all();
+                } else if let Some(c) = bound_char(tokens[1]) {
+                    self.keymap.unbind_char(c);
+                } else {
+                    match Key::parse(tokens[1]) {
+                        Ok(key) => self.keymap.unbind(&key),
+                        Err(s)  => {
+                            return Err(s);
+                        }
+                    }
+                }
+            },
+            ("set", 3) => {
+                match tokens[1] {
+                    "history_depth" => {
+                        self.history_depth = match tokens[2].parse::<usize>() {
+                            Ok(d) if d > 0 => d,
+                            _ => {
+                                return Err(format!("history_depth has to be a positive number, got '{}'", tokens[2]));
+                            }
+                        };
+                    },
+                    "clip_low" | "clip_high" => {
+                        let p = match tokens[2].parse::<f64>() {
+                            Ok(p) if p >= 0.0 && p <= 100.0 => p,
+                            _ => {
+                                return Err(format!("{} has to be a percentile between 0 and 100, got '{}'", tokens[1], tokens[2]));
+                            }
+                        };
+                        if tokens[1] == "clip_low" {
+                            self.clip_low = p;
+                        } else {
+                            self.clip_high = p;
+                        }
+                    },
+                    _ => {
+                        return Err(format!("unknown setting '{}'", tokens[1]));
+                    }
+                }
+            },
+            _ => {
+                return Err(format!("cannot parse '{}'", tokens.join(" ")));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Single punctuation characters like `?` are bound as typed, independent of the keyboard layout.
+fn bound_char(token: &str) -> Option<char> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_punctuation() => Some(c),
+        _ => None,
+    }
+}
+
+
+/// `$XDG_CONFIG_HOME/fluxcore_ng/config`, falling back to `~/.config/fluxcore_ng/config`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(p) => PathBuf::from(p),
+        None    => {
+            match env::home_dir() {
+                Some(h) => h.join(".config"),
+                None    => {
+                    return None;
+                }
+            }
+        }
+    };
+    Some(base.join("fluxcore_ng").join("config"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use glium::glutin::VirtualKeyCode;
+
+    #[test]
+    fn binds_and_unbinds_keys() {
+        let mut settings = Settings::new();
+        assert_eq!(settings.apply_line("bind Ctrl+J quit  # vim-style"), Ok(()));
+        assert_eq!(settings.keymap.lookup(&Key::parse("Ctrl+J").unwrap()), Some(Action::Quit));
+        assert_eq!(settings.apply_line("bind Ctrl++ gamma_increase"), Ok(()));
+        assert_eq!(settings.keymap.lookup(&Key::parse("Ctrl++").unwrap()), Some(Action::GammaIncrease));
+        assert_eq!(settings.apply_line("unbind Q"), Ok(()));
+        assert_eq!(settings.keymap.lookup(&Key::new(VirtualKeyCode::Q)), None);
+        assert_eq!(settings.apply_line("unbind all"), Ok(()));
+        assert_eq!(settings.keymap.lookup(&Key::new(VirtualKeyCode::Escape)), None);
+    }
+
+    #[test]
+    fn binds_characters_including_the_comment_sign() {
+        let mut settings = Settings::new();
+        assert_eq!(settings.keymap.lookup_char('?'), Some(Action::ToggleHelp));
+        assert_eq!(settings.apply_line("bind # toggle_help # like ?"), Ok(()));
+        assert_eq!(settings.keymap.lookup_char('#'), Some(Action::ToggleHelp));
+        assert_eq!(settings.apply_line("unbind ?"), Ok(()));
+        assert_eq!(settings.keymap.lookup_char('?'), None);
+        assert_eq!(settings.apply_line("  # bind J quit"), Ok(()));
+        assert_eq!(settings.keymap.lookup(&Key::new(VirtualKeyCode::J)), Some(Action::PointsizeIncrease));
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        let mut settings = Settings::new();
+        for line in &["bind J", "bind J no_such_action", "bind Alt+J quit", "bind J quit now", "unbind", "set nothing 1", "quit"] {
+            assert!(settings.apply_line(line).is_err(), "{:?} was accepted", line);
+        }
+    }
+
+    #[test]
+    fn sets_the_history_depth() {
+        let mut settings = Settings::new();
+        assert_eq!(settings.apply_line("set history_depth 500"), Ok(()));
+        assert_eq!(settings.history_depth, 500);
+        for line in &["set history_depth 0", "set history_depth -1", "set history_depth many"] {
+            assert!(settings.apply_line(line).is_err(), "{:?} was accepted", line);
+        }
+        assert_eq!(settings.history_depth, 500);
+    }
+
+    #[test]
+    fn sets_the_clip_percentiles() {
+        let mut settings = Settings::new();
+        assert_eq!(settings.apply_line("set clip_low 0.5"), Ok(()));
+        assert_eq!(settings.apply_line("set clip_high 99.5"), Ok(()));
+        assert_eq!((settings.clip_low, settings.clip_high), (0.5, 99.5));
+        for line in &["set clip_low -1", "set clip_high 101", "set clip_high high"] {
+            assert!(settings.apply_line(line).is_err(), "{:?} was accepted", line);
+        }
+    }
+}
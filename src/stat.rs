@@ -0,0 +1,85 @@
+use data::Column;
+
+use cfg;
+
+
+/// How the Z values of the points in a pixel or cell are reduced to its color. The brightness is
+/// always the density.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stat {
+    Mean,
+    /// no Z color
+    Count,
+    Min,
+    Max,
+    /// scaled to the sums in the view
+    Sum,
+    /// standard deviation from the second moment, scaled to the largest one in the view
+    Std,
+    /// number of distinct values of a categorical Z
+    Distinct,
+    /// only for bins, approximated by a histogram of 64 bins over the Z range
+    Median,
+}
+
+static STATS: &'static [(Stat, &'static str)] = &[
+    (Stat::Mean,     "mean"),
+    (Stat::Count,    "count"),
+    (Stat::Min,      "min"),
+    (Stat::Max,      "max"),
+    (Stat::Sum,      "sum"),
+    (Stat::Std,      "std"),
+    (Stat::Distinct, "distinct"),
+    (Stat::Median,   "median"),
+];
+
+impl Stat {
+    pub fn from_name(s: &str) -> Option<Stat> {
+        STATS.iter().find(|&&(_, name)| name == s).map(|&(stat, _)| stat)
+    }
+
+    pub fn names() -> Vec<&'static str> {
+        STATS.iter().map(|&(_, name)| name).collect()
+    }
+
+    /// The next statistic, skipping the median unless the points are `binned`.
+    pub fn next(&self, binned: bool) -> Stat {
+        let pos = STATS.iter().position(|&(s, _)| s == *self).unwrap();
+        let next = STATS[(pos + 1) % STATS.len()].0;
+        if next == Stat::Median && !binned {
+            next.next(binned)
+        } else {
+            next
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        STATS.iter().find(|&&(s, _)| s == *self).unwrap().1
+    }
+
+    /// Index in the shaders.
+    pub fn index(&self) -> usize {
+        STATS.iter().position(|&(s, _)| s == *self).unwrap()
+    }
+
+    /// Whether the colors are scaled to the values in the view.
+    pub fn is_scaled(&self) -> bool {
+        *self == Stat::Sum || *self == Stat::Std
+    }
+}
+
+
+/// Distinct values of the rows of `column` selected by `mask`, ascending, for `Stat::Distinct`.
+pub fn categories(column: &Column, mask: &[bool]) -> Result<Vec<f64>, String> {
+    let mut values = vec![];
+    for (&v, _) in column.data.iter().zip(mask.iter()).filter(|&(v, m)| *m && !v.is_nan()) {
+        if !values.contains(&v) {
+            if values.len() == cfg::CATEGORIES_MAX {
+                return Err(format!("{} has more than {} distinct values", column.name, cfg::CATEGORIES_MAX));
+            }
+            values.push(v);
+        }
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    Ok(values)
+}
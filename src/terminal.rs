@@ -0,0 +1,882 @@
+//! Density plots in a text terminal, e.g. for a quick look over SSH. Points are accumulated and tone
+//! mapped on the CPU and shown as half blocks or braille dots in true color, or as sixel or kitty
+//! graphics. Keys go through the same keymap as in the window, dragging and scrolling with the
+//! mouse pans and zooms.
+
+extern crate libc;
+
+use axes;
+use bookmarks::Bookmarks;
+use cfg;
+use cpu;
+use data::{Column, Kind};
+use filter::Filter;
+use glium::glutin::VirtualKeyCode;
+use history::Gesture;
+use image;
+use image::Image;
+use keymap::{Action, Key, Keymap};
+use settings::Settings;
+use transform::{Transform, Units};
+use video;
+use view;
+use view::{Prompt, View};
+
+use std::char;
+use std::cmp;
+use std::env;
+use std::io::{self, Write};
+use std::mem;
+use std::str;
+use std::time::{Duration, Instant};
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    /// kitty or sixel graphics if the terminal supports them, half blocks otherwise
+    Auto,
+    /// two pixels per character cell, as upper half block with foreground and background color
+    Blocks,
+    /// 2x4 dots per character cell, in the average color of the cell
+    Braille,
+    Sixel,
+    Kitty,
+}
+
+static MODES: &'static [(Mode, &'static str)] = &[
+    (Mode::Auto,    "auto"),
+    (Mode::Blocks,  "blocks"),
+    (Mode::Braille, "braille"),
+    (Mode::Sixel,   "sixel"),
+    (Mode::Kitty,   "kitty"),
+];
+
+impl Mode {
+    pub fn from_name(s: &str) -> Option<Mode> {
+        MODES.iter().find(|&&(_, name)| name == s).map(|&(m, _)| m)
+    }
+
+    pub fn name(&self) -> &'static str {
+        MODES.iter().find(|&&(m, _)| m == *self).unwrap().1
+    }
+}
+
+
+enum Input {
+    /// a key press as it would arrive from the window
+    Key(Key),
+    /// the character typed by the preceding key, for prompts
+    Char(char),
+    /// SGR mouse report: button code with modifier bits, cell column and row (from 1), and whether
+    /// it is a press or drag rather than a release
+    Mouse(u32, u32, u32, bool),
+}
+
+/// Keys that are typed with Shift on a US layout, and the key they are on.
+static SHIFTED: &'static [(char, char)] = &[
+    ('!', '1'), ('@', '2'), ('#', '3'), ('$', '4'), ('%', '5'), ('^', '6'), ('&', '7'), ('*', '8'),
+    ('(', '9'), (')', '0'), ('_', '-'), ('+', '='), ('{', '['), ('}', ']'), ('|', '\\'), (':', ';'),
+    ('"', '\''), ('<', ','), ('>', '.'), ('?', '/'), ('~', '`'),
+];
+
+static PUNCTUATION: &'static [(char, VirtualKeyCode)] = &[
+    (' ',  VirtualKeyCode::Space),
+    ('-',  VirtualKeyCode::Minus),
+    ('=',  VirtualKeyCode::Equals),
+    (',',  VirtualKeyCode::Comma),
+    ('.',  VirtualKeyCode::Period),
+    ('/',  VirtualKeyCode::Slash),
+    ('\\', VirtualKeyCode::Backslash),
+    (';',  VirtualKeyCode::Semicolon),
+    ('\'', VirtualKeyCode::Apostrophe),
+    ('[',  VirtualKeyCode::LBracket),
+    (']',  VirtualKeyCode::RBracket),
+    ('`',  VirtualKeyCode::Grave),
+];
+
+/// Numbers of `CSI <n> ~` sequences.
+static TILDE_KEYS: &'static [(u32, VirtualKeyCode)] = &[
+    (1,  VirtualKeyCode::Home),
+    (2,  VirtualKeyCode::Insert),
+    (3,  VirtualKeyCode::Delete),
+    (4,  VirtualKeyCode::End),
+    (5,  VirtualKeyCode::PageUp),
+    (6,  VirtualKeyCode::PageDown),
+    (7,  VirtualKeyCode::Home),
+    (8,  VirtualKeyCode::End),
+    (11, VirtualKeyCode::F1),
+    (12, VirtualKeyCode::F2),
+    (13, VirtualKeyCode::F3),
+    (14, VirtualKeyCode::F4),
+    (15, VirtualKeyCode::F5),
+    (17, VirtualKeyCode::F6),
+    (18, VirtualKeyCode::F7),
+    (19, VirtualKeyCode::F8),
+    (20, VirtualKeyCode::F9),
+    (21, VirtualKeyCode::F10),
+    (23, VirtualKeyCode::F11),
+    (24, VirtualKeyCode::F12),
+];
+
+fn key_for_char(c: char) -> Option<Key> {
+    let (base, shift) = match SHIFTED.iter().find(|&&(s, _)| s == c) {
+        Some(&(_, base)) => (base, true),
+        None => (c.to_ascii_lowercase(), c.is_ascii_uppercase()),
+    };
+    let code = match PUNCTUATION.iter().find(|&&(p, _)| p == base) {
+        Some(&(_, code)) => Some(code),
+        None if base.is_ascii_alphanumeric() => Key::parse(&base.to_string()).ok().map(|k| k.code),
+        None => None,
+    };
+    code.map(|code| Key {
+        code: code,
+        ctrl: false,
+        shift: shift,
+    })
+}
+
+fn control_key(b: u8) -> Option<Key> {
+    match b {
+        0x09        => Some(Key::new(VirtualKeyCode::Tab)),
+        0x0a | 0x0d => Some(Key::new(VirtualKeyCode::Return)),
+        0x08 | 0x7f => Some(Key::new(VirtualKeyCode::Back)),
+        0x01..=0x1a => key_for_char((b'a' + b - 1) as char).map(|mut k| {
+            k.ctrl = true;
+            k
+        }),
+        _ => None,
+    }
+}
+
+/// Decodes `CSI` (`intro` is `[`) and `SS3` (`intro` is `O`) sequences.
+fn parse_sequence(intro: u8, params: &str, last: u8) -> Option<Input> {
+    if intro == b'[' && params.starts_with('<') {
+        let values = params[1..].split(';').map(|v| v.parse::<u32>().unwrap_or(0)).collect::<Vec<u32>>();
+        return match (values.len(), last) {
+            (3, b'M') | (3, b'm') => Some(Input::Mouse(values[0], values[1], values[2], last == b'M')),
+            _ => None,
+        };
+    }
+
+    let values = params.split(';').map(|v| v.parse::<u32>().unwrap_or(1)).collect::<Vec<u32>>();
+    let code = match last {
+        b'A' => VirtualKeyCode::Up,
+        b'B' => VirtualKeyCode::Down,
+        b'C' => VirtualKeyCode::Right,
+        b'D' => VirtualKeyCode::Left,
+        b'H' => VirtualKeyCode::Home,
+        b'F' => VirtualKeyCode::End,
+        b'P' => VirtualKeyCode::F1,
+        b'Q' => VirtualKeyCode::F2,
+        b'R' => VirtualKeyCode::F3,
+        b'S' => VirtualKeyCode::F4,
+        b'Z' => {
+            return Some(Input::Key(Key {
+                code: VirtualKeyCode::Tab,
+                ctrl: false,
+                shift: true,
+            }));
+        },
+        b'~' => {
+            match TILDE_KEYS.iter().find(|&&(n, _)| n == values[0]) {
+                Some(&(_, code)) => code,
+                None => {
+                    return None;
+                }
+            }
+        },
+        _ => {
+            return None;
+        }
+    };
+    // the modifier parameter is 1 plus a bit mask of shift (1), alt (2) and ctrl (4)
+    let modifiers = values.get(1).map(|m| m.saturating_sub(1)).unwrap_or(0);
+    Some(Input::Key(Key {
+        code: code,
+        ctrl: modifiers & 4 != 0,
+        shift: modifiers & 1 != 0,
+    }))
+}
+
+/// Splits what was read from the terminal into keys, characters and mouse reports. Alt + key is
+/// treated like the key alone.
+fn parse_input(bytes: &[u8]) -> Vec<Input> {
+    let mut inputs = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b == 0x1b && i + 1 < bytes.len() && (bytes[i + 1] == b'[' || bytes[i + 1] == b'O') {
+            // parameters up to a final byte in 0x40..0x7e
+            let start = i + 2;
+            let mut end = start;
+            while end < bytes.len() && !(bytes[end] >= 0x40 && bytes[end] <= 0x7e) {
+                end += 1;
+            }
+            if end == bytes.len() {
+                break;
+            }
+            let params = String::from_utf8_lossy(&bytes[start..end]).into_owned();
+            if let Some(input) = parse_sequence(bytes[i + 1], &params, bytes[end]) {
+                inputs.push(input);
+            }
+            i = end + 1;
+        } else if b == 0x1b {
+            if i + 1 == bytes.len() {
+                inputs.push(Input::Key(Key::new(VirtualKeyCode::Escape)));
+            }
+            i += 1;
+        } else if b < 0x20 || b == 0x7f {
+            if let Some(key) = control_key(b) {
+                inputs.push(Input::Key(key));
+            }
+            i += 1;
+        } else {
+            let len = if b >= 0xf0 { 4 } else if b >= 0xe0 { 3 } else if b >= 0xc0 { 2 } else { 1 };
+            let end = cmp::min(i + len, bytes.len());
+            if let Some(c) = str::from_utf8(&bytes[i..end]).ok().and_then(|s| s.chars().next()) {
+                // the character comes first, so that a key that opens a prompt does not end up in it
+                inputs.push(Input::Char(c));
+                if let Some(key) = key_for_char(c) {
+                    inputs.push(Input::Key(key));
+                }
+            }
+            i = end;
+        }
+    }
+    inputs
+}
+
+
+/// Raw input, the alternate screen and mouse reporting while it exists.
+#[cfg(unix)]
+struct Screen {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl Screen {
+    fn enter() -> Result<Screen, String> {
+        let mut termios: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::isatty(libc::STDIN_FILENO) == 0 || libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 } {
+            return Err(String::from("the terminal renderer needs an interactive terminal"));
+        }
+        let original = termios;
+        unsafe { libc::cfmakeraw(&mut termios) };
+        // reads return after 100 ms without input
+        termios.c_cc[libc::VMIN] = 0;
+        termios.c_cc[libc::VTIME] = 1;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) } != 0 {
+            return Err(String::from("cannot switch the terminal to raw input"));
+        }
+        write_out(b"\x1b[?1049h\x1b[?25l\x1b[?1002h\x1b[?1006h");
+        Ok(Screen {
+            original: original,
+        })
+    }
+
+    /// Whatever arrived within 100 ms, possibly nothing.
+    fn read(&self) -> Vec<u8> {
+        let mut buf = [0u8; 4096];
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n > 0 {
+            buf[..(n as usize)].to_vec()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Columns, rows, and the size in pixels if the terminal reports it (zero otherwise).
+    fn size(&self) -> (u32, u32, u32, u32) {
+        let mut ws: libc::winsize = unsafe { mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } != 0 || ws.ws_col == 0 || ws.ws_row == 0 {
+            return (80, 24, 0, 0);
+        }
+        (ws.ws_col as u32, ws.ws_row as u32, ws.ws_xpixel as u32, ws.ws_ypixel as u32)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Screen {
+    fn drop(&mut self) {
+        write_out(b"\x1b[0m\x1b[?1006l\x1b[?1002l\x1b[?25h\x1b[?1049l");
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+#[cfg(not(unix))]
+struct Screen;
+
+#[cfg(not(unix))]
+impl Screen {
+    fn enter() -> Result<Screen, String> {
+        Err(String::from("the terminal renderer is only available on Unix"))
+    }
+
+    fn read(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn size(&self) -> (u32, u32, u32, u32) {
+        (80, 24, 0, 0)
+    }
+}
+
+fn write_out(data: &[u8]) {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let _ = out.write_all(data).and_then(|_| out.flush());
+}
+
+
+/// Kitty graphics if the environment says so, sixel if the terminal reports it in its primary
+/// device attributes, half blocks otherwise.
+fn detect_mode(screen: &Screen) -> Mode {
+    let term = env::var("TERM").unwrap_or_default();
+    let program = env::var("TERM_PROGRAM").unwrap_or_default();
+    if term == "xterm-kitty" || term == "xterm-ghostty" || env::var("KITTY_WINDOW_ID").is_ok() || program == "WezTerm" || program == "ghostty" {
+        return Mode::Kitty;
+    }
+
+    write_out(b"\x1b[c");
+    let start = Instant::now();
+    let mut reply = vec![];
+    while !reply.contains(&b'c') && start.elapsed() < Duration::from_millis(cfg::TERMINAL_QUERY_MILLIS) {
+        reply.extend(screen.read());
+    }
+    // e.g. `CSI ? 62 ; 4 ; 22 c`, attribute 4 is sixel graphics
+    let reply = String::from_utf8_lossy(&reply).into_owned();
+    let attributes = match (reply.find("\x1b[?"), reply.find('c')) {
+        (Some(start), Some(end)) if start + 3 <= end => reply[start + 3..end].split(';').map(String::from).collect(),
+        _ => vec![],
+    };
+    if attributes.iter().any(|a| a == "4") {
+        Mode::Sixel
+    } else {
+        Mode::Blocks
+    }
+}
+
+
+fn srgb8(p: &[f32; 4]) -> [u8; 3] {
+    let encode = |c: f32| (image::srgb_encode(c) * 255.0).round() as u8;
+    [encode(p[0]), encode(p[1]), encode(p[2])]
+}
+
+/// `rows` lines of half blocks from line `top` on, for an image of `2 * rows` pixel rows.
+fn encode_blocks(image: &Image, top: u32, rows: u32) -> String {
+    let mut out = String::new();
+    let width = image.width as usize;
+    for r in 0..(rows as usize) {
+        out.push_str(&format!("\x1b[{};1H", top + (r as u32) + 1));
+        let mut last = None;
+        for c in 0..width {
+            let upper = srgb8(&image.pixels[2 * r * width + c]);
+            let lower = srgb8(&image.pixels[(2 * r + 1) * width + c]);
+            if last != Some((upper, lower)) {
+                out.push_str(&format!("\x1b[38;2;{};{};{};48;2;{};{};{}m", upper[0], upper[1], upper[2], lower[0], lower[1], lower[2]));
+                last = Some((upper, lower));
+            }
+            out.push('\u{2580}');
+        }
+        out.push_str("\x1b[0m");
+    }
+    out
+}
+
+/// Braille dot bits by column and row within a cell.
+static BRAILLE_DOTS: [[u32; 4]; 2] = [
+    [0x01, 0x02, 0x04, 0x40],
+    [0x08, 0x10, 0x20, 0x80],
+];
+
+/// `rows` lines of braille characters from line `top` on, for images of `4 * rows` pixel rows. A
+/// dot is set for pixels with points, the cell gets the average color of its dots.
+fn encode_braille(accumulation: &Image, tonemapped: &Image, top: u32, rows: u32) -> String {
+    let mut out = String::new();
+    let width = accumulation.width as usize;
+    for r in 0..(rows as usize) {
+        out.push_str(&format!("\x1b[{};1H", top + (r as u32) + 1));
+        let mut last = None;
+        for c in 0..(width / 2) {
+            let mut bits = 0;
+            let mut sum = [0.0f32; 4];
+            for dx in 0..2 {
+                for dy in 0..4 {
+                    let i = (4 * r + dy) * width + 2 * c + dx;
+                    if accumulation.pixels[i][3] > 0.0 {
+                        bits |= BRAILLE_DOTS[dx][dy];
+                        for k in 0..3 {
+                            sum[k] += tonemapped.pixels[i][k];
+                        }
+                        sum[3] += 1.0;
+                    }
+                }
+            }
+            if bits == 0 {
+                if last.is_some() {
+                    out.push_str("\x1b[0m");
+                    last = None;
+                }
+                out.push(' ');
+                continue;
+            }
+            let color = srgb8(&[sum[0] / sum[3], sum[1] / sum[3], sum[2] / sum[3], 1.0]);
+            if last != Some(color) {
+                out.push_str(&format!("\x1b[38;2;{};{};{}m", color[0], color[1], color[2]));
+                last = Some(color);
+            }
+            out.push(char::from_u32(0x2800 + bits).unwrap());
+        }
+        out.push_str("\x1b[0m");
+    }
+    out
+}
+
+/// Sixel image with the dithered palette of the GIF export, run length encoded.
+fn encode_sixel(image: &Image) -> Vec<u8> {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let indices = video::dither(image.width, &image.to_srgb8());
+    let levels = video::PALETTE_LEVELS;
+    let colors = (levels * levels * levels) as usize;
+
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height).into_bytes();
+    for i in 0..colors {
+        let (r, g, b) = ((i as u32) / (levels * levels), ((i as u32) / levels) % levels, (i as u32) % levels);
+        out.extend(format!("#{};2;{};{};{}", i, r * 100 / (levels - 1), g * 100 / (levels - 1), b * 100 / (levels - 1)).bytes());
+    }
+
+    let mut bits = vec![0u8; width];
+    for band in 0..((height + 5) / 6) {
+        let rows = (6 * band)..cmp::min(6 * band + 6, height);
+        let mut used = vec![false; colors];
+        for y in rows.clone() {
+            for &i in &indices[y * width..(y + 1) * width] {
+                used[i as usize] = true;
+            }
+        }
+        let mut first = true;
+        for color in (0..colors).filter(|&c| used[c]) {
+            for x in 0..width {
+                bits[x] = 0;
+                for y in rows.clone() {
+                    if indices[y * width + x] as usize == color {
+                        bits[x] |= 1 << (y - 6 * band);
+                    }
+                }
+            }
+            if !first {
+                // back to the start of the band
+                out.push(b'$');
+            }
+            first = false;
+            out.extend(format!("#{}", color).bytes());
+            let mut x = 0;
+            while x < width {
+                let run = bits[x..].iter().take_while(|&&b| b == bits[x]).count();
+                let c = 63 + bits[x];
+                if run > 3 {
+                    out.extend(format!("!{}", run).bytes());
+                    out.push(c);
+                } else {
+                    out.extend((0..run).map(|_| c));
+                }
+                x += run;
+            }
+        }
+        out.push(b'-');
+    }
+    out.extend_from_slice(b"\x1b\\");
+    out
+}
+
+/// Kitty graphics image 1, scaled to `columns` x `rows` cells at the cursor. Sending it again
+/// replaces the previous image.
+fn encode_kitty(image: &Image, columns: u32, rows: u32) -> Vec<u8> {
+    let payload = image::base64(&image.to_srgb8()).into_bytes();
+    let mut out = vec![];
+    let chunks = payload.chunks(4096).collect::<Vec<&[u8]>>();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        let control = if i == 0 {
+            format!("a=T,i=1,f=24,s={},v={},c={},r={},C=1,q=2,m={}", image.width, image.height, columns, rows, more)
+        } else {
+            format!("m={}", more)
+        };
+        out.extend(format!("\x1b_G{};", control).bytes());
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+    out
+}
+
+
+enum PromptPurpose {
+    BookmarkName(usize),
+    Filter,
+    DeriveColumn,
+}
+
+
+pub struct Terminal {
+    fname: String,
+    view: View,
+    keymap: Keymap,
+    mode: Mode,
+    show_help: bool,
+    prompt: Option<Prompt<PromptPurpose>>,
+    status: Option<(String, Instant)>,
+    /// cell of the last mouse report while the left button is down
+    drag: Option<(u32, u32)>,
+    /// columns, rows and pixel size of the terminal
+    size: (u32, u32, u32, u32),
+    /// the plot has to be rendered again, or only the text around it
+    redraw: bool,
+    redraw_text: bool,
+}
+
+impl Terminal {
+    pub fn new(columns: Vec<Column>, fname: String, settings: Settings, bookmarks: Bookmarks, filter: Filter, mode: Mode) -> Result<Terminal, String> {
+        let view = match View::new(columns, filter, bookmarks, &settings) {
+            Ok(v) => v,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+
+        Ok(Terminal {
+            fname: fname,
+            view: view,
+            keymap: settings.keymap,
+            mode: mode,
+            show_help: false,
+            prompt: None,
+            status: None,
+            drag: None,
+            size: (0, 0, 0, 0),
+            redraw: true,
+            redraw_text: true,
+        })
+    }
+
+    /// Takes over the terminal until `Quit` (or Ctrl+C).
+    pub fn run_forever(&mut self) -> Result<(), String> {
+        let screen = match Screen::enter() {
+            Ok(s) => s,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        if self.mode == Mode::Auto {
+            self.mode = detect_mode(&screen);
+            info!("terminal output: {}", self.mode.name());
+        }
+
+        loop {
+            let size = screen.size();
+            if size != self.size {
+                self.size = size;
+                self.redraw = true;
+            }
+            if let Some((_, since)) = self.status {
+                if since.elapsed() > Duration::from_millis(cfg::STATUS_MILLIS) {
+                    self.status = None;
+                    self.redraw_text = true;
+                }
+            }
+            self.draw();
+
+            let mut exit = false;
+            for input in parse_input(&screen.read()) {
+                self.handle_input(input, &mut exit);
+                if exit {
+                    break;
+                }
+            }
+            self.view.record_history();
+            if exit {
+                break;
+            }
+        }
+
+        if self.mode == Mode::Kitty {
+            write_out(b"\x1b_Ga=d,d=I,i=1,q=2\x1b\\");
+        }
+        Ok(())
+    }
+
+    /// Lines above the plot.
+    fn header_lines(&self) -> u32 {
+        if self.view.user_state.showaxes { 1 } else { 0 }
+    }
+
+    /// Character cells of the plot, between the header and the status line.
+    fn plot_cells(&self) -> (u32, u32) {
+        let (columns, rows, _, _) = self.size;
+        (columns, rows.saturating_sub(1 + self.header_lines()))
+    }
+
+    /// Pixels per character cell, as reported by the terminal or a guess.
+    fn cell_pixels(&self) -> (u32, u32) {
+        let (columns, rows, width, height) = self.size;
+        if width >= columns && height >= rows && width > 0 && height > 0 {
+            (width / columns, height / rows)
+        } else {
+            (cfg::TERMINAL_CELL_WIDTH, cfg::TERMINAL_CELL_HEIGHT)
+        }
+    }
+
+    fn render(&mut self, width: u32, height: u32) -> (Image, Image) {
+        let cs = self.view.column_state.clone();
+        self.view.transform_cache.prepare(&self.view.columns, cs.x, cs.tx);
+        self.view.transform_cache.prepare(&self.view.columns, cs.y, cs.ty);
+        self.view.transform_cache.prepare(&self.view.columns, cs.z, cs.tz);
+        let (x, _) = self.view.transform_cache.get(&self.view.columns, cs.x, cs.tx);
+        let (y, _) = self.view.transform_cache.get(&self.view.columns, cs.y, cs.ty);
+        let (z, _) = self.view.transform_cache.get(&self.view.columns, cs.z, cs.tz);
+        let accumulation = cpu::accumulate(&x.data, &y.data, &z.data, &self.view.mask, &self.view.projection, self.view.user_state.showborder, 1.0, width, height);
+        let tonemapped = cpu::tonemap(&accumulation, self.view.user_state.gamma);
+        (accumulation, tonemapped)
+    }
+
+    fn draw(&mut self) {
+        if !self.redraw && !self.redraw_text {
+            return;
+        }
+        let (columns, rows) = self.plot_cells();
+        let top = self.header_lines();
+        let mut out = vec![];
+
+        if self.redraw {
+            out.extend_from_slice(b"\x1b[0m\x1b[2J");
+            if self.show_help {
+                if self.mode == Mode::Kitty {
+                    out.extend_from_slice(b"\x1b_Ga=d,d=I,i=1,q=2\x1b\\");
+                }
+                for (i, line) in self.keymap.help_lines().iter().take(rows as usize).enumerate() {
+                    out.extend(format!("\x1b[{};1H{}", top + (i as u32) + 1, truncate(line, columns)).bytes());
+                }
+            } else if columns > 0 && rows > 0 {
+                let (cell_width, cell_height) = self.cell_pixels();
+                match self.mode {
+                    Mode::Braille => {
+                        let (accumulation, tonemapped) = self.render(2 * columns, 4 * rows);
+                        out.extend(encode_braille(&accumulation, &tonemapped, top, rows).bytes());
+                    },
+                    Mode::Sixel => {
+                        let (_, tonemapped) = self.render(columns * cell_width, rows * cell_height);
+                        out.extend(format!("\x1b[{};1H", top + 1).bytes());
+                        out.extend(encode_sixel(&tonemapped));
+                    },
+                    Mode::Kitty => {
+                        let (_, tonemapped) = self.render(columns * cell_width, rows * cell_height);
+                        out.extend(format!("\x1b[{};1H", top + 1).bytes());
+                        out.extend(encode_kitty(&tonemapped, columns, rows));
+                    },
+                    _ => {
+                        let (_, tonemapped) = self.render(columns, 2 * rows);
+                        out.extend(encode_blocks(&tonemapped, top, rows).bytes());
+                    },
+                }
+            }
+        }
+
+        let (total_columns, total_rows, _, _) = self.size;
+        if top > 0 {
+            out.extend(format!("\x1b[1;1H\x1b[0m\x1b[2K{}", truncate(&self.axes_line(), total_columns)).bytes());
+        }
+        let status = match (&self.prompt, &self.status) {
+            (&Some(ref prompt), _) => prompt.to_string(),
+            (&None, &Some((ref msg, _))) => msg.clone(),
+            (&None, &None) => format!("{}  ({} rows, ? for help)", self.fname, self.view.mask.iter().filter(|m| **m).count()),
+        };
+        out.extend(format!("\x1b[{};1H\x1b[0m\x1b[2K{}", total_rows, truncate(&status, total_columns)).bytes());
+        write_out(&out);
+
+        self.redraw = false;
+        self.redraw_text = false;
+    }
+
+    fn axis_label(&self, axis: &str, idx: usize, t: Transform, range: (f64, f64)) -> String {
+        let column = &self.view.columns[idx];
+        let name = match t {
+            Transform::Identity => column.name.clone(),
+            _ => format!("{} ({})", column.name, t.name()),
+        };
+        let (_, units) = self.view.transform_cache.get(&self.view.columns, idx, t);
+        let (min, max) = (units.to_original(range.0), units.to_original(range.1));
+        let (_, step) = axes::nice_ticks(min, max, 5);
+        match units {
+            Units::Linear { .. } if column.kind != Kind::Time && step > 0.0 => {
+                format!("{}: {} [{} .. {}]", axis, name, axes::format_value(min, step / 10.0), axes::format_value(max, step / 10.0))
+            },
+            _ => {
+                // time and percentile axes are shown by their outermost ticks
+                let ticks = axes::ticks(range.0, range.1, units, column.kind, 5);
+                match (ticks.first(), ticks.last()) {
+                    (Some(first), Some(last)) => format!("{}: {} [{} .. {}]", axis, name, first.label, last.label),
+                    _ => format!("{}: {}", axis, name),
+                }
+            },
+        }
+    }
+
+    fn axes_line(&self) -> String {
+        let cs = &self.view.column_state;
+        vec![
+            self.axis_label("x", cs.x, cs.tx, self.view.projection.range_x()),
+            self.axis_label("y", cs.y, cs.ty, self.view.projection.range_y()),
+            self.axis_label("z", cs.z, cs.tz, self.view.projection.range_z()),
+        ].join("   ")
+    }
+
+    fn set_status(&mut self, msg: String) {
+        self.status = Some((msg, Instant::now()));
+        self.redraw_text = true;
+    }
+
+    fn handle_input(&mut self, input: Input, exit: &mut bool) {
+        match input {
+            Input::Key(key) if key.ctrl && key.code == VirtualKeyCode::C => {
+                // like closing the window
+                *exit = true;
+            },
+            Input::Key(key) if self.prompt.is_some() => {
+                self.handle_prompt_key(key.code);
+            },
+            Input::Key(key) => {
+                if let Some(action) = self.keymap.lookup(&key) {
+                    self.view.record_history();
+                    self.view.gesture = None;
+                    self.apply_action(action, exit);
+                }
+            },
+            Input::Char(c) => {
+                if let Some(ref mut prompt) = self.prompt {
+                    if !c.is_control() {
+                        prompt.input.push(c);
+                        self.redraw_text = true;
+                    }
+                } else if let Some(action) = self.keymap.lookup_char(c) {
+                    self.view.record_history();
+                    self.view.gesture = None;
+                    self.apply_action(action, exit);
+                }
+            },
+            Input::Mouse(button, column, row, pressed) => {
+                self.handle_mouse(button, column, row, pressed);
+            },
+        }
+    }
+
+    /// Dragging with the left button pans, the wheel zooms around the pointer like in the window:
+    /// vertical scrolling zooms Y, horizontal (or Shift + vertical) scrolling zooms X.
+    fn handle_mouse(&mut self, button: u32, column: u32, row: u32, pressed: bool) {
+        let (columns, rows) = self.plot_cells();
+        if columns == 0 || rows == 0 {
+            return;
+        }
+        let x = column.saturating_sub(1);
+        let y = row.saturating_sub(1 + self.header_lines());
+        // bits 2 to 4 are shift, alt and ctrl, 32 marks motion
+        match button & !(4 | 8 | 16) {
+            0 | 32 if pressed => {
+                if let Some((last_x, last_y)) = self.drag {
+                    self.view.projection.move_x((x as i32) - (last_x as i32), columns);
+                    self.view.projection.move_y((y as i32) - (last_y as i32), rows);
+                    self.view.gesture = Some(Gesture::Pan);
+                    self.redraw = true;
+                }
+                self.drag = Some((x, y));
+            },
+            0 => {
+                self.drag = None;
+                self.view.record_history();
+                self.view.history.seal();
+            },
+            64 | 65 | 66 | 67 => {
+                let delta = if button & 1 == 0 { 1.0 } else { -1.0 };
+                if button & 2 != 0 || button & 4 != 0 {
+                    self.view.projection.scroll_x(delta, x, columns);
+                } else {
+                    self.view.projection.scroll_y(delta, cmp::min(y, rows), rows);
+                }
+                self.view.gesture = Some(Gesture::Zoom);
+                self.redraw = true;
+            },
+            _ => (),
+        }
+    }
+
+    fn handle_prompt_key(&mut self, code: VirtualKeyCode) {
+        if let Some(prompt) = view::prompt_key(&mut self.prompt, code) {
+            let result = match prompt.purpose {
+                PromptPurpose::BookmarkName(slot) => self.view.store_bookmark(slot, &prompt.input),
+                PromptPurpose::Filter => {
+                    let result = self.view.add_filter(&prompt.input);
+                    if result.is_ok() {
+                        self.redraw = true;
+                    }
+                    result
+                },
+                PromptPurpose::DeriveColumn => self.view.derive_column(&prompt.input),
+            };
+            match result {
+                Ok(msg) => self.set_status(msg),
+                Err(s)  => self.set_status(s),
+            }
+        }
+        self.redraw_text = true;
+    }
+
+    fn apply_action(&mut self, action: Action, exit: &mut bool) {
+        debug!("action: {}", action.name());
+        if let Some(change) = self.view.apply_action(action) {
+            if let Some(msg) = change.status {
+                self.set_status(msg);
+            }
+            self.redraw = true;
+            return;
+        }
+        match action {
+            Action::Quit => {
+                *exit = true;
+            },
+            Action::ToggleHelp => {
+                self.show_help = !self.show_help;
+                self.redraw = true;
+            },
+            Action::StoreBookmark(slot) => {
+                self.open_prompt(PromptPurpose::BookmarkName(slot), &format!("name for bookmark {}", slot));
+            },
+            Action::AddFilter => {
+                self.open_prompt(PromptPurpose::Filter, "add filter (e.g. temp>300 or 0<=x<1)");
+            },
+            Action::ClearFilter => {
+                match self.view.set_filter(Filter::new()) {
+                    Ok(msg) => {
+                        self.redraw = true;
+                        self.set_status(msg);
+                    },
+                    Err(s) => self.set_status(s),
+                }
+            },
+            Action::DeriveColumn => {
+                self.open_prompt(PromptPurpose::DeriveColumn, "derive column (e.g. ratio = a / b)");
+            },
+            // points cover single pixels, and exports, animations, bins, layers and contours need OpenGL
+            _ => {
+                self.set_status(format!("not available in the terminal: {}", action.description()));
+            },
+        }
+    }
+
+    fn open_prompt(&mut self, purpose: PromptPurpose, label: &str) {
+        self.prompt = Some(Prompt::new(purpose, label));
+        self.redraw_text = true;
+    }
+}
+
+/// At most `width` characters of `s`.
+fn truncate(s: &str, width: u32) -> String {
+    s.chars().take(width as usize).collect()
+}
@@ -0,0 +1,193 @@
+extern crate glium;
+
+use glium::Surface;
+use glium::backend::Facade;
+
+use res;
+
+use std::cmp;
+
+
+pub static GLYPH_WIDTH:  u32 = 8;
+pub static GLYPH_HEIGHT: u32 = 16;
+
+// printable ASCII (32..127) + one solid block used for backgrounds
+static GLYPH_FIRST: u32 = 32;
+static GLYPH_COUNT: u32 = 96;
+static GLYPH_SOLID: u32 = 95;
+
+
+#[derive(Clone, Copy)]
+pub struct TextVertex {
+    position:   [f32; 2],
+    tex_coords: [f32; 2],
+    color:      [f32; 4],
+}
+
+implement_vertex!(TextVertex, position, tex_coords, color);
+
+
+/// Collects glyph and box quads in pixel coordinates (origin top left) for one draw call.
+pub struct TextBatch {
+    vertices: Vec<TextVertex>,
+    width: u32,
+    height: u32,
+}
+
+impl TextBatch {
+    pub fn new(width: u32, height: u32) -> TextBatch {
+        TextBatch {
+            vertices: vec![],
+            width: width,
+            height: height,
+        }
+    }
+
+    fn push_quad(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, glyph: u32, color: [f32; 4]) {
+        let width  = self.width as f32;
+        let height = self.height as f32;
+        let px = |x: f32| 2.0 * x / width - 1.0;
+        let py = |y: f32| 1.0 - 2.0 * y / height;
+        let u0 = (glyph as f32) / (GLYPH_COUNT as f32);
+        let u1 = ((glyph + 1) as f32) / (GLYPH_COUNT as f32);
+
+        // row 0 of the atlas is the top row of the glyphs
+        let a = TextVertex { position: [px(x0), py(y0)], tex_coords: [u0, 0.0], color: color };
+        let b = TextVertex { position: [px(x1), py(y0)], tex_coords: [u1, 0.0], color: color };
+        let c = TextVertex { position: [px(x0), py(y1)], tex_coords: [u0, 1.0], color: color };
+        let d = TextVertex { position: [px(x1), py(y1)], tex_coords: [u1, 1.0], color: color };
+        self.vertices.extend_from_slice(&[a, b, c, c, b, d]);
+    }
+
+    pub fn push_box(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, color: [f32; 4]) {
+        self.push_quad(x0, y0, x1, y1, GLYPH_SOLID, color);
+    }
+
+    /// Draws a line of `width` pixels from `(x0, y0)` to `(x1, y1)`.
+    pub fn push_line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, width: f32, color: [f32; 4]) {
+        let length = ((x1 - x0) * (x1 - x0) + (y1 - y0) * (y1 - y0)).sqrt();
+        if length == 0.0 {
+            return;
+        }
+        let (w, h) = (self.width as f32, self.height as f32);
+        // half the width across the line
+        let nx = -(y1 - y0) / length * width / 2.0;
+        let ny = (x1 - x0) / length * width / 2.0;
+        let u = ((GLYPH_SOLID as f32) + 0.5) / (GLYPH_COUNT as f32);
+        let vertex = |x: f32, y: f32| TextVertex { position: [2.0 * x / w - 1.0, 1.0 - 2.0 * y / h], tex_coords: [u, 0.5], color: color };
+
+        let a = vertex(x0 + nx, y0 + ny);
+        let b = vertex(x1 + nx, y1 + ny);
+        let c = vertex(x0 - nx, y0 - ny);
+        let d = vertex(x1 - nx, y1 - ny);
+        self.vertices.extend_from_slice(&[a, b, c, c, b, d]);
+    }
+
+    /// Places `s` with its top left corner at `(x, y)`. Non-ASCII characters are rendered as `?`.
+    pub fn push_text(&mut self, x: f32, y: f32, s: &str, color: [f32; 4]) {
+        let w = GLYPH_WIDTH as f32;
+        let h = GLYPH_HEIGHT as f32;
+        for (i, c) in s.chars().enumerate() {
+            let code = c as u32;
+            let glyph = if code >= GLYPH_FIRST && code < GLYPH_FIRST + GLYPH_SOLID {
+                code - GLYPH_FIRST
+            } else {
+                ('?' as u32) - GLYPH_FIRST
+            };
+            if c != ' ' {
+                let x0 = x + (i as f32) * w;
+                self.push_quad(x0, y, x0 + w, y + h, glyph, color);
+            }
+        }
+    }
+
+    /// Draws `lines` on a semi-transparent box, with the top left corner at `(x, y)`. Lines that do
+    /// not fit below each other are wrapped into further columns.
+    pub fn push_panel(&mut self, x: f32, y: f32, lines: &[String]) {
+        let pad = 8.0;
+        let gw = GLYPH_WIDTH as f32;
+        let gh = GLYPH_HEIGHT as f32;
+        let rows = cmp::max(1, ((self.height as f32 - y - 2.0 * pad) / gh) as usize);
+
+        let columns = lines.chunks(rows).collect::<Vec<&[String]>>();
+        let widths = columns.iter().map(|c| {
+            c.iter().map(|l| l.chars().count()).max().unwrap_or(0) as f32 * gw
+        }).collect::<Vec<f32>>();
+        let w = widths.iter().fold(0.0, |acc, w| acc + w) + ((columns.len() as f32) - 1.0).max(0.0) * 2.0 * gw;
+        let h = (cmp::min(rows, lines.len()) as f32) * gh;
+        self.push_box(x, y, x + w + 2.0 * pad, y + h + 2.0 * pad, [0.0, 0.0, 0.0, 0.75]);
+
+        let mut x_column = x + pad;
+        for (column, width) in columns.iter().zip(widths.iter()) {
+            for (i, line) in column.iter().enumerate() {
+                self.push_text(x_column, y + pad + (i as f32) * gh, line, [1.0, 1.0, 1.0, 1.0]);
+            }
+            x_column += width + 2.0 * gw;
+        }
+    }
+}
+
+pub fn text_width(s: &str) -> f32 {
+    (s.chars().count() as u32 * GLYPH_WIDTH) as f32
+}
+
+
+pub struct TextRenderer {
+    texture: glium::Texture2d,
+    program: glium::Program,
+}
+
+impl TextRenderer {
+    pub fn new<F>(facade: &F) -> TextRenderer where F: Facade {
+        let atlas_width = GLYPH_COUNT * GLYPH_WIDTH;
+        let mut pixels = vec![0u8; (atlas_width * GLYPH_HEIGHT * 4) as usize];
+        for glyph in 0..GLYPH_COUNT {
+            for row in 0..GLYPH_HEIGHT {
+                let bits = if glyph == GLYPH_SOLID {
+                    0xff
+                } else {
+                    res::FONT_BITMAP[(glyph * GLYPH_HEIGHT + row) as usize]
+                };
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (0x80 >> col) != 0 {
+                        let idx = ((row * atlas_width + glyph * GLYPH_WIDTH + col) * 4) as usize;
+                        for c in 0..4 {
+                            pixels[idx + c] = 255;
+                        }
+                    }
+                }
+            }
+        }
+        let image = glium::texture::RawImage2d::from_raw_rgba(pixels, (atlas_width, GLYPH_HEIGHT));
+
+        TextRenderer {
+            texture: glium::Texture2d::new(facade, image).unwrap(),
+            program: glium::Program::from_source(facade, res::VERTEX_SHADER_TEXT_SRC, res::FRAGMENT_SHADER_TEXT_SRC, None).unwrap(),
+        }
+    }
+
+    pub fn draw<F, S>(&self, facade: &F, surface: &mut S, batch: &TextBatch) where F: Facade, S: Surface {
+        if batch.vertices.is_empty() {
+            return;
+        }
+
+        let vertex_buffer = glium::VertexBuffer::new(facade, &batch.vertices).unwrap();
+        let sampler = glium::uniforms::Sampler::new(&self.texture)
+            .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
+            .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest);
+        let params = glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            .. Default::default()
+        };
+
+        surface.draw(
+            &vertex_buffer,
+            &glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+            &self.program,
+            &uniform! {
+                tex: sampler,
+            },
+            &params
+        ).unwrap();
+    }
+}
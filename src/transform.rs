@@ -0,0 +1,252 @@
+use data::Column;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::f64;
+
+
+/// Per-axis normalisation that is applied to a column before projection.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Transform {
+    Identity,
+    ZScore,
+    Robust,
+    Rank,
+    Clip,
+}
+
+static TRANSFORMS: &'static [(Transform, &'static str)] = &[
+    (Transform::Identity, "identity"),
+    (Transform::ZScore,   "zscore"),
+    (Transform::Robust,   "robust"),
+    (Transform::Rank,     "rank"),
+    (Transform::Clip,     "clip"),
+];
+
+impl Transform {
+    pub fn next(&self) -> Transform {
+        let pos = TRANSFORMS.iter().position(|&(t, _)| t == *self).unwrap();
+        TRANSFORMS[(pos + 1) % TRANSFORMS.len()].0
+    }
+
+    pub fn name(&self) -> &'static str {
+        TRANSFORMS.iter().find(|&&(t, _)| t == *self).unwrap().1
+    }
+
+    pub fn from_name(s: &str) -> Option<Transform> {
+        TRANSFORMS.iter().find(|&&(_, name)| name == s).map(|&(t, _)| t)
+    }
+}
+
+
+/// How transformed values relate to the units of the original column.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Units {
+    /// `original = transformed * scale + offset`
+    Linear {
+        offset: f64,
+        scale: f64,
+    },
+    /// Transformed values are fractions of rows, in [0, 1].
+    Percentile,
+}
+
+impl Units {
+    pub fn identity() -> Units {
+        Units::Linear {
+            offset: 0.0,
+            scale: 1.0,
+        }
+    }
+
+    /// `v` in the units of the original column; percentiles stay fractions of rows.
+    pub fn to_original(&self, v: f64) -> f64 {
+        match *self {
+            Units::Linear { offset, scale } => v * scale + offset,
+            Units::Percentile => v,
+        }
+    }
+}
+
+
+/// Sorted copy of all non-NA values.
+fn sorted_values(data: &[f64]) -> Vec<f64> {
+    let mut values = data.iter().cloned().filter(|v| !v.is_nan()).collect::<Vec<f64>>();
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    values
+}
+
+/// Linearly interpolated percentile `p` (in [0, 1]) of sorted values.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let pos = p * ((sorted.len() - 1) as f64);
+    let lo = pos.floor() as usize;
+    let hi = pos.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - (lo as f64))
+}
+
+fn linear(column: &Column, center: f64, spread: f64) -> (Column, Units) {
+    let spread = if spread > 0.0 { spread } else { 1.0 };
+    let data = column.data.iter().map(|v| (v - center) / spread).collect();
+    (Column::from_data(&column.name, data), Units::Linear { offset: center, scale: spread })
+}
+
+fn zscore(column: &Column) -> (Column, Units) {
+    let values = column.data.iter().cloned().filter(|v| !v.is_nan()).collect::<Vec<f64>>();
+    let n = values.len() as f64;
+    let mean = values.iter().fold(0.0, |acc, v| acc + v) / n;
+    let var = values.iter().fold(0.0, |acc, v| acc + (v - mean).powi(2)) / n;
+    linear(column, mean, var.sqrt())
+}
+
+fn robust(column: &Column) -> (Column, Units) {
+    let sorted = sorted_values(&column.data);
+    let median = percentile(&sorted, 0.5);
+    let iqr = percentile(&sorted, 0.75) - percentile(&sorted, 0.25);
+    linear(column, median, iqr)
+}
+
+/// Percentile rank, ties get the average of their ranks.
+fn rank(column: &Column) -> (Column, Units) {
+    let mut order = (0..column.data.len()).filter(|&i| !column.data[i].is_nan()).collect::<Vec<usize>>();
+    order.sort_by(|&a, &b| column.data[a].partial_cmp(&column.data[b]).unwrap_or(Ordering::Equal));
+
+    let mut data = vec![f64::NAN; column.data.len()];
+    let denom = if order.len() > 1 { (order.len() - 1) as f64 } else { 1.0 };
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && column.data[order[end]] == column.data[order[start]] {
+            end += 1;
+        }
+        let r = ((start + end - 1) as f64) / 2.0 / denom;
+        for i in start..end {
+            data[order[i]] = r;
+        }
+        start = end;
+    }
+
+    (Column::from_data(&column.name, data), Units::Percentile)
+}
+
+fn clip(column: &Column, low: f64, high: f64) -> (Column, Units) {
+    let sorted = sorted_values(&column.data);
+    let lo = percentile(&sorted, low);
+    let hi = percentile(&sorted, high);
+    let data = column.data.iter().map(|v| if v.is_nan() { *v } else { v.max(lo).min(hi) }).collect();
+    (Column::from_data(&column.name, data), Units::identity())
+}
+
+
+/// Transformed versions of columns, computed on first use.
+pub struct TransformCache {
+    clip_low: f64,
+    clip_high: f64,
+    entries: HashMap<(usize, Transform), (Column, Units)>,
+}
+
+impl TransformCache {
+    /// `clip_low` and `clip_high` are the percentiles (in [0, 1]) used by `Transform::Clip`.
+    pub fn new(clip_low: f64, clip_high: f64) -> TransformCache {
+        TransformCache {
+            clip_low: clip_low,
+            clip_high: clip_high,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn prepare(&mut self, columns: &[Column], idx: usize, t: Transform) {
+        if t == Transform::Identity || self.entries.contains_key(&(idx, t)) {
+            return;
+        }
+
+        debug!("compute {} transform of column {}", t.name(), columns[idx].name);
+        let column = &columns[idx];
+        let entry = match t {
+            Transform::Identity => unreachable!(),
+            Transform::ZScore   => zscore(column),
+            Transform::Robust   => robust(column),
+            Transform::Rank     => rank(column),
+            Transform::Clip     => clip(column, self.clip_low, self.clip_high),
+        };
+        self.entries.insert((idx, t), entry);
+    }
+
+    /// Forgets all transformed columns, e.g. after loading another file.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Needs a previous `prepare` for the same column and transform.
+    pub fn get<'a>(&'a self, columns: &'a [Column], idx: usize, t: Transform) -> (&'a Column, Units) {
+        if t == Transform::Identity {
+            (&columns[idx], Units::identity())
+        } else {
+            let &(ref column, units) = self.entries.get(&(idx, t)).expect("transform was not prepared");
+            (column, units)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transformed(data: Vec<f64>, t: Transform) -> (Vec<f64>, Units) {
+        let columns = vec![Column::from_data("a", data)];
+        let mut cache = TransformCache::new(0.25, 0.75);
+        cache.prepare(&columns, 0, t);
+        let (column, units) = cache.get(&columns, 0, t);
+        (column.data.to_vec(), units)
+    }
+
+    #[test]
+    fn maps_linear_transforms_back_to_the_original_units() {
+        let data = vec![3.0, -1.0, f64::NAN, 10.0, 4.5, 4.5];
+        for &t in &[Transform::Identity, Transform::ZScore, Transform::Robust] {
+            let (values, units) = transformed(data.clone(), t);
+            for (v, original) in values.iter().zip(data.iter()) {
+                if original.is_nan() {
+                    assert!(v.is_nan());
+                } else {
+                    assert!((units.to_original(*v) - original).abs() < 1e-9, "{} of {} gave {}", t.name(), original, units.to_original(*v));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scales_the_zscore_to_unit_variance() {
+        let (values, units) = transformed(vec![1.0, 2.0, 3.0, 4.0], Transform::ZScore);
+        let var = values.iter().map(|v| v * v).sum::<f64>() / 4.0;
+        assert!((values.iter().sum::<f64>()).abs() < 1e-9);
+        assert!((var - 1.0).abs() < 1e-9);
+        assert_eq!(units, Units::Linear { offset: 2.5, scale: 1.25f64.sqrt() });
+    }
+
+    #[test]
+    fn ranks_ties_by_their_average() {
+        let (values, units) = transformed(vec![30.0, 10.0, 20.0, 20.0, f64::NAN], Transform::Rank);
+        assert_eq!(&values[..4], &[1.0, 0.0, 0.5, 0.5]);
+        assert!(values[4].is_nan());
+        assert_eq!(units, Units::Percentile);
+        assert_eq!(units.to_original(0.5), 0.5);
+    }
+
+    #[test]
+    fn clips_to_the_percentiles() {
+        let (values, units) = transformed(vec![0.0, 1.0, 2.0, 3.0, 4.0], Transform::Clip);
+        assert_eq!(values, vec![1.0, 1.0, 2.0, 3.0, 3.0]);
+        assert_eq!(units, Units::identity());
+    }
+
+    #[test]
+    fn keeps_constant_columns_finite() {
+        let (values, units) = transformed(vec![2.0, 2.0, 2.0], Transform::Robust);
+        assert_eq!(values, vec![0.0; 3]);
+        assert_eq!(units.to_original(0.0), 2.0);
+    }
+}
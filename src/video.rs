@@ -0,0 +1,252 @@
+use image;
+use image::Image;
+
+use std::cmp;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+
+/// Palette levels per RGB channel, for GIF and sixel output.
+pub static PALETTE_LEVELS: u32 = 6;
+
+/// 4x4 Bayer matrix for ordered dithering.
+static BAYER: [[u32; 4]; 4] = [
+    [ 0,  8,  2, 10],
+    [12,  4, 14,  6],
+    [ 3, 11,  1,  9],
+    [15,  7, 13,  5],
+];
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    /// `<stem>_00000.png`, `<stem>_00001.png`, ...
+    PngSeries,
+    /// uncompressed YUV4MPEG2, 4:4:4
+    Y4m,
+    /// animated GIF with a fixed palette, looping
+    Gif,
+}
+
+
+/// Writes a sequence of tone mapped frames of the same size, one at a time.
+pub struct VideoWriter {
+    fname: String,
+    width: u32,
+    height: u32,
+    frame_millis: u64,
+    format: Format,
+    file: Option<BufWriter<File>>,
+    frames: usize,
+}
+
+impl VideoWriter {
+    /// The format follows from the extension of `fname`: `.png`, `.y4m` or `.gif`.
+    pub fn create(fname: &str, width: u32, height: u32, frame_millis: u64) -> Result<VideoWriter, String> {
+        let lower = fname.to_lowercase();
+        let format = if lower.ends_with(".png") {
+            Format::PngSeries
+        } else if lower.ends_with(".y4m") {
+            Format::Y4m
+        } else if lower.ends_with(".gif") {
+            Format::Gif
+        } else {
+            return Err(format!("cannot export an animation to {}, use .png, .y4m or .gif", fname));
+        };
+        if format == Format::Gif && (width > 0xffff || height > 0xffff) {
+            return Err(String::from("frames are too large for a GIF file"));
+        }
+
+        let mut writer = VideoWriter {
+            fname: String::from(fname),
+            width: width,
+            height: height,
+            frame_millis: frame_millis,
+            format: format,
+            file: None,
+            frames: 0,
+        };
+        let header = match format {
+            Format::PngSeries => {
+                return Ok(writer);
+            },
+            Format::Y4m => format!("YUV4MPEG2 W{} H{} F1000:{} Ip A1:1 C444\n", width, height, frame_millis).into_bytes(),
+            Format::Gif => gif_header(width, height),
+        };
+        writer.file = match File::create(fname) {
+            Ok(f) => Some(BufWriter::new(f)),
+            Err(_) => {
+                return Err(format!("cannot write file {}!", fname));
+            }
+        };
+        match writer.write(&header) {
+            Ok(_) => Ok(writer),
+            Err(s) => Err(s),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        let ok = match self.file {
+            Some(ref mut f) => f.write_all(data).is_ok(),
+            None => false,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(format!("cannot write file {}!", self.fname))
+        }
+    }
+
+    pub fn push(&mut self, frame: &Image) -> Result<(), String> {
+        let rgb = frame.to_srgb8();
+        let result = match self.format {
+            Format::PngSeries => {
+                let stem = &self.fname[..self.fname.len() - 4];
+                let name = format!("{}_{:05}.png", stem, self.frames);
+                image::write_file(&name, &image::encode_png(self.width, self.height, 8, &rgb))
+            },
+            Format::Y4m => {
+                let data = y4m_frame(&rgb);
+                self.write(&data)
+            },
+            Format::Gif => {
+                let delay = (self.frame_millis / 10) as u16;
+                let data = gif_frame(self.width, self.height, delay, &rgb);
+                self.write(&data)
+            },
+        };
+        self.frames += 1;
+        result
+    }
+
+    /// Returns the number of frames written.
+    pub fn finish(mut self) -> Result<usize, String> {
+        if self.format == Format::Gif {
+            // trailer
+            if let Err(s) = self.write(&[0x3b]) {
+                return Err(s);
+            }
+        }
+        let ok = match self.file {
+            Some(ref mut f) => f.flush().is_ok(),
+            None => true,
+        };
+        if ok {
+            Ok(self.frames)
+        } else {
+            Err(format!("cannot write file {}!", self.fname))
+        }
+    }
+}
+
+
+/// `FRAME` followed by the Y, Cb and Cr planes (BT.601, limited range).
+fn y4m_frame(rgb: &[u8]) -> Vec<u8> {
+    let pixels = rgb.len() / 3;
+    let mut planes = vec![0u8; 3 * pixels];
+    for (i, p) in rgb.chunks(3).enumerate() {
+        let (r, g, b) = ((p[0] as f32) / 255.0, (p[1] as f32) / 255.0, (p[2] as f32) / 255.0);
+        planes[i]              = ( 16.0 +  65.481 * r + 128.553 * g +  24.966 * b).round() as u8;
+        planes[pixels + i]     = (128.0 -  37.797 * r -  74.203 * g + 112.0   * b).round() as u8;
+        planes[2 * pixels + i] = (128.0 + 112.0   * r -  93.786 * g -  18.214 * b).round() as u8;
+    }
+    let mut data = b"FRAME\n".to_vec();
+    data.extend_from_slice(&planes);
+    data
+}
+
+
+/// Header, a global palette with `PALETTE_LEVELS` levels per channel and endless looping.
+fn gif_header(width: u32, height: u32) -> Vec<u8> {
+    let mut gif = b"GIF89a".to_vec();
+    image::push_le16(&mut gif, width as u16);
+    image::push_le16(&mut gif, height as u16);
+    // global color table with 256 entries, 8 bit color resolution
+    gif.extend_from_slice(&[0xf7, 0, 0]);
+    for i in 0..256 {
+        let rgb = if i < PALETTE_LEVELS * PALETTE_LEVELS * PALETTE_LEVELS {
+            [i / (PALETTE_LEVELS * PALETTE_LEVELS), (i / PALETTE_LEVELS) % PALETTE_LEVELS, i % PALETTE_LEVELS]
+        } else {
+            [0, 0, 0]
+        };
+        for c in &rgb {
+            gif.push((c * 255 / (PALETTE_LEVELS - 1)) as u8);
+        }
+    }
+    gif.extend_from_slice(&[0x21, 0xff, 0x0b]);
+    gif.extend_from_slice(b"NETSCAPE2.0");
+    gif.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+    gif
+}
+
+/// Palette indices of 8 bit sRGB pixels with ordered dithering. Index `(r * levels + g) * levels + b`
+/// stands for the color `(r, g, b) * 255 / (levels - 1)`.
+pub fn dither(width: u32, rgb: &[u8]) -> Vec<u8> {
+    let mut indices = Vec::with_capacity(rgb.len() / 3);
+    for (i, p) in rgb.chunks(3).enumerate() {
+        let (x, y) = ((i as u32) % width, (i as u32) / width);
+        let threshold = BAYER[(y % 4) as usize][(x % 4) as usize] * 2 + 1;
+        let mut index = 0;
+        for &c in p {
+            // scaled to 32 steps per level, rounded up from the threshold
+            let scaled = (c as u32) * (PALETTE_LEVELS - 1) * 32 / 255;
+            let level = cmp::min(scaled / 32 + if scaled % 32 >= threshold { 1 } else { 0 }, PALETTE_LEVELS - 1);
+            index = index * PALETTE_LEVELS + level;
+        }
+        indices.push(index as u8);
+    }
+    indices
+}
+
+/// Graphic control extension and image with `delay` in 1/100 s, dithered to the global palette.
+fn gif_frame(width: u32, height: u32, delay: u16, rgb: &[u8]) -> Vec<u8> {
+    let indices = dither(width, rgb);
+
+    let mut gif = vec![0x21, 0xf9, 0x04, 0x00];
+    image::push_le16(&mut gif, delay);
+    gif.extend_from_slice(&[0x00, 0x00]);
+    gif.push(0x2c);
+    image::push_le16(&mut gif, 0);
+    image::push_le16(&mut gif, 0);
+    image::push_le16(&mut gif, width as u16);
+    image::push_le16(&mut gif, height as u16);
+    gif.push(0x00);
+
+    gif.push(8);
+    for block in lzw_uncompressed(&indices).chunks(255) {
+        gif.push(block.len() as u8);
+        gif.extend_from_slice(block);
+    }
+    gif.push(0x00);
+    gif
+}
+
+/// LZW stream with 8 bit symbols that only uses literal codes, with a clear code often enough to
+/// keep the code size at 9 bits.
+fn lzw_uncompressed(indices: &[u8]) -> Vec<u8> {
+    let (clear, end) = (256u32, 257u32);
+    let mut out = vec![];
+    let (mut acc, mut bits) = (0u32, 0u32);
+    {
+        let mut emit = |code: u32| {
+            acc |= code << bits;
+            bits += 9;
+            while bits >= 8 {
+                out.push(acc as u8);
+                acc >>= 8;
+                bits -= 8;
+            }
+        };
+        for chunk in indices.chunks(254) {
+            emit(clear);
+            for &i in chunk {
+                emit(i as u32);
+            }
+        }
+        emit(end);
+    }
+    if bits > 0 {
+        out.push(acc as u8);
+    }
+    out
+}
@@ -0,0 +1,674 @@
+//! State of a view that does not depend on how it is drawn, shared by the OpenGL renderer and the
+//! terminal.
+
+extern crate glium;
+
+use bookmarks::{AxisBookmark, Bookmark, Bookmarks};
+
+use cfg;
+
+use data;
+use data::Column;
+
+use expr;
+
+use filter::Filter;
+
+use glium::glutin::VirtualKeyCode;
+
+use history::{Gesture, History};
+
+use keymap::Action;
+
+use settings::Settings;
+
+use transform::{Transform, TransformCache};
+
+use std::f32;
+use std::f64;
+
+
+#[derive(Clone, PartialEq)]
+pub struct Projection {
+    pub scale_x: f64,
+    pub scale_y: f64,
+    pub scale_z: f64,
+    pub delta_x: f64,
+    pub delta_y: f64,
+    pub delta_z: f64,
+}
+
+impl Projection {
+    pub fn new() -> Projection {
+        Projection {
+            scale_x: 1.0,
+            scale_y: 1.0,
+            scale_z: 1.0,
+            delta_x: 0.0,
+            delta_y: 0.0,
+            delta_z: 0.0,
+        }
+    }
+
+    pub fn adjust_x(&mut self, min: f64, max: f64) {
+        if (max - min).abs() > f64::EPSILON * f64::max(min.abs(), max.abs()) {
+            self.scale_x = 2.0 / (max - min);
+        }
+        self.delta_x = -1.0 - min * self.scale_x;
+        debug!("adjust x projection: data_range=[{}, {}] scale={} delta={}", min, max, self.scale_x, self.delta_x);
+    }
+
+    pub fn adjust_y(&mut self, min: f64, max: f64) {
+        if (max - min).abs() > f64::EPSILON * f64::max(min.abs(), max.abs()) {
+            self.scale_y = 2.0 / (max - min);
+        }
+        self.delta_y = -1.0 - min * self.scale_y;
+        debug!("adjust y projection: data_range=[{}, {}] scale={} delta={}", min, max, self.scale_y, self.delta_y);
+    }
+
+    pub fn adjust_z(&mut self, min: f64, max: f64) {
+        if (max - min).abs() > f64::EPSILON * f64::max(min.abs(), max.abs()) {
+            self.scale_z = 1.0 / (max - min);
+        }
+        self.delta_z = -min * self.scale_z;
+        debug!("adjust z projection: data_range=[{}, {}] scale={} delta={}", min, max, self.scale_z, self.delta_z);
+    }
+
+    pub fn move_x(&mut self, dx: i32, width: u32) {
+        self.delta_x += 2.0 * (dx as f64) / (width as f64);
+    }
+
+    pub fn move_y(&mut self, dy: i32, height: u32) {
+        self.delta_y -= 2.0 * (dy as f64) / (height as f64);
+    }
+
+    pub fn scroll_x(&mut self, dx: f32, posx: u32, width: u32) {
+        let posx_relative = 2.0 * (posx as f64) / (width as f64) - 1.0;
+        let scale_x_old = self.scale_x;
+        let factor_x = cfg::SCROLL_BASE.powf(dx as f64);
+        self.scale_x = f64::max(cfg::SCALE_MIN, self.scale_x * factor_x);
+        self.delta_x += (scale_x_old - self.scale_x) * (posx_relative - self.delta_x) / scale_x_old;
+    }
+
+    pub fn scroll_y(&mut self, dy: f32, posy: u32, height: u32) {
+        let posy_relative = -(2.0 * (posy as f64) / (height as f64) - 1.0);
+        let scale_y_old = self.scale_y;
+        let factor_y = cfg::SCROLL_BASE.powf(dy as f64);
+        self.scale_y = f64::max(cfg::SCALE_MIN, self.scale_y * factor_y);
+        self.delta_y += (scale_y_old - self.scale_y) * (posy_relative - self.delta_y) / scale_y_old;
+    }
+
+    /// The part `[x0, x1] x [y0, y1]` of the view (as fractions from the bottom left corner),
+    /// stretched to the whole viewport.
+    pub fn crop(&self, x0: f64, x1: f64, y0: f64, y1: f64) -> Projection {
+        let mut p = self.clone();
+        p.scale_x = self.scale_x / (x1 - x0);
+        p.delta_x = (self.delta_x - (x0 + x1 - 1.0)) / (x1 - x0);
+        p.scale_y = self.scale_y / (y1 - y0);
+        p.delta_y = (self.delta_y - (y0 + y1 - 1.0)) / (y1 - y0);
+        p
+    }
+
+    pub fn range_x(&self) -> (f64, f64) {
+        ((-1.0 - self.delta_x) / self.scale_x, (1.0 - self.delta_x) / self.scale_x)
+    }
+
+    pub fn range_y(&self) -> (f64, f64) {
+        ((-1.0 - self.delta_y) / self.scale_y, (1.0 - self.delta_y) / self.scale_y)
+    }
+
+    pub fn range_z(&self) -> (f64, f64) {
+        (-self.delta_z / self.scale_z, (1.0 - self.delta_z) / self.scale_z)
+    }
+
+    /// Data coordinates of the view center (Z: middle of the color range).
+    pub fn center(&self) -> [f64; 3] {
+        [-self.delta_x / self.scale_x, -self.delta_y / self.scale_y, (0.5 - self.delta_z) / self.scale_z]
+    }
+
+    /// How far `origin` is from the view center, in multiples of half the view size.
+    pub fn distance(&self, origin: &[f64; 3]) -> f64 {
+        let center = self.center();
+        let scales = [self.scale_x, self.scale_y, self.scale_z];
+        (0..3).fold(0.0, |acc, i| f64::max(acc, ((center[i] - origin[i]) * scales[i]).abs()))
+    }
+
+    /// Matrix for points that were uploaded relative to `origin`. The offset is folded into the
+    /// translation in double precision, so only small numbers reach the GPU.
+    pub fn get_matrix(&self, origin: &[f64; 3]) -> [[f32; 4]; 4] {
+        let dx = (self.delta_x + self.scale_x * origin[0]) as f32;
+        let dy = (self.delta_y + self.scale_y * origin[1]) as f32;
+        let dz = (self.delta_z + self.scale_z * origin[2]) as f32;
+        let (sx, sy, sz) = (self.scale_x as f32, self.scale_y as f32, self.scale_z as f32);
+        [
+            [sx , 0.0, 0.0, 0.0],
+            [0.0, sy , 0.0, 0.0],
+            [0.0, 0.0, sz , 0.0],
+            [dx , dy , dz , 1.0],
+        ]
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct ColumnState {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+    pub tx: Transform,
+    pub ty: Transform,
+    pub tz: Transform,
+}
+
+impl ColumnState {
+    pub fn new(m: usize) -> ColumnState {
+        ColumnState {
+            x: 0,
+            y: 1,
+            z: if m > 2 { 2 } else { 1 },
+            tx: Transform::Identity,
+            ty: Transform::Identity,
+            tz: Transform::Identity,
+        }
+    }
+
+    pub fn x_prev(&mut self, m: usize) {
+        if self.x == 0 {
+            self.x = m;
+        }
+        self.x -= 1;
+    }
+
+    pub fn y_prev(&mut self, m: usize) {
+        if self.y == 0 {
+            self.y = m;
+        }
+        self.y -= 1;
+    }
+
+    pub fn z_prev(&mut self, m: usize) {
+        if self.z == 0 {
+            self.z = m;
+        }
+        self.z -= 1;
+    }
+
+    pub fn x_next(&mut self, m: usize) {
+        self.x += 1;
+        if self.x >= m {
+            self.x = 0;
+        }
+    }
+
+    pub fn y_next(&mut self, m: usize) {
+        self.y += 1;
+        if self.y >= m {
+            self.y = 0;
+        }
+    }
+
+    pub fn z_next(&mut self, m: usize) {
+        self.z += 1;
+        if self.z >= m {
+            self.z = 0;
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct UserState {
+    pub gamma: f32,
+    pub pointsize: f32,
+    pub showborder: bool,
+    pub showaxes: bool,
+}
+
+impl UserState {
+    pub fn new() -> UserState {
+        UserState {
+            gamma:      cfg::GAMMA_DEFAULT,
+            pointsize:  cfg::POINTSIZE_DEFAULT,
+            showborder: cfg::SHOWBORDER_DEFAULT,
+            showaxes:   cfg::SHOWAXES_DEFAULT,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.gamma      = cfg::GAMMA_DEFAULT;
+        self.pointsize  = cfg::POINTSIZE_DEFAULT;
+        self.showborder = cfg::SHOWBORDER_DEFAULT;
+        self.showaxes   = cfg::SHOWAXES_DEFAULT;
+    }
+
+    pub fn showborder_toggle(&mut self) {
+        self.showborder = !self.showborder;
+    }
+
+    pub fn showaxes_toggle(&mut self) {
+        self.showaxes = !self.showaxes;
+    }
+
+    pub fn pointsize_increase(&mut self) {
+        self.pointsize = f32::min(self.pointsize * cfg::POINTSIZE_CHANGE, cfg::POINTSIZE_MAX);
+    }
+
+    pub fn pointsize_decrease(&mut self) {
+        self.pointsize = f32::max(self.pointsize / cfg::POINTSIZE_CHANGE, cfg::POINTSIZE_MIN);
+    }
+
+    pub fn gamma_increase(&mut self) {
+        self.gamma = f32::min(self.gamma * cfg::GAMMA_CHANGE, cfg::GAMMA_MAX);
+
+    }
+
+    pub fn gamma_decrease(&mut self) {
+        self.gamma = f32::max(self.gamma / cfg::GAMMA_CHANGE, cfg::GAMMA_MIN);
+    }
+}
+
+/// Everything that undo/redo restores.
+#[derive(Clone, PartialEq)]
+pub struct ViewState {
+    pub projection: Projection,
+    pub columns: ColumnState,
+    pub user: UserState,
+}
+
+pub fn same_view(a: &ViewState, b: &ViewState) -> bool {
+    a.projection == b.projection && a.columns == b.columns
+}
+
+
+/// Bookmark of the current columns and view ranges.
+pub fn bookmark(slot: usize, name: String, columns: &[Column], cs: &ColumnState, projection: &Projection) -> Bookmark {
+    let axis = |column: &Column, transform: Transform, range: (f64, f64)| {
+        AxisBookmark {
+            column: column.name.clone(),
+            transform: transform,
+            min: range.0,
+            max: range.1,
+        }
+    };
+    Bookmark {
+        slot: slot,
+        name: name,
+        x: axis(&columns[cs.x], cs.tx, projection.range_x()),
+        y: axis(&columns[cs.y], cs.ty, projection.range_y()),
+        z: axis(&columns[cs.z], cs.tz, projection.range_z()),
+    }
+}
+
+/// Columns of `bookmark`, `None` if it refers to columns that do not exist (anymore).
+pub fn bookmark_columns(bookmark: &Bookmark, columns: &[Column]) -> Option<ColumnState> {
+    let find = |axis: &AxisBookmark| columns.iter().position(|c| c.name == axis.column);
+    match (find(&bookmark.x), find(&bookmark.y), find(&bookmark.z)) {
+        (Some(x), Some(y), Some(z)) => Some(ColumnState {
+            x: x,
+            y: y,
+            z: z,
+            tx: bookmark.x.transform,
+            ty: bookmark.y.transform,
+            tz: bookmark.z.transform,
+        }),
+        _ => None,
+    }
+}
+
+
+/// Single line text input in the status line, for `purpose`.
+pub struct Prompt<P> {
+    pub purpose: P,
+    pub label: String,
+    pub input: String,
+}
+
+impl<P> Prompt<P> {
+    pub fn new(purpose: P, label: &str) -> Prompt<P> {
+        Prompt {
+            purpose: purpose,
+            label: String::from(label),
+            input: String::new(),
+        }
+    }
+
+    pub fn to_string(&self) -> String {
+        format!("{}: {}_", self.label, self.input)
+    }
+}
+
+/// Handles a key while `prompt` is open: Return submits the prompt, which is then returned, Escape
+/// cancels it and Backspace deletes the last character.
+pub fn prompt_key<P>(prompt: &mut Option<Prompt<P>>, code: VirtualKeyCode) -> Option<Prompt<P>> {
+    match code {
+        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+            return prompt.take();
+        },
+        VirtualKeyCode::Escape => {
+            *prompt = None;
+        },
+        VirtualKeyCode::Back => {
+            if let Some(ref mut prompt) = *prompt {
+                prompt.input.pop();
+            }
+        },
+        _ => ()
+    }
+    None
+}
+
+
+/// What an action changed, besides the view itself.
+pub struct Change {
+    /// other columns or transforms are shown
+    pub columns: bool,
+    /// message for the status line
+    pub status: Option<String>,
+}
+
+impl Change {
+    fn view() -> Change {
+        Change {
+            columns: false,
+            status: None,
+        }
+    }
+
+    fn columns() -> Change {
+        Change {
+            columns: true,
+            status: None,
+        }
+    }
+
+    fn status(msg: String) -> Change {
+        Change {
+            columns: false,
+            status: Some(msg),
+        }
+    }
+}
+
+
+/// The data, its selection and the view of it with its history and bookmarks, along with the
+/// actions on them that both front ends share.
+pub struct View {
+    pub columns: Vec<Column>,
+    pub column_state: ColumnState,
+    pub filter: Filter,
+    pub mask: Vec<bool>,
+    pub transform_cache: TransformCache,
+    pub projection: Projection,
+    pub user_state: UserState,
+    pub history: History<ViewState>,
+    pub gesture: Option<Gesture>,
+    pub bookmarks: Bookmarks,
+}
+
+impl View {
+    /// Shows the first columns, scaled to the rows that pass `filter`.
+    pub fn new(columns: Vec<Column>, filter: Filter, bookmarks: Bookmarks, settings: &Settings) -> Result<View, String> {
+        let mask = match filter.mask(&columns) {
+            Ok(m) => m,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+        let column_state = ColumnState::new(columns.len());
+        let initial = ViewState {
+            projection: Projection::new(),
+            columns: column_state.clone(),
+            user: UserState::new(),
+        };
+
+        let mut view = View {
+            columns: columns,
+            column_state: column_state,
+            filter: filter,
+            mask: mask,
+            transform_cache: TransformCache::new(settings.clip_low / 100.0, settings.clip_high / 100.0),
+            projection: Projection::new(),
+            user_state: UserState::new(),
+            history: History::new(initial, settings.history_depth),
+            gesture: None,
+            bookmarks: bookmarks,
+        };
+        view.autoscale();
+        let state = view.view_state();
+        view.history.reset(state);
+        Ok(view)
+    }
+
+    /// Range of a transformed column over the rows that pass the filter.
+    pub fn axis_range(&mut self, idx: usize, t: Transform) -> (f64, f64) {
+        self.transform_cache.prepare(&self.columns, idx, t);
+        let (column, _) = self.transform_cache.get(&self.columns, idx, t);
+        data::column_range(column, &self.mask)
+    }
+
+    pub fn autoscale_x(&mut self) {
+        let (min, max) = self.axis_range(self.column_state.x, self.column_state.tx);
+        self.projection.adjust_x(min, max);
+    }
+
+    pub fn autoscale_y(&mut self) {
+        let (min, max) = self.axis_range(self.column_state.y, self.column_state.ty);
+        self.projection.adjust_y(min, max);
+    }
+
+    pub fn autoscale_z(&mut self) {
+        let (min, max) = self.axis_range(self.column_state.z, self.column_state.tz);
+        self.projection.adjust_z(min, max);
+    }
+
+    pub fn autoscale(&mut self) {
+        self.autoscale_x();
+        self.autoscale_y();
+        self.autoscale_z();
+    }
+
+    pub fn view_state(&self) -> ViewState {
+        ViewState {
+            projection: self.projection.clone(),
+            columns: self.column_state.clone(),
+            user: self.user_state.clone(),
+        }
+    }
+
+    fn restore_view(&mut self, view: ViewState) -> Change {
+        let change = if view.columns != self.column_state { Change::columns() } else { Change::view() };
+        self.projection   = view.projection;
+        self.column_state = view.columns;
+        self.user_state   = view.user;
+        change
+    }
+
+    /// Adds the current view to the history if it changed, coalescing with the running gesture.
+    pub fn record_history(&mut self) {
+        let view = self.view_state();
+        if view != *self.history.current() {
+            self.history.record(view, self.gesture);
+        }
+    }
+
+    /// Applies `filter` and autoscales all axes to the remaining rows. Returns the status message.
+    pub fn set_filter(&mut self, filter: Filter) -> Result<String, String> {
+        let mask = match filter.mask(&self.columns) {
+            Ok(m) => m,
+            Err(s) => {
+                return Err(s);
+            }
+        };
+
+        let selected = mask.iter().filter(|m| **m).count();
+        self.mask = mask;
+        self.filter = filter;
+        self.autoscale();
+
+        if self.filter.is_empty() {
+            Ok(String::from("filter cleared"))
+        } else {
+            Ok(format!("filter: {} ({} of {} rows)", self.filter.to_string(), selected, self.mask.len()))
+        }
+    }
+
+    /// Narrows the filter by the predicate `s`, see `set_filter`.
+    pub fn add_filter(&mut self, s: &str) -> Result<String, String> {
+        let mut filter = self.filter.clone();
+        match filter.add(s) {
+            Ok(_)  => self.set_filter(filter),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Appends a (derived) column, so it takes part in column cycling. Returns the status message.
+    pub fn add_column(&mut self, column: Column) -> String {
+        let msg = format!("added column {} [{}, {}]", column.name, column.min, column.max);
+        self.columns.push(column);
+        msg
+    }
+
+    /// Adds the column defined like `ratio = a / b`, see `add_column`.
+    pub fn derive_column(&mut self, definition: &str) -> Result<String, String> {
+        match expr::derive_column(definition, &self.columns) {
+            Ok(column) => Ok(self.add_column(column)),
+            Err(s) => Err(s),
+        }
+    }
+
+    /// Stores the current view in `slot`, named `name` or after the slot if it is empty.
+    pub fn store_bookmark(&mut self, slot: usize, name: &str) -> Result<String, String> {
+        let name = match name.trim() {
+            "" => format!("bookmark {}", slot),
+            s  => String::from(s),
+        };
+        self.bookmarks.set(bookmark(slot, name.clone(), &self.columns, &self.column_state, &self.projection));
+        match self.bookmarks.save() {
+            Ok(_)  => Ok(format!("stored bookmark {} ({})", slot, name)),
+            Err(s) => Err(s),
+        }
+    }
+
+    fn recall_bookmark(&mut self, slot: usize) -> Change {
+        let bookmark = match self.bookmarks.get(slot) {
+            Some(b) => b.clone(),
+            None => {
+                return Change::status(format!("bookmark {} is empty", slot));
+            }
+        };
+        let column_state = match bookmark_columns(&bookmark, &self.columns) {
+            Some(cs) => cs,
+            None => {
+                return Change::status(format!("bookmark {} ({}) refers to unknown columns", slot, bookmark.name));
+            }
+        };
+
+        let columns = column_state != self.column_state;
+        self.column_state = column_state;
+        self.projection.adjust_x(bookmark.x.min, bookmark.x.max);
+        self.projection.adjust_y(bookmark.y.min, bookmark.y.max);
+        self.projection.adjust_z(bookmark.z.min, bookmark.z.max);
+        Change {
+            columns: columns,
+            status: Some(format!("bookmark {} ({})", slot, bookmark.name)),
+        }
+    }
+
+    /// Carries out `action` if it only changes the view, `None` for the actions that are up to the
+    /// front end.
+    pub fn apply_action(&mut self, action: Action) -> Option<Change> {
+        let m = self.columns.len();
+        let change = match action {
+            Action::ToggleBorder => {
+                self.user_state.showborder_toggle();
+                Change::view()
+            },
+            Action::ToggleAxes => {
+                self.user_state.showaxes_toggle();
+                Change::view()
+            },
+            Action::GammaIncrease => {
+                self.user_state.gamma_increase();
+                Change::view()
+            },
+            Action::GammaDecrease => {
+                self.user_state.gamma_decrease();
+                Change::view()
+            },
+            Action::Reset => {
+                self.autoscale();
+                self.user_state.reset();
+                Change::view()
+            },
+            Action::XPrev => {
+                self.column_state.x_prev(m);
+                self.autoscale_x();
+                Change::columns()
+            },
+            Action::XNext => {
+                self.column_state.x_next(m);
+                self.autoscale_x();
+                Change::columns()
+            },
+            Action::YPrev => {
+                self.column_state.y_prev(m);
+                self.autoscale_y();
+                Change::columns()
+            },
+            Action::YNext => {
+                self.column_state.y_next(m);
+                self.autoscale_y();
+                Change::columns()
+            },
+            Action::ZPrev => {
+                self.column_state.z_prev(m);
+                self.autoscale_z();
+                Change::columns()
+            },
+            Action::ZNext => {
+                self.column_state.z_next(m);
+                self.autoscale_z();
+                Change::columns()
+            },
+            Action::TransformX => {
+                self.column_state.tx = self.column_state.tx.next();
+                self.autoscale_x();
+                Change {
+                    columns: true,
+                    status: Some(format!("x transform: {}", self.column_state.tx.name())),
+                }
+            },
+            Action::TransformY => {
+                self.column_state.ty = self.column_state.ty.next();
+                self.autoscale_y();
+                Change {
+                    columns: true,
+                    status: Some(format!("y transform: {}", self.column_state.ty.name())),
+                }
+            },
+            Action::TransformZ => {
+                self.column_state.tz = self.column_state.tz.next();
+                self.autoscale_z();
+                Change {
+                    columns: true,
+                    status: Some(format!("z transform: {}", self.column_state.tz.name())),
+                }
+            },
+            Action::Undo | Action::Redo | Action::Back | Action::Forward => {
+                let view = match action {
+                    Action::Undo => self.history.undo(),
+                    Action::Redo => self.history.redo(),
+                    Action::Back => self.history.back(same_view),
+                    _            => self.history.forward(same_view),
+                };
+                match view {
+                    Some(view) => self.restore_view(view),
+                    None => Change::view(),
+                }
+            },
+            Action::RecallBookmark(slot) => self.recall_bookmark(slot),
+            _ => {
+                return None;
+            },
+        };
+        Some(change)
+    }
+}